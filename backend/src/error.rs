@@ -16,6 +16,9 @@ pub enum BackendError {
     #[error("Scene not found")]
     NotFound,
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Multipart error")]
     Multipart(#[from] MultipartError),
 
@@ -27,6 +30,9 @@ pub enum BackendError {
 
     #[error("Pipeline error")]
     Pipeline(#[from] pipeline::PipelineError),
+
+    #[error("Dataset error: {0}")]
+    Dataset(#[from] dataset::error::DatasetError),
 }
 
 impl IntoResponse for BackendError {
@@ -34,11 +40,13 @@ impl IntoResponse for BackendError {
         let status = match self {
             BackendError::BadRequest(_) => StatusCode::BAD_REQUEST,
             BackendError::NotFound => StatusCode::NOT_FOUND,
+            BackendError::Conflict(_) => StatusCode::CONFLICT,
             BackendError::Multipart(_) => StatusCode::BAD_REQUEST,
             BackendError::TokioIo(_) => StatusCode::INTERNAL_SERVER_ERROR,
             BackendError::Zip(_) => StatusCode::BAD_REQUEST,
             BackendError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
             BackendError::Pipeline(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            BackendError::Dataset(_) => StatusCode::UNPROCESSABLE_ENTITY,
         };
 
         (status, self.to_string()).into_response()
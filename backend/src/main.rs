@@ -17,6 +17,7 @@ mod routes;
 mod state;
 mod error;
 mod pipeline;
+mod storage;
 
 #[tokio::main]
 async fn main() {
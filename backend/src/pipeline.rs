@@ -1,7 +1,63 @@
 use burn::prelude::Backend;
+use dataset::{EvalSplit, LoadConfig, LoaderConfig, ViewSampling};
+use pipeline::PipelineConfig;
 use render::gaussian_splats::Splats;
+use web_cmn::pipeline::{self as wired, TrainOptions};
 use web_cmn::splats::RawSplats;
 
+// One in every 8 views is held out for evaluation, unless the run asks otherwise.
+const DEFAULT_EVAL_EVERY: usize = 8;
+// Loss-weighted sampling keeps most of the previous loss of a view, and still picks some views at random.
+const LOSS_SMOOTHING: f32 = 0.9;
+const LOSS_UNIFORM_MIX: f32 = 0.1;
+
+/// How the dataset of a run is loaded. The layout route loads with the same config, so it shows
+/// which views training holds out.
+pub fn load_config(options: &TrainOptions) -> LoadConfig {
+    let eval_split = match options.eval_every.unwrap_or(DEFAULT_EVAL_EVERY) {
+        0 => EvalSplit::None,
+        every => EvalSplit::EveryNth { every },
+    };
+    let sampling = match options.sampling {
+        wired::ViewSampling::Uniform => ViewSampling::Uniform,
+        wired::ViewSampling::LossWeighted => ViewSampling::LossWeighted {
+            smoothing: LOSS_SMOOTHING,
+            uniform_mix: LOSS_UNIFORM_MIX,
+        },
+        wired::ViewSampling::Sequential => ViewSampling::Sequential,
+    };
+    let mut loader = LoaderConfig::new()
+        .with_decode_workers(options.decode_workers)
+        .with_sampling(sampling);
+    if let Some(prefetch) = options.prefetch {
+        loader = loader.with_prefetch(prefetch);
+    }
+
+    LoadConfig::new()
+        .with_eval_split(eval_split)
+        .with_downscale(options.downscale)
+        .with_normalize_scene(options.normalize_scene)
+        .with_loader(loader)
+}
+
+/// When a run evaluates and stops. The export path is set by the pipeline.
+pub fn pipeline_config(options: &TrainOptions) -> PipelineConfig {
+    let mut config = PipelineConfig::new()
+        .with_eval_save_to_disk(true)
+        .with_stop_plateau_evals(options.stop_plateau_evals)
+        .with_stop_target_psnr(options.stop_target_psnr)
+        .with_stop_target_ssim(options.stop_target_ssim)
+        .with_stop_time_budget_secs(options.stop_time_budget_secs)
+        .with_stop_splat_count_refines(options.stop_splat_count_refines);
+    if let Some(min_delta) = options.stop_plateau_min_delta {
+        config = config.with_stop_plateau_min_delta(min_delta);
+    }
+    if let Some(tolerance) = options.stop_splat_count_tolerance {
+        config = config.with_stop_splat_count_tolerance(tolerance);
+    }
+    config
+}
+
 pub fn splats_from_module<B: Backend>(splats: &Splats<B>) -> RawSplats {
     let means = splats.means.val().into_data().to_vec().unwrap();
     let rotation_data = splats.rotations_normed().into_data().to_vec().unwrap(); // Use normalized rotations
@@ -33,4 +89,60 @@ pub fn splats_from_module<B: Backend>(splats: &Splats<B>) -> RawSplats {
         sh_coeffs,
         sh_coeffs_dims: [sh_coeffs_dims[0], sh_coeffs_dims[1], sh_coeffs_dims[2]],
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_config_eval_split() {
+        let config = load_config(&TrainOptions::default());
+        assert_eq!(config.eval_split, EvalSplit::EveryNth { every: DEFAULT_EVAL_EVERY });
+
+        let options = TrainOptions { eval_every: Some(0), ..Default::default() };
+        assert_eq!(load_config(&options).eval_split, EvalSplit::None, "0 trains on all views");
+
+        let options = TrainOptions { eval_every: Some(3), ..Default::default() };
+        assert_eq!(load_config(&options).eval_split, EvalSplit::EveryNth { every: 3 });
+    }
+
+    #[test]
+    fn load_config_loader() {
+        let defaults = load_config(&TrainOptions::default()).loader;
+        assert_eq!(defaults.prefetch, LoaderConfig::new().prefetch, "Unset options keep the defaults");
+        assert_eq!(defaults.sampling, ViewSampling::Uniform);
+
+        let options = TrainOptions {
+            decode_workers: Some(2),
+            prefetch: Some(4),
+            sampling: wired::ViewSampling::LossWeighted,
+            ..Default::default()
+        };
+        let loader = load_config(&options).loader;
+        assert_eq!((loader.decode_workers, loader.prefetch), (Some(2), 4));
+        assert!(matches!(loader.sampling, ViewSampling::LossWeighted { .. }));
+    }
+
+    #[test]
+    fn pipeline_config_stop_rules() {
+        let defaults = pipeline_config(&TrainOptions::default());
+        assert!(defaults.eval_save_to_disk);
+        assert_eq!(defaults.stop_plateau_evals, None, "No stop rule unless asked for");
+        assert_eq!(defaults.stop_time_budget_secs, None);
+
+        let options = TrainOptions {
+            stop_plateau_evals: Some(3),
+            stop_target_psnr: Some(30.0),
+            stop_time_budget_secs: Some(600),
+            stop_splat_count_tolerance: Some(0.05),
+            ..Default::default()
+        };
+        let config = pipeline_config(&options);
+        assert_eq!(config.stop_plateau_evals, Some(3));
+        assert_eq!(config.stop_target_psnr, Some(30.0));
+        assert_eq!(config.stop_time_budget_secs, Some(600));
+        assert!((config.stop_splat_count_tolerance - 0.05).abs() < 1e-6);
+        assert_eq!(config.stop_plateau_min_delta, PipelineConfig::new().stop_plateau_min_delta, "Unset options keep the defaults");
+    }
+}
@@ -1,19 +1,31 @@
+mod artifact;
 mod scene;
 mod pipeline;
+mod upload;
 
 use std::sync::Arc;
 use axum::{Extension, Router};
 use axum::extract::DefaultBodyLimit;
 use axum::routing::{any, get, post, put};
+use crate::routes::artifact::{download_artifact, get_artifacts};
 use crate::routes::pipeline::train_scene;
-use crate::routes::scene::{get_scene, get_scenes, upload_scene};
+use crate::routes::upload::{cancel_upload, create_upload, finalize_upload, get_upload, upload_chunk};
+use crate::routes::scene::{delete_scene, get_scene, get_scene_layout, get_scenes, rename_scene, update_scene, upload_scene};
 use crate::state::AppState;
 
 pub fn api_routes() -> Router<Arc<AppState>> {
     Router::new()
+        // Uploads are streamed to disk, so their size isn't limited by memory.
         .route("/upload_scene", post(upload_scene))
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024 * 2))
-        .route("/scene/{name}", get(get_scene))
+        .route("/uploads", post(create_upload))
+        .route("/uploads/{id}", get(get_upload).put(upload_chunk).delete(cancel_upload))
+        .route("/uploads/{id}/finalize", post(finalize_upload))
+        .layer(DefaultBodyLimit::disable())
+        .route("/scene/{name}", get(get_scene).patch(update_scene).delete(delete_scene))
+        .route("/scene/{name}/rename", post(rename_scene))
+        .route("/scene/{name}/layout", get(get_scene_layout))
+        .route("/scene/{name}/artifacts", get(get_artifacts))
+        .route("/scene/{name}/artifacts/{*path}", get(download_artifact))
         .route("/scenes", get(get_scenes))
         .route("/train/{name}", any(train_scene))
 }
\ No newline at end of file
@@ -0,0 +1,232 @@
+use std::io::SeekFrom;
+use std::path::Path as FsPath;
+use std::sync::Arc;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use db::repo::{ArtifactKind, ArtifactMetadata, SplatRepository};
+use web_cmn::artifact::{self, ArtifactResponse};
+use crate::error::{BackendError, Result};
+use crate::state::AppState;
+use crate::storage;
+
+/// Records a file the pipeline wrote for a training run of the scene.
+pub async fn register_artifact(
+    state: &AppState,
+    scene_name: &str,
+    run: &str,
+    kind: pipeline::ArtifactKind,
+    iteration: u32,
+    path: &FsPath,
+) -> Result<ArtifactMetadata> {
+    let relative_path = path
+        .strip_prefix(storage::scene_dir(scene_name))
+        .map_err(|_| BackendError::Internal(anyhow::anyhow!("Artifact {path:?} is outside of scene {scene_name}")))?;
+
+    let size = fs::metadata(path).await?.len();
+    let checksum = storage::sha256_file(path).await?;
+
+    let artifact = ArtifactMetadata {
+        scene: scene_name.to_owned(),
+        run: run.to_owned(),
+        kind: match kind {
+            pipeline::ArtifactKind::EvalImage => ArtifactKind::EvalImage,
+            pipeline::ArtifactKind::Splats => ArtifactKind::Splats,
+            pipeline::ArtifactKind::Cameras => ArtifactKind::Cameras,
+            pipeline::ArtifactKind::Background => ArtifactKind::Background,
+        },
+        iteration,
+        // Always use forward slashes, the path ends up in urls.
+        path: relative_path.to_string_lossy().replace('\\', "/"),
+        size,
+        checksum,
+    };
+    state.repo.add_artifact(artifact.clone()).await?;
+    Ok(artifact)
+}
+
+pub fn artifact_metadata_to_response(artifact: ArtifactMetadata) -> ArtifactResponse {
+    ArtifactResponse {
+        run: artifact.run,
+        kind: match artifact.kind {
+            ArtifactKind::EvalImage => artifact::ArtifactKind::EvalImage,
+            ArtifactKind::Splats => artifact::ArtifactKind::Splats,
+            ArtifactKind::Cameras => artifact::ArtifactKind::Cameras,
+            ArtifactKind::Background => artifact::ArtifactKind::Background,
+        },
+        iteration: artifact.iteration,
+        path: artifact.path,
+        size: artifact.size,
+        checksum: artifact.checksum,
+    }
+}
+
+pub async fn get_artifacts(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<ArtifactResponse>>> {
+    if state.repo.get_scene(&name).await?.is_none() {
+        return Err(BackendError::NotFound);
+    }
+
+    let artifacts = state.repo.list_artifacts(&name).await?;
+    Ok(Json(artifacts.into_iter().map(artifact_metadata_to_response).collect()))
+}
+
+/// Serves the artifact file. Supports a single byte range so large downloads can be resumed.
+pub async fn download_artifact(
+    State(state): State<Arc<AppState>>,
+    Path((name, path)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    // Only registered paths are served, so the path can't be used to escape the scene directory.
+    let Some(artifact) = state.repo.get_artifact(&name, &path).await? else {
+        return Err(BackendError::NotFound);
+    };
+
+    let mut file = fs::File::open(storage::scene_dir(&name).join(&artifact.path)).await?;
+    let size = file.metadata().await?.len();
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_range);
+    let (start, end) = match range {
+        Some(range) => match range.resolve(size) {
+            Some(bounds) => bounds,
+            None => {
+                return Ok((
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(header::CONTENT_RANGE, format!("bytes */{size}"))],
+                ).into_response());
+            }
+        },
+        None => (0, size.saturating_sub(1)),
+    };
+    let len = if size == 0 { 0 } else { end - start + 1 };
+
+    file.seek(SeekFrom::Start(start)).await?;
+    let body = Body::from_stream(ReaderStream::new(file.take(len)));
+
+    let file_name = artifact.path.rsplit('/').next().unwrap_or(&artifact.path);
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, format!("\"{}\"", artifact.checksum))
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{file_name}\""));
+    response = match range {
+        Some(_) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}")),
+        None => response.status(StatusCode::OK),
+    };
+
+    response.body(body).map_err(|e| BackendError::Internal(e.into()))
+}
+
+/// A single range of a `Range: bytes=...` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ByteRange {
+    /// `bytes=start-` or `bytes=start-end`
+    From { start: u64, end: Option<u64> },
+    /// `bytes=-len`, the last `len` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Inclusive bounds of the range in a file of `size` bytes, or None if it can't be satisfied.
+    fn resolve(self, size: u64) -> Option<(u64, u64)> {
+        let last = size.checked_sub(1)?;
+        match self {
+            ByteRange::From { start, end } if start <= last => Some((start, end.unwrap_or(last).min(last))),
+            ByteRange::Suffix(len) if len > 0 => Some((size.saturating_sub(len), last)),
+            _ => None,
+        }
+    }
+}
+
+// Multiple ranges and malformed headers are ignored, the whole file is served instead.
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        return Some(ByteRange::Suffix(end.parse().ok()?));
+    }
+
+    let start = start.parse().ok()?;
+    let end = if end.is_empty() { None } else { Some(end.parse().ok()?) };
+    if end.is_some_and(|end| end < start) {
+        return None;
+    }
+    Some(ByteRange::From { start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(header: &str, size: u64) -> Option<(u64, u64)> {
+        parse_range(header).expect("Range should parse").resolve(size)
+    }
+
+    #[test]
+    fn open_ended_range_covers_the_rest() {
+        assert_eq!(parse_range("bytes=0-"), Some(ByteRange::From { start: 0, end: None }));
+        assert_eq!(resolve("bytes=0-", 100), Some((0, 99)));
+        assert_eq!(resolve("bytes=40-", 100), Some((40, 99)));
+    }
+
+    #[test]
+    fn closed_range_is_inclusive() {
+        assert_eq!(parse_range("bytes=10-19"), Some(ByteRange::From { start: 10, end: Some(19) }));
+        assert_eq!(resolve("bytes=10-19", 100), Some((10, 19)));
+    }
+
+    #[test]
+    fn suffix_range_is_the_last_bytes() {
+        assert_eq!(parse_range("bytes=-5"), Some(ByteRange::Suffix(5)));
+        assert_eq!(resolve("bytes=-5", 100), Some((95, 99)));
+        // A suffix longer than the file is the whole file.
+        assert_eq!(resolve("bytes=-500", 100), Some((0, 99)));
+        assert_eq!(resolve("bytes=-0", 100), None);
+    }
+
+    #[test]
+    fn end_past_the_file_is_clamped() {
+        assert_eq!(resolve("bytes=90-200", 100), Some((90, 99)));
+    }
+
+    #[test]
+    fn start_after_end_is_ignored() {
+        assert_eq!(parse_range("bytes=20-10"), None);
+    }
+
+    #[test]
+    fn start_past_eof_is_unsatisfiable() {
+        // Served as 416 Range Not Satisfiable.
+        assert_eq!(resolve("bytes=100-", 100), None);
+        assert_eq!(resolve("bytes=150-160", 100), None);
+        assert_eq!(resolve("bytes=0-", 0), None);
+    }
+
+    #[test]
+    fn multiple_and_malformed_ranges_are_ignored() {
+        assert_eq!(parse_range("bytes=0-10,20-30"), None);
+        assert_eq!(parse_range("bytes=abc-"), None);
+        assert_eq!(parse_range("bytes=5"), None);
+        assert_eq!(parse_range("bytes=-"), None);
+        assert_eq!(parse_range("items=0-10"), None);
+        assert_eq!(parse_range(""), None);
+    }
+}
@@ -2,80 +2,222 @@ use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
 use axum::body::Body;
-use axum::extract::{Multipart, Path, Request, State, WebSocketUpgrade};
+use axum::extract::{Multipart, Path, Query, Request, State, WebSocketUpgrade};
 use axum::extract::ws::{Message, WebSocket};
 use axum::http::StatusCode;
 use axum::Json;
 use axum::response::IntoResponse;
+use burn::tensor::ElementConversion;
+use futures::future::{self, Either};
+use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use tokio::sync::{mpsc, RwLock};
 use tracing::{error, info};
 use db::repo::SplatRepository;
-use pipeline::{Pipeline, PipelineMessage};
+use db::repo::ArtifactKind;
+use pipeline::{Pipeline, PipelineMessage, TrainConfig};
 use scene_source::Source;
-use web_cmn::pipeline::WiredPipelineMessage;
+use web_cmn::pipeline::{DivergenceEvent, EvalEvent, LearningRates, RefineEvent, TrainOptions, TrainProgress, WiredClientMessage, WiredPipelineMessage};
 use crate::error::{BackendError, Result};
-use crate::pipeline::splats_from_module;
+use crate::pipeline::{load_config, pipeline_config, splats_from_module};
+use crate::routes::artifact::{artifact_metadata_to_response, register_artifact};
 use crate::state::AppState;
+use crate::storage;
 
 pub async fn train_scene(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
+    Query(options): Query<TrainOptions>,
 ) -> impl IntoResponse {
     info!("🔌 Incoming WebSocket upgrade request for scene: {}", name);
-    ws.on_upgrade(|socket| start_pipeline(socket, name, state))
+    ws.on_upgrade(|socket| start_pipeline(socket, name, options, state))
 }
 
 async fn start_pipeline(
-    mut socket: WebSocket,
+    socket: WebSocket,
     scene_name: String,
+    options: TrainOptions,
     state: Arc<AppState>,
 ) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let Some(scene) = state.repo.get_scene(&scene_name).await.ok().flatten() else {
+        // send a failure message over WebSocket
+        send_wired_msg(&mut sender, &WiredPipelineMessage::Error(String::from("Scene not found"))).await;
+        let _ = sender.close().await;
+        return;
+    };
+
+    // The scene can't be renamed or deleted while this is alive.
+    let _run_guard = state.start_run(&scene_name);
+
+    // Every run gets its own folder, so the outputs of earlier runs are kept.
+    let run = storage::new_run_id();
+    let pipeline = match Pipeline::new(scene.source, storage::artifacts_dir(&scene_name).join(&run)) {
+        Ok(pipeline) => configure_pipeline(pipeline, &state, &scene_name, &options).await,
+        Err(err) => Err(err),
+    };
+    let mut pipeline = match pipeline {
+        Ok(pipeline) => pipeline,
+        Err(err) => {
+            send_wired_msg(&mut sender, &WiredPipelineMessage::Error(err.to_string())).await;
+            let _ = sender.close().await;
+            return;
+        }
+    };
+
     // Start training
-    if let Some(scene) = state.repo.get_scene(&scene_name).await.unwrap() {
-        let mut pipeline = Pipeline::new(scene.source).unwrap();
-        let mut stream = std::pin::pin!(pipeline.launch());
-        while let Some(msg) = stream.next().await {
-            match msg {
-                Ok(msg) => {
-                    info!("Received pipeline message: {:?}", msg);
-                    if let Some(wired_msg) = create_wired_msg(msg) {
-                        let json = serde_json::to_string(&wired_msg).unwrap();
-                        if socket.send(Message::from(json)).await.is_err() {
-                            break;
-                        }
-                        if matches!(wired_msg, WiredPipelineMessage::Done) {
+    let mut stream = Box::pin(pipeline.launch());
+    'pipeline: loop {
+        // Listen to the client while training so a stop request is handled between steps.
+        match future::select(stream.next(), receiver.next()).await {
+            Either::Left((Some(Ok(PipelineMessage::ArtifactSaved { kind, iter, path })), _)) => {
+                match register_artifact(&state, &scene_name, &run, kind, iter, &path).await {
+                    Ok(artifact) => {
+                        let wired_msg = WiredPipelineMessage::Artifact(artifact_metadata_to_response(artifact));
+                        if !send_wired_msg(&mut sender, &wired_msg).await {
                             break;
                         }
                     }
+                    Err(err) => error!("Failed to register artifact {:?}: {}", path, err),
+                }
+            }
+            Either::Left((Some(Ok(msg)), _)) => {
+                let wired_msgs = create_wired_msgs(msg).await;
+                for wired_msg in wired_msgs {
+                    if !send_wired_msg(&mut sender, &wired_msg).await {
+                        break 'pipeline;
+                    }
+                    if matches!(wired_msg, WiredPipelineMessage::Done { .. }) {
+                        break 'pipeline;
+                    }
                 }
-                Err(err) => {
-                    error!("Training websocket loop failed due to invalid message: {}", err);
-                    break;
+            }
+            Either::Left((Some(Err(err)), _)) => {
+                error!("Training websocket loop failed due to invalid message: {}", err);
+                send_wired_msg(&mut sender, &WiredPipelineMessage::Error(err.to_string())).await;
+                break;
+            }
+            Either::Left((None, _)) => break,
+            Either::Right((Some(Ok(Message::Text(text))), _)) => {
+                match serde_json::from_str::<WiredClientMessage>(text.as_str()) {
+                    Ok(WiredClientMessage::Stop) => {
+                        info!("Training of scene {} stopped by client", scene_name);
+                        let done = WiredPipelineMessage::Done { reason: String::from("stopped by client") };
+                        send_wired_msg(&mut sender, &done).await;
+                        break;
+                    }
+                    Err(err) => error!("Invalid client message: {}", err),
                 }
             }
+            // The client went away, no point in training any further.
+            Either::Right((None | Some(Err(_)) | Some(Ok(Message::Close(_))), _)) => break,
+            Either::Right(_) => {}
         }
-    } else {
-        // send a failure message over WebSocket
-        let _ = socket.send(Message::from("{\"error\": \"Scene not found\"}")).await;
-    };
+    }
 
-    let _ = socket.close().await;
+    let _ = sender.close().await;
     info!("End of pipeline websocket");
 }
-fn create_wired_msg(msg: PipelineMessage) -> Option<WiredPipelineMessage> {
+
+// Applies the options of the run: how the dataset is loaded, when to stop, the freeze & refine flags, and the
+// splats to start from.
+async fn configure_pipeline(
+    pipeline: Pipeline,
+    state: &AppState,
+    scene_name: &str,
+    options: &TrainOptions,
+) -> anyhow::Result<Pipeline> {
+    let train_config = TrainConfig::new()
+        .with_freeze_means(options.freeze_means)
+        .with_freeze_rotation(options.freeze_rotation)
+        .with_freeze_scales(options.freeze_scales)
+        .with_freeze_sh(options.freeze_sh)
+        .with_freeze_opacity(options.freeze_opacity)
+        .with_disable_refine(options.disable_refine);
+    let pipeline = pipeline
+        .with_image_cache(storage::IMAGE_CACHE_DIR)
+        .with_load_config(load_config(options))
+        .with_pipeline_config(pipeline_config(options))
+        .with_train_config(train_config);
+
+    let Some(path) = &options.init_splats else {
+        return Ok(pipeline);
+    };
+    // The splats can come from another scene, e.g. to fine-tune it on new views.
+    let source_scene = options.init_splats_scene.as_deref().unwrap_or(scene_name);
+    // Only registered splats are read, so the path can't be used to escape the scene directory.
+    let artifact = state
+        .repo
+        .get_artifact(source_scene, path)
+        .await?
+        .filter(|artifact| artifact.kind == ArtifactKind::Splats)
+        .ok_or_else(|| anyhow::anyhow!("No splats artifact {path} in scene {source_scene}"))?;
+    let bytes = tokio::fs::read(storage::scene_dir(source_scene).join(&artifact.path)).await?;
+    info!("Continuing training of {} from {} of {}", scene_name, artifact.path, source_scene);
+    pipeline.with_initial_ply(&bytes)
+}
+
+async fn send_wired_msg(sender: &mut SplitSink<WebSocket, Message>, msg: &WiredPipelineMessage) -> bool {
+    let json = serde_json::to_string(msg).unwrap();
+    sender.send(Message::from(json)).await.is_ok()
+}
+
+async fn create_wired_msgs(msg: PipelineMessage) -> Vec<WiredPipelineMessage> {
     match msg {
+        PipelineMessage::TrainingStarted { start_iter, total_steps } => {
+            vec![WiredPipelineMessage::Started { start_iter, total_steps }]
+        }
         PipelineMessage::TrainStep {
             splats,
             stats,
             iter,
             total_elapsed,
         } => {
-            Some(WiredPipelineMessage::TrainStep(splats_from_module(&*splats)))
+            let progress = TrainProgress {
+                iter,
+                elapsed_secs: total_elapsed.as_secs_f32(),
+                loss: stats.loss.clone().into_scalar_async().await.elem::<f32>(),
+                num_splats: splats.num_splats(),
+                lr: LearningRates {
+                    mean: stats.lr_mean,
+                    rotation: stats.lr_rotation,
+                    scale: stats.lr_scale,
+                    coeffs: stats.lr_coeffs,
+                    opacity: stats.lr_opac,
+                    camera: stats.lr_camera,
+                    background: stats.lr_background,
+                },
+            };
+            vec![
+                WiredPipelineMessage::Progress(progress),
+                WiredPipelineMessage::TrainStep(splats_from_module(&*splats)),
+            ]
         },
-        PipelineMessage::Finished => Some(WiredPipelineMessage::Done),
-        _ => None,
+        PipelineMessage::RefineStep { stats, cur_splat_count, iter } => {
+            vec![WiredPipelineMessage::Refine(RefineEvent {
+                iter,
+                num_added: stats.num_added,
+                num_pruned: stats.num_pruned,
+                num_splats: cur_splat_count,
+            })]
+        }
+        PipelineMessage::Diverged { stats, iter } => {
+            vec![WiredPipelineMessage::Diverged(DivergenceEvent {
+                iter,
+                rolled_back_to: stats.rolled_back_to,
+                lr_scale: stats.lr_scale,
+                reason: stats.reason.to_string(),
+            })]
+        }
+        PipelineMessage::EvalResult { iter, avg_psnr, avg_ssim, split } => {
+            vec![WiredPipelineMessage::Eval(EvalEvent { iter, psnr: avg_psnr, ssim: avg_ssim, split })]
+        }
+        PipelineMessage::Finished { reason } => {
+            info!("Training finished: {reason}");
+            vec![WiredPipelineMessage::Done { reason: reason.to_string() }]
+        }
+        _ => vec![],
     }
 }
-
@@ -3,24 +3,32 @@ use std::{
     sync::Arc,
     path::PathBuf,
 };
-use anyhow::Error;
 use axum::body::Body;
-use axum::extract::{FromRequest, Multipart, Path, Request};
+use axum::extract::{FromRequest, Multipart, Path, Query, Request};
+use axum::extract::multipart::Field;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use serde::{Serialize, Deserialize};
 use tempfile::tempdir;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use tracing::{error, info};
 use zip_extract::extract;
+use dataset::{Dataset, SparsePoint};
 use db::repo::{SceneMetadata, SplatRepository};
 use pipeline::Pipeline;
-use web_cmn::scene::{SceneResponse};
+use web_cmn::pipeline::TrainOptions;
+use web_cmn::scene::{RenameSceneRequest, SceneLayoutResponse, SceneResponse, SparsePoints, TrainingCamera, UpdateSceneRequest, ValidationReport};
 use crate::error::{Result, BackendError};
+use crate::pipeline::load_config;
 use crate::state::AppState;
+use crate::storage;
 
 use scene_source::Source;
 
+/// Name uploaded zip archives are stored under in the scene directory.
+pub const ZIP_FILE_NAME: &str = "scene.zip";
+
 pub async fn upload_scene(
     State(state): State<Arc<AppState>>,
     req: Request<Body>
@@ -48,35 +56,60 @@ pub async fn upload_scene(
 
 async fn handle_multipart_upload(
     state: Arc<AppState>,
-    mut multipart: Multipart
+    multipart: Multipart
+) -> Result<Json<SceneResponse>> {
+    let mut created_dir = None;
+    let result = receive_multipart_upload(&state, multipart, &mut created_dir).await;
+
+    // Don't leave partial data behind, it would block uploading the scene again.
+    if let (Err(_), Some(dir)) = (&result, created_dir) {
+        if let Err(err) = fs::remove_dir_all(&dir).await {
+            error!("Failed to remove data of failed upload {}: {}", dir, err);
+        }
+    }
+    result
+}
+
+// Writes the fields to the scene directory. `created_dir` is set once the directory is created.
+async fn receive_multipart_upload(
+    state: &Arc<AppState>,
+    mut multipart: Multipart,
+    created_dir: &mut Option<String>,
 ) -> Result<Json<SceneResponse>> {
     let mut base_path = None;
     let mut scene_name = None;
     let mut source = None;
-    while let Some(mut field) = multipart.next_field().await.unwrap_or(None) {
+    while let Some(mut field) = multipart.next_field().await? {
         let field_name = field.name().unwrap_or_default().to_string();
         let filename = field.file_name().unwrap_or("unknown").to_string();
-        let data = field.bytes().await
-            .map_err(|_| BackendError::BadRequest("Failed to read upload data".into()))?;
 
         if field_name == "name" {
-            let name = String::from_utf8_lossy(&data).to_string();
+            let name = field.text().await
+                .map_err(|_| BackendError::BadRequest("Failed to read upload data".into()))?;
+            let name = name.trim().to_owned();
+            if !storage::is_valid_scene_name(&name) {
+                return Err(BackendError::BadRequest(format!("Invalid scene name: {name}")));
+            }
             scene_name = Some(name.clone());
             if state.repo.can_add(name.as_str()).await {
-                base_path = Some(format!("data/scenes/{}", name));
-                fs::create_dir_all(base_path.clone().unwrap()).await?;
+                let dir = storage::scene_dir(&name).to_string_lossy().to_string();
+                if !fs::try_exists(&dir).await? {
+                    fs::create_dir_all(&dir).await?;
+                    *created_dir = Some(dir.clone());
+                }
+                base_path = Some(dir);
             } else {
                 return Err(BackendError::BadRequest(format!("Upload already exists: {}", name).into()));
             }
         } else if filename.ends_with(".zip") {
             if let Some(dir) = base_path.clone() {
-                let zip_path = extract_zip_data(&data, dir.as_str()).await
-                    .map_err(|e| BackendError::Internal(Error::from(e)))?;
+                let zip_path = PathBuf::from(&dir).join(ZIP_FILE_NAME);
+                write_field_to_file(&mut field, &zip_path).await?;
                 source = Some(Source::Zip {path: zip_path.to_string_lossy().to_string()});
             }
         } else {
             if let Some(dir) = base_path.clone() {
-                copy_to_dir(&data, filename.as_str(), dir.as_str()).await?;
+                copy_to_dir(&mut field, filename.as_str(), dir.as_str()).await?;
 
                 if source.is_none() {
                     source = Some(Source::Dir { path: dir });
@@ -85,17 +118,18 @@ async fn handle_multipart_upload(
         }
     }
 
-    if let Some(name) = scene_name {
+    // Only register the scene once all of its data is on disk.
+    if let (Some(name), Some(source)) = (scene_name, source) {
         let metadata = SceneMetadata {
-            name: name.clone(),
-            source: source.unwrap(),
+            name,
+            validation: Some(validate_source(&source).await),
+            source,
+            description: None,
         };
 
-        state.repo.add_scene(metadata).await.expect(format!("Failed to add scene: {}", &name).as_str());
+        state.repo.add_scene(metadata.clone()).await?;
 
-        return Ok(Json(SceneResponse {
-            name
-        }));
+        return Ok(Json(scene_metadata_to_response(metadata)));
     }
 
     Err(BackendError::BadRequest("Failed to parse multipart data".parse().unwrap()))
@@ -114,14 +148,14 @@ async fn handle_json_upload(
 
             let metadata = SceneMetadata {
                 name: url.clone(),
+                validation: Some(validate_source(&final_source).await),
                 source: final_source,
+                description: None,
             };
             
             state.repo.add_scene(metadata.clone()).await?;
             
-            Ok(Json(SceneResponse {
-                name: url,
-            }))
+            Ok(Json(scene_metadata_to_response(metadata)))
         }
         other => Err(BackendError::BadRequest(format!(
             "Only Source::Url is supported via JSON. Got: {:?}", other
@@ -129,26 +163,28 @@ async fn handle_json_upload(
     }
 }
 
-async fn extract_zip_data(
+async fn save_zip_data(
     data: &[u8],
-    extract_path: &str
+    dir: &str
 ) -> Result<PathBuf> {
-    let zip_filename = "scene.zip";
-    let zip_path = PathBuf::from(format!("{}/{}", extract_path, zip_filename));
-
-    if let Some(parent) = zip_path.parent() {
-        // Spawn dir creation in the background
-        let parent = parent.to_path_buf();
-        let data = data.to_vec();
-        let zip_path_clone = zip_path.clone();
-        
-        tokio::spawn(async move {
-            let _ = tokio::fs::create_dir_all(parent).await;
-            let _ = tokio::fs::write(zip_path_clone, data).await;
-        });
+    let zip_path = PathBuf::from(dir).join(ZIP_FILE_NAME);
+    fs::create_dir_all(dir).await?;
+    fs::write(&zip_path, data).await?;
+    Ok(zip_path)
+}
+
+// Streams the field to disk, so uploads don't have to fit in memory.
+async fn write_field_to_file(field: &mut Field<'_>, path: &std::path::Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
     }
 
-    Ok(zip_path)
+    let mut file = fs::File::create(path).await?;
+    while let Some(chunk) = field.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
 }
 
 fn sanitize_file_path(path: &str) -> String {
@@ -167,17 +203,10 @@ fn sanitize_file_path(path: &str) -> String {
     components.join("/")
 }
 
-async fn copy_to_dir(data: &[u8], file_path: &str, dir: &str) -> Result<()> {
+async fn copy_to_dir(field: &mut Field<'_>, file_path: &str, dir: &str) -> Result<()> {
     let sanitized_path = sanitize_file_path(file_path);
-    let file_path = format!("{}/{}", dir, sanitized_path);
-
-    if let Some(parent) = PathBuf::from(&file_path).parent() {
-        fs::create_dir_all(parent).await
-            .map_err(|e| BackendError::Internal(e.into()))?;
-    }
-
-    tokio::fs::write(&file_path, &data).await
-        .map_err(|e| BackendError::Internal(e.into()))
+    let file_path = PathBuf::from(dir).join(sanitized_path);
+    write_field_to_file(field, &file_path).await
 }
 
 async fn download_and_process_url(url: &str) -> Result<( Source)> {
@@ -201,8 +230,8 @@ async fn download_and_process_url(url: &str) -> Result<( Source)> {
     let data = response.bytes().await.map_err(|e| BackendError::Internal(e.into()))?;
 
     let source = if content_type.contains("application/zip") || url.to_lowercase().ends_with(".zip") {
-        extract_zip_data(&data, &base_path).await.map_err(|e| BackendError::Internal(e.into()))?;
-        Source::Zip { path: base_path }
+        let zip_path = save_zip_data(&data, &base_path).await?;
+        Source::Zip { path: zip_path.to_string_lossy().to_string() }
     } else {
         let filename = url.split('/').last().unwrap_or("downloaded_file");
         let sanitized = sanitize_file_path(filename);
@@ -217,6 +246,36 @@ async fn download_and_process_url(url: &str) -> Result<( Source)> {
 pub fn scene_metadata_to_response(metadata: SceneMetadata) -> SceneResponse {
     SceneResponse {
         name: metadata.name,
+        description: metadata.description,
+        validation: metadata.validation,
+    }
+}
+
+/// Checks the uploaded dataset, so problems show up right away instead of once training starts.
+pub async fn validate_source(source: &Source) -> ValidationReport {
+    // Every image gets decoded, which would block a runtime worker for the whole validation.
+    let runtime = tokio::runtime::Handle::current();
+    let source = source.clone();
+    let report = tokio::task::spawn_blocking(move || runtime.block_on(dataset::validate_dataset(source)))
+        .await
+        .unwrap_or_else(|err| {
+            let mut report = dataset::ValidationReport::default();
+            report.error(format!("Validation failed: {err}"));
+            report
+        });
+    if !report.is_valid() {
+        info!("Uploaded dataset has {} errors", report.errors.len());
+    }
+
+    ValidationReport {
+        format: report.format,
+        errors: report.errors,
+        warnings: report.warnings,
+        image_count: report.image_count,
+        min_resolution: report.min_resolution.map(|r| r.to_array()),
+        max_resolution: report.max_resolution.map(|r| r.to_array()),
+        camera_models: report.camera_models,
+        point_count: report.point_count,
     }
 }
 
@@ -230,6 +289,103 @@ pub async fn get_scene(
     Err(BackendError::NotFound)
 }
 
+pub async fn update_scene(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(request): Json<UpdateSceneRequest>,
+) -> Result<Json<SceneResponse>> {
+    let Some(mut scene) = state.repo.get_scene(&name).await? else {
+        return Err(BackendError::NotFound);
+    };
+
+    if let Some(description) = request.description {
+        let description = description.trim();
+        scene.description = (!description.is_empty()).then(|| description.to_owned());
+    }
+
+    state.repo.update_scene(scene.clone()).await?;
+    Ok(Json(scene_metadata_to_response(scene)))
+}
+
+pub async fn rename_scene(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(request): Json<RenameSceneRequest>,
+) -> Result<Json<SceneResponse>> {
+    let new_name = request.name.trim().to_owned();
+    if !storage::is_valid_scene_name(&new_name) {
+        return Err(BackendError::BadRequest(format!("Invalid scene name: {new_name}")));
+    }
+
+    let Some(scene) = state.repo.get_scene(&name).await? else {
+        return Err(BackendError::NotFound);
+    };
+    if state.is_training(&name) {
+        return Err(BackendError::Conflict(format!("Scene {name} is being trained")));
+    }
+    if new_name == name {
+        return Ok(Json(scene_metadata_to_response(scene)));
+    }
+
+    let old_dir = storage::scene_dir(&name);
+    let new_dir = storage::scene_dir(&new_name);
+    if !state.repo.can_add(&new_name).await || fs::try_exists(&new_dir).await? {
+        return Err(BackendError::Conflict(format!("Scene already exists: {new_name}")));
+    }
+
+    // Move the data first, the database rename is a single transaction and is undone by moving it back.
+    let moved = fs::try_exists(&old_dir).await?;
+    if moved {
+        fs::rename(&old_dir, &new_dir).await?;
+    }
+
+    let renamed = SceneMetadata {
+        name: new_name,
+        source: storage::relocate_source(&scene.source, &old_dir, &new_dir),
+        description: scene.description,
+        validation: scene.validation,
+    };
+    if let Err(err) = state.repo.rename_scene(&name, renamed.clone()).await {
+        if moved {
+            fs::rename(&new_dir, &old_dir).await?;
+        }
+        return Err(err.into());
+    }
+
+    info!("Renamed scene {} to {}", name, renamed.name);
+    Ok(Json(scene_metadata_to_response(renamed)))
+}
+
+pub async fn delete_scene(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    if state.repo.get_scene(&name).await?.is_none() {
+        return Err(BackendError::NotFound);
+    }
+    if state.is_training(&name) {
+        return Err(BackendError::Conflict(format!("Scene {name} is being trained")));
+    }
+
+    // Park the data so it can be put back if the database delete fails.
+    let trash = storage::move_to_trash(&name).await?;
+    if let Err(err) = state.repo.delete_scene(&name).await {
+        if let Some(trash) = &trash {
+            storage::restore_from_trash(&name, trash).await?;
+        }
+        return Err(err.into());
+    }
+
+    if let Some(trash) = trash {
+        if let Err(err) = fs::remove_dir_all(&trash).await {
+            error!("Failed to remove data of deleted scene {}: {}", name, err);
+        }
+    }
+
+    info!("Deleted scene {}", name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
 pub async fn get_scenes(
     State(state): State<Arc<AppState>>
 ) -> Result<Json<Vec<SceneResponse>>> {
@@ -239,4 +395,51 @@ pub async fn get_scenes(
         .map(scene_metadata_to_response)
         .collect();
     Ok(Json(responses))
-}
\ No newline at end of file
+}
+
+pub async fn get_scene_layout(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(options): Query<TrainOptions>,
+) -> Result<Json<SceneLayoutResponse>> {
+    let Some(scene) = state.repo.get_scene(&name).await? else {
+        return Err(BackendError::NotFound);
+    };
+
+    // Load like a training run with the same options would, so eval views can be told apart.
+    let (dataset, points) = dataset::load_layout(scene.source, load_config(&options)).await?;
+
+    Ok(Json(dataset_to_layout_response(&dataset, &points)))
+}
+
+fn dataset_to_layout_response(dataset: &Dataset, points: &[SparsePoint]) -> SceneLayoutResponse {
+    let train_views = dataset.train.views.iter().map(|v| (v, false));
+    let eval_views = dataset.eval.iter().flat_map(|s| s.views.iter()).map(|v| (v, true));
+
+    let cameras = train_views
+        .chain(eval_views)
+        .map(|(view, is_eval)| {
+            let dim = view.image.dim();
+            TrainingCamera {
+                image_name: view.image.path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                position: view.camera.position.to_array(),
+                rotation: view.camera.rotation.to_array(),
+                fov_x: view.camera.fov_x as f32,
+                fov_y: view.camera.fov_y as f32,
+                width: dim.x,
+                height: dim.y,
+                is_eval,
+            }
+        })
+        .collect();
+
+    let points = SparsePoints {
+        positions: points.iter().flat_map(|p| p.position.to_array()).collect(),
+        colors: points.iter().flat_map(|p| p.color).collect(),
+    };
+
+    SceneLayoutResponse { cameras, points }
+}
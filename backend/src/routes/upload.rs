@@ -0,0 +1,385 @@
+use std::fmt::Display;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info};
+use db::repo::{SceneMetadata, SplatRepository};
+use scene_source::Source;
+use web_cmn::scene::SceneResponse;
+use web_cmn::upload::{CreateUploadRequest, FinalizeUploadRequest, UploadChunkQuery, UploadStatus};
+use crate::error::{BackendError, Result};
+use crate::routes::scene::{scene_metadata_to_response, validate_source, ZIP_FILE_NAME};
+use crate::state::AppState;
+use crate::storage;
+
+const SESSION_FILE: &str = "session.json";
+const PARTIAL_FILE: &str = "data.part";
+/// Sessions that received no data for this long are abandoned, and removed.
+const SESSION_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// What is kept on disk about an upload. The number of received bytes is the length of the partial file,
+/// so a dropped connection or a restart never loses track of what arrived.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UploadSession {
+    id: String,
+    name: String,
+    file_name: String,
+    size: u64,
+    /// Directory of the session, see [`storage::upload_dir`].
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl UploadSession {
+    async fn load(id: &str) -> Result<Self> {
+        // Ids are generated as hex, anything else could point outside the uploads directory.
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(BackendError::NotFound);
+        }
+        Self::load_from(storage::upload_dir(id)).await
+    }
+
+    async fn load_from(dir: PathBuf) -> Result<Self> {
+        let json = match fs::read(dir.join(SESSION_FILE)).await {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(BackendError::NotFound),
+            Err(err) => return Err(err.into()),
+        };
+        let session: Self = serde_json::from_slice(&json).map_err(|e| BackendError::Internal(e.into()))?;
+        Ok(Self { dir, ..session })
+    }
+
+    /// Creates the directory, an empty partial file and the state of the session.
+    async fn create(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        fs::File::create(self.partial_path()).await?;
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<()> {
+        let json = serde_json::to_vec(self).map_err(|e| BackendError::Internal(e.into()))?;
+        fs::write(self.dir.join(SESSION_FILE), json).await?;
+        Ok(())
+    }
+
+    fn partial_path(&self) -> PathBuf {
+        self.dir.join(PARTIAL_FILE)
+    }
+
+    /// Appends the `chunks` to the partial file. `offset` has to be the number of bytes received so far.
+    /// Whatever arrives before an error is kept, so the client can resume from there.
+    async fn append<E: Display>(
+        &self,
+        offset: u64,
+        mut chunks: impl Stream<Item = std::result::Result<Bytes, E>> + Unpin,
+    ) -> Result<()> {
+        let mut file = fs::OpenOptions::new().append(true).open(self.partial_path()).await?;
+        let mut received = file.metadata().await?.len();
+        if offset != received {
+            return Err(BackendError::Conflict(format!("Expected a chunk at offset {received}, got {offset}")));
+        }
+
+        let result = loop {
+            let Some(chunk) = chunks.next().await else { break Ok(()) };
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(err) => break Err(BackendError::BadRequest(format!("Upload interrupted: {err}"))),
+            };
+            if received + chunk.len() as u64 > self.size {
+                break Err(BackendError::BadRequest(format!("Upload is larger than the announced {} bytes", self.size)));
+            }
+            if let Err(err) = file.write_all(&chunk).await {
+                break Err(err.into());
+            }
+            received += chunk.len() as u64;
+        };
+
+        file.flush().await?;
+        result
+    }
+
+    /// Checks that the whole file arrived intact. Corrupt data is thrown away, so it has to be uploaded again.
+    async fn verify(&self, checksum: &str) -> Result<()> {
+        let status = self.status().await?;
+        if status.received != self.size {
+            return Err(BackendError::BadRequest(format!(
+                "Upload is incomplete: received {} of {} bytes", status.received, self.size
+            )));
+        }
+
+        let actual = storage::sha256_file(&self.partial_path()).await?;
+        if !actual.eq_ignore_ascii_case(checksum.trim()) {
+            fs::File::create(self.partial_path()).await?;
+            return Err(BackendError::BadRequest("Checksum mismatch, the file has to be uploaded again".into()));
+        }
+        Ok(())
+    }
+
+    async fn status(&self) -> Result<UploadStatus> {
+        let received = fs::metadata(self.partial_path()).await?.len();
+        Ok(UploadStatus {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            file_name: self.file_name.clone(),
+            size: self.size,
+            received,
+        })
+    }
+}
+
+pub async fn create_upload(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateUploadRequest>,
+) -> Result<Json<UploadStatus>> {
+    let name = request.name.trim().to_owned();
+    if !storage::is_valid_scene_name(&name) {
+        return Err(BackendError::BadRequest(format!("Invalid scene name: {name}")));
+    }
+    if !request.file_name.to_lowercase().ends_with(".zip") {
+        return Err(BackendError::BadRequest("Only .zip files can be uploaded in chunks".into()));
+    }
+    if !state.repo.can_add(&name).await || fs::try_exists(storage::scene_dir(&name)).await? {
+        return Err(BackendError::Conflict(format!("Upload already exists: {name}")));
+    }
+
+    if let Err(err) = remove_stale_uploads(&state).await {
+        error!("Failed to clean up stale uploads: {}", err);
+    }
+    // The name is reserved by the session, finalizing a second one would overwrite the first.
+    for id in storage::upload_ids().await? {
+        if UploadSession::load(&id).await.is_ok_and(|session| session.name == name) {
+            return Err(BackendError::Conflict(format!("Upload {id} is already uploading {name}")));
+        }
+    }
+
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let id = format!("{stamp:x}");
+    let session = UploadSession {
+        dir: storage::upload_dir(&id),
+        id,
+        name,
+        file_name: request.file_name,
+        size: request.size,
+    };
+    session.create().await?;
+
+    info!("Started upload {} for scene {} ({} bytes)", session.id, session.name, session.size);
+    Ok(Json(session.status().await?))
+}
+
+pub async fn get_upload(Path(id): Path<String>) -> Result<Json<UploadStatus>> {
+    let session = UploadSession::load(&id).await?;
+    Ok(Json(session.status().await?))
+}
+
+/// Appends the body to the upload. The offset has to match the number of bytes received so far,
+/// after a dropped connection the client asks for the status and continues from there.
+pub async fn upload_chunk(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<UploadChunkQuery>,
+    body: Body,
+) -> Result<Json<UploadStatus>> {
+    let Some(_guard) = state.lock_upload(&id) else {
+        return Err(BackendError::Conflict(format!("Upload {id} is already receiving data")));
+    };
+    let session = UploadSession::load(&id).await?;
+    session.append(query.offset, body.into_data_stream()).await?;
+    Ok(Json(session.status().await?))
+}
+
+/// Verifies the completed upload and turns it into a scene.
+pub async fn finalize_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<FinalizeUploadRequest>,
+) -> Result<Json<SceneResponse>> {
+    let Some(_guard) = state.lock_upload(&id) else {
+        return Err(BackendError::Conflict(format!("Upload {id} is still receiving data")));
+    };
+    let session = UploadSession::load(&id).await?;
+    session.verify(&request.checksum).await?;
+
+    // Never write into the data of another scene, e.g. one uploaded in the meantime.
+    let scene_dir = storage::scene_dir(&session.name);
+    if !state.repo.can_add(&session.name).await || fs::try_exists(&scene_dir).await? {
+        return Err(BackendError::Conflict(format!("Upload already exists: {}", session.name)));
+    }
+
+    let zip_path = scene_dir.join(ZIP_FILE_NAME);
+    fs::create_dir_all(&scene_dir).await?;
+    fs::rename(session.partial_path(), &zip_path).await?;
+
+    let source = Source::Zip { path: zip_path.to_string_lossy().to_string() };
+    let metadata = SceneMetadata {
+        name: session.name.clone(),
+        validation: Some(validate_source(&source).await),
+        source,
+        description: None,
+    };
+    if let Err(err) = state.repo.add_scene(metadata.clone()).await {
+        // Put the data back so finalizing can be retried.
+        fs::rename(&zip_path, session.partial_path()).await?;
+        let _ = fs::remove_dir_all(&scene_dir).await;
+        return Err(err.into());
+    }
+
+    if let Err(err) = fs::remove_dir_all(storage::upload_dir(&id)).await {
+        error!("Failed to clean up upload {}: {}", id, err);
+    }
+
+    info!("Finished upload {} as scene {}", id, session.name);
+    Ok(Json(scene_metadata_to_response(metadata)))
+}
+
+pub async fn cancel_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    let Some(_guard) = state.lock_upload(&id) else {
+        return Err(BackendError::Conflict(format!("Upload {id} is still receiving data")));
+    };
+    UploadSession::load(&id).await?;
+
+    fs::remove_dir_all(storage::upload_dir(&id)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Removes the sessions nothing was written to for longer than `SESSION_EXPIRY`.
+async fn remove_stale_uploads(state: &AppState) -> Result<()> {
+    for id in storage::upload_ids().await? {
+        // Sessions receiving data right now aren't stale.
+        let Some(_guard) = state.lock_upload(&id) else {
+            continue;
+        };
+        remove_if_expired(&storage::upload_dir(&id), SESSION_EXPIRY).await?;
+    }
+    Ok(())
+}
+
+// Removes the session directory when nothing was written to it for longer than `expiry`.
+async fn remove_if_expired(dir: &FsPath, expiry: Duration) -> Result<bool> {
+    let metadata = match fs::metadata(dir.join(PARTIAL_FILE)).await {
+        Ok(metadata) => metadata,
+        // Sessions that never got as far as creating the partial file.
+        Err(_) => fs::metadata(dir).await?,
+    };
+    let idle = metadata.modified()?.elapsed().unwrap_or_default();
+    if idle <= expiry {
+        return Ok(false);
+    }
+    info!("Removing upload {:?} that was abandoned {} hours ago", dir, idle.as_secs() / 3600);
+    fs::remove_dir_all(dir).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    const DATA: &[u8] = b"0123456789";
+
+    async fn new_session(dir: &FsPath) -> UploadSession {
+        let session = UploadSession {
+            id: String::from("abc"),
+            name: String::from("scene"),
+            file_name: String::from("scene.zip"),
+            size: DATA.len() as u64,
+            dir: dir.join("abc"),
+        };
+        session.create().await.expect("Failed to create session");
+        session
+    }
+
+    fn chunks(chunks: &[&'static [u8]]) -> impl Stream<Item = std::io::Result<Bytes>> + Unpin {
+        futures::stream::iter(chunks.iter().map(|c| Ok(Bytes::from_static(c))).collect::<Vec<_>>())
+    }
+
+    async fn received(session: &UploadSession) -> u64 {
+        session.status().await.expect("Failed to get status").received
+    }
+
+    #[tokio::test]
+    async fn chunks_must_start_at_the_received_offset() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let session = new_session(dir.path()).await;
+
+        session.append(0, chunks(&[&DATA[..4]])).await.expect("First chunk failed");
+        let result = session.append(2, chunks(&[&DATA[4..]])).await;
+        assert!(matches!(result, Err(BackendError::Conflict(_))), "Overlapping chunk was accepted");
+        assert_eq!(received(&session).await, 4);
+
+        session.append(4, chunks(&[&DATA[4..]])).await.expect("Next chunk failed");
+        assert_eq!(received(&session).await, DATA.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn data_past_the_announced_size_is_rejected() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let session = new_session(dir.path()).await;
+
+        let result = session.append(0, chunks(&[&DATA[..6], b"0123456789"])).await;
+        assert!(matches!(result, Err(BackendError::BadRequest(_))), "Oversized upload was accepted");
+        // The chunks that fit are kept.
+        assert_eq!(received(&session).await, 6);
+    }
+
+    #[tokio::test]
+    async fn interrupted_upload_resumes() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let session = new_session(dir.path()).await;
+
+        let interrupted = futures::stream::iter(vec![
+            Ok(Bytes::from_static(&DATA[..3])),
+            Err(std::io::Error::other("connection reset")),
+        ]);
+        let result = session.append(0, interrupted).await;
+        assert!(matches!(result, Err(BackendError::BadRequest(_))));
+
+        // A restart picks the session up from disk, and continues after what arrived.
+        let session = UploadSession::load_from(session.dir.clone()).await.expect("Failed to load session");
+        let offset = received(&session).await;
+        assert_eq!(offset, 3);
+        session.append(offset, chunks(&[&DATA[3..]])).await.expect("Resuming failed");
+
+        let checksum = format!("{:x}", Sha256::digest(DATA));
+        session.verify(&checksum).await.expect("Complete upload didn't verify");
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatch_resets_the_upload() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let session = new_session(dir.path()).await;
+
+        let incomplete = session.verify("").await;
+        assert!(matches!(incomplete, Err(BackendError::BadRequest(_))), "Incomplete upload verified");
+
+        session.append(0, chunks(&[DATA])).await.expect("Upload failed");
+        let checksum = format!("{:x}", Sha256::digest(b"something else"));
+        let result = session.verify(&checksum).await;
+        assert!(matches!(result, Err(BackendError::BadRequest(_))), "Corrupt upload verified");
+        assert_eq!(received(&session).await, 0, "Corrupt data should be thrown away");
+    }
+
+    #[tokio::test]
+    async fn only_expired_sessions_are_removed() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let session = new_session(dir.path()).await;
+
+        let removed = remove_if_expired(&session.dir, SESSION_EXPIRY).await.expect("Cleanup failed");
+        assert!(!removed && session.dir.exists(), "Active session was removed");
+
+        std::thread::sleep(Duration::from_millis(20));
+        let removed = remove_if_expired(&session.dir, Duration::from_millis(1)).await.expect("Cleanup failed");
+        assert!(removed && !session.dir.exists(), "Stale session was kept");
+    }
+}
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use serde::Serialize;
 use tokio::sync::RwLock;
 use db::repo::SplatRepo;
@@ -8,6 +8,10 @@ use pipeline::Pipeline;
 pub struct AppState {
     pub repo: Arc<SplatRepo>,
     pub pipeline: Arc<RwLock<Option<Pipeline>>>,
+    /// Upload sessions a request is currently writing to.
+    uploads_in_use: Mutex<HashSet<String>>,
+    /// Number of training runs in progress, per scene.
+    active_runs: Mutex<HashMap<String, usize>>,
 }
 
 impl AppState {
@@ -15,6 +19,55 @@ impl AppState {
         Self {
             repo: Arc::new(SplatRepo::new().await.unwrap()),
             pipeline: Arc::new(RwLock::new(None)),
+            uploads_in_use: Mutex::new(HashSet::new()),
+            active_runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Claims the upload session for the current request, None if another request is using it.
+    pub fn lock_upload(&self, id: &str) -> Option<UploadGuard<'_>> {
+        let mut in_use = self.uploads_in_use.lock().unwrap();
+        in_use.insert(id.to_owned()).then(|| UploadGuard { state: self, id: id.to_owned() })
+    }
+
+    /// Marks a training run of the scene as active until the guard is dropped.
+    pub fn start_run(&self, scene: &str) -> RunGuard<'_> {
+        *self.active_runs.lock().unwrap().entry(scene.to_owned()).or_default() += 1;
+        RunGuard { state: self, scene: scene.to_owned() }
+    }
+
+    /// Whether the scene is being trained, its data can't be moved while it is.
+    pub fn is_training(&self, scene: &str) -> bool {
+        self.active_runs.lock().unwrap().contains_key(scene)
+    }
+}
+
+/// Releases the upload session when dropped.
+pub struct UploadGuard<'a> {
+    state: &'a AppState,
+    id: String,
+}
+
+impl Drop for UploadGuard<'_> {
+    fn drop(&mut self) {
+        self.state.uploads_in_use.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Ends the training run when dropped.
+pub struct RunGuard<'a> {
+    state: &'a AppState,
+    scene: String,
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        let mut active = self.state.active_runs.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.scene) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.scene);
+            }
         }
     }
 }
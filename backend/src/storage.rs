@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use scene_source::Source;
+
+const SCENES_DIR: &str = "data/scenes";
+const UPLOADS_DIR: &str = "data/uploads";
+// Scenes being deleted are parked here until their database record is gone.
+const TRASH_DIR: &str = "data/trash";
+/// Decoded training images, shared between all scenes and runs.
+pub const IMAGE_CACHE_DIR: &str = "data/cache/images";
+
+/// Directory holding everything uploaded or produced for a scene.
+pub fn scene_dir(name: &str) -> PathBuf {
+    Path::new(SCENES_DIR).join(name)
+}
+
+/// Directory holding the outputs of all training runs of a scene.
+pub fn artifacts_dir(name: &str) -> PathBuf {
+    scene_dir(name).join("artifacts")
+}
+
+/// Identifies a training run, runs started later sort after earlier ones.
+pub fn new_run_id() -> String {
+    // Tells apart runs started within the same second.
+    static RUN_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let count = RUN_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("run-{secs}-{count:06}")
+}
+
+/// Directory holding the partial file and state of an upload session.
+pub fn upload_dir(id: &str) -> PathBuf {
+    Path::new(UPLOADS_DIR).join(id)
+}
+
+/// Ids of all upload sessions on disk.
+pub async fn upload_ids() -> std::io::Result<Vec<String>> {
+    let mut entries = match fs::read_dir(UPLOADS_DIR).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let mut ids = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            ids.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Hex encoded SHA-256 of the file, read in chunks so large files aren't loaded into memory.
+pub async fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Scene names double as directory names, so they must be a single plain path component.
+pub fn is_valid_scene_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// Moves the scene directory out of the way. Returns where it went, or None if the scene had no data on disk.
+pub async fn move_to_trash(name: &str) -> std::io::Result<Option<PathBuf>> {
+    let dir = scene_dir(name);
+    if !fs::try_exists(&dir).await? {
+        return Ok(None);
+    }
+
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let trash = Path::new(TRASH_DIR).join(format!("{name}-{stamp}"));
+    fs::create_dir_all(TRASH_DIR).await?;
+    fs::rename(&dir, &trash).await?;
+    Ok(Some(trash))
+}
+
+/// Puts a scene directory moved by [`move_to_trash`] back in place.
+pub async fn restore_from_trash(name: &str, trash: &Path) -> std::io::Result<()> {
+    fs::rename(trash, scene_dir(name)).await
+}
+
+/// Rewrites paths of the source that point into `from` so they point into `to` instead.
+pub fn relocate_source(source: &Source, from: &Path, to: &Path) -> Source {
+    let relocate = |path: &str| match Path::new(path).strip_prefix(from) {
+        Ok(rest) if rest.as_os_str().is_empty() => to.to_string_lossy().to_string(),
+        Ok(rest) => to.join(rest).to_string_lossy().to_string(),
+        Err(_) => path.to_owned(),
+    };
+
+    match source {
+        Source::Zip { path } => Source::Zip { path: relocate(path) },
+        Source::Dir { path } => Source::Dir { path: relocate(path) },
+        Source::Url { url } => Source::Url { url: url.clone() },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_ids_are_unique_and_ordered() {
+        let first = new_run_id();
+        let second = new_run_id();
+        assert_ne!(first, second, "Runs started in the same second got the same id");
+        assert!(first < second, "Later runs should sort after earlier ones");
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::LazyLock;
+
+/// Decodes an sRGB encoded channel value in [0, 1] to linear.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear channel value to sRGB. Values outside of [0, 1] are clamped.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// 8-bit images are by far the most common, look their values up instead of calling powf for each pixel.
+static SRGB8_TO_LINEAR: LazyLock<[f32; 256]> =
+    LazyLock::new(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)));
+
+pub(crate) fn srgb8_to_linear(c: u8) -> f32 {
+    SRGB8_TO_LINEAR[c as usize]
+}
+
+/// Maps linear values of an image into [0, 1] for display, followed by [`linear_to_srgb`].
+///
+/// Uses extended Reinhard with the brightest channel of the image as white point. Images that
+/// are already in [0, 1] are left as they are, brighter images are compressed smoothly.
+pub fn tone_map_to_srgb(linear: &[f32]) -> Vec<f32> {
+    let white = linear.iter().copied().filter(|c| c.is_finite()).fold(1.0f32, f32::max);
+    let white_sq = white * white;
+    linear
+        .iter()
+        .map(|&c| {
+            let c = c.max(0.0);
+            linear_to_srgb(c * (1.0 + c / white_sq) / (1.0 + c))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_roundtrip() {
+        for i in 0..=255u8 {
+            let c = i as f32 / 255.0;
+            assert!((linear_to_srgb(srgb8_to_linear(i)) - c).abs() < 1e-5);
+        }
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((linear_to_srgb(4.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tone_map_keeps_ldr_values() {
+        let ldr = [0.0, 0.2, 0.5, 1.0];
+        let mapped = tone_map_to_srgb(&ldr);
+        for (c, m) in ldr.iter().zip(mapped) {
+            assert!((linear_to_srgb(*c) - m).abs() < 1e-5);
+        }
+
+        let hdr = tone_map_to_srgb(&[0.5, 2.0, 8.0]);
+        assert!(hdr[0] < hdr[1] && hdr[1] < hdr[2]);
+        assert!((hdr[2] - 1.0).abs() < 1e-5);
+    }
+}
@@ -7,10 +7,135 @@ pub struct LoadConfig {
     /// Max resolution of images to load.
     #[config(default = 1920)]
     pub max_resolution: u32,
-    /// Create an eval dataset by selecting every nth image
-    pub eval_split_every: Option<usize>,
+    /// Load images downscaled by this factor, using the `images_N` folder when the dataset has one.
+    /// Intrinsics are scaled by exactly 1/N, matching the convention of benchmark results.
+    pub downscale: Option<u32>,
+    /// How images are divided between training and evaluation.
+    #[config(default = "EvalSplit::None")]
+    pub eval_split: EvalSplit,
     /// Load only every nth frame
     pub subsample_frames: Option<u32>,
     /// Load only every nth point from the initial sfm data
     pub subsample_points: Option<u32>,
-}
\ No newline at end of file
+    /// Recenter & rescale the cameras and points into a canonical frame with +Y up.
+    /// Exports are mapped back to the original coordinates.
+    #[config(default = false)]
+    pub normalize_scene: bool,
+    /// Color space the splats are trained in.
+    #[config(default = "ColorSpace::Auto")]
+    pub color_space: ColorSpace,
+    /// Where decoded images are kept while training.
+    #[config(default = "ImageCacheConfig::new()")]
+    pub image_cache: ImageCacheConfig,
+    /// How training views are decoded and fed to the trainer.
+    #[config(default = "LoaderConfig::new()")]
+    pub loader: LoaderConfig,
+}
+
+// On WASM, not much hope a big dataset will work anyway but let's not
+// cache more than what fits in memory.
+#[cfg(not(target_family = "wasm"))]
+const DEFAULT_MEMORY_CACHE_MB: usize = 6 * 1024;
+#[cfg(target_family = "wasm")]
+const DEFAULT_MEMORY_CACHE_MB: usize = 2 * 1024;
+
+#[derive(Config, Debug)]
+pub struct ImageCacheConfig {
+    /// Memory to keep decoded images in, in MB. The least recently used images are evicted first.
+    #[config(default = "DEFAULT_MEMORY_CACHE_MB")]
+    pub memory_mb: usize,
+    /// Directory to keep decoded images in between runs, so they don't have to be decoded again.
+    /// The disk cache is disabled when not set.
+    pub disk_path: Option<String>,
+    /// Disk space the cache can use, in MB. The least recently used images are evicted first.
+    #[config(default = 20480)]
+    pub disk_mb: u64,
+}
+
+#[derive(Config, Debug)]
+pub struct LoaderConfig {
+    /// Nr. of images decoded in parallel. Defaults to the number of cores (1 on wasm).
+    pub decode_workers: Option<usize>,
+    /// Nr. of decoded images to queue up ahead of the trainer.
+    #[config(default = 32)]
+    pub prefetch: usize,
+    /// Order in which training views are visited.
+    #[config(default = "ViewSampling::Uniform")]
+    pub sampling: ViewSampling,
+}
+
+/// Policy deciding which training view comes next.
+#[derive(Config, Debug, PartialEq)]
+pub enum ViewSampling {
+    /// Visit every view once per epoch, in a random order.
+    Uniform,
+    /// Pick views with a probability proportional to their recent loss, so views that are
+    /// slow to converge are trained on more often. `smoothing` is the weight of the previous
+    /// loss of a view when a new one is reported, and a fraction `uniform_mix` of the views
+    /// is still picked uniformly at random.
+    LossWeighted { smoothing: f32, uniform_mix: f32 },
+    /// Visit the views in dataset order. With more than one decode worker, neighbouring
+    /// views can arrive slightly out of order.
+    Sequential,
+}
+
+/// Color space of the training targets, and so of the colors the splats learn.
+///
+/// Integer images (8 and 16-bit) are assumed to be sRGB encoded, float images (e.g. `.hdr`) linear.
+#[derive(Config, Debug, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Linear when the dataset has float images, sRGB otherwise.
+    Auto,
+    /// Train on sRGB encoded values, like most published results. Float images are encoded
+    /// to sRGB, which clamps them to [0, 1].
+    Srgb,
+    /// Train on linear values. Integer images are decoded from sRGB, float images keep their
+    /// full range. Eval metrics are still computed in sRGB.
+    Linear,
+}
+
+impl ColorSpace {
+    /// Picks the color space for `Auto`, given whether the dataset has float images.
+    pub fn resolve(self, has_float_images: bool) -> Self {
+        match self {
+            ColorSpace::Auto if has_float_images => ColorSpace::Linear,
+            ColorSpace::Auto => ColorSpace::Srgb,
+            space => space,
+        }
+    }
+}
+
+/// Policy deciding which images are held out for evaluation.
+#[derive(Config, Debug, PartialEq)]
+pub enum EvalSplit {
+    /// Train on all images.
+    None,
+    /// Select every nth image, starting with the first.
+    EveryNth { every: usize },
+    /// Evaluate on the images listed in a file of the dataset (e.g. `test.txt`), one name per line.
+    /// With a train list only the images listed there are trained on, the rest aren't loaded.
+    List {
+        test_file: String,
+        train_file: Option<String>,
+    },
+    /// Select a random fraction of the images.
+    Random { seed: u64, fraction: f32 },
+    /// Select the images whose name matches a glob pattern, e.g. `*_test_*.png`.
+    Glob { pattern: String },
+}
+
+impl EvalSplit {
+    /// Human readable summary, recorded with eval results.
+    pub fn describe(&self) -> String {
+        match self {
+            EvalSplit::None => String::from("no eval split"),
+            EvalSplit::EveryNth { every } => format!("one in every {every} images"),
+            EvalSplit::List { test_file, train_file: None } => format!("images listed in {test_file}"),
+            EvalSplit::List { test_file, train_file: Some(train_file) } => {
+                format!("images listed in {test_file}, training on {train_file}")
+            }
+            EvalSplit::Random { seed, fraction } => format!("random {:.1}% (seed {seed})", fraction * 100.0),
+            EvalSplit::Glob { pattern } => format!("images matching `{pattern}`"),
+        }
+    }
+}
@@ -1,20 +1,70 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
 use thiserror::Error;
 
 pub(crate) type Result<T> = std::result::Result<T, DatasetError>;
 
+/// Where in a file a parse error happened.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FileLocation {
+    /// 1-based line number of a text file.
+    Line(usize),
+    /// Byte offset into a binary file.
+    Offset(u64),
+}
+
+impl Display for FileLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileLocation::Line(line) => write!(f, "line {line}"),
+            FileLocation::Offset(offset) => write!(f, "byte {offset}"),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum FormatError {
-    #[error("IO error while loading dataset: {0}")]
-    Io(String),
+    #[error("Missing file: {0}")]
+    MissingFile(String),
+
+    #[error("Invalid eval split: {0}")]
+    InvalidSplit(String),
 
     #[error("File IO error: {0}")]
     File(#[from] tokio::io::Error),
 
+    #[error("Failed to read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: tokio::io::Error,
+    },
+
+    #[error("Failed to parse {} at {location} in {record}: {reason}", path.display())]
+    Parse {
+        path: PathBuf,
+        location: FileLocation,
+        /// The offending record, the line itself for text files.
+        record: String,
+        reason: String,
+    },
+
+    #[error("Image {image} references camera {camera_id} which doesn't exist")]
+    UnknownCamera { image: String, camera_id: i32 },
+
     #[error("Error decoding camera parameters: {0}")]
     InvalidCamera(&'static str),
 
     #[error("Image error: {0}")]
     InvalidImage(#[from] image::ImageError),
+
+    #[error("Failed to load image {}: {source}", path.display())]
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+
+    #[error("The scene loader stopped unexpectedly")]
+    LoaderStopped,
 }
 
 #[derive(Debug, Error)]
@@ -25,6 +75,6 @@ pub enum DatasetError {
     #[error("Format not recognized: Only colmap and nerfstudio json are supported.")]
     FormatNotSupported,
 
-    #[error("Scene source error")]
+    #[error("Scene source error: {0}")]
     SceneSource(#[from] scene_source::SceneSourceError)
-}
\ No newline at end of file
+}
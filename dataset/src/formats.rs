@@ -1,4 +1,5 @@
 mod colmap;
+mod split;
 
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
@@ -28,6 +29,8 @@ mod wasm_send {
 
 pub use wasm_send::*;
 use crate::scene::splat::SplatMessage;
+use crate::scene::SparsePoint;
+use crate::validation::ValidationReport;
 
 pub trait DynStream<Item>: Stream<Item = Item> + SendNotWasm {}
 impl<Item, T: Stream<Item = Item> + SendNotWasm> DynStream<Item> for T {}
@@ -37,4 +40,27 @@ pub type DataStream<T> = Pin<Box<dyn DynStream<Result<T, FormatError>>>>;
 pub async fn load_dataset(source: Source, config: LoadConfig, device: &WgpuDevice) -> crate::error::Result<(DataStream<SplatMessage>, Dataset)> {
     let fs = source.into_fs().await?;
     Ok(colmap::load(Arc::new(fs), config, device).await?)
-}
\ No newline at end of file
+}
+
+/// Loads the views & sparse points of a dataset without creating any GPU resources.
+/// Useful to inspect the layout of a scene without training on it.
+pub async fn load_layout(source: Source, config: LoadConfig) -> crate::error::Result<(Dataset, Vec<SparsePoint>)> {
+    let fs = Arc::new(source.into_fs().await?);
+    let dataset = colmap::load_views(fs.clone(), &config).await?;
+    let mut points = colmap::load_points(&fs, &config).await?;
+    for point in &mut points {
+        point.position = dataset.transform.transform_point(point.position);
+    }
+    Ok((dataset, points))
+}
+
+/// Checks the dataset can be trained on, without creating any GPU resources.
+/// Problems are collected in the report rather than returned as an error.
+pub async fn validate_dataset(source: Source) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    match source.into_fs().await {
+        Ok(fs) => colmap::validate(&fs, &mut report).await,
+        Err(err) => report.error(format!("Failed to open dataset: {err}")),
+    }
+    report.finish()
+}
@@ -3,6 +3,9 @@ mod image;
 mod camera;
 mod input;
 mod parse;
+mod validate;
+
+pub use validate::validate;
 
 use std::collections::HashMap;
 use std::fs::File;
@@ -20,108 +23,152 @@ use path_clean::PathClean;
 use render::gaussian_splats::Splats;
 use render::sh::rgb_to_sh;
 use scene_source::Filesystem;
-use crate::config::LoadConfig;
+use crate::color;
+use crate::config::{ColorSpace, LoadConfig};
 use crate::Dataset;
 use crate::error::FormatError;
 use crate::formats::colmap::camera::Camera;
 use crate::formats::colmap::image::Image;
-use crate::formats::colmap::input::{InputFile, InputType};
+use crate::formats::colmap::input::{InputData, InputFile, InputType};
 use crate::formats::colmap::parse::ImagesParser;
 use crate::formats::DataStream;
-use crate::scene::{ImageFile, SceneView};
+use crate::formats::split::{self, Assignment};
+use crate::scene::{ImageFile, SceneView, SparsePoint};
 use crate::scene::splat::{ParseMetadata, SplatMessage};
 
 pub async fn load(fs: Arc<Filesystem>, config: LoadConfig, device: &WgpuDevice) -> Result<(DataStream<SplatMessage>, Dataset), FormatError> {
-    let (cam_path, img_path, is_bin) = if let Some(file) = fs.files_ending_in("cameras.bin").next() {
-        let path = file.parent().expect("unreachable");
-        (path.join("cameras.bin"), path.join("images.bin"), true)
-    } else if let Some(file) = fs.files_ending_in("cameras.txt").next() {
-        let path = file.parent().expect("unreachable");
-        (path.join("cameras.txt"), path.join("images.txt"), false)
-    } else {
-        return Err(FormatError::Io(String::from("Camera file could be found")));
+    let dataset = load_views(fs.clone(), &config).await?;
+
+    let load_args = config.clone();
+    let transform = dataset.transform;
+    let color_space = dataset.color_space;
+    let fs = fs.clone();
+    let device = device.clone();
+    let init_stream = try_fn_stream(|emitter| async move {
+        let points = load_points(&fs, &load_args).await?;
+
+        // Ignore empty points data.
+        if !points.is_empty() {
+            log::info!("Starting from colmap points {}", points.len());
+
+            let positions: Vec<Vec3> = points.iter().map(|p| transform.transform_point(p.position)).collect();
+            let colors: Vec<f32> = points
+                .iter()
+                .flat_map(|p| {
+                    let rgb = p.color.map(|c| match color_space {
+                        ColorSpace::Linear => color::srgb8_to_linear(c),
+                        _ => c as f32 / 255.0,
+                    });
+                    let sh = rgb_to_sh(glam::Vec3::from_array(rgb));
+                    [sh.x, sh.y, sh.z]
+                })
+                .collect();
+
+            let init_splat =
+                Splats::from_raw(&positions, None, None, Some(&colors), None, &device);
+            emitter
+                .emit(SplatMessage {
+                    meta: ParseMetadata {
+                        up_axis: None,
+                        total_splats: init_splat.num_splats(),
+                        frame_count: 1,
+                        current_frame: 0,
+                    },
+                    splats: init_splat,
+                })
+                .await;
+        }
+
+        Ok(())
+    });
+
+    Ok((Box::pin(init_stream), dataset))
+}
+
+/// Reads the cameras & images of the colmap reconstruction, without touching the sfm points.
+pub async fn load_views(fs: Arc<Filesystem>, config: &LoadConfig) -> Result<Dataset, FormatError> {
+    let Some((cam_path, img_path, is_bin)) = find_model_files(&fs) else {
+        return Err(FormatError::MissingFile(String::from("cameras.bin or cameras.txt")));
     };
 
     info!("Located cameras file at: {}", cam_path.as_display());
     info!("Located images file at: {}", img_path.as_display());
-    let cam_file = fs.reader_at_path(&cam_path).await?;
-    let cam_model_data = InputFile::new(cam_file, InputType::Cameras, is_bin).parse().await.unwrap().as_cameras().unwrap();
-    let img_file = fs.reader_at_path(&img_path).await?;
-    let img_infos = InputFile::new(img_file, InputType::Images, is_bin).parse().await.unwrap().as_images().unwrap();
+    let cam_model_data = read_input(&fs, &cam_path, InputType::Cameras, is_bin).await?.as_cameras().unwrap_or_default();
+    let img_infos = read_input(&fs, &img_path, InputType::Images, is_bin).await?.as_images().unwrap_or_default();
 
     let mut img_info_list = img_infos.into_iter().collect::<Vec<_>>();
     img_info_list.sort_by_key(|key_img| key_img.1.name.clone());
-    
-    let (train_views, eval_views) = create_views(fs.clone(), &cam_model_data, &img_info_list, &config).await?;
 
-    let load_args = config.clone();
-    let fs = fs.clone();
-    let device = device.clone();
-    let init_stream = try_fn_stream(|emitter| async move {
-        let points_path = fs.files_ending_in("points3d.bin").next()
-            .or_else(|| fs.files_ending_in("points3d.txt").next());
+    let (train_views, eval_views) = create_views(fs, &cam_model_data, &img_info_list, config).await?;
+    let mut dataset = Dataset::from_views(train_views, eval_views, config.eval_split.clone())
+        .with_color_space(config.color_space);
+    info!("Training in {:?} color space", dataset.color_space);
+    if config.normalize_scene {
+        dataset.normalize();
+        info!("Normalized scene with {:?}", dataset.transform);
+    }
+    Ok(dataset)
+}
 
-        let Some(points_path) = points_path else {
-            return Err(FormatError::Io("Could not find points file".parse().unwrap()));
-        };
+/// Reads the sparse sfm points of the colmap reconstruction, subsampled according to the config.
+/// The points are in the original coordinates, see [`Dataset::transform`].
+pub async fn load_points(fs: &Filesystem, config: &LoadConfig) -> Result<Vec<SparsePoint>, FormatError> {
+    let Some(points_path) = find_points_file(fs) else {
+        return Err(FormatError::MissingFile(String::from("points3D.bin or points3D.txt")));
+    };
 
-        info!("Located points file at: {}", points_path.as_display());
-        let is_binary = matches!(
-            points_path.extension().and_then(|p| p.to_str()),
-            Some("bin")
-        );
+    info!("Located points file at: {}", points_path.as_display());
+    let is_binary = matches!(
+        points_path.extension().and_then(|p| p.to_str()),
+        Some("bin")
+    );
 
-        // Extract COLMAP sfm points.
-        let points_file = fs.reader_at_path(&points_path).await.unwrap();
-        let points_data = InputFile::new(points_file, InputType::Points3D, is_binary).parse().await.unwrap().as_points();
+    // Extract COLMAP sfm points.
+    let points_data = read_input(fs, &points_path, InputType::Points3D, is_binary).await?.as_points();
 
-        // Ignore empty points data.
-        if let Some(points_data) = points_data {
-            if !points_data.is_empty() {
-                log::info!("Starting from colmap points {}", points_data.len());
-
-                // The ply importer handles subsampling normally. Here just
-                // do it manually, maybe nice to unify at some point.
-                let step = load_args.subsample_points.unwrap_or(1) as usize;
-
-                let positions: Vec<Vec3> =
-                    points_data.values().step_by(step).map(|p| p.xyz).collect();
-                let colors: Vec<f32> = points_data
-                    .values()
-                    .step_by(step)
-                    .flat_map(|p| {
-                        let sh = rgb_to_sh(glam::vec3(
-                            p.rgb[0] as f32 / 255.0,
-                            p.rgb[1] as f32 / 255.0,
-                            p.rgb[2] as f32 / 255.0,
-                        ));
-                        [sh.x, sh.y, sh.z]
-                    })
-                    .collect();
-
-                let init_splat =
-                    Splats::from_raw(&positions, None, None, Some(&colors), None, &device);
-                emitter
-                    .emit(SplatMessage {
-                        meta: ParseMetadata {
-                            up_axis: None,
-                            total_splats: init_splat.num_splats(),
-                            frame_count: 1,
-                            current_frame: 0,
-                        },
-                        splats: init_splat,
-                    })
-                    .await;
-            }
-        }
+    let Some(points_data) = points_data else {
+        return Ok(vec![]);
+    };
 
-        Ok(())
-    });
+    // The ply importer handles subsampling normally. Here just
+    // do it manually, maybe nice to unify at some point.
+    let step = config.subsample_points.unwrap_or(1).max(1) as usize;
+
+    Ok(points_data
+        .values()
+        .step_by(step)
+        .map(|p| SparsePoint {
+            position: p.xyz,
+            color: p.rgb,
+        })
+        .collect())
+}
+
+/// Opens & parses one of the reconstruction files.
+async fn read_input(fs: &Filesystem, path: &Path, input_type: InputType, is_bin: bool) -> Result<InputData, FormatError> {
+    let file = fs.reader_at_path(path).await.map_err(|source| FormatError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    InputFile::new(path, file, input_type, is_bin).parse().await
+}
+
+/// Locates the cameras & images files, and whether they are binary.
+fn find_model_files(fs: &Filesystem) -> Option<(PathBuf, PathBuf, bool)> {
+    if let Some(file) = fs.files_ending_in("cameras.bin").next() {
+        let path = file.parent()?;
+        Some((path.join("cameras.bin"), path.join("images.bin"), true))
+    } else if let Some(file) = fs.files_ending_in("cameras.txt").next() {
+        let path = file.parent()?;
+        Some((path.join("cameras.txt"), path.join("images.txt"), false))
+    } else {
+        None
+    }
+}
 
-    Ok((
-        Box::pin(init_stream),
-        Dataset::from_views(train_views, eval_views),
-    ))
+fn find_points_file(fs: &Filesystem) -> Option<PathBuf> {
+    fs.files_ending_in("points3d.bin").next()
+        .or_else(|| fs.files_ending_in("points3d.txt").next())
 }
 
 async fn create_views(fs: Arc<Filesystem>, cam_model_data: &HashMap<i32, Camera>, img_info_list: &Vec<(i32, Image)>, config: &LoadConfig)
@@ -130,22 +177,60 @@ async fn create_views(fs: Arc<Filesystem>, cam_model_data: &HashMap<i32, Camera>
     let mut train_views = vec![];
     let mut eval_views = vec![];
 
-    for (i, (_img_id, img_info)) in img_info_list
-        .into_iter()
+    let img_infos: Vec<&Image> = img_info_list
+        .iter()
         .take(config.max_frames.unwrap_or(usize::MAX))
-        .step_by(config.subsample_frames.unwrap_or(1) as usize)
-        .enumerate()
-    {
-        let cam_data = cam_model_data[&img_info.camera_id].clone();
+        .step_by(config.subsample_frames.unwrap_or(1).max(1) as usize)
+        .map(|(_img_id, img_info)| img_info)
+        .collect();
+    let names: Vec<&str> = img_infos.iter().map(|img_info| img_info.name.as_str()).collect();
+    let assignments = split::assign(&config.eval_split, &fs, &names).await?;
+
+    for (img_info, assignment) in img_infos.into_iter().zip(assignments) {
+        if assignment == Assignment::Skip {
+            continue;
+        }
+
+        let Some(cam_data) = cam_model_data.get(&img_info.camera_id) else {
+            return Err(FormatError::UnknownCamera {
+                image: img_info.name.clone(),
+                camera_id: img_info.camera_id,
+            });
+        };
+
+        let Some((img_path, mask_path)) = find_mask_and_img(&fs, &img_info.name) else {
+            log::warn!("Image not found: {}", img_info.name);
+            continue;
+        };
 
-        // Create a future to handle loading the image.
-        let focal = cam_data.focal();
+        // Prefer a pre-scaled copy of the image, decoding it is a lot cheaper.
+        let (factor, img_path) = choose_scaled_image(&fs, &img_path, &img_info.name, cam_data, config);
+        let img_file = ImageFile::new(fs.clone(), &img_path, mask_path, config.max_resolution)
+            .await
+            .map_err(|source| FormatError::Image {
+                path: img_path.clone(),
+                source,
+            })?;
+        // Without a folder for the requested factor, scale the full resolution image on load.
+        let img_file = match config.downscale {
+            Some(downscale) if factor == 1 => img_file.with_downscale(downscale),
+            _ => img_file,
+        };
+        let factor = config.downscale.unwrap_or(factor).max(1);
 
-        let fovx = render::camera::focal_to_fov(focal.0, cam_data.width as u32);
-        let fovy = render::camera::focal_to_fov(focal.1, cam_data.height as u32);
+        // Scale the intrinsics by exactly 1/factor, relative to the size of the image that is loaded.
+        let (focal, center, size) = if factor == 1 {
+            let size = glam::uvec2(cam_data.width as u32, cam_data.height as u32);
+            (cam_data.focal(), cam_data.principal_point(), size)
+        } else {
+            let focal = cam_data.focal();
+            let focal = (focal.0 / factor as f64, focal.1 / factor as f64);
+            (focal, cam_data.principal_point() / factor as f32, img_file.scaled_dim())
+        };
 
-        let center = cam_data.principal_point();
-        let center_uv = center / glam::vec2(cam_data.width as f32, cam_data.height as f32);
+        let fovx = render::camera::focal_to_fov(focal.0, size.x);
+        let fovy = render::camera::focal_to_fov(focal.1, size.y);
+        let center_uv = center / size.as_vec2();
 
         // Convert w2c to c2w.
         let world_to_cam = glam::Affine3A::from_rotation_translation(img_info.quat, img_info.tvec);
@@ -154,28 +239,13 @@ async fn create_views(fs: Arc<Filesystem>, cam_model_data: &HashMap<i32, Camera>
 
         let camera = render::camera::Camera::new(translation, quat, fovx, fovy, center_uv);
 
-        let Some((img_path, mask_path)) = find_mask_and_img(&fs, &img_info.name) else {
-            log::warn!("Image not found: {}", img_info.name);
-            continue;
-        };
-
-        let img_file = if let Some(img_path) = fs.files_ending_in(&img_info.name).next() {
-            Ok(ImageFile::new(fs.clone(), &img_path, mask_path, config.max_resolution).await?)
-        } else {
-            Err(FormatError::Io(format!("Image file {} not found", &img_info.name)))
-        }?;
-
         let view = SceneView {
             camera,
             image: img_file
         };
 
-        if let Some(eval_period) = config.eval_split_every {
-            if i % eval_period == 0 {
-                eval_views.push(view);
-            } else {
-                train_views.push(view);
-            }
+        if assignment == Assignment::Eval {
+            eval_views.push(view);
         } else {
             train_views.push(view);
         }
@@ -184,6 +254,46 @@ async fn create_views(fs: Arc<Filesystem>, cam_model_data: &HashMap<i32, Camera>
     Ok((train_views, eval_views))
 }
 
+/// Picks the `images_N` copy of an image to load, along with its factor N. With an explicit downscale
+/// factor only that folder is considered, otherwise the smallest copy that still has the max resolution.
+fn choose_scaled_image(fs: &Filesystem, img_path: &Path, name: &str, camera: &Camera, config: &LoadConfig) -> (u32, PathBuf) {
+    const FACTORS: [u32; 3] = [8, 4, 2];
+
+    // The folder holding the images is the one above all components of the image name.
+    let depth = Path::new(name).components().count();
+    let Some(images_dir) = img_path.ancestors().nth(depth) else {
+        return (1, img_path.to_path_buf());
+    };
+    let Some(dir_name) = images_dir.file_name().and_then(|n| n.to_str()) else {
+        return (1, img_path.to_path_buf());
+    };
+    let relative = img_path.strip_prefix(images_dir).unwrap_or(img_path);
+
+    let find = |factor: u32| {
+        let scaled_dir = images_dir.with_file_name(format!("{dir_name}_{factor}"));
+        let exact = scaled_dir.join(relative);
+        if fs.has_file(&exact) {
+            return Some(exact);
+        }
+        // Some datasets store the scaled copies in another format, e.g. png next to jpg originals.
+        let stem = exact.file_stem()?.to_str()?;
+        fs.files_with_stem(stem).find(|p| p.parent() == exact.parent())
+    };
+
+    let candidates: Vec<u32> = match config.downscale {
+        Some(factor) if factor > 1 => vec![factor],
+        Some(_) => vec![],
+        None => {
+            let largest_side = camera.width.max(camera.height) as u32;
+            FACTORS.into_iter().filter(|f| largest_side / f >= config.max_resolution).collect()
+        }
+    };
+    candidates
+        .into_iter()
+        .find_map(|factor| find(factor).map(|path| (factor, path)))
+        .unwrap_or_else(|| (1, img_path.to_path_buf()))
+}
+
 fn find_mask_and_img(fs: &Filesystem, name: &str) -> Option<(PathBuf, Option<PathBuf>)> {
     // Colmap only specifies an image name, not a full path. We brute force
     // search for the image in the archive.
@@ -228,4 +338,45 @@ fn find_mask_path(fs: &Filesystem, path: &Path) -> Option<PathBuf> {
         }
     }
     None
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::colmap::camera::CameraModel;
+
+    #[tokio::test]
+    async fn chooses_scaled_images() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        // The 4x copies were converted to png, like some datasets do.
+        for name in ["images/a.jpg", "images/b.jpg", "images_2/a.jpg", "images_4/a.png"] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().expect("File has a parent")).expect("Failed to create dir");
+            std::fs::write(path, b"").expect("Failed to write file");
+        }
+        let fs = Filesystem::from_dir(dir.path()).await.expect("Failed to read dir");
+
+        let camera = Camera { id: 1, model: CameraModel::Pinhole, width: 4000, height: 3000, params: vec![] };
+        let choose = |name: &str, config: &LoadConfig| {
+            let path = Path::new("images").join(name);
+            choose_scaled_image(&fs, &path, name, &camera, config)
+        };
+
+        // The smallest copy whose largest side still has the max resolution.
+        let config = LoadConfig::new().with_max_resolution(1000);
+        assert_eq!(choose("a.jpg", &config), (4, PathBuf::from("images_4/a.png")));
+        let config = LoadConfig::new().with_max_resolution(1920);
+        assert_eq!(choose("a.jpg", &config), (2, PathBuf::from("images_2/a.jpg")));
+
+        // An explicit factor only considers its own folder.
+        let config = LoadConfig::new().with_max_resolution(1000).with_downscale(Some(2));
+        assert_eq!(choose("a.jpg", &config), (2, PathBuf::from("images_2/a.jpg")));
+        let config = LoadConfig::new().with_downscale(Some(1));
+        assert_eq!(choose("a.jpg", &config), (1, PathBuf::from("images/a.jpg")));
+
+        // Without the folder the full resolution images are used.
+        let config = LoadConfig::new().with_downscale(Some(8));
+        assert_eq!(choose("a.jpg", &config), (1, PathBuf::from("images/a.jpg")));
+        let config = LoadConfig::new().with_max_resolution(1000);
+        assert_eq!(choose("b.jpg", &config), (1, PathBuf::from("images/b.jpg")));
+    }
+}
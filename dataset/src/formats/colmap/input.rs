@@ -1,7 +1,7 @@
 use std::collections::HashMap;
-use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::io::{AsyncRead, BufReader};
+use crate::error::FormatError;
 use crate::formats::colmap::camera::Camera;
 use crate::formats::colmap::image::Image;
 use crate::formats::colmap::parse::{CamerasParser, ImagesParser, Parseable, Parser, PointsParser};
@@ -54,13 +54,14 @@ impl InputData {
 }
 
 pub struct InputFile {
+    path: PathBuf,
     reader: Box<dyn AsyncRead + Unpin + Send>,
     parser: Box<dyn Parseable>,
     input_format: InputFormat,
 }
 
 impl InputFile {
-    pub fn new(reader: Box<dyn AsyncRead + Unpin + Send>, input_type: InputType, is_bin: bool) -> InputFile {
+    pub fn new(path: &Path, reader: Box<dyn AsyncRead + Unpin + Send>, input_type: InputType, is_bin: bool) -> InputFile {
         let parser: Box<dyn Parseable> = match input_type {
             InputType::Cameras => Box::new(CamerasParser),
             InputType::Images => Box::new(ImagesParser),
@@ -68,18 +69,20 @@ impl InputFile {
         };
         
         Self {
+            path: path.to_path_buf(),
             reader,
             input_format: if is_bin { InputFormat::Binary} else { InputFormat::Text },
             parser
         }
     }
 
-    pub async fn parse(self) -> io::Result<InputData> {
-        let mut reader = BufReader::new(self.reader);
+    pub async fn parse(self) -> Result<InputData, FormatError> {
+        let reader = BufReader::new(self.reader);
 
-        match self.input_format {
+        let result = match self.input_format {
             InputFormat::Binary => self.parser.parse_bin(reader).await,
             InputFormat::Text => self.parser.parse_txt(reader).await
-        }
+        };
+        result.map_err(|err| err.with_path(&self.path))
     }
 }
\ No newline at end of file
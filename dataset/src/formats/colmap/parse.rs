@@ -2,9 +2,10 @@ use std::collections::HashMap;
 use std::future::Future;
 use std::path::Path;
 use std::pin::Pin;
-use tokio::fs::File;
+use std::str::FromStr;
 use tokio::io;
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use crate::error::{FileLocation, FormatError};
 use crate::formats::colmap::camera::{Camera, CameraModel};
 use crate::formats::colmap::image::Image;
 use crate::formats::colmap::input::InputData;
@@ -16,16 +17,178 @@ pub enum Parser {
     Cameras(Box<CamerasParser>),
 }
 
-type ParseResult = Pin<Box<dyn Future<Output = io::Result<InputData>> + Send>>;
+type Reader = BufReader<Box<dyn AsyncRead + Unpin + Send>>;
+type ParseResult = Pin<Box<dyn Future<Output = Result<InputData, ParseError>> + Send>>;
 
 pub trait Parseable: Send + Sync {
-    fn parse_bin(&self, reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult;
-    fn parse_txt(&self, reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult;
+    fn parse_bin(&self, reader: Reader) -> ParseResult;
+    fn parse_txt(&self, reader: Reader) -> ParseResult;
 }
 
-fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
-    s.parse()
-        .map_err(|_e| io::Error::new(io::ErrorKind::InvalidData, "Parse error"))
+/// A failure while parsing a file, the path is attached by the caller.
+#[derive(Debug)]
+pub struct ParseError {
+    pub location: FileLocation,
+    pub record: String,
+    pub reason: String,
+}
+
+impl ParseError {
+    pub fn with_path(self, path: &Path) -> FormatError {
+        FormatError::Parse {
+            path: path.to_path_buf(),
+            location: self.location,
+            record: self.record,
+            reason: self.reason,
+        }
+    }
+}
+
+// Counts in the binary files come straight from the file, don't trust them for allocations.
+const MAX_PREALLOCATE: u64 = 1024;
+
+/// Reads little endian values, keeping track of the offset and record for error messages.
+struct BinReader {
+    inner: Reader,
+    offset: u64,
+    record: String,
+}
+
+impl BinReader {
+    fn new(inner: Reader) -> Self {
+        Self {
+            inner,
+            offset: 0,
+            record: String::from("header"),
+        }
+    }
+
+    fn begin_record(&mut self, record: String) {
+        self.record = record;
+    }
+
+    fn error(&self, reason: impl Into<String>) -> ParseError {
+        ParseError {
+            location: FileLocation::Offset(self.offset),
+            record: self.record.clone(),
+            reason: reason.into(),
+        }
+    }
+
+    fn io_error(&self, err: io::Error) -> ParseError {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            self.error("file is truncated")
+        } else {
+            self.error(format!("read failed: {err}"))
+        }
+    }
+
+    async fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+        let mut bytes = [0; N];
+        if let Err(err) = self.inner.read_exact(&mut bytes).await {
+            return Err(self.io_error(err));
+        }
+        self.offset += N as u64;
+        Ok(bytes)
+    }
+
+    async fn read_u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.read_bytes::<1>().await?[0])
+    }
+
+    async fn read_i32(&mut self) -> Result<i32, ParseError> {
+        Ok(i32::from_le_bytes(self.read_bytes().await?))
+    }
+
+    async fn read_i64(&mut self) -> Result<i64, ParseError> {
+        Ok(i64::from_le_bytes(self.read_bytes().await?))
+    }
+
+    async fn read_u64(&mut self) -> Result<u64, ParseError> {
+        Ok(u64::from_le_bytes(self.read_bytes().await?))
+    }
+
+    async fn read_f64(&mut self) -> Result<f64, ParseError> {
+        Ok(f64::from_le_bytes(self.read_bytes().await?))
+    }
+
+    /// Reads a null terminated UTF-8 string.
+    async fn read_str(&mut self) -> Result<String, ParseError> {
+        let mut bytes = Vec::new();
+        if let Err(err) = self.inner.read_until(b'\0', &mut bytes).await {
+            return Err(self.io_error(err));
+        }
+        if bytes.pop() != Some(b'\0') {
+            return Err(self.error("file is truncated"));
+        }
+        let value = String::from_utf8(bytes).map_err(|_| self.error("string is not valid UTF-8"))?;
+        self.offset += value.len() as u64 + 1;
+        Ok(value)
+    }
+}
+
+/// Reads the lines of a text file, skipping comments and keeping track of the line number.
+struct TextReader {
+    inner: Reader,
+    line: String,
+    line_no: usize,
+}
+
+impl TextReader {
+    fn new(inner: Reader) -> Self {
+        Self {
+            inner,
+            line: String::new(),
+            line_no: 0,
+        }
+    }
+
+    /// Moves to the next line that isn't a comment, false at the end of the file.
+    async fn next_line(&mut self, skip_empty: bool) -> Result<bool, ParseError> {
+        loop {
+            self.line.clear();
+            let read = match self.inner.read_line(&mut self.line).await {
+                Ok(read) => read,
+                Err(err) => {
+                    self.line_no += 1;
+                    return Err(self.error(format!("read failed: {err}")));
+                }
+            };
+            if read == 0 {
+                return Ok(false);
+            }
+            self.line_no += 1;
+
+            let line = self.line.trim();
+            if !line.starts_with('#') && !(skip_empty && line.is_empty()) {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn fields(&self) -> Vec<&str> {
+        self.line.split_whitespace().collect()
+    }
+
+    fn error(&self, reason: impl Into<String>) -> ParseError {
+        const MAX_RECORD_LEN: usize = 80;
+
+        let line = self.line.trim();
+        let record = match line.char_indices().nth(MAX_RECORD_LEN) {
+            Some((end, _)) => format!("`{}...`", &line[..end]),
+            None => format!("`{line}`"),
+        };
+        ParseError {
+            location: FileLocation::Line(self.line_no),
+            record,
+            reason: reason.into(),
+        }
+    }
+}
+
+fn field<T: FromStr>(fields: &[&str], index: usize, name: &str) -> Result<T, String> {
+    let value = fields.get(index).ok_or_else(|| format!("missing {name}"))?;
+    value.parse().map_err(|_| format!("invalid {name} `{value}`"))
 }
 
 pub struct PointsParser;
@@ -33,44 +196,42 @@ pub struct ImagesParser;
 pub struct CamerasParser;
 
 impl Parseable for ImagesParser {
-    fn parse_bin(&self, mut reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult {
+    fn parse_bin(&self, reader: Reader) -> ParseResult {
         Box::pin(async move {
+            let mut reader = BinReader::new(reader);
             let mut images = HashMap::new();
-            let num_images = reader.read_u64_le().await?;
+            let num_images = reader.read_u64().await?;
 
-            for _ in 0..num_images {
-                let image_id = reader.read_i32_le().await?;
+            for i in 0..num_images {
+                reader.begin_record(format!("image #{i}"));
+                let image_id = reader.read_i32().await?;
+                reader.begin_record(format!("image {image_id}"));
 
                 let [w, x, y, z] = [
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
                 ];
                 let quat = glam::quat(x, y, z, w);
 
                 let tvec = glam::vec3(
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
                 );
 
-                let camera_id = reader.read_i32_le().await?;
-                let mut name_bytes = Vec::new();
-                reader.read_until(b'\0', &mut name_bytes).await?;
+                let camera_id = reader.read_i32().await?;
+                let name = reader.read_str().await?;
 
-                let name = std::str::from_utf8(&name_bytes[..name_bytes.len() - 1])
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-                    .to_owned();
-
-                let num_points2d = reader.read_u64_le().await?;
-                let mut xys = Vec::with_capacity(num_points2d as usize);
-                let mut point3d_ids = Vec::with_capacity(num_points2d as usize);
+                let num_points2d = reader.read_u64().await?;
+                let mut xys = Vec::with_capacity(num_points2d.min(MAX_PREALLOCATE) as usize);
+                let mut point3d_ids = Vec::with_capacity(num_points2d.min(MAX_PREALLOCATE) as usize);
 
                 for _ in 0..num_points2d {
                     xys.push(glam::Vec2::new(
-                        reader.read_f64_le().await? as f32,
-                        reader.read_f64_le().await? as f32,
+                        reader.read_f64().await? as f32,
+                        reader.read_f64().await? as f32,
                     ));
                     point3d_ids.push(reader.read_i64().await?);
                 }
@@ -92,57 +253,26 @@ impl Parseable for ImagesParser {
         })
     }
 
-    fn parse_txt(&self, mut reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult {
+    fn parse_txt(&self, reader: Reader) -> ParseResult {
         Box::pin(async move {
+            let mut reader = TextReader::new(reader);
             let mut images = HashMap::new();
-            let mut line = String::new();
-
-            let mut img_data = true;
 
-            loop {
-                line.clear();
-                if reader.read_line(&mut line).await? == 0 {
-                    break;
-                }
-
-                if !line.is_empty() && !line.starts_with('#') {
-                    let elems: Vec<&str> = line.split_whitespace().collect();
-                    let id: i32 = parse(elems[0])?;
-
-                    let [w, x, y, z] = [
-                        parse(elems[1])?,
-                        parse(elems[2])?,
-                        parse(elems[3])?,
-                        parse(elems[4])?,
-                    ];
-                    let quat = glam::quat(x, y, z, w);
-                    let tvec = glam::vec3(parse(elems[5])?, parse(elems[6])?, parse(elems[7])?);
-                    let camera_id: i32 = parse(elems[8])?;
-                    let name = elems[9].to_owned();
-
-                    line.clear();
-                    reader.read_line(&mut line).await?;
-                    let elems: Vec<&str> = line.split_whitespace().collect();
-                    let mut xys = Vec::new();
-                    let mut point3d_ids = Vec::new();
-
-                    for chunk in elems.chunks(3) {
-                        xys.push(glam::vec2(parse(chunk[0])?, parse(chunk[1])?));
-                        point3d_ids.push(parse(chunk[2])?);
+            while reader.next_line(true).await? {
+                let (id, mut image) = parse_image_line(&reader.fields()).map_err(|e| reader.error(e))?;
+
+                // Every image is followed by its 2D points, which can be an empty line.
+                if reader.next_line(false).await? {
+                    let fields = reader.fields();
+                    for point in fields.chunks(3) {
+                        let x = field(point, 0, "point x").map_err(|e| reader.error(e))?;
+                        let y = field(point, 1, "point y").map_err(|e| reader.error(e))?;
+                        image.xys.push(glam::vec2(x, y));
+                        image.point3d_ids.push(field(point, 2, "point 3D id").map_err(|e| reader.error(e))?);
                     }
-
-                    images.insert(
-                        id,
-                        Image {
-                            quat,
-                            tvec,
-                            camera_id,
-                            name,
-                            xys,
-                            point3d_ids,
-                        },
-                    );
                 }
+
+                images.insert(id, image);
             }
 
             Ok(InputData::Images(images))
@@ -150,33 +280,61 @@ impl Parseable for ImagesParser {
     }
 }
 
+fn parse_image_line(fields: &[&str]) -> Result<(i32, Image), String> {
+    let id = field(fields, 0, "image id")?;
+    let [w, x, y, z] = [
+        field(fields, 1, "quaternion w")?,
+        field(fields, 2, "quaternion x")?,
+        field(fields, 3, "quaternion y")?,
+        field(fields, 4, "quaternion z")?,
+    ];
+    let tvec = glam::vec3(
+        field(fields, 5, "translation x")?,
+        field(fields, 6, "translation y")?,
+        field(fields, 7, "translation z")?,
+    );
+    let image = Image {
+        quat: glam::quat(x, y, z, w),
+        tvec,
+        camera_id: field(fields, 8, "camera id")?,
+        name: field(fields, 9, "image name")?,
+        xys: vec![],
+        point3d_ids: vec![],
+    };
+    Ok((id, image))
+}
+
 impl Parseable for PointsParser {
-    fn parse_bin(&self, mut reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult {
+    fn parse_bin(&self, reader: Reader) -> ParseResult {
         Box::pin(async move {
+            let mut reader = BinReader::new(reader);
             let mut points3d = HashMap::new();
-            let num_points = reader.read_u64_le().await?;
+            let num_points = reader.read_u64().await?;
 
-            for _ in 0..num_points {
+            for i in 0..num_points {
+                reader.begin_record(format!("point #{i}"));
                 let point3d_id = reader.read_i64().await?;
+                reader.begin_record(format!("point {point3d_id}"));
+
                 let xyz = glam::Vec3::new(
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
-                    reader.read_f64_le().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
+                    reader.read_f64().await? as f32,
                 );
                 let rgb = [
                     reader.read_u8().await?,
                     reader.read_u8().await?,
                     reader.read_u8().await?,
                 ];
-                let error = reader.read_f64_le().await?;
+                let error = reader.read_f64().await?;
 
-                let track_length = reader.read_u64_le().await?;
-                let mut image_ids = Vec::with_capacity(track_length as usize);
-                let mut point2d_idxs = Vec::with_capacity(track_length as usize);
+                let track_length = reader.read_u64().await?;
+                let mut image_ids = Vec::with_capacity(track_length.min(MAX_PREALLOCATE) as usize);
+                let mut point2d_idxs = Vec::with_capacity(track_length.min(MAX_PREALLOCATE) as usize);
 
                 for _ in 0..track_length {
-                    image_ids.push(reader.read_i32_le().await?);
-                    point2d_idxs.push(reader.read_i32_le().await?);
+                    image_ids.push(reader.read_i32().await?);
+                    point2d_idxs.push(reader.read_i32().await?);
                 }
 
                 points3d.insert(
@@ -195,59 +353,14 @@ impl Parseable for PointsParser {
         })
     }
 
-    fn parse_txt(&self, mut reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult {
+    fn parse_txt(&self, reader: Reader) -> ParseResult {
         Box::pin(async move {
+            let mut reader = TextReader::new(reader);
             let mut points3d = HashMap::new();
-            let mut line = String::new();
 
-            while reader.read_line(&mut line).await? > 0 {
-                if line.starts_with('#') {
-                    line.clear();
-                    continue;
-                }
-
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() < 8 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Invalid point3D data",
-                    ));
-                }
-
-                let id: i64 = parse(parts[0])?;
-                let xyz = glam::Vec3::new(parse(parts[1])?, parse(parts[2])?, parse(parts[3])?);
-                let rgb = [
-                    parse::<u8>(parts[4])?,
-                    parse::<u8>(parts[5])?,
-                    parse::<u8>(parts[6])?,
-                ];
-                let error: f64 = parse(parts[7])?;
-
-                let mut image_ids = Vec::new();
-                let mut point2d_idxs = Vec::new();
-
-                for chunk in parts[8..].chunks(2) {
-                    if chunk.len() < 2 {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Invalid point3D track data",
-                        ));
-                    }
-                    image_ids.push(parse(chunk[0])?);
-                    point2d_idxs.push(parse(chunk[1])?);
-                }
-
-                points3d.insert(
-                    id,
-                    Point3D {
-                        xyz,
-                        rgb,
-                        error,
-                        image_ids,
-                        point2d_idxs,
-                    },
-                );
-                line.clear();
+            while reader.next_line(true).await? {
+                let (id, point) = parse_point_line(&reader.fields()).map_err(|e| reader.error(e))?;
+                points3d.insert(id, point);
             }
 
             Ok(InputData::Points3D(points3d))
@@ -255,25 +368,60 @@ impl Parseable for PointsParser {
     }
 }
 
+fn parse_point_line(fields: &[&str]) -> Result<(i64, Point3D), String> {
+    let id = field(fields, 0, "point id")?;
+    let xyz = glam::Vec3::new(
+        field(fields, 1, "x")?,
+        field(fields, 2, "y")?,
+        field(fields, 3, "z")?,
+    );
+    let rgb = [
+        field(fields, 4, "red")?,
+        field(fields, 5, "green")?,
+        field(fields, 6, "blue")?,
+    ];
+    let error = field(fields, 7, "error")?;
+
+    let track = fields.get(8..).unwrap_or_default();
+    let mut image_ids = Vec::with_capacity(track.len() / 2);
+    let mut point2d_idxs = Vec::with_capacity(track.len() / 2);
+    for entry in track.chunks(2) {
+        image_ids.push(field(entry, 0, "track image id")?);
+        point2d_idxs.push(field(entry, 1, "track point index")?);
+    }
+
+    let point = Point3D {
+        xyz,
+        rgb,
+        error,
+        image_ids,
+        point2d_idxs,
+    };
+    Ok((id, point))
+}
+
 impl Parseable for CamerasParser {
-    fn parse_bin(&self, mut reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult {
+    fn parse_bin(&self, reader: Reader) -> ParseResult {
         Box::pin(async move {
+            let mut reader = BinReader::new(reader);
             let mut cameras = HashMap::new();
-            let num_cameras = reader.read_u64_le().await?;
+            let num_cameras = reader.read_u64().await?;
 
-            for _ in 0..num_cameras {
-                let camera_id = reader.read_i32_le().await?;
-                let model_id = reader.read_i32_le().await?;
-                let width = reader.read_u64_le().await?;
-                let height = reader.read_u64_le().await?;
+            for i in 0..num_cameras {
+                reader.begin_record(format!("camera #{i}"));
+                let camera_id = reader.read_i32().await?;
+                reader.begin_record(format!("camera {camera_id}"));
 
+                let model_id = reader.read_i32().await?;
                 let model = CameraModel::from_id(model_id)
-                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid camera model"))?;
+                    .ok_or_else(|| reader.error(format!("unknown camera model id {model_id}")))?;
+                let width = reader.read_u64().await?;
+                let height = reader.read_u64().await?;
 
                 let num_params = model.num_params();
                 let mut params = Vec::with_capacity(num_params);
                 for _ in 0..num_params {
-                    params.push(reader.read_f64_le().await?);
+                    params.push(reader.read_f64().await?);
                 }
 
                 cameras.insert(
@@ -292,57 +440,233 @@ impl Parseable for CamerasParser {
         })
     }
 
-    fn parse_txt(&self, mut reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>) -> ParseResult {
+    fn parse_txt(&self, reader: Reader) -> ParseResult {
         Box::pin(async move {
+            let mut reader = TextReader::new(reader);
             let mut cameras = HashMap::new();
-            let mut line = String::new();
 
-            while reader.read_line(&mut line).await? > 0 {
-                if line.starts_with('#') {
-                    line.clear();
-                    continue;
-                }
-
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() < 4 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Invalid camera data",
-                    ));
-                }
-
-                let id = parse(parts[0])?;
-                let model = CameraModel::from_name(parts[1])
-                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid camera model"))?;
-
-                let width = parse(parts[2])?;
-                let height = parse(parts[3])?;
-                let params: Vec<f64> = parts[4..]
-                    .iter()
-                    .map(|&s| parse(s))
-                    .collect::<Result<_, _>>()?;
-
-                if params.len() != model.num_params() {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "Invalid number of camera parameters",
-                    ));
-                }
-
-                cameras.insert(
-                    id,
-                    Camera {
-                        id,
-                        model,
-                        width,
-                        height,
-                        params,
-                    },
-                );
-                line.clear();
+            while reader.next_line(true).await? {
+                let camera = parse_camera_line(&reader.fields()).map_err(|e| reader.error(e))?;
+                cameras.insert(camera.id, camera);
             }
 
             Ok(InputData::Cameras(cameras))
         })
     }
-}
\ No newline at end of file
+}
+
+fn parse_camera_line(fields: &[&str]) -> Result<Camera, String> {
+    let id = field(fields, 0, "camera id")?;
+    let model_name = fields.get(1).ok_or("missing camera model")?;
+    let model = CameraModel::from_name(model_name)
+        .ok_or_else(|| format!("unknown camera model `{model_name}`"))?;
+    let width = field(fields, 2, "width")?;
+    let height = field(fields, 3, "height")?;
+
+    let params = (4..fields.len())
+        .map(|i| field(fields, i, "camera parameter"))
+        .collect::<Result<Vec<f64>, _>>()?;
+    if params.len() != model.num_params() {
+        return Err(format!(
+            "{model_name} expects {} parameters, got {}", model.num_params(), params.len()
+        ));
+    }
+
+    Ok(Camera {
+        id,
+        model,
+        width,
+        height,
+        params,
+    })
+}
+
+/// Small binary reconstructions shared by the parser and validation tests.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    // A single pinhole camera, the parameters start at byte 32.
+    pub(crate) fn cameras_bin() -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(1u64.to_le_bytes());
+        data.extend(7i32.to_le_bytes());
+        data.extend(1i32.to_le_bytes());
+        data.extend(640u64.to_le_bytes());
+        data.extend(480u64.to_le_bytes());
+        for param in [500.0f64, 500.0, 320.0, 240.0] {
+            data.extend(param.to_le_bytes());
+        }
+        data
+    }
+
+    // A single image with one 2D point, the name starts at byte 72.
+    pub(crate) fn images_bin() -> Vec<u8> {
+        let mut data = vec![];
+        data.extend(1u64.to_le_bytes());
+        data.extend(3i32.to_le_bytes());
+        for value in [1.0f64, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(7i32.to_le_bytes());
+        data.extend(b"frame.png\0");
+        data.extend(1u64.to_le_bytes());
+        data.extend(10.0f64.to_le_bytes());
+        data.extend(20.0f64.to_le_bytes());
+        data.extend(42i64.to_le_bytes());
+        data
+    }
+
+    // A point cloud without any points.
+    pub(crate) fn empty_points_bin() -> Vec<u8> {
+        0u64.to_le_bytes().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::{cameras_bin, images_bin};
+    use futures::executor::block_on;
+
+    fn reader(data: impl Into<Vec<u8>>) -> Reader {
+        BufReader::new(Box::new(std::io::Cursor::new(data.into())))
+    }
+
+    fn parse_txt(parser: impl Parseable, text: &str) -> Result<InputData, ParseError> {
+        block_on(parser.parse_txt(reader(text)))
+    }
+
+    fn parse_bin(parser: impl Parseable, data: &[u8]) -> Result<InputData, ParseError> {
+        block_on(parser.parse_bin(reader(data)))
+    }
+
+    #[test]
+    fn parses_binary_fixtures() {
+        let cameras = parse_bin(CamerasParser, &cameras_bin()).unwrap().as_cameras().unwrap();
+        assert_eq!(cameras[&7].width, 640);
+        assert_eq!(cameras[&7].params, vec![500.0, 500.0, 320.0, 240.0]);
+
+        let images = parse_bin(ImagesParser, &images_bin()).unwrap().as_images().unwrap();
+        assert_eq!(images[&3].name, "frame.png");
+        assert_eq!(images[&3].camera_id, 7);
+        assert_eq!(images[&3].point3d_ids, vec![42]);
+    }
+
+    #[test]
+    fn empty_binary_file_is_truncated() {
+        let err = parse_bin(CamerasParser, &[]).unwrap_err();
+        assert_eq!(err.location, FileLocation::Offset(0));
+        assert_eq!(err.record, "header");
+        assert_eq!(err.reason, "file is truncated");
+    }
+
+    #[test]
+    fn truncated_binary_camera() {
+        let data = cameras_bin();
+        // Cut off halfway through the last parameter.
+        let err = parse_bin(CamerasParser, &data[..data.len() - 4]).unwrap_err();
+        assert_eq!(err.location, FileLocation::Offset(56));
+        assert_eq!(err.record, "camera 7");
+        assert_eq!(err.reason, "file is truncated");
+    }
+
+    #[test]
+    fn truncated_binary_image_name() {
+        let data = images_bin();
+        let err = parse_bin(ImagesParser, &data[..78]).unwrap_err();
+        assert_eq!(err.location, FileLocation::Offset(72));
+        assert_eq!(err.record, "image 3");
+        assert_eq!(err.reason, "file is truncated");
+    }
+
+    #[test]
+    fn truncated_binary_point_count() {
+        // The header promises two points but the file ends after the first.
+        let mut data = 2u64.to_le_bytes().to_vec();
+        data.extend(5i64.to_le_bytes());
+        for value in [0.0f64, 1.0, 2.0] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend([255, 0, 0]);
+        data.extend(0.5f64.to_le_bytes());
+        data.extend(0u64.to_le_bytes());
+
+        let err = parse_bin(PointsParser, &data).unwrap_err();
+        assert_eq!(err.location, FileLocation::Offset(data.len() as u64));
+        assert_eq!(err.record, "point #1");
+    }
+
+    #[test]
+    fn unknown_binary_camera_model() {
+        let mut data = cameras_bin();
+        data[12..16].copy_from_slice(&99i32.to_le_bytes());
+        let err = parse_bin(CamerasParser, &data).unwrap_err();
+        assert_eq!(err.location, FileLocation::Offset(16));
+        assert_eq!(err.reason, "unknown camera model id 99");
+    }
+
+    #[test]
+    fn parses_text_fixtures() {
+        let cameras = "# Camera list\n1 PINHOLE 640 480 500 500 320 240\n\n";
+        let cameras = parse_txt(CamerasParser, cameras).unwrap().as_cameras().unwrap();
+        assert_eq!(cameras[&1].height, 480);
+
+        let images = "# Image list\n2 1 0 0 0 0.5 0.5 0.5 1 frame.png\n10 20 -1 30 40 5\n3 1 0 0 0 0 0 0 1 other.png\n";
+        let images = parse_txt(ImagesParser, images).unwrap().as_images().unwrap();
+        assert_eq!(images[&2].point3d_ids, vec![-1, 5]);
+        assert!(images[&3].xys.is_empty());
+
+        let points = "1 0.5 0.5 0.5 255 128 0 0.1 2 0 3 1\n";
+        let points = parse_txt(PointsParser, points).unwrap().as_points().unwrap();
+        assert_eq!(points[&1].image_ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn bad_text_camera_value() {
+        let text = "# Camera list\n1 PINHOLE 640 480 500 500 320 240\n2 PINHOLE 640 abc 500 500 320 240\n";
+        let err = parse_txt(CamerasParser, text).unwrap_err();
+        assert_eq!(err.location, FileLocation::Line(3));
+        assert_eq!(err.record, "`2 PINHOLE 640 abc 500 500 320 240`");
+        assert_eq!(err.reason, "invalid height `abc`");
+    }
+
+    #[test]
+    fn bad_text_camera_fields() {
+        let err = parse_txt(CamerasParser, "1 PINHOLE 640\n").unwrap_err();
+        assert_eq!(err.location, FileLocation::Line(1));
+        assert_eq!(err.reason, "missing height");
+
+        let err = parse_txt(CamerasParser, "1 PINHOLE 640 480 500\n").unwrap_err();
+        assert_eq!(err.reason, "PINHOLE expects 4 parameters, got 1");
+
+        let err = parse_txt(CamerasParser, "1 FISHEYE 640 480 500\n").unwrap_err();
+        assert_eq!(err.reason, "unknown camera model `FISHEYE`");
+    }
+
+    #[test]
+    fn bad_text_image_lines() {
+        let err = parse_txt(ImagesParser, "2 1 0 0 0 0.5 0.5 0.5 1\n\n").unwrap_err();
+        assert_eq!(err.location, FileLocation::Line(1));
+        assert_eq!(err.reason, "missing image name");
+
+        // An incomplete 2D point on the second line of the image.
+        let err = parse_txt(ImagesParser, "# Images\n2 1 0 0 0 0.5 0.5 0.5 1 frame.png\n10 20 -1 30 40\n").unwrap_err();
+        assert_eq!(err.location, FileLocation::Line(3));
+        assert_eq!(err.reason, "missing point 3D id");
+    }
+
+    #[test]
+    fn bad_text_point_line() {
+        let err = parse_txt(PointsParser, "1 0.5 0.5 0.5 300 128 0 0.1\n").unwrap_err();
+        assert_eq!(err.location, FileLocation::Line(1));
+        assert_eq!(err.reason, "invalid red `300`");
+    }
+
+    #[test]
+    fn long_records_are_shortened() {
+        let line = format!("1 0.5 0.5 0.5 255 128 0 0.1{}", " 1 2".repeat(100) + " 3");
+        let err = parse_txt(PointsParser, &line).unwrap_err();
+        assert_eq!(err.reason, "missing track point index");
+        assert!(err.record.ends_with("...`"));
+        assert!(err.record.len() < 100);
+    }
+}
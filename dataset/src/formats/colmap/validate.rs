@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+use std::io::Cursor;
+use image::{ImageReader, ImageResult};
+use tokio::io::AsyncReadExt;
+use scene_source::Filesystem;
+use crate::formats::colmap::camera::CameraModel;
+use crate::formats::colmap::input::InputType;
+use crate::formats::colmap::{find_mask_and_img, find_model_files, find_points_file, read_input};
+use crate::validation::ValidationReport;
+
+/// Checks that the reconstruction parses and that every image it references can be loaded.
+pub async fn validate(fs: &Filesystem, report: &mut ValidationReport) {
+    let Some((cam_path, img_path, is_bin)) = find_model_files(fs) else {
+        report.error("No COLMAP reconstruction found, expected a cameras.bin or cameras.txt file");
+        return;
+    };
+    report.format = Some(String::from(if is_bin { "COLMAP (binary)" } else { "COLMAP (text)" }));
+
+    let cameras = match read_input(fs, &cam_path, InputType::Cameras, is_bin).await {
+        Ok(data) => data.as_cameras().unwrap_or_default(),
+        Err(err) => {
+            report.error(err.to_string());
+            return;
+        }
+    };
+
+    let models: BTreeSet<String> = cameras.values().map(|c| format!("{:?}", c.model)).collect();
+    report.camera_models = models.into_iter().collect();
+    for camera in cameras.values() {
+        if !matches!(camera.model, CameraModel::SimplePinhole | CameraModel::Pinhole) {
+            report.warn(format!(
+                "Camera {} uses the {:?} model, its distortion parameters are ignored", camera.id, camera.model
+            ));
+        }
+    }
+
+    let images = match read_input(fs, &img_path, InputType::Images, is_bin).await {
+        Ok(data) => data.as_images().unwrap_or_default(),
+        Err(err) => {
+            report.error(err.to_string());
+            return;
+        }
+    };
+    if images.is_empty() {
+        report.error(format!("{} doesn't contain any images", img_path.display()));
+    }
+
+    let mut images: Vec<_> = images.into_values().collect();
+    images.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut mismatched_size = 0;
+    for image in &images {
+        let Some(camera) = cameras.get(&image.camera_id) else {
+            report.error(format!("Image {} references camera {} which doesn't exist", image.name, image.camera_id));
+            continue;
+        };
+
+        let Some((path, _mask)) = find_mask_and_img(fs, &image.name) else {
+            report.warn(format!("Image {} not found, it will be skipped", image.name));
+            continue;
+        };
+
+        let mut bytes = vec![];
+        let read = match fs.reader_at_path(&path).await {
+            Ok(mut file) => file.read_to_end(&mut bytes).await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = read {
+            report.error(format!("Failed to read image {}: {err}", path.display()));
+            continue;
+        }
+        match image_dimensions(&bytes) {
+            Ok((width, height)) => {
+                let size = glam::uvec2(width, height);
+                if size.x as u64 != camera.width || size.y as u64 != camera.height {
+                    mismatched_size += 1;
+                }
+                report.add_image(size);
+            }
+            Err(err) => report.error(format!("Image {} could not be decoded: {err}", path.display())),
+        }
+    }
+
+    if !images.is_empty() && report.image_count == 0 {
+        report.error("None of the images referenced by the reconstruction could be loaded");
+    }
+    if mismatched_size > 0 {
+        report.warn(format!("{mismatched_size} images don't have the resolution of their camera"));
+    }
+
+    let Some(points_path) = find_points_file(fs) else {
+        report.error("No points3D.bin or points3D.txt file found");
+        return;
+    };
+    let is_bin = points_path.extension().is_some_and(|ext| ext == "bin");
+    match read_input(fs, &points_path, InputType::Points3D, is_bin).await {
+        Ok(points) => {
+            report.point_count = points.as_points().map_or(0, |p| p.len());
+            if report.point_count == 0 {
+                report.warn("The sparse point cloud is empty, training starts from random splats");
+            }
+        }
+        Err(err) => report.error(err.to_string()),
+    }
+}
+
+// Only the header is decoded, fully decoding thousands of images would make validation very slow.
+fn image_dimensions(bytes: &[u8]) -> ImageResult<(u32, u32)> {
+    ImageReader::new(Cursor::new(bytes)).with_guessed_format()?.into_dimensions()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::colmap::parse::fixtures::{cameras_bin, empty_points_bin, images_bin};
+
+    async fn validate_files(files: &[(&str, Vec<u8>)]) -> ValidationReport {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        for (name, contents) in files {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().expect("File has a parent")).expect("Failed to create dir");
+            std::fs::write(path, contents).expect("Failed to write file");
+        }
+        let fs = Filesystem::from_dir(dir.path()).await.expect("Failed to read dir");
+        let mut report = ValidationReport::default();
+        validate(&fs, &mut report).await;
+        report.finish()
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = vec![];
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .expect("Failed to encode image");
+        bytes
+    }
+
+    fn reconstruction(image: Option<Vec<u8>>) -> Vec<(&'static str, Vec<u8>)> {
+        let mut files = vec![
+            ("sparse/0/cameras.bin", cameras_bin()),
+            ("sparse/0/images.bin", images_bin()),
+            ("sparse/0/points3D.bin", empty_points_bin()),
+        ];
+        if let Some(image) = image {
+            files.push(("images/frame.png", image));
+        }
+        files
+    }
+
+    #[tokio::test]
+    async fn valid_reconstruction() {
+        let report = validate_files(&reconstruction(Some(png(640, 480)))).await;
+        assert!(report.is_valid(), "Unexpected errors: {:?}", report.errors);
+        assert_eq!(report.format.as_deref(), Some("COLMAP (binary)"));
+        assert_eq!(report.image_count, 1);
+        assert_eq!(report.max_resolution, Some(glam::uvec2(640, 480)));
+        assert_eq!(report.camera_models, vec![String::from("Pinhole")]);
+    }
+
+    #[tokio::test]
+    async fn missing_cameras_file() {
+        let files: Vec<_> = reconstruction(Some(png(640, 480)))
+            .into_iter()
+            .filter(|(name, _)| !name.ends_with("cameras.bin"))
+            .collect();
+        let report = validate_files(&files).await;
+        assert!(!report.is_valid(), "A reconstruction needs cameras");
+        assert!(report.errors[0].contains("No COLMAP reconstruction found"), "Got {:?}", report.errors);
+        assert_eq!(report.format, None);
+    }
+
+    #[tokio::test]
+    async fn unknown_camera_id() {
+        let mut files = reconstruction(Some(png(640, 480)));
+        // The image references camera 7, renumber the only camera.
+        files[0].1[8..12].copy_from_slice(&9i32.to_le_bytes());
+        let report = validate_files(&files).await;
+        assert!(
+            report.errors.iter().any(|e| e.contains("references camera 7 which doesn't exist")),
+            "Got {:?}", report.errors
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_image() {
+        let report = validate_files(&reconstruction(None)).await;
+        assert!(report.warnings.iter().any(|w| w.contains("frame.png not found")), "Got {:?}", report.warnings);
+        assert!(
+            report.errors.iter().any(|e| e.contains("None of the images")),
+            "A reconstruction without any loadable image can't be trained on"
+        );
+    }
+
+    #[tokio::test]
+    async fn undecodable_image() {
+        let report = validate_files(&reconstruction(Some(b"not an image".to_vec()))).await;
+        assert!(report.errors.iter().any(|e| e.contains("could not be decoded")), "Got {:?}", report.errors);
+        assert_eq!(report.image_count, 0);
+    }
+
+    #[tokio::test]
+    async fn mismatched_image_size() {
+        let report = validate_files(&reconstruction(Some(png(320, 240)))).await;
+        assert!(report.is_valid(), "A different resolution is only a warning");
+        assert!(
+            report.warnings.iter().any(|w| w.contains("don't have the resolution of their camera")),
+            "Got {:?}", report.warnings
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_point_cloud() {
+        let report = validate_files(&reconstruction(Some(png(640, 480)))).await;
+        assert_eq!(report.point_count, 0);
+        assert!(report.warnings.iter().any(|w| w.contains("sparse point cloud is empty")), "Got {:?}", report.warnings);
+
+        let files: Vec<_> = reconstruction(Some(png(640, 480)))
+            .into_iter()
+            .filter(|(name, _)| !name.ends_with("points3D.bin"))
+            .collect();
+        let report = validate_files(&files).await;
+        assert!(report.errors.iter().any(|e| e.contains("No points3D.bin")), "Got {:?}", report.errors);
+    }
+}
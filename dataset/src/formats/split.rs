@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use std::path::Path;
+use rand::SeedableRng;
+use tokio::io::AsyncReadExt;
+use scene_source::Filesystem;
+use crate::config::EvalSplit;
+use crate::error::FormatError;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Assignment {
+    Train,
+    Eval,
+    /// Not part of either set, e.g. missing from an explicit train list.
+    Skip,
+}
+
+/// Assigns each image, by its name in the reconstruction, to the train or eval set.
+pub(crate) async fn assign(split: &EvalSplit, fs: &Filesystem, names: &[&str]) -> Result<Vec<Assignment>, FormatError> {
+    let eval_if = |is_eval: bool| if is_eval { Assignment::Eval } else { Assignment::Train };
+
+    let assignments = match split {
+        EvalSplit::None => vec![Assignment::Train; names.len()],
+        EvalSplit::EveryNth { every: 0 } => {
+            return Err(FormatError::InvalidSplit(String::from("every nth image needs n > 0")));
+        }
+        EvalSplit::EveryNth { every } => (0..names.len()).map(|i| eval_if(i % every == 0)).collect(),
+        EvalSplit::List { test_file, train_file } => {
+            let test = read_list(fs, test_file).await?;
+            let train = match train_file {
+                Some(train_file) => Some(read_list(fs, train_file).await?),
+                None => None,
+            };
+            names
+                .iter()
+                .map(|name| {
+                    if list_contains(&test, name) {
+                        Assignment::Eval
+                    } else if train.as_ref().is_none_or(|train| list_contains(train, name)) {
+                        Assignment::Train
+                    } else {
+                        Assignment::Skip
+                    }
+                })
+                .collect()
+        }
+        EvalSplit::Random { seed, fraction } => {
+            if !(0.0..=1.0).contains(fraction) {
+                return Err(FormatError::InvalidSplit(format!("random fraction {fraction} isn't between 0 and 1")));
+            }
+            let count = (names.len() as f32 * fraction).round() as usize;
+            let mut rng = rand::rngs::StdRng::seed_from_u64(*seed);
+            let mut assignments = vec![Assignment::Train; names.len()];
+            for i in rand::seq::index::sample(&mut rng, names.len(), count) {
+                assignments[i] = Assignment::Eval;
+            }
+            assignments
+        }
+        EvalSplit::Glob { pattern } => {
+            let pattern = glob::Pattern::new(pattern)
+                .map_err(|e| FormatError::InvalidSplit(format!("bad glob `{pattern}`: {e}")))?;
+            names.iter().map(|name| eval_if(pattern.matches(name))).collect()
+        }
+    };
+    Ok(assignments)
+}
+
+/// Reads a list of image names, ignoring empty lines and comments. The list is found by its exact
+/// file name, which may include the directories it is in.
+async fn read_list(fs: &Filesystem, file_name: &str) -> Result<HashSet<String>, FormatError> {
+    let mut candidates = fs.lookup.values().filter(|path| path.ends_with(file_name));
+    let Some(path) = candidates.next().cloned() else {
+        return Err(FormatError::MissingFile(file_name.to_owned()));
+    };
+    if candidates.next().is_some() {
+        return Err(FormatError::InvalidSplit(format!(
+            "more than one file is named {file_name}, include its directory"
+        )));
+    }
+    let mut contents = String::new();
+    let read = match fs.reader_at_path(&path).await {
+        Ok(mut reader) => reader.read_to_string(&mut contents).await.map(|_| ()),
+        Err(err) => Err(err),
+    };
+    read.map_err(|source| FormatError::Read { path, source })?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+// Lists differ in whether they include directories, so also accept a match on the file name.
+fn list_contains(list: &HashSet<String>, name: &str) -> bool {
+    list.contains(name) || Path::new(name).file_name().and_then(|n| n.to_str()).is_some_and(|n| list.contains(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn filesystem(files: &[(&str, &str)]) -> (tempfile::TempDir, Filesystem) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        for (name, contents) in files {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().expect("File has a parent")).expect("Failed to create dir");
+            std::fs::write(path, contents).expect("Failed to write file");
+        }
+        let fs = Filesystem::from_dir(dir.path()).await.expect("Failed to read dir");
+        (dir, fs)
+    }
+
+    fn eval_indices(assignments: &[Assignment]) -> Vec<usize> {
+        assignments.iter().enumerate().filter(|(_, a)| **a == Assignment::Eval).map(|(i, _)| i).collect()
+    }
+
+    const NAMES: [&str; 8] = [
+        "images/a.png", "images/b.png", "images/c.png", "images/d.png",
+        "images/e.png", "images/f.png", "images/g_eval.png", "images/h_eval.png",
+    ];
+
+    #[tokio::test]
+    async fn every_nth() {
+        let (_dir, fs) = filesystem(&[]).await;
+
+        let assignments = assign(&EvalSplit::EveryNth { every: 3 }, &fs, &NAMES).await.expect("Valid split");
+        assert_eq!(eval_indices(&assignments), vec![0, 3, 6]);
+        assert!(!assignments.contains(&Assignment::Skip), "Every image is either train or eval");
+
+        assert!(assign(&EvalSplit::EveryNth { every: 0 }, &fs, &NAMES).await.is_err(), "n = 0 is invalid");
+    }
+
+    #[tokio::test]
+    async fn random_is_seeded() {
+        let (_dir, fs) = filesystem(&[]).await;
+        let split = |seed| EvalSplit::Random { seed, fraction: 0.25 };
+
+        let first = assign(&split(7), &fs, &NAMES).await.expect("Valid split");
+        assert_eq!(eval_indices(&first).len(), 2);
+        let again = assign(&split(7), &fs, &NAMES).await.expect("Valid split");
+        assert_eq!(first, again, "The same seed should give the same split");
+
+        let invalid = EvalSplit::Random { seed: 7, fraction: 1.5 };
+        assert!(assign(&invalid, &fs, &NAMES).await.is_err(), "Fractions above 1 are invalid");
+    }
+
+    #[tokio::test]
+    async fn glob_matches_names() {
+        let (_dir, fs) = filesystem(&[]).await;
+
+        let split = EvalSplit::Glob { pattern: String::from("*_eval.png") };
+        let assignments = assign(&split, &fs, &NAMES).await.expect("Valid split");
+        assert_eq!(eval_indices(&assignments), vec![6, 7]);
+
+        let invalid = EvalSplit::Glob { pattern: String::from("[") };
+        assert!(assign(&invalid, &fs, &NAMES).await.is_err(), "Invalid patterns are an error");
+    }
+
+    #[tokio::test]
+    async fn lists_assign_eval_train_and_skip() {
+        let (_dir, fs) = filesystem(&[
+            ("test.txt", "# Held out\nimages/a.png\n\nb.png\n"),
+            ("train.txt", "c.png\nimages/d.png\n"),
+            // Ends in the name of the test list, but isn't it.
+            ("my_test.txt", "e.png\n"),
+        ])
+        .await;
+
+        let split = EvalSplit::List { test_file: String::from("test.txt"), train_file: Some(String::from("train.txt")) };
+        let assignments = assign(&split, &fs, &NAMES).await.expect("Valid split");
+        use Assignment::{Eval, Skip, Train};
+        assert_eq!(assignments, vec![Eval, Eval, Train, Train, Skip, Skip, Skip, Skip]);
+
+        // Without a train list everything else is trained on.
+        let split = EvalSplit::List { test_file: String::from("test.txt"), train_file: None };
+        let assignments = assign(&split, &fs, &NAMES).await.expect("Valid split");
+        assert_eq!(eval_indices(&assignments), vec![0, 1]);
+        assert!(!assignments.contains(&Skip), "Nothing is skipped without a train list");
+
+        let missing = EvalSplit::List { test_file: String::from("missing.txt"), train_file: None };
+        assert!(matches!(assign(&missing, &fs, &NAMES).await, Err(FormatError::MissingFile(_))));
+    }
+
+    #[tokio::test]
+    async fn ambiguous_list_names_are_an_error() {
+        let (_dir, fs) = filesystem(&[("a/test.txt", "a.png"), ("b/test.txt", "b.png")]).await;
+
+        let split = EvalSplit::List { test_file: String::from("test.txt"), train_file: None };
+        assert!(assign(&split, &fs, &NAMES).await.is_err(), "Two lists match the name");
+
+        let split = EvalSplit::List { test_file: String::from("b/test.txt"), train_file: None };
+        let assignments = assign(&split, &fs, &NAMES).await.expect("Directory picks the list");
+        assert_eq!(eval_indices(&assignments), vec![1]);
+    }
+
+    #[test]
+    fn list_contains_full_paths_and_file_names() {
+        let list: HashSet<String> = ["images/a.png", "b.png"].into_iter().map(str::to_owned).collect();
+
+        assert!(list_contains(&list, "images/a.png"));
+        assert!(list_contains(&list, "images/b.png"), "A file name matches in any directory");
+        assert!(list_contains(&list, "b.png"));
+        assert!(!list_contains(&list, "a.png"), "A path doesn't match a bare file name");
+        assert!(!list_contains(&list, "other/c.png"));
+    }
+}
@@ -2,24 +2,35 @@
 #![recursion_limit = "512"]
 
 mod config;
+pub mod color;
 mod formats;
 pub mod scene;
 pub mod error;
+mod validation;
+mod transform;
 
 use crate::scene::{Scene};
-use glam::{Mat3, Mat4, Vec3};
-pub use formats::load_dataset;
-pub use config::LoadConfig;
-pub use scene::{SceneView, SceneLoader, view_to_sample_image, sample_to_tensor};
+use glam::{Mat3, Mat4, Quat, Vec3};
+pub use formats::{load_dataset, load_layout, validate_dataset};
+pub use validation::ValidationReport;
+pub use transform::SceneTransform;
+pub use config::{ColorSpace, EvalSplit, ImageCacheConfig, LoadConfig, LoaderConfig, ViewSampling};
+pub use scene::{SceneView, SceneLoader, SparsePoint, view_to_sample_image, sample_to_tensor};
 
 #[derive(Clone)]
 pub struct Dataset {
     pub train: Scene,
     pub eval: Option<Scene>,
+    /// Maps the original coordinates of the dataset to the coordinates of the views.
+    pub transform: SceneTransform,
+    /// How the eval views were selected.
+    pub eval_split: EvalSplit,
+    /// Color space the views are trained in, never `Auto`.
+    pub color_space: ColorSpace,
 }
 
 impl Dataset {
-    pub fn from_views(train_views: Vec<SceneView>, eval_views: Vec<SceneView>) -> Self {
+    pub fn from_views(train_views: Vec<SceneView>, eval_views: Vec<SceneView>, eval_split: EvalSplit) -> Self {
         Self {
             train: Scene::new(train_views),
             eval: if eval_views.is_empty() {
@@ -27,9 +38,53 @@ impl Dataset {
             } else {
                 Some(Scene::new(eval_views))
             },
+            transform: SceneTransform::IDENTITY,
+            eval_split,
+            color_space: ColorSpace::Srgb,
         }
     }
 
+    /// Resolves the color space from the config, see [`ColorSpace::resolve`].
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        let has_float_images = self.train.views.iter().any(|view| view.image.is_float());
+        self.color_space = color_space.resolve(has_float_images);
+        self
+    }
+
+    /// Moves the views into a canonical frame: centered on the mean camera position, scaled so
+    /// all cameras are within a unit sphere, and with the estimated up direction along +Y.
+    pub fn normalize(&mut self) {
+        let up = self.estimate_up();
+        let rotation = if up.is_finite() && up.length_squared() > 0.0 {
+            Quat::from_rotation_arc(up.normalize(), Vec3::Y)
+        } else {
+            Quat::IDENTITY
+        };
+
+        let positions: Vec<Vec3> = self
+            .train
+            .views
+            .iter()
+            .chain(self.eval.iter().flat_map(|e| e.views.as_slice()))
+            .map(|v| rotation * v.camera.position)
+            .collect();
+        if positions.is_empty() {
+            return;
+        }
+        let center = positions.iter().sum::<Vec3>() / positions.len() as f32;
+        let radius = positions.iter().map(|p| p.distance(center)).fold(0.0, f32::max);
+        let scale = if radius > f32::EPSILON { 1.0 / radius } else { 1.0 };
+
+        let transform = SceneTransform {
+            rotation,
+            translation: -scale * center,
+            scale,
+        };
+        self.train = self.train.transformed(&transform);
+        self.eval = self.eval.as_ref().map(|eval| eval.transformed(&transform));
+        self.transform = self.transform.then(&transform);
+    }
+
     pub fn estimate_up(&self) -> Vec3 {
         // based on https://github.com/jonbarron/camp_zipnerf/blob/8e6d57e3aee34235faf3ef99decca0994efe66c9/camp_zipnerf/internal/camera_utils.py#L233
         let (c2ws, ts): (Vec<_>, Vec<_>) = self
@@ -3,10 +3,15 @@ use ::image::DynamicImage;
 use burn::prelude::{Backend, Tensor, TensorData};
 use glam::{vec3, Affine3A, Vec3};
 pub(crate) use crate::scene::image::ImageFile;
+use crate::color;
+use crate::config::ColorSpace;
+use crate::SceneTransform;
 
 mod image;
 pub mod splat;
 mod loader;
+mod cache;
+mod sampler;
 
 pub use loader::SceneLoader;
 use render::bounding_box::BoundingBox;
@@ -18,6 +23,13 @@ pub struct SceneView {
     pub camera: Camera,
 }
 
+/// A point of the sparse reconstruction that accompanies the dataset.
+#[derive(Clone, Debug)]
+pub struct SparsePoint {
+    pub position: Vec3,
+    pub color: [u8; 3],
+}
+
 #[derive(Clone)]
 pub struct Scene {
     pub views: Arc<Vec<SceneView>>,
@@ -30,6 +42,19 @@ impl Scene {
         }
     }
 
+    /// Returns a copy of the scene with all cameras moved by the transform.
+    pub fn transformed(&self, transform: &SceneTransform) -> Self {
+        let views = self
+            .views
+            .iter()
+            .map(|view| SceneView {
+                image: view.image.clone(),
+                camera: transform.transform_camera(&view.camera),
+            })
+            .collect();
+        Self::new(views)
+    }
+
     // Returns the extent of the cameras in the scene.
     pub fn bounds(&self) -> BoundingBox {
         self.adjusted_bounds(0.0, 0.0)
@@ -97,6 +122,8 @@ fn find_two_smallest(v: Vec3) -> (f32, f32) {
 
 #[derive(Clone, Debug)]
 pub struct SceneBatch<B: Backend> {
+    /// Index of the view in the scene this batch was made from.
+    pub view_index: usize,
     pub img_tensor: Tensor<B, 3>,
     pub alpha_is_mask: bool,
     pub camera: Camera,
@@ -108,10 +135,36 @@ impl<B: Backend> SceneBatch<B> {
     }
 }
 
-// Converts an image to a train sample. The tensor will be a floating point image with a [0, 1] image.
+// Converts an image to a train sample in the given color space. The tensor will be a floating point
+// image with a [0, 1] image, except for float images trained in linear space which keep their range.
 //
 // This assume the input image has un-premultiplied alpha, whereas the output has pre-multiplied alpha.
-pub fn view_to_sample_image(image: DynamicImage, alpha_is_mask: bool) -> DynamicImage {
+pub fn view_to_sample_image(image: DynamicImage, alpha_is_mask: bool, color_space: ColorSpace) -> DynamicImage {
+    let is_float = matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_));
+    match color_space {
+        ColorSpace::Linear => linear_sample_image(image, alpha_is_mask, is_float),
+        // Encode float images to sRGB, afterwards they're treated like any other image.
+        _ if is_float => {
+            let has_alpha = image.color().has_alpha();
+            let mut image = image.into_rgba32f();
+            for pixel in image.pixels_mut() {
+                for c in &mut pixel.0[..3] {
+                    *c = color::linear_to_srgb(*c);
+                }
+            }
+            let image = DynamicImage::ImageRgba32F(image);
+            let image = if has_alpha {
+                DynamicImage::ImageRgba16(image.into_rgba16())
+            } else {
+                DynamicImage::ImageRgb16(image.into_rgb16())
+            };
+            srgb_sample_image(image, alpha_is_mask)
+        }
+        _ => srgb_sample_image(image, alpha_is_mask),
+    }
+}
+
+fn srgb_sample_image(image: DynamicImage, alpha_is_mask: bool) -> DynamicImage {
     if image.color().has_alpha() && !alpha_is_mask {
         let mut rgba_bytes = image.to_rgba8();
 
@@ -134,6 +187,57 @@ pub fn view_to_sample_image(image: DynamicImage, alpha_is_mask: bool) -> Dynamic
     }
 }
 
+// Linear samples of integer images are stored in 16 bits, which is plenty for values in [0, 1]
+// and half the memory of floats. Float images stay floats to keep values above 1.
+fn linear_sample_image(image: DynamicImage, alpha_is_mask: bool, is_float: bool) -> DynamicImage {
+    let has_alpha = image.color().has_alpha();
+    let mut linear = match &image {
+        DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) | DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_) => {
+            // Avoids powf for every pixel of the most common images.
+            let rgba = image.to_rgba8();
+            let pixels = rgba
+                .pixels()
+                .flat_map(|p| {
+                    let [r, g, b, a] = p.0;
+                    [color::srgb8_to_linear(r), color::srgb8_to_linear(g), color::srgb8_to_linear(b), a as f32 / 255.0]
+                })
+                .collect();
+            ::image::Rgba32FImage::from_raw(rgba.width(), rgba.height(), pixels)
+                .expect("Pixel count matches the image size")
+        }
+        _ => {
+            let mut rgba = image.to_rgba32f();
+            if !is_float {
+                for pixel in rgba.pixels_mut() {
+                    for c in &mut pixel.0[..3] {
+                        *c = color::srgb_to_linear(*c);
+                    }
+                }
+            }
+            rgba
+        }
+    };
+
+    // Pre-multiply in linear space, where blending happens.
+    if has_alpha && !alpha_is_mask {
+        for pixel in linear.pixels_mut() {
+            let a = pixel.0[3];
+            for c in &mut pixel.0[..3] {
+                *c *= a;
+            }
+        }
+    }
+
+    let linear = DynamicImage::ImageRgba32F(linear);
+    match (is_float, has_alpha) {
+        (true, true) => linear,
+        (true, false) => DynamicImage::ImageRgb32F(linear.into_rgb32f()),
+        (false, true) => DynamicImage::ImageRgba16(linear.into_rgba16()),
+        (false, false) => DynamicImage::ImageRgb16(linear.into_rgb16()),
+    }
+}
+
 pub fn sample_to_tensor<B: Backend>(sample: &DynamicImage, device: &B::Device) -> Tensor<B, 3> {
     let (w, h) = (sample.width(), sample.height());
     let data = if sample.color().has_alpha() {
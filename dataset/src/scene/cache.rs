@@ -0,0 +1,451 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer};
+use sha2::{Digest, Sha256};
+use crate::config::{ColorSpace, ImageCacheConfig};
+use crate::scene::image::EncodedImage;
+use crate::scene::{view_to_sample_image, ImageFile, SceneView};
+
+/// Keeps the train samples of views around, so images don't have to be decoded every epoch.
+///
+/// Samples are kept in memory, and optionally on disk so later runs on the same scene can skip decoding too.
+pub(crate) struct ImageCache {
+    memory: Mutex<MemoryCache>,
+    scaled: Mutex<ScaledCache>,
+    disk: Option<DiskCache>,
+    color_space: ColorSpace,
+    // Disk keys of the views, so the source is only hashed once.
+    keys: Mutex<HashMap<usize, String>>,
+}
+
+impl ImageCache {
+    pub(crate) fn new(config: &ImageCacheConfig, color_space: ColorSpace) -> Self {
+        Self {
+            color_space,
+            memory: Mutex::new(MemoryCache::new(config.memory_mb * 1024 * 1024)),
+            // A downscaled sample is at most a quarter of the full sample.
+            scaled: Mutex::new(ScaledCache::new(config.memory_mb * 1024 * 1024 / 4)),
+            disk: config.disk_path.as_ref().map(|path| DiskCache::new(path.into(), config.disk_mb * 1024 * 1024)),
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the train sample of the view at `index`, decoding it only when no tier has it.
+    pub(crate) async fn get_or_load(&self, index: usize, view: &SceneView) -> image::ImageResult<Arc<DynamicImage>> {
+        if let Some(sample) = self.memory.lock().unwrap().get(index) {
+            return Ok(sample);
+        }
+
+        let sample = match &self.disk {
+            Some(disk) => self.load_through_disk(disk, index, &view.image).await?,
+            None => view_to_sample_image(view.image.load().await?, view.image.is_masked(), self.color_space),
+        };
+        let sample = Arc::new(sample);
+        self.memory.lock().unwrap().insert(index, sample.clone());
+        Ok(sample)
+    }
+
+    /// Returns the train sample of the view at `index` downscaled by `factor`. Samples are only resized
+    /// once per factor.
+    pub(crate) async fn get_or_load_scaled(
+        &self,
+        index: usize,
+        view: &SceneView,
+        factor: u32,
+    ) -> image::ImageResult<Arc<DynamicImage>> {
+        if factor <= 1 {
+            return self.get_or_load(index, view).await;
+        }
+        if let Some(sample) = self.scaled.lock().unwrap().get(index, factor) {
+            return Ok(sample);
+        }
+
+        let sample = Arc::new(downscale_sample(&*self.get_or_load(index, view).await?, factor));
+        self.scaled.lock().unwrap().insert(index, factor, sample.clone());
+        Ok(sample)
+    }
+
+    async fn load_through_disk(&self, disk: &DiskCache, index: usize, image: &ImageFile) -> image::ImageResult<DynamicImage> {
+        let known_key = self.keys.lock().unwrap().get(&index).cloned();
+        let (key, mut bytes) = match known_key {
+            Some(key) => (key, None),
+            None => {
+                let bytes = image.read_bytes().await?;
+                let key = cache_key(image, &bytes, self.color_space);
+                self.keys.lock().unwrap().insert(index, key.clone());
+                (key, Some(bytes))
+            }
+        };
+
+        if let Some(sample) = disk.get(&key).await {
+            return Ok(sample);
+        }
+
+        let bytes = match bytes.take() {
+            Some(bytes) => bytes,
+            None => image.read_bytes().await?,
+        };
+        let sample = view_to_sample_image(image.decode(&bytes)?, image.is_masked(), self.color_space);
+        if let Err(err) = disk.put(&key, &sample).await {
+            log::warn!("Failed to write {} to the image cache: {err}", image.path.display());
+        }
+        Ok(sample)
+    }
+}
+
+// Identifies a sample by everything that goes into it: the encoded image & mask, the size it is loaded at
+// and the color space it is converted to.
+fn cache_key(image: &ImageFile, bytes: &EncodedImage, color_space: ColorSpace) -> String {
+    const VERSION: &[u8] = b"sample-v2";
+
+    let mut hasher = Sha256::new();
+    hasher.update(VERSION);
+    let dim = image.dim();
+    hasher.update(dim.x.to_le_bytes());
+    hasher.update(dim.y.to_le_bytes());
+    hasher.update([image.is_masked() as u8]);
+    hasher.update(format!("{color_space:?}").as_bytes());
+    hasher.update((bytes.image.len() as u64).to_le_bytes());
+    hasher.update(&bytes.image);
+    if let Some(mask) = &bytes.mask {
+        hasher.update(mask);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Shrinks a sample by `factor`. The cameras don't depend on the resolution, only the image is scaled.
+fn downscale_sample(sample: &DynamicImage, factor: u32) -> DynamicImage {
+    let width = (sample.width() / factor).max(1);
+    let height = (sample.height() / factor).max(1);
+    sample.resize_exact(width, height, FilterType::Triangle)
+}
+
+/// Downscaled samples of a single factor. Training only moves to higher resolutions, so the
+/// samples of a previous factor are dropped when the factor changes.
+struct ScaledCache {
+    factor: u32,
+    memory: MemoryCache,
+}
+
+impl ScaledCache {
+    fn new(max_size: usize) -> Self {
+        Self {
+            factor: 1,
+            memory: MemoryCache::new(max_size),
+        }
+    }
+
+    fn get(&mut self, index: usize, factor: u32) -> Option<Arc<DynamicImage>> {
+        if factor != self.factor {
+            return None;
+        }
+        self.memory.get(index)
+    }
+
+    fn insert(&mut self, index: usize, factor: u32, image: Arc<DynamicImage>) {
+        if factor != self.factor {
+            self.factor = factor;
+            self.memory = MemoryCache::new(self.memory.max_size);
+        }
+        self.memory.insert(index, image);
+    }
+}
+
+struct MemoryCache {
+    entries: HashMap<usize, (Arc<DynamicImage>, u64)>,
+    // Time of last use -> index, the first entry is the least recently used.
+    recency: BTreeMap<u64, usize>,
+    clock: u64,
+    size: usize,
+    max_size: usize,
+}
+
+impl MemoryCache {
+    fn new(max_size: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            size: 0,
+            max_size,
+        }
+    }
+
+    fn get(&mut self, index: usize) -> Option<Arc<DynamicImage>> {
+        let (image, last_used) = self.entries.get_mut(&index)?;
+        self.recency.remove(last_used);
+        self.clock += 1;
+        *last_used = self.clock;
+        self.recency.insert(self.clock, index);
+        Some(image.clone())
+    }
+
+    fn insert(&mut self, index: usize, image: Arc<DynamicImage>) {
+        let size = image.as_bytes().len();
+        if size > self.max_size || self.entries.contains_key(&index) {
+            return;
+        }
+
+        while self.size + size > self.max_size {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = self.entries.remove(&oldest) {
+                self.size -= evicted.as_bytes().len();
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(index, (image, self.clock));
+        self.recency.insert(self.clock, index);
+        self.size += size;
+    }
+}
+
+const RAW_EXTENSION: &str = "raw";
+const RAW_MAGIC: &[u8; 4] = b"GSR1";
+
+/// Decoded samples stored as raw pixels, one file per sample. The modification time of a file is
+/// bumped when it is read, so the least recently used files are evicted first.
+struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+    // Bytes in use, counted when the first sample is written.
+    size: tokio::sync::Mutex<Option<u64>>,
+}
+
+impl DiskCache {
+    fn new(dir: PathBuf, max_size: u64) -> Self {
+        Self {
+            dir,
+            max_size,
+            size: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension(RAW_EXTENSION)
+    }
+
+    async fn get(&self, key: &str) -> Option<DynamicImage> {
+        let path = self.path(key);
+        let data = tokio::fs::read(&path).await.ok()?;
+        let Some(image) = decode_raw(&data) else {
+            log::warn!("Ignoring corrupt image cache entry {}", path.display());
+            let _ = tokio::fs::remove_file(&path).await;
+            return None;
+        };
+
+        // Failing to mark it as used only makes it a candidate for eviction sooner.
+        let _ = tokio::task::spawn_blocking(move || {
+            std::fs::File::options().append(true).open(&path)?.set_modified(SystemTime::now())
+        })
+        .await;
+        Some(image)
+    }
+
+    async fn put(&self, key: &str, image: &DynamicImage) -> io::Result<()> {
+        let Some(data) = encode_raw(image) else {
+            return Ok(());
+        };
+
+        let mut size = self.size.lock().await;
+        let used = match *size {
+            Some(used) => used,
+            None => {
+                tokio::fs::create_dir_all(&self.dir).await?;
+                self.entries().await?.iter().map(|e| e.1).sum()
+            }
+        };
+
+        // Write under a temporary name so a crash never leaves a partial entry behind.
+        let path = self.path(key);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let temp_path = self.dir.join(format!("{key}.{nanos}.tmp"));
+        tokio::fs::write(&temp_path, &data).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+
+        let mut used = used + data.len() as u64;
+        if used > self.max_size {
+            used = self.evict(used).await?;
+        }
+        *size = Some(used);
+        Ok(())
+    }
+
+    // Removes the least recently used entries until well below the limit, returns the new size.
+    async fn evict(&self, mut used: u64) -> io::Result<u64> {
+        let target = self.max_size / 10 * 9;
+        let mut entries = self.entries().await?;
+        entries.sort_by_key(|e| e.2);
+        for (path, len, _) in entries {
+            if used <= target {
+                break;
+            }
+            if tokio::fs::remove_file(&path).await.is_ok() {
+                used = used.saturating_sub(len);
+            }
+        }
+        Ok(used)
+    }
+
+    // Path, size & last use of every entry.
+    async fn entries(&self) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut entries = vec![];
+        let mut dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != RAW_EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            entries.push((path, metadata.len(), metadata.modified().unwrap_or(UNIX_EPOCH)));
+        }
+        Ok(entries)
+    }
+}
+
+// Layout: magic, width & height as u32, color type tag, then the pixels in native byte order.
+fn encode_raw(image: &DynamicImage) -> Option<Vec<u8>> {
+    let tag: u8 = match image {
+        DynamicImage::ImageLuma8(_) => 0,
+        DynamicImage::ImageLumaA8(_) => 1,
+        DynamicImage::ImageRgb8(_) => 2,
+        DynamicImage::ImageRgba8(_) => 3,
+        DynamicImage::ImageLuma16(_) => 4,
+        DynamicImage::ImageLumaA16(_) => 5,
+        DynamicImage::ImageRgb16(_) => 6,
+        DynamicImage::ImageRgba16(_) => 7,
+        DynamicImage::ImageRgb32F(_) => 8,
+        DynamicImage::ImageRgba32F(_) => 9,
+        _ => return None,
+    };
+
+    let pixels = image.as_bytes();
+    let mut data = Vec::with_capacity(13 + pixels.len());
+    data.extend_from_slice(RAW_MAGIC);
+    data.extend(image.width().to_le_bytes());
+    data.extend(image.height().to_le_bytes());
+    data.push(tag);
+    data.extend_from_slice(pixels);
+    Some(data)
+}
+
+fn decode_raw(data: &[u8]) -> Option<DynamicImage> {
+    let (header, pixels) = data.split_at_checked(13)?;
+    if &header[..4] != RAW_MAGIC {
+        return None;
+    }
+    let width = u32::from_le_bytes(header[4..8].try_into().ok()?);
+    let height = u32::from_le_bytes(header[8..12].try_into().ok()?);
+
+    let u16s = || pixels.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect::<Vec<_>>();
+    let f32s = || pixels.chunks_exact(4).map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect::<Vec<_>>();
+    // from_raw checks the buffer has the right length for the size.
+    match header[12] {
+        0 => ImageBuffer::from_raw(width, height, pixels.to_vec()).map(DynamicImage::ImageLuma8),
+        1 => ImageBuffer::from_raw(width, height, pixels.to_vec()).map(DynamicImage::ImageLumaA8),
+        2 => ImageBuffer::from_raw(width, height, pixels.to_vec()).map(DynamicImage::ImageRgb8),
+        3 => ImageBuffer::from_raw(width, height, pixels.to_vec()).map(DynamicImage::ImageRgba8),
+        4 => ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageLuma16),
+        5 => ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageLumaA16),
+        6 => ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageRgb16),
+        7 => ImageBuffer::from_raw(width, height, u16s()).map(DynamicImage::ImageRgba16),
+        8 => ImageBuffer::from_raw(width, height, f32s()).map(DynamicImage::ImageRgb32F),
+        9 => ImageBuffer::from_raw(width, height, f32s()).map(DynamicImage::ImageRgba32F),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb32FImage, RgbaImage};
+
+    fn rgba(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, image::Rgba([value, 2, 3, 4])))
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let hdr = Rgb32FImage::from_fn(3, 2, |x, y| image::Rgb([x as f32 * 0.5, y as f32, -1.5]));
+        for image in [rgba(5, 3, 1), DynamicImage::ImageRgb32F(hdr), rgba(5, 3, 1).into_luma16().into()] {
+            let data = encode_raw(&image).expect("Color type can be cached");
+            let decoded = decode_raw(&data).expect("Encoded data decodes");
+            assert_eq!(decoded, image);
+        }
+    }
+
+    #[test]
+    fn raw_rejects_bad_data() {
+        let mut data = encode_raw(&rgba(4, 4, 1)).expect("Color type can be cached");
+        assert!(decode_raw(&data[..data.len() - 1]).is_none(), "Truncated pixels");
+        assert!(decode_raw(&data[..8]).is_none(), "Truncated header");
+
+        data[..4].copy_from_slice(b"GSR0");
+        assert!(decode_raw(&data).is_none(), "Wrong magic");
+    }
+
+    #[test]
+    fn memory_cache_evicts_least_recently_used() {
+        let image = || Arc::new(rgba(4, 4, 1));
+        let image_size = image().as_bytes().len();
+        let mut cache = MemoryCache::new(image_size * 2);
+
+        cache.insert(0, image());
+        cache.insert(1, image());
+        // Using 0 makes 1 the least recently used.
+        assert!(cache.get(0).is_some());
+        cache.insert(2, image());
+
+        assert!(cache.get(1).is_none(), "Least recently used entry is evicted");
+        assert!(cache.get(0).is_some() && cache.get(2).is_some());
+        assert_eq!(cache.size, image_size * 2);
+
+        // Images larger than the whole cache are never kept.
+        cache.insert(3, Arc::new(rgba(8, 8, 1)));
+        assert!(cache.get(3).is_none());
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn scaled_cache_keeps_one_factor() {
+        let mut cache = ScaledCache::new(1024 * 1024);
+        let sample = Arc::new(downscale_sample(&rgba(9, 5, 1), 2));
+        assert_eq!((sample.width(), sample.height()), (4, 2));
+        let tiny = downscale_sample(&rgba(3, 3, 1), 4);
+        assert_eq!((tiny.width(), tiny.height()), (1, 1), "Samples keep at least one pixel");
+
+        cache.insert(0, 2, sample.clone());
+        assert!(cache.get(0, 2).is_some());
+        assert!(cache.get(0, 4).is_none(), "Other factors are resized separately");
+
+        // Moving to the next factor drops the old samples.
+        cache.insert(1, 4, sample);
+        assert!(cache.get(0, 2).is_none());
+        assert!(cache.get(1, 4).is_some());
+        assert_eq!(cache.memory.entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn disk_cache_round_trip_and_eviction() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let image = rgba(16, 16, 7);
+        let entry_size = encode_raw(&image).expect("Color type can be cached").len() as u64;
+        // Room for two entries, the third evicts.
+        let cache = DiskCache::new(dir.path().to_owned(), entry_size * 2 + entry_size / 2);
+
+        cache.put("a", &image).await.expect("Failed to write entry");
+        assert_eq!(cache.get("a").await, Some(image.clone()));
+        assert_eq!(cache.get("missing").await, None);
+
+        cache.put("b", &image).await.expect("Failed to write entry");
+        cache.put("c", &image).await.expect("Failed to write entry");
+        let remaining = cache.entries().await.expect("Failed to list entries").len();
+        assert_eq!(remaining, 2, "Writing over the limit should evict down to below the limit");
+    }
+}
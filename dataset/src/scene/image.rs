@@ -9,10 +9,18 @@ use image::{ColorType, DynamicImage, ImageError, ImageReader, ImageDecoder};
 use tokio::io::{AsyncRead, AsyncReadExt};
 use scene_source::Filesystem;
 
+/// The undecoded contents of an image file and its mask.
+pub struct EncodedImage {
+    pub image: Vec<u8>,
+    pub mask: Option<Vec<u8>>,
+}
+
 #[derive(Clone)]
 pub struct ImageFile {
     pub path: PathBuf,
     max_res: u32,
+    /// Factor the image is shrunk by after decoding, before limiting it to `max_res`.
+    downscale: u32,
     mask_path: Option<PathBuf>,
     size: UVec2,
     color_fmt: ColorType,
@@ -32,6 +40,7 @@ impl ImageFile {
         Ok(Self {
             path: path.to_path_buf(),
             max_res: max_resolution,
+            downscale: 1,
             mask_path,
             size: prelim.0,
             color_fmt: prelim.1,
@@ -39,20 +48,43 @@ impl ImageFile {
         })
     }
 
-    pub fn dim(&self) -> glam::UVec2 {
-        if self.size.x <= self.max_res && self.size.y <= self.max_res {
+    /// Shrinks the image by an integer factor when it is loaded, rounding the size like the
+    /// `images_N` folders of benchmark datasets do.
+    pub(crate) fn with_downscale(mut self, factor: u32) -> Self {
+        self.downscale = factor.max(1);
+        self
+    }
+
+    /// Size of the image after downscaling, before it is limited to the max resolution.
+    pub(crate) fn scaled_dim(&self) -> glam::UVec2 {
+        if self.downscale == 1 {
             self.size
+        } else {
+            let scale = |v: u32| ((f64::from(v) / f64::from(self.downscale)).round() as u32).max(1);
+            glam::uvec2(scale(self.size.x), scale(self.size.y))
+        }
+    }
+
+    pub fn dim(&self) -> glam::UVec2 {
+        let size = self.scaled_dim();
+        if size.x <= self.max_res && size.y <= self.max_res {
+            size
         } else {
             // Take from image crate, just to be sure logic here matches exactly.
-            let wratio = f64::from(self.max_res) / f64::from(self.size.x);
-            let hratio = f64::from(self.max_res) / f64::from(self.size.y);
+            let wratio = f64::from(self.max_res) / f64::from(size.x);
+            let hratio = f64::from(self.max_res) / f64::from(size.y);
             let ratio = f64::min(wratio, hratio);
-            let nw = u64::max((f64::from(self.size.x) * ratio).round() as u64, 1);
-            let nh = u64::max((f64::from(self.size.y) * ratio).round() as u64, 1);
+            let nw = u64::max((f64::from(size.x) * ratio).round() as u64, 1);
+            let nh = u64::max((f64::from(size.y) * ratio).round() as u64, 1);
             glam::uvec2(nw as u32, nh as u32)
         }
     }
 
+    /// Whether the image stores float values, which are taken to be linear.
+    pub fn is_float(&self) -> bool {
+        matches!(self.color_fmt, ColorType::Rgb32F | ColorType::Rgba32F)
+    }
+
     pub fn has_alpha(&self) -> bool {
         self.color_fmt.has_alpha() || self.is_masked()
     }
@@ -75,38 +107,74 @@ impl ImageFile {
     }
 
     pub async fn load(&self) -> image::ImageResult<DynamicImage> {
-        let mut img_bytes = vec![];
+        let bytes = self.read_bytes().await?;
+        self.decode(&bytes)
+    }
+
+    /// Reads the encoded image, and mask if there is one, without decoding anything.
+    pub async fn read_bytes(&self) -> std::io::Result<EncodedImage> {
+        let mut image = vec![];
         self.fs
             .reader_at_path(&self.path)
             .await?
-            .read_to_end(&mut img_bytes)
+            .read_to_end(&mut image)
             .await?;
-        let mut img = image::load_from_memory(&img_bytes)?;
 
-        // Copy over mask.
-        // TODO: Interleave this work better & speed things up here.
-        if let Some(mask_path) = &self.mask_path {
-            // Add in alpha channel if needed to the image to copy the mask into.
-            let mut masked_img = img.into_rgba8();
-            let mut mask_bytes = vec![];
+        let mask = if let Some(mask_path) = &self.mask_path {
+            let mut mask = vec![];
             self.fs
                 .reader_at_path(mask_path)
                 .await?
-                .read_to_end(&mut mask_bytes)
+                .read_to_end(&mut mask)
                 .await?;
-            let mask_img = image::load_from_memory(&mask_bytes)?;
-            if mask_img.color().has_alpha() {
-                let mask_img = mask_img.into_rgba8();
-                for (pixel, mask_pixel) in masked_img.pixels_mut().zip(mask_img.pixels()) {
-                    pixel[3] = mask_pixel[3];
+            Some(mask)
+        } else {
+            None
+        };
+        Ok(EncodedImage { image, mask })
+    }
+
+    /// Decodes the image read by [`Self::read_bytes`], resized to [`Self::dim`].
+    pub fn decode(&self, bytes: &EncodedImage) -> image::ImageResult<DynamicImage> {
+        let mut img = image::load_from_memory(&bytes.image)?;
+
+        // Copy over mask.
+        // TODO: Interleave this work better & speed things up here.
+        if let Some(mask_bytes) = &bytes.mask {
+            let mut mask_img = image::load_from_memory(mask_bytes)?;
+            // Masks aren't pre-scaled, match them to a downscaled image.
+            if mask_img.width() != img.width() || mask_img.height() != img.height() {
+                mask_img = mask_img.resize_exact(
+                    img.width(),
+                    img.height(),
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+            let mask: Vec<u8> = if mask_img.color().has_alpha() {
+                mask_img.into_rgba8().pixels().map(|p| p[3]).collect()
+            } else {
+                mask_img.into_rgb8().pixels().map(|p| p[0]).collect()
+            };
+
+            // Add in alpha channel if needed to the image to copy the mask into. Float images
+            // stay float so they keep their range.
+            img = if matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) {
+                let mut masked_img = img.into_rgba32f();
+                for (pixel, alpha) in masked_img.pixels_mut().zip(mask) {
+                    pixel[3] = alpha as f32 / 255.0;
                 }
+                masked_img.into()
             } else {
-                let mask_img = mask_img.into_rgb8();
-                for (pixel, mask_pixel) in masked_img.pixels_mut().zip(mask_img.pixels()) {
-                    pixel[3] = mask_pixel[0];
+                let mut masked_img = img.into_rgba8();
+                for (pixel, alpha) in masked_img.pixels_mut().zip(mask) {
+                    pixel[3] = alpha;
                 }
-            }
-            img = masked_img.into();
+                masked_img.into()
+            };
+        }
+        if self.downscale > 1 {
+            let size = self.scaled_dim();
+            img = img.resize_exact(size.x, size.y, image::imageops::FilterType::Triangle);
         }
         if img.width() <= self.max_res && img.height() <= self.max_res {
             return Ok(img);
@@ -1,59 +1,31 @@
-use std::sync::Arc;
-use burn::prelude::{Backend, Tensor, TensorData};
-use image::DynamicImage;
-use rand::prelude::SliceRandom;
-use rand::SeedableRng;
-use tokio::sync::{mpsc, RwLock};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use burn::prelude::Backend;
+use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
+use crate::config::{ColorSpace, ImageCacheConfig, LoaderConfig};
+use crate::error::FormatError;
+use crate::scene::cache::ImageCache;
+use crate::scene::sampler::ViewSampler;
 use crate::scene::{sample_to_tensor, Scene, SceneBatch};
 use tokio_with_wasm::alias as tokio_wasm;
 
-// Cache at most some nr. of gigs of data.
-// TODO: Not sure if this should be configurable or not.
-#[cfg(not(target_family = "wasm"))]
-const MAX_CACHE_MB: usize = 6 * 1024;
-
-// On WASM, not much hope a big dataset will work anyway but let's not
-// cache more than what fits in memory.
-#[cfg(target_family = "wasm")]
-const MAX_CACHE_MB: usize = 2 * 1024;
-
 pub struct SceneLoader<B: Backend> {
-    receiver: Receiver<SceneBatch<B>>,
-}
-
-struct ImageCache {
-    states: Vec<Option<Arc<DynamicImage>>>,
-    max_size: usize,
-    size: usize,
-}
-
-impl ImageCache {
-    fn new(max_size: usize, n_images: usize) -> Self {
-        Self {
-            states: vec![None; n_images],
-            max_size,
-            size: 0,
-        }
-    }
-
-    fn try_get(&self, index: usize) -> Option<Arc<DynamicImage>> {
-        self.states[index].clone()
-    }
-
-    fn insert(&mut self, index: usize, data: Arc<DynamicImage>) {
-        let data_size_mb = data.as_bytes().len() / (1024 * 1024);
-
-        if self.size + data_size_mb < self.max_size && self.states[index].is_none() {
-            self.states[index] = Some(data);
-            self.size += data_size_mb;
-        }
-    }
+    receiver: Receiver<Result<SceneBatch<B>, FormatError>>,
+    sampler: Arc<Mutex<ViewSampler>>,
+    downscale: Arc<AtomicU32>,
 }
 
 impl<B: Backend> SceneLoader<B> {
-    pub fn new(scene: &Scene, seed: u64, device: &B::Device) -> Self {
-        let num_img_queue = 32;
+    pub fn new(
+        scene: &Scene,
+        seed: u64,
+        color_space: ColorSpace,
+        config: &LoaderConfig,
+        cache_config: &ImageCacheConfig,
+        device: &B::Device,
+    ) -> Self {
+        let num_img_queue = config.prefetch.max(1);
 
         // The bounded size == number of batches to prefetch.
         let (send_img, mut rec_imag) = mpsc::channel(num_img_queue);
@@ -64,57 +36,52 @@ impl<B: Backend> SceneLoader<B> {
         let parallelism = if cfg!(target_family = "wasm") {
             1
         } else {
-            std::thread::available_parallelism()
-                .map(|x| x.get())
-                .unwrap_or(8)
+            config
+                .decode_workers
+                .unwrap_or_else(|| {
+                    std::thread::available_parallelism()
+                        .map(|x| x.get())
+                        .unwrap_or(8)
+                })
                 // Don't need more threads than the image queue can hold, most
                 // threads would just sit around idling!
-                .min(num_img_queue) as u64
+                .clamp(1, num_img_queue)
         };
-        let num_views = scene.views.len();
 
-        let load_cache = Arc::new(RwLock::new(ImageCache::new(MAX_CACHE_MB, num_views)));
+        let sampler = Arc::new(Mutex::new(ViewSampler::new(
+            config.sampling.clone(),
+            scene.views.len(),
+            seed,
+        )));
+        let load_cache = Arc::new(ImageCache::new(cache_config, color_space));
+        let downscale = Arc::new(AtomicU32::new(1));
 
-        for i in 0..parallelism {
-            let mut rng = rand::rngs::StdRng::seed_from_u64(seed + i);
+        for _ in 0..parallelism {
             let send_img = send_img.clone();
             let views = scene.views.clone();
 
+            let sampler = sampler.clone();
             let load_cache = load_cache.clone();
+            let downscale = downscale.clone();
 
             tokio_wasm::spawn(async move {
-                let mut shuf_indices = vec![];
-
                 loop {
-                    let index = shuf_indices.pop().unwrap_or_else(|| {
-                        shuf_indices = (0..num_views).collect();
-                        shuf_indices.shuffle(&mut rng);
-                        shuf_indices
-                            .pop()
-                            .expect("Need at least one view in dataset")
-                    });
+                    let index = sampler
+                        .lock()
+                        .expect("View sampler lock poisoned")
+                        .next_index();
 
                     let view = &views[index];
 
-                    let sample = if let Some(image) = load_cache.read().await.try_get(index) {
-                        image
-                    } else {
-                        let image = view
-                            .image
-                            .load()
-                            .await
-                            .expect("Scene loader encountered an error while loading an image");
-                        // Don't premultiply the image if it's a mask - treat as fully opaque.
-                        let sample = Arc::new(view_to_sample_image(image, view.image.is_masked()));
-                        load_cache.write().await.insert(index, sample.clone());
-                        sample
-                    };
-
-                    if send_img
-                        .send((sample, view.image.is_masked(), view.camera.clone()))
+                    let sample = load_cache
+                        .get_or_load_scaled(index, view, downscale.load(Ordering::Relaxed))
                         .await
-                        .is_err()
-                    {
+                        .map_err(|source| FormatError::Image { path: view.image.path.clone(), source });
+                    let failed = sample.is_err();
+                    let sample = sample.map(|sample| (index, sample, view.image.is_masked(), view.camera.clone()));
+
+                    // Training stops at the first error, there's no point in loading more images.
+                    if send_img.send(sample).await.is_err() || failed {
                         break;
                     }
                 }
@@ -125,18 +92,14 @@ impl<B: Backend> SceneLoader<B> {
         let device = device.clone();
         tokio_wasm::spawn(async move {
             while let Some(rec) = rec_imag.recv().await {
-                let (sample, alpha_is_mask, camera) = rec;
-                let img_tensor = sample_to_tensor(&sample, &device);
-
-                if send_batch
-                    .send(SceneBatch {
-                        img_tensor,
-                        alpha_is_mask,
-                        camera,
-                    })
-                    .await
-                    .is_err()
-                {
+                let batch = rec.map(|(view_index, sample, alpha_is_mask, camera)| SceneBatch {
+                    view_index,
+                    img_tensor: sample_to_tensor(&sample, &device),
+                    alpha_is_mask,
+                    camera,
+                });
+
+                if send_batch.send(batch).await.is_err() {
                     break;
                 }
             }
@@ -144,39 +107,41 @@ impl<B: Backend> SceneLoader<B> {
 
         Self {
             receiver: rec_batch,
+            sampler,
+            downscale,
         }
     }
 
-    pub async fn next_batch(&mut self) -> SceneBatch<B> {
-        self.receiver
-            .recv()
-            .await
-            .expect("Somehow lost data loading channel!")
+    /// Waits for the next view. Fails if its image couldn't be loaded.
+    pub async fn next_batch(&mut self) -> Result<SceneBatch<B>, FormatError> {
+        self.receiver.recv().await.unwrap_or(Err(FormatError::LoaderStopped))
     }
-}
 
-// Converts an image to a train sample. The tensor will be a floating point image with a [0, 1] image.
-//
-// This assume the input image has un-premultiplied alpha, whereas the output has pre-multiplied alpha.
-pub fn view_to_sample_image(image: DynamicImage, alpha_is_mask: bool) -> DynamicImage {
-    if image.color().has_alpha() && !alpha_is_mask {
-        let mut rgba_bytes = image.to_rgba8();
-
-        // Assume image has un-multiplied alpha and convert it to pre-multiplied.
-        // Perform multiplication in byte space before converting to float.
-        for pixel in rgba_bytes.chunks_exact_mut(4) {
-            let r = pixel[0];
-            let g = pixel[1];
-            let b = pixel[2];
-            let a = pixel[3];
-
-            pixel[0] = ((r as u16 * a as u16 + 127) / 255) as u8;
-            pixel[1] = ((g as u16 * a as u16 + 127) / 255) as u8;
-            pixel[2] = ((b as u16 * a as u16 + 127) / 255) as u8;
-            pixel[3] = a;
+    /// Downscales the images of the following batches by this factor. Images already queued keep
+    /// their resolution.
+    pub fn set_downscale(&self, factor: u32) {
+        self.downscale.store(factor.max(1), Ordering::Relaxed);
+    }
+
+    /// Waits for the next `count` views, to train on as one batch.
+    pub async fn next_batches(&mut self, count: usize) -> Result<Vec<SceneBatch<B>>, FormatError> {
+        let mut batches = Vec::with_capacity(count);
+        for _ in 0..count.max(1) {
+            batches.push(self.next_batch().await?);
         }
-        DynamicImage::ImageRgba8(rgba_bytes)
-    } else {
-        image
+        Ok(batches)
+    }
+
+    /// Whether the sampling strategy uses the loss reported with [`Self::report_loss`].
+    pub fn wants_feedback(&self) -> bool {
+        self.sampler.lock().expect("View sampler lock poisoned").wants_feedback()
+    }
+
+    /// Reports the training loss of a view, used by loss-weighted sampling.
+    pub fn report_loss(&self, view_index: usize, loss: f32) {
+        self.sampler
+            .lock()
+            .expect("View sampler lock poisoned")
+            .report_loss(view_index, loss);
     }
 }
@@ -0,0 +1,129 @@
+use rand::distr::weighted::WeightedIndex;
+use rand::prelude::{Distribution, SliceRandom};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::config::ViewSampling;
+
+/// Decides which view the loader decodes next. Shared by all decode workers.
+pub(crate) struct ViewSampler {
+    strategy: ViewSampling,
+    rng: StdRng,
+    num_views: usize,
+    order: Vec<usize>,
+    next: usize,
+    // Smoothed loss per view, None until the view has been trained on.
+    errors: Vec<Option<f32>>,
+}
+
+impl ViewSampler {
+    pub(crate) fn new(strategy: ViewSampling, num_views: usize, seed: u64) -> Self {
+        assert!(num_views > 0, "Need at least one view in dataset");
+
+        Self {
+            strategy,
+            rng: StdRng::seed_from_u64(seed),
+            num_views,
+            order: (0..num_views).collect(),
+            next: num_views,
+            errors: vec![None; num_views],
+        }
+    }
+
+    pub(crate) fn wants_feedback(&self) -> bool {
+        matches!(self.strategy, ViewSampling::LossWeighted { .. })
+    }
+
+    pub(crate) fn next_index(&mut self) -> usize {
+        match self.strategy {
+            ViewSampling::Uniform => {
+                if self.next == self.num_views {
+                    self.order.shuffle(&mut self.rng);
+                    self.next = 0;
+                }
+                self.next_in_order()
+            }
+            ViewSampling::Sequential => {
+                if self.next == self.num_views {
+                    self.next = 0;
+                }
+                self.next_in_order()
+            }
+            ViewSampling::LossWeighted { uniform_mix, .. } => {
+                if self.rng.random::<f32>() < uniform_mix {
+                    return self.rng.random_range(0..self.num_views);
+                }
+
+                // Views that haven't been trained on yet count as the worst view seen so far,
+                // so every view gets visited early on.
+                let worst = self.errors.iter().flatten().fold(0.0f32, |a, &b| a.max(b));
+                let weights = self.errors.iter().map(|e| e.unwrap_or(worst).max(0.0));
+
+                match WeightedIndex::new(weights) {
+                    Ok(dist) => dist.sample(&mut self.rng),
+                    // All weights are zero, nothing to prefer.
+                    Err(_) => self.rng.random_range(0..self.num_views),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn report_loss(&mut self, index: usize, loss: f32) {
+        let ViewSampling::LossWeighted { smoothing, .. } = self.strategy else {
+            return;
+        };
+        if !loss.is_finite() {
+            return;
+        }
+
+        let error = &mut self.errors[index];
+        *error = Some(match *error {
+            Some(prev) => prev * smoothing + loss * (1.0 - smoothing),
+            None => loss,
+        });
+    }
+
+    fn next_in_order(&mut self) -> usize {
+        let index = self.order[self.next];
+        self.next += 1;
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_visits_every_view_each_epoch() {
+        let mut sampler = ViewSampler::new(ViewSampling::Uniform, 7, 3);
+        for _ in 0..3 {
+            let mut epoch: Vec<_> = (0..7).map(|_| sampler.next_index()).collect();
+            epoch.sort();
+            assert_eq!(epoch, (0..7).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn sequential_wraps_around() {
+        let mut sampler = ViewSampler::new(ViewSampling::Sequential, 3, 0);
+        let order: Vec<_> = (0..7).map(|_| sampler.next_index()).collect();
+        assert_eq!(order, vec![0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn loss_weighted_prefers_high_loss_views() {
+        let strategy = ViewSampling::LossWeighted {
+            smoothing: 0.0,
+            uniform_mix: 0.0,
+        };
+        let mut sampler = ViewSampler::new(strategy, 4, 0);
+        for i in 0..4 {
+            sampler.report_loss(i, if i == 2 { 1.0 } else { 0.0 });
+        }
+        assert!((0..100).all(|_| sampler.next_index() == 2));
+
+        // Non finite losses are ignored.
+        sampler.report_loss(1, f32::NAN);
+        assert!((0..100).all(|_| sampler.next_index() == 2));
+    }
+}
@@ -0,0 +1,144 @@
+use glam::{Quat, Vec3};
+use render::camera::Camera;
+
+/// A similarity transform, mapping `x` to `scale * (rotation * x) + translation`.
+///
+/// Records how a dataset was moved into its training frame, so results can be mapped back.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SceneTransform {
+    pub rotation: Quat,
+    pub translation: Vec3,
+    pub scale: f32,
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl SceneTransform {
+    pub const IDENTITY: Self = Self {
+        rotation: Quat::IDENTITY,
+        translation: Vec3::ZERO,
+        scale: 1.0,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.scale * (self.rotation * point) + self.translation
+    }
+
+    /// Moves the camera along with the scene. The intrinsics are unaffected.
+    pub fn transform_camera(&self, camera: &Camera) -> Camera {
+        Camera {
+            position: self.transform_point(camera.position),
+            rotation: (self.rotation * camera.rotation).normalize(),
+            ..camera.clone()
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        let rotation = self.rotation.inverse();
+        let scale = 1.0 / self.scale;
+        Self {
+            rotation,
+            translation: -scale * (rotation * self.translation),
+            scale,
+        }
+    }
+
+    /// The transform that applies `self` first and `next` after.
+    pub fn then(&self, next: &Self) -> Self {
+        Self {
+            rotation: (next.rotation * self.rotation).normalize(),
+            translation: next.transform_point(self.translation),
+            scale: next.scale * self.scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use glam::{vec2, vec3, EulerRot};
+    use scene_source::Filesystem;
+    use crate::scene::{ImageFile, SceneView};
+    use crate::{Dataset, EvalSplit};
+
+    const EPS: f32 = 1e-4;
+
+    fn transform() -> SceneTransform {
+        SceneTransform {
+            rotation: Quat::from_euler(EulerRot::YXZ, 0.3, -1.1, 2.0),
+            translation: vec3(1.0, -2.0, 0.5),
+            scale: 2.5,
+        }
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, EPS), "{a} != {b}");
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let t = transform();
+        let identity = t.then(&t.inverse());
+        assert!(identity.rotation.abs_diff_eq(Quat::IDENTITY, EPS) || identity.rotation.abs_diff_eq(-Quat::IDENTITY, EPS));
+        assert_close(identity.translation, Vec3::ZERO);
+        assert!((identity.scale - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn points_and_cameras_map_back() {
+        let t = transform();
+        let point = vec3(0.2, 3.0, -4.0);
+        assert_close(t.inverse().transform_point(t.transform_point(point)), point);
+
+        let camera = Camera::new(point, Quat::from_rotation_y(0.7), 0.8, 0.6, vec2(0.5, 0.5));
+        let moved = t.transform_camera(&camera);
+        assert!((moved.fov_x - camera.fov_x).abs() < 1e-6, "Intrinsics are unaffected");
+        let back = t.inverse().transform_camera(&moved);
+        assert_close(back.position, camera.position);
+        assert!(back.rotation.dot(camera.rotation).abs() > 1.0 - EPS, "{} != {}", back.rotation, camera.rotation);
+    }
+
+    #[tokio::test]
+    async fn normalize_puts_up_on_y() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        image::RgbImage::new(4, 4).save(dir.path().join("view.png")).expect("Failed to write image");
+        let fs = Arc::new(Filesystem::from_dir(dir.path()).await.expect("Failed to read dir"));
+        let image = ImageFile::new(fs, std::path::Path::new("view.png"), None, 64).await.expect("Valid image");
+
+        // Cameras on a tilted ellipse around an off-center point, looking inwards.
+        let tilt = Quat::from_euler(EulerRot::XYZ, 0.4, 0.2, -0.3);
+        let views: Vec<_> = (0..12)
+            .map(|i| {
+                let angle = i as f32 / 12.0 * std::f32::consts::TAU;
+                let position = tilt * vec3(3.0 * angle.cos(), 0.2 * (2.0 * angle).sin(), angle.sin()) + vec3(5.0, 1.0, -2.0);
+                let rotation = tilt * Quat::from_rotation_y(-angle);
+                SceneView { image: image.clone(), camera: Camera::new(position, rotation, 0.8, 0.8, vec2(0.5, 0.5)) }
+            })
+            .collect();
+        let original: Vec<Vec3> = views.iter().map(|v| v.camera.position).collect();
+        let mut dataset = Dataset::from_views(views, vec![], EvalSplit::None);
+
+        let up = dataset.estimate_up().normalize();
+        dataset.normalize();
+
+        assert_close(dataset.transform.rotation * up, Vec3::Y);
+        let positions: Vec<Vec3> = dataset.train.views.iter().map(|v| v.camera.position).collect();
+        assert_close(positions.iter().sum::<Vec3>() / positions.len() as f32, Vec3::ZERO);
+        let radius = positions.iter().map(|p| p.length()).fold(0.0, f32::max);
+        assert!((radius - 1.0).abs() < EPS, "Cameras should fit the unit sphere, radius is {radius}");
+
+        // The recorded transform maps the original coordinates onto the normalized ones.
+        for (original, normalized) in original.iter().zip(&positions) {
+            assert_close(dataset.transform.transform_point(*original), *normalized);
+        }
+    }
+}
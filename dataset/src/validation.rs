@@ -0,0 +1,88 @@
+use glam::UVec2;
+
+// Datasets can have thousands of images, don't list the same kind of problem for every one of them.
+const MAX_MESSAGES: usize = 25;
+
+/// Findings of checking a dataset before training on it.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// Detected format, None if it wasn't recognized.
+    pub format: Option<String>,
+    /// Problems that make training fail.
+    pub errors: Vec<String>,
+    /// Problems training works around, like skipping an image.
+    pub warnings: Vec<String>,
+    /// Number of images that were found and decoded.
+    pub image_count: usize,
+    /// Smallest & largest image by pixel count.
+    pub min_resolution: Option<UVec2>,
+    pub max_resolution: Option<UVec2>,
+    pub camera_models: Vec<String>,
+    pub point_count: usize,
+    omitted_errors: usize,
+    omitted_warnings: usize,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        if self.errors.len() < MAX_MESSAGES {
+            self.errors.push(message.into());
+        } else {
+            self.omitted_errors += 1;
+        }
+    }
+
+    pub fn warn(&mut self, message: impl Into<String>) {
+        if self.warnings.len() < MAX_MESSAGES {
+            self.warnings.push(message.into());
+        } else {
+            self.omitted_warnings += 1;
+        }
+    }
+
+    pub(crate) fn add_image(&mut self, size: UVec2) {
+        let pixels = |s: UVec2| s.x as u64 * s.y as u64;
+        self.image_count += 1;
+        if self.min_resolution.is_none_or(|min| pixels(size) < pixels(min)) {
+            self.min_resolution = Some(size);
+        }
+        if self.max_resolution.is_none_or(|max| pixels(size) > pixels(max)) {
+            self.max_resolution = Some(size);
+        }
+    }
+
+    /// Notes how many messages were left out, call once all checks are done.
+    pub(crate) fn finish(mut self) -> Self {
+        if self.omitted_errors > 0 {
+            self.errors.push(format!("…and {} more errors", self.omitted_errors));
+        }
+        if self.omitted_warnings > 0 {
+            self.warnings.push(format!("…and {} more warnings", self.omitted_warnings));
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish_notes_omitted_messages() {
+        let mut report = ValidationReport::default();
+        for i in 0..MAX_MESSAGES + 3 {
+            report.error(format!("error {i}"));
+        }
+        report.warn("only warning");
+
+        let report = report.finish();
+        assert_eq!(report.errors.len(), MAX_MESSAGES + 1);
+        assert_eq!(report.errors[MAX_MESSAGES - 1], format!("error {}", MAX_MESSAGES - 1));
+        assert_eq!(report.errors[MAX_MESSAGES], "…and 3 more errors");
+        assert_eq!(report.warnings, vec![String::from("only warning")], "Nothing was omitted from the warnings");
+    }
+}
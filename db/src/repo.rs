@@ -6,11 +6,39 @@ use surrealdb::opt::IntoQuery;
 use surrealdb::sql::Thing;
 use tracing::error;
 use scene_source::Source;
+use web_cmn::scene::ValidationReport;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SceneMetadata {
     pub name: String,
     pub source: Source,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Report of checking the dataset at upload time.
+    #[serde(default)]
+    pub validation: Option<ValidationReport>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ArtifactKind {
+    EvalImage,
+    Splats,
+    Cameras,
+    Background,
+}
+
+/// A file produced by a training run.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArtifactMetadata {
+    pub scene: String,
+    pub run: String,
+    pub kind: ArtifactKind,
+    pub iteration: u32,
+    /// Location relative to the scene directory, so renaming a scene keeps it valid.
+    pub path: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the file.
+    pub checksum: String,
 }
 
 #[async_trait]
@@ -18,13 +46,24 @@ pub trait SplatRepository: Send + Sync {
     async fn add_scene(&self, scene: SceneMetadata) -> anyhow::Result<()>;
     async fn get_scene(&self, name: &str) -> anyhow::Result<Option<SceneMetadata>>;
     async fn list_scenes(&self) -> anyhow::Result<Vec<SceneMetadata>>;
-    
+    /// Replaces the stored metadata of an existing scene.
+    async fn update_scene(&self, scene: SceneMetadata) -> anyhow::Result<()>;
+    /// Moves the scene stored under `old_name` to `scene.name` in a single transaction.
+    async fn rename_scene(&self, old_name: &str, scene: SceneMetadata) -> anyhow::Result<()>;
+    /// Removes the scene and its artifacts in a single transaction, returning its metadata if it existed.
+    async fn delete_scene(&self, name: &str) -> anyhow::Result<Option<SceneMetadata>>;
+
+    async fn add_artifact(&self, artifact: ArtifactMetadata) -> anyhow::Result<()>;
+    async fn get_artifact(&self, scene: &str, path: &str) -> anyhow::Result<Option<ArtifactMetadata>>;
+    async fn list_artifacts(&self, scene: &str) -> anyhow::Result<Vec<ArtifactMetadata>>;
+
     async fn can_add(&self, name: &str) -> bool;
 }
 
 const ROOT_NS: &'static str = "ggs";
 const DB_NAME: &'static str = "ggs_db";
 const TABLE_SCENE: &str = "scene";
+const TABLE_ARTIFACT: &str = "artifact";
 
 pub struct SplatRepo {
     db: Surreal<Db>,
@@ -55,6 +94,79 @@ impl SplatRepository for SplatRepo {
     async fn list_scenes(&self) -> anyhow::Result<Vec<SceneMetadata>> {
         Ok(self.db.select(TABLE_SCENE).await?)
     }
+
+    async fn update_scene(&self, scene: SceneMetadata) -> anyhow::Result<()> {
+        let updated: Option<SceneMetadata> = self.db
+            .update((TABLE_SCENE, scene.name.as_str()))
+            .content(scene.clone())
+            .await?;
+        if updated.is_none() {
+            anyhow::bail!("Scene does not exist: {}", scene.name);
+        }
+        Ok(())
+    }
+
+    async fn rename_scene(&self, old_name: &str, scene: SceneMetadata) -> anyhow::Result<()> {
+        self.db
+            .query(
+                "BEGIN TRANSACTION; \
+                 CREATE type::thing($table, $new_name) CONTENT $scene; \
+                 DELETE type::thing($table, $old_name); \
+                 UPDATE type::table($artifacts) SET scene = $new_name WHERE scene = $old_name; \
+                 COMMIT TRANSACTION;",
+            )
+            .bind(("table", TABLE_SCENE))
+            .bind(("artifacts", TABLE_ARTIFACT))
+            .bind(("new_name", scene.name.clone()))
+            .bind(("old_name", old_name.to_owned()))
+            .bind(("scene", scene))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    async fn delete_scene(&self, name: &str) -> anyhow::Result<Option<SceneMetadata>> {
+        let mut response = self.db
+            .query(
+                "BEGIN TRANSACTION; \
+                 DELETE type::thing($table, $scene) RETURN BEFORE; \
+                 DELETE type::table($artifacts) WHERE scene = $scene; \
+                 COMMIT TRANSACTION;",
+            )
+            .bind(("table", TABLE_SCENE))
+            .bind(("artifacts", TABLE_ARTIFACT))
+            .bind(("scene", name.to_owned()))
+            .await?
+            .check()?;
+        Ok(response.take(0)?)
+    }
+
+    async fn add_artifact(&self, artifact: ArtifactMetadata) -> anyhow::Result<()> {
+        let _: Option<ArtifactMetadata> = self.db
+            .create(TABLE_ARTIFACT)
+            .content(artifact)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_artifact(&self, scene: &str, path: &str) -> anyhow::Result<Option<ArtifactMetadata>> {
+        let mut response = self.db
+            .query("SELECT * FROM type::table($artifacts) WHERE scene = $scene AND path = $path LIMIT 1")
+            .bind(("artifacts", TABLE_ARTIFACT))
+            .bind(("scene", scene.to_owned()))
+            .bind(("path", path.to_owned()))
+            .await?;
+        Ok(response.take(0)?)
+    }
+
+    async fn list_artifacts(&self, scene: &str) -> anyhow::Result<Vec<ArtifactMetadata>> {
+        let mut response = self.db
+            .query("SELECT * FROM type::table($artifacts) WHERE scene = $scene ORDER BY run, iteration, path")
+            .bind(("artifacts", TABLE_ARTIFACT))
+            .bind(("scene", scene.to_owned()))
+            .await?;
+        Ok(response.take(0)?)
+    }
     
     async fn can_add(&self, name: &str) -> bool {
         !self.get_scene(name).await.unwrap().is_some()
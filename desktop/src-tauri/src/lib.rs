@@ -58,14 +58,18 @@ async fn start_pipeline(app: tauri::AppHandle, zip_path: String) -> Result<(), S
               let payload = RefineStepEvent { iter, cur_splat_count };
               let _ = app.emit("pipeline://refine_step", payload);
             }
-            pipeline::message::PipelineMessage::EvalResult { iter, avg_psnr, avg_ssim } => {
-              let payload = EvalResultEvent { iter, avg_psnr, avg_ssim };
+            pipeline::message::PipelineMessage::EvalResult { iter, avg_psnr, avg_ssim, split } => {
+              let payload = EvalResultEvent { iter, avg_psnr, avg_ssim, split };
               let _ = app.emit("pipeline://eval_result", payload);
             }
-            pipeline::message::PipelineMessage::Finished => {
-              let _ = app.emit("pipeline://finished", FinishedEvent {});
+            pipeline::message::PipelineMessage::Finished { reason } => {
+              let _ = app.emit("pipeline://finished", FinishedEvent { reason: reason.to_string() });
             }
-            pipeline::message::PipelineMessage::NewSource | pipeline::message::PipelineMessage::StartLoading { .. } => {
+            pipeline::message::PipelineMessage::NewSource
+            | pipeline::message::PipelineMessage::StartLoading { .. }
+            | pipeline::message::PipelineMessage::TrainingStarted { .. }
+            | pipeline::message::PipelineMessage::Diverged { .. }
+            | pipeline::message::PipelineMessage::ArtifactSaved { .. } => {
               // Currently unused in desktop bridge.
             }
           }
@@ -106,10 +110,13 @@ struct EvalResultEvent {
   iter: u32,
   avg_psnr: f32,
   avg_ssim: f32,
+  split: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct FinishedEvent {}
+struct FinishedEvent {
+  reason: String,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ErrorEvent { message: String }
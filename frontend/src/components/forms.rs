@@ -1 +1,2 @@
-pub mod scene_upload;
\ No newline at end of file
+pub mod scene_upload;
+pub mod validation_report;
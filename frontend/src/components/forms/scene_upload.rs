@@ -3,7 +3,9 @@ use stylist::yew::styled_component;
 use wasm_bindgen::{JsCast, JsValue};
 use web_sys::{js_sys, HtmlInputElement};
 use yew::prelude::*;
-use web_cmn::scene::SceneResponse;
+use web_cmn::scene::{SceneResponse, ValidationReport};
+use crate::components::forms::validation_report::ValidationReportView;
+use crate::services::upload::upload_zip;
 
 #[derive(Properties, PartialEq)]
 pub struct SceneUploadFormProps {
@@ -16,6 +18,10 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
     let file_input_ref = use_node_ref();
     let name = use_state(|| "".to_string());
     let upload_mode = use_state(|| "folder".to_string()); // "folder" or "zip"
+    let progress = use_state(|| None::<f64>);
+    let upload_error = use_state(|| None::<String>);
+    // Shown after uploading a dataset that has problems, instead of closing right away.
+    let report = use_state(|| None::<ValidationReport>);
 
     let on_name_change = {
         let name = name.clone();
@@ -38,15 +44,52 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
         let file_input_ref = file_input_ref.clone();
         let on_scene_uploaded = props.on_scene_uploaded.clone();
         let on_close = props.on_close.clone();
+        let upload_mode = upload_mode.clone();
+        let progress = progress.clone();
+        let upload_error = upload_error.clone();
+        let report = report.clone();
 
         use_callback(
-            (name, file_input_ref, on_scene_uploaded, on_close),
-            move |_, (name, file_input_ref, on_scene_uploaded, on_close)| {
+            (name, file_input_ref, on_scene_uploaded, on_close, upload_mode, progress, upload_error, report),
+            move |_, (name, file_input_ref, on_scene_uploaded, on_close, upload_mode, progress, upload_error, report)| {
                 let name = (*name).clone();
 
                 let Some(input) = file_input_ref.cast::<HtmlInputElement>() else { return };
                 let Some(files) = input.files() else { return };
 
+                // Zips can be several gigabytes, send them in resumable chunks.
+                if **upload_mode == "zip" {
+                    let Some(file) = files.item(0) else { return };
+                    let on_scene_uploaded = on_scene_uploaded.clone();
+                    let on_close = on_close.clone();
+                    let progress = progress.clone();
+                    let upload_error = upload_error.clone();
+                    let report = report.clone();
+                    upload_error.set(None);
+                    progress.set(Some(0.0));
+
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let on_progress = |received: u64, size: u64| {
+                            progress.set(Some(received as f64 / size.max(1) as f64));
+                        };
+                        match upload_zip(&name, &file, on_progress).await {
+                            Ok(scene) => {
+                                match scene.validation.clone().filter(needs_review) {
+                                    Some(validation) => report.set(Some(validation)),
+                                    None => on_close.emit(()),
+                                }
+                                on_scene_uploaded.emit(scene);
+                            }
+                            Err(err) => {
+                                gloo_console::error!(format!("Upload failed: {:?}", err));
+                                upload_error.set(Some(err.to_string()));
+                            }
+                        }
+                        progress.set(None);
+                    });
+                    return;
+                }
+
                 let mut form = web_sys::FormData::new().unwrap();
                 form.append_with_str("name", &name).unwrap();
 
@@ -67,8 +110,10 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
 
                 let on_scene_uploaded = on_scene_uploaded.clone();
                 let on_close = on_close.clone();
+                let report = report.clone();
 
                 wasm_bindgen_futures::spawn_local(async move {
+                    let mut close = true;
                     let fetch_promise = window.fetch_with_request(&request);
                     let resp_result = wasm_bindgen_futures::JsFuture::from(fetch_promise).await;
 
@@ -83,7 +128,13 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
                                     Ok(js_value) => {
                                         let scene = js_value.into_serde::<SceneResponse>();
                                         match scene {
-                                            Ok(scene) => on_scene_uploaded.emit(scene),
+                                            Ok(scene) => {
+                                                if let Some(validation) = scene.validation.clone().filter(needs_review) {
+                                                    report.set(Some(validation));
+                                                    close = false;
+                                                }
+                                                on_scene_uploaded.emit(scene);
+                                            }
                                             Err(e) => gloo_console::error!(format!("JSON parsing failed: {:?}", e))
                                         }
                                     }
@@ -98,12 +149,30 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
                         }
                     }
 
-                    on_close.emit(());
+                    if close {
+                        on_close.emit(());
+                    }
                 });
             },
         )
     };
 
+    if let Some(validation) = &*report {
+        return html! {
+            <div class="bg-white rounded-2xl shadow-xl p-6 w-full max-w-md">
+                <div class="bg-white dark:bg-gray-800 rounded-xl shadow-glass p-6 w-full max-w-md">
+                    <h2 class="text-2xl font-bold mb-4 text-center">{"Dataset Check"}</h2>
+                    <ValidationReportView report={validation.clone()} />
+                    <div class="flex justify-end">
+                        <button onclick={props.on_close.reform(|_| ())} class="px-4 py-2 bg-gray-300 text-gray-900 rounded hover:bg-gray-400 transition">
+                            {"Close"}
+                        </button>
+                    </div>
+                </div>
+            </div>
+        };
+    }
+
     html! {
         <div class="bg-white rounded-2xl shadow-xl p-6 w-full max-w-md">
             <div class="bg-white dark:bg-gray-800 rounded-xl shadow-glass p-6 w-full max-w-md">
@@ -149,8 +218,17 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
                     class="w-full mb-4"
                 />
 
+                if let Some(progress) = *progress {
+                    <div class="w-full h-2 mb-4 bg-gray-200 rounded">
+                        <div class="h-2 bg-aeroPurple rounded" style={format!("width: {:.1}%", progress * 100.0)} />
+                    </div>
+                }
+                if let Some(err) = &*upload_error {
+                    <div class="mb-4 text-sm text-red-600">{ err.as_str() }</div>
+                }
+
                 <div class="flex justify-end gap-2">
-                    <button onclick={on_submit} class="px-4 py-2 bg-aeroPurple text-white rounded hover:bg-opacity-90 transition">
+                    <button onclick={on_submit} disabled={progress.is_some()} class="px-4 py-2 bg-aeroPurple text-white rounded hover:bg-opacity-90 transition">
                         {"Upload"}
                     </button>
                     <button onclick={props.on_close.reform(|_| ())} class="px-4 py-2 bg-gray-300 text-gray-900 rounded hover:bg-gray-400 transition">
@@ -162,6 +240,10 @@ pub fn upload_modal(props: &SceneUploadFormProps) -> Html {
     }
 }
 
+fn needs_review(report: &ValidationReport) -> bool {
+    !report.errors.is_empty() || !report.warnings.is_empty()
+}
+
 fn get_webkit_relative_path(file: &web_sys::File) -> String {
     js_sys::Reflect::get(file.as_ref(), &JsValue::from_str("webkitRelativePath"))
         .ok()
@@ -0,0 +1,42 @@
+use stylist::yew::styled_component;
+use yew::{html, Html, Properties};
+use web_cmn::scene::ValidationReport;
+
+#[derive(Properties, PartialEq)]
+pub struct ValidationReportViewProps {
+    pub report: ValidationReport,
+}
+
+/// Summary of the dataset check that runs on upload, with everything that was found wrong.
+#[styled_component(ValidationReportView)]
+pub fn validation_report_view(props: &ValidationReportViewProps) -> Html {
+    let report = &props.report;
+    let resolution = |r: Option<[u32; 2]>| r.map(|[w, h]| format!("{w}×{h}")).unwrap_or_else(|| "-".into());
+
+    html! {
+        <div class="mb-4 text-sm space-y-2">
+            <div class="grid grid-cols-2 gap-x-2">
+                <span>{ "Format" }</span>
+                <span>{ report.format.clone().unwrap_or_else(|| "Unknown".into()) }</span>
+                <span>{ "Images" }</span>
+                <span>{ report.image_count }</span>
+                <span>{ "Resolution" }</span>
+                <span>{ format!("{} – {}", resolution(report.min_resolution), resolution(report.max_resolution)) }</span>
+                <span>{ "Camera models" }</span>
+                <span>{ report.camera_models.join(", ") }</span>
+                <span>{ "Sparse points" }</span>
+                <span>{ report.point_count }</span>
+            </div>
+            if !report.errors.is_empty() {
+                <ul class="list-disc pl-5 text-red-600">
+                    { for report.errors.iter().map(|e| html! { <li>{ e.as_str() }</li> }) }
+                </ul>
+            }
+            if !report.warnings.is_empty() {
+                <ul class="list-disc pl-5 text-yellow-600">
+                    { for report.warnings.iter().map(|w| html! { <li>{ w.as_str() }</li> }) }
+                </ul>
+            }
+        </div>
+    }
+}
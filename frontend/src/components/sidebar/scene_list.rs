@@ -1,10 +1,12 @@
+use gloo::dialogs::{alert, confirm, prompt};
 use gloo_console::info;
 use stylist::yew::styled_component;
 use web_sys::MouseEvent;
 use yew::{html, Callback, Html, NodeRef, Properties, UseStateHandle};
 use yew_router::prelude::use_navigator;
-use web_cmn::scene::SceneResponse;
+use web_cmn::scene::{SceneResponse, UpdateSceneRequest};
 use crate::route::Route;
+use crate::services::scene::{delete_scene, rename_scene, update_scene};
 
 #[derive(Properties, PartialEq)]
 pub struct SceneListProps {
@@ -18,8 +20,55 @@ pub fn scene_list(props: &SceneListProps) -> Html {
     let delete_scene = {
         let scenes = props.scenes.clone();
         Callback::from(move |scene_name: String| {
-            let new_list = scenes.iter().cloned().filter(|s| s.name != scene_name).collect();
-            scenes.set(new_list);
+            if !confirm(&format!("Delete scene \"{scene_name}\" and all of its data?")) {
+                return;
+            }
+
+            let scenes = scenes.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match delete_scene(&scene_name).await {
+                    Ok(()) => {
+                        let new_list = scenes.iter().cloned().filter(|s| s.name != scene_name).collect();
+                        scenes.set(new_list);
+                    }
+                    Err(err) => alert(&format!("Failed to delete scene: {err}")),
+                }
+            });
+        })
+    };
+
+    let rename = {
+        let scenes = props.scenes.clone();
+        Callback::from(move |scene_name: String| {
+            let Some(new_name) = prompt("New scene name", Some(&scene_name)) else { return };
+            if new_name.trim().is_empty() || new_name == scene_name {
+                return;
+            }
+
+            let scenes = scenes.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match rename_scene(&scene_name, &new_name).await {
+                    Ok(renamed) => replace_scene(&scenes, &scene_name, renamed),
+                    Err(err) => alert(&format!("Failed to rename scene: {err}")),
+                }
+            });
+        })
+    };
+
+    let edit_description = {
+        let scenes = props.scenes.clone();
+        Callback::from(move |scene: SceneResponse| {
+            let current = scene.description.clone().unwrap_or_default();
+            let Some(description) = prompt("Scene description", Some(&current)) else { return };
+
+            let scenes = scenes.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let request = UpdateSceneRequest { description: Some(description) };
+                match update_scene(&scene.name, &request).await {
+                    Ok(updated) => replace_scene(&scenes, &scene.name, updated),
+                    Err(err) => alert(&format!("Failed to update scene: {err}")),
+                }
+            });
         })
     };
 
@@ -40,22 +89,60 @@ pub fn scene_list(props: &SceneListProps) -> Html {
                     delete_scene.emit(s.name.clone());
                 });
 
+                let rename = rename.clone();
+                let s = response.clone();
+                let on_click_rename = Callback::from(move |e: MouseEvent| {
+                    e.stop_propagation();
+                    rename.emit(s.name.clone());
+                });
+
+                let edit_description = edit_description.clone();
+                let s = response.clone();
+                let on_click_edit = Callback::from(move |e: MouseEvent| {
+                    e.stop_propagation();
+                    edit_description.emit(s.clone());
+                });
+
                 html! {
                     <div
                         class="mb-2 cursor-pointer rounded p-2 hover:bg-gray-200 transition flex items-center justify-between group"
                         onclick={on_click}
                     >
-                        <span class="truncate">{ response.name.as_str() }</span>
-                        <button
-                            class="text-red-500 opacity-0 group-hover:opacity-100 transition-opacity"
-                            onclick={on_click_delete}
-                            title="Delete Scene"
-                        >
-                            { "🗑️" }
-                        </button>
+                        <div class="min-w-0">
+                            <div class="truncate">
+                                { response.name.as_str() }
+                                if let Some(errors) = response.validation.as_ref().map(|v| &v.errors).filter(|e| !e.is_empty()) {
+                                    <span class="ml-1 text-red-500" title={errors.join("\n")}>{ "⚠" }</span>
+                                }
+                            </div>
+                            if let Some(description) = &response.description {
+                                <div class="truncate text-xs text-gray-500">{ description.as_str() }</div>
+                            }
+                        </div>
+                        <div class="flex gap-1 opacity-0 group-hover:opacity-100 transition-opacity">
+                            <button onclick={on_click_rename} title="Rename Scene">
+                                { "✏️" }
+                            </button>
+                            <button onclick={on_click_edit} title="Edit Description">
+                                { "📝" }
+                            </button>
+                            <button class="text-red-500" onclick={on_click_delete} title="Delete Scene">
+                                { "🗑️" }
+                            </button>
+                        </div>
                     </div>
                 }
             })
         }
     }
-}
\ No newline at end of file
+}
+
+// Swaps the scene stored under `name` for its updated version, keeping its place in the list.
+fn replace_scene(scenes: &UseStateHandle<Vec<SceneResponse>>, name: &str, updated: SceneResponse) {
+    let new_list = scenes
+        .iter()
+        .cloned()
+        .map(|s| if s.name == name { updated.clone() } else { s })
+        .collect();
+    scenes.set(new_list);
+}
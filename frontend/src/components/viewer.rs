@@ -1,9 +1,12 @@
 mod state;
+mod dashboard;
+mod artifacts;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use futures_util::StreamExt;
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
 use gloo::render::{request_animation_frame, AnimationFrame};
 use gloo::utils::window;
 use gloo_console::{error, log};
@@ -14,7 +17,12 @@ use wasm_bindgen_futures::spawn_local;
 use web_sys::{HtmlCanvasElement, MouseEvent};
 use yew::prelude::*;
 
-use web_cmn::pipeline::WiredPipelineMessage;
+use web_cmn::artifact::ArtifactResponse;
+use web_cmn::pipeline::{WiredClientMessage, WiredPipelineMessage};
+use web_cmn::scene::SceneLayoutResponse;
+use crate::services::scene::{fetch_artifacts, fetch_scene_layout};
+use super::viewer::artifacts::ArtifactList;
+use super::viewer::dashboard::{TrainingDashboard, TrainingStats};
 use super::viewer::state::ViewerState;
 
 pub enum Msg {
@@ -24,7 +32,14 @@ pub enum Msg {
     MouseUp,
     MouseMove(f32, f32),
     MouseWheel(f32),
+    Click(f32, f32),
+    SetSceneLayout(SceneLayoutResponse),
+    ToggleOverlay,
+    SetArtifacts(Vec<ArtifactResponse>),
+    ToggleArtifacts,
     StartTraining,
+    StopTraining,
+    TrainingConnected(SplitSink<WebSocket, Message>),
     TrainingMsg(WiredPipelineMessage),
     TrainingDone,
     Resize,
@@ -39,6 +54,12 @@ pub struct Viewer {
     canvas_ref: NodeRef,
     viewer_state: Option<ViewerState>,
     training: bool,
+    training_stats: Rc<TrainingStats>,
+    ws_sink: Option<SplitSink<WebSocket, Message>>,
+    show_overlay: bool,
+    snapped_view: Option<String>,
+    artifacts: Rc<Vec<ArtifactResponse>>,
+    show_artifacts: bool,
     raf_handle: Option<AnimationFrame>,
 }
 
@@ -51,6 +72,12 @@ impl Component for Viewer {
             canvas_ref: NodeRef::default(),
             viewer_state: None,
             training: false,
+            training_stats: Rc::default(),
+            ws_sink: None,
+            show_overlay: true,
+            snapped_view: None,
+            artifacts: Rc::default(),
+            show_artifacts: false,
             raf_handle: None,
         }
     }
@@ -95,6 +122,51 @@ impl Component for Viewer {
                 });
                 self.raf_handle = Some(handle);
 
+                // fetch the dataset cameras & sparse points to draw on top of the splats
+                let scene_name = ctx.props().scene_name.clone();
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match fetch_scene_layout(&scene_name).await {
+                        Ok(layout) => link.send_message(Msg::SetSceneLayout(layout)),
+                        Err(err) => error!(format!("Failed to fetch scene layout: {:?}", err)),
+                    }
+                });
+
+                // results of earlier training runs
+                let scene_name = ctx.props().scene_name.clone();
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match fetch_artifacts(&scene_name).await {
+                        Ok(artifacts) => link.send_message(Msg::SetArtifacts(artifacts)),
+                        Err(err) => error!(format!("Failed to fetch artifacts: {:?}", err)),
+                    }
+                });
+
+                true
+            }
+
+            Msg::SetSceneLayout(layout) => {
+                if let Some(state) = self.viewer_state.as_mut() {
+                    state.set_scene_layout(layout);
+                }
+                false
+            }
+
+            Msg::ToggleOverlay => {
+                self.show_overlay = !self.show_overlay;
+                if let Some(state) = self.viewer_state.as_mut() {
+                    state.set_overlay_visible(self.show_overlay);
+                }
+                true
+            }
+
+            Msg::SetArtifacts(artifacts) => {
+                self.artifacts = Rc::new(artifacts);
+                true
+            }
+
+            Msg::ToggleArtifacts => {
+                self.show_artifacts = !self.show_artifacts;
                 true
             }
 
@@ -131,6 +203,22 @@ impl Component for Viewer {
                 }
                 false
             }
+            Msg::Click(x, y) => {
+                if !self.show_overlay {
+                    return false;
+                }
+                let Some(canvas) = self.canvas_ref.cast::<HtmlCanvasElement>() else {
+                    return false;
+                };
+                let (width, height) = (canvas.client_width() as f32, canvas.client_height() as f32);
+                if let Some(state) = self.viewer_state.as_mut() {
+                    if let Some(view) = state.handle_click(x, y, width, height) {
+                        self.snapped_view = Some(view);
+                        return true;
+                    }
+                }
+                false
+            }
             Msg::MouseWheel(delta) => {
                 if let Some(state) = self.viewer_state.as_mut() {
                     state.handle_mouse_wheel(delta);
@@ -148,8 +236,9 @@ impl Component for Viewer {
                     let ws_url = format!("ws://localhost:3000/train/{scene_name}");
                     match WebSocket::open(&ws_url) {
                         Ok(ws) => {
-                            let (_write, mut read) = ws.split();
+                            let (write, mut read) = ws.split();
                             log!(format!("Training connected: {}", ws_url));
+                            link.send_message(Msg::TrainingConnected(write));
 
                             while let Some(msg) = read.next().await {
                                 match msg {
@@ -191,15 +280,39 @@ impl Component for Viewer {
                 true // re-render to update button disabled/label
             }
 
+            Msg::StopTraining => {
+                // Ask the backend to stop, it answers with Done & closes the socket.
+                if let Some(mut sink) = self.ws_sink.take() {
+                    spawn_local(async move {
+                        let msg = serde_json::to_string(&WiredClientMessage::Stop).unwrap();
+                        if let Err(e) = sink.send(Message::Text(msg)).await {
+                            error!(format!("Failed to send stop message: {:?}", e));
+                        }
+                    });
+                }
+                true
+            }
+
+            Msg::TrainingConnected(sink) => {
+                self.ws_sink = Some(sink);
+                true
+            }
+
             Msg::TrainingMsg(pipeline_msg) => {
+                let mut changed = Rc::make_mut(&mut self.training_stats).on_pipeline_msg(&pipeline_msg);
+                if let WiredPipelineMessage::Artifact(artifact) = &pipeline_msg {
+                    Rc::make_mut(&mut self.artifacts).push(artifact.clone());
+                    changed = true;
+                }
                 if let Some(state) = self.viewer_state.as_mut() {
                     state.on_pipeline_msg(pipeline_msg);
                 }
-                false
+                changed
             }
 
             Msg::TrainingDone => {
                 self.training = false;
+                self.ws_sink = None;
                 true
             }
 
@@ -226,6 +339,9 @@ impl Component for Viewer {
         let onmousemove = ctx.link().callback(|e: MouseEvent| {
             Msg::MouseMove(e.offset_x() as f32, e.offset_y() as f32)
         });
+        let onclick = ctx.link().callback(|e: MouseEvent| {
+            Msg::Click(e.offset_x() as f32, e.offset_y() as f32)
+        });
         let onwheel = ctx.link().callback(|e: WheelEvent| {
             // prevent scrolling the page
             e.prevent_default();
@@ -241,11 +357,29 @@ impl Component for Viewer {
                     {onmousedown}
                     {onmouseup}
                     {onmousemove}
+                    {onclick}
                     {onwheel}
                     style="cursor: grab;"
                     class="w-full h-full block"
                 />
-                <div class="absolute bottom-4 left-4 z-10">
+                if self.training || self.training_stats.latest.is_some() {
+                    <TrainingDashboard stats={self.training_stats.clone()} />
+                }
+                if self.show_artifacts {
+                    <ArtifactList scene_name={ctx.props().scene_name.clone()} artifacts={self.artifacts.clone()} />
+                }
+                if let Some(view) = &self.snapped_view {
+                    <div class="absolute top-4 left-4 z-10 bg-black/60 text-white text-sm px-2 py-1 rounded">
+                        { view.as_str() }
+                    </div>
+                }
+                <div class="absolute bottom-4 left-4 z-10 flex gap-2">
+                    <button
+                        onclick={ctx.link().callback(|_| Msg::ToggleOverlay)}
+                        class="bg-gray-700 text-white px-4 py-2 rounded shadow hover:bg-gray-800"
+                    >
+                        { if self.show_overlay { "Hide Cameras" } else { "Show Cameras" } }
+                    </button>
                     <button
                         onclick={ctx.link().callback(|_| Msg::StartTraining)}
                         disabled={self.training}
@@ -253,6 +387,21 @@ impl Component for Viewer {
                     >
                         { if self.training { "Training…" } else { "Start Training" } }
                     </button>
+                    <button
+                        onclick={ctx.link().callback(|_| Msg::StopTraining)}
+                        disabled={self.ws_sink.is_none()}
+                        class="bg-red-600 text-white px-4 py-2 rounded shadow hover:bg-red-700 disabled:opacity-50"
+                    >
+                        { "Stop Training" }
+                    </button>
+                </div>
+                <div class="absolute bottom-4 right-4 z-10">
+                    <button
+                        onclick={ctx.link().callback(|_| Msg::ToggleArtifacts)}
+                        class="bg-gray-700 text-white px-4 py-2 rounded shadow hover:bg-gray-800"
+                    >
+                        { format!("Results ({})", self.artifacts.len()) }
+                    </button>
                 </div>
             </div>
         }
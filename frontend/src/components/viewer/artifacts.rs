@@ -0,0 +1,66 @@
+use std::rc::Rc;
+use stylist::yew::styled_component;
+use yew::{html, Html, Properties};
+use web_cmn::artifact::{ArtifactKind, ArtifactResponse};
+
+#[derive(Properties, PartialEq)]
+pub struct ArtifactListProps {
+    pub scene_name: String,
+    pub artifacts: Rc<Vec<ArtifactResponse>>,
+}
+
+/// Download links for everything the training runs of a scene produced, newest run first.
+#[styled_component(ArtifactList)]
+pub fn artifact_list(props: &ArtifactListProps) -> Html {
+    let mut runs: Vec<&str> = props.artifacts.iter().map(|a| a.run.as_str()).collect();
+    runs.sort_unstable();
+    runs.dedup();
+
+    html! {
+        <div class="absolute bottom-16 right-4 z-10 w-80 max-h-96 overflow-y-auto bg-black/70 text-white text-xs rounded shadow p-3 space-y-2">
+            if props.artifacts.is_empty() {
+                <div>{ "No results yet, they are saved while training." }</div>
+            }
+            {
+                for runs.iter().rev().map(|run| html! {
+                    <div>
+                        <div class="font-semibold">{ *run }</div>
+                        {
+                            for props.artifacts.iter().filter(|a| a.run == *run).map(|artifact| {
+                                let file_name = artifact.path.rsplit('/').next().unwrap_or(&artifact.path);
+                                let kind = match artifact.kind {
+                                    ArtifactKind::Splats => "Splats",
+                                    ArtifactKind::EvalImage => "Eval",
+                                    ArtifactKind::Cameras => "Cameras",
+                                    ArtifactKind::Background => "Background",
+                                };
+                                html! {
+                                    <a
+                                        class="flex justify-between hover:underline"
+                                        href={format!("/api/scene/{}/artifacts/{}", props.scene_name, artifact.path)}
+                                        download={file_name.to_owned()}
+                                        title={artifact.checksum.clone()}
+                                    >
+                                        <span class="truncate">{ format!("{kind} #{} {file_name}", artifact.iteration) }</span>
+                                        <span class="ml-2 shrink-0">{ format_size(artifact.size) }</span>
+                                    </a>
+                                }
+                            })
+                        }
+                    </div>
+                })
+            }
+        </div>
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
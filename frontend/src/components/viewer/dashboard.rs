@@ -0,0 +1,196 @@
+use std::rc::Rc;
+use stylist::yew::styled_component;
+use yew::{html, Html, Properties};
+use web_cmn::pipeline::{DivergenceEvent, EvalEvent, RefineEvent, TrainProgress, WiredPipelineMessage};
+
+// Only show the most recent refine events, there can be hundreds of them.
+const MAX_REFINE_EVENTS: usize = 8;
+
+/// Everything the dashboard knows about the current training run, accumulated from the websocket.
+#[derive(Clone, Default, PartialEq)]
+pub struct TrainingStats {
+    pub start_iter: u32,
+    pub total_steps: u32,
+    pub latest: Option<TrainProgress>,
+    pub loss_history: Vec<(u32, f32)>,
+    pub splat_history: Vec<(u32, u32)>,
+    pub refines: Vec<RefineEvent>,
+    pub evals: Vec<EvalEvent>,
+    pub rollbacks: Vec<DivergenceEvent>,
+    /// Why training finished.
+    pub finished: Option<String>,
+    pub error: Option<String>,
+}
+
+impl TrainingStats {
+    /// Updates the stats from a pipeline message. Returns true if anything changed.
+    pub fn on_pipeline_msg(&mut self, msg: &WiredPipelineMessage) -> bool {
+        match msg {
+            WiredPipelineMessage::Started { start_iter, total_steps } => {
+                *self = Self {
+                    start_iter: *start_iter,
+                    total_steps: *total_steps,
+                    ..Default::default()
+                };
+            }
+            WiredPipelineMessage::Progress(progress) => {
+                self.loss_history.push((progress.iter, progress.loss));
+                self.splat_history.push((progress.iter, progress.num_splats));
+                self.latest = Some(progress.clone());
+            }
+            WiredPipelineMessage::Refine(refine) => {
+                self.splat_history.push((refine.iter, refine.num_splats));
+                self.refines.push(refine.clone());
+            }
+            WiredPipelineMessage::Eval(eval) => self.evals.push(eval.clone()),
+            WiredPipelineMessage::Diverged(rollback) => self.rollbacks.push(rollback.clone()),
+            WiredPipelineMessage::Error(err) => self.error = Some(err.clone()),
+            WiredPipelineMessage::Done { reason } => self.finished = Some(reason.clone()),
+            WiredPipelineMessage::TrainStep(_) | WiredPipelineMessage::Artifact(_) => return false,
+        }
+        true
+    }
+
+    /// Estimated seconds until training is done, based on the average step time so far.
+    pub fn eta_secs(&self) -> Option<f32> {
+        let latest = self.latest.as_ref()?;
+        let steps_done = latest.iter.checked_sub(self.start_iter).filter(|s| *s > 0)?;
+        let steps_left = self.total_steps.saturating_sub(latest.iter);
+        Some(latest.elapsed_secs / steps_done as f32 * steps_left as f32)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TrainingDashboardProps {
+    pub stats: Rc<TrainingStats>,
+}
+
+#[styled_component(TrainingDashboard)]
+pub fn training_dashboard(props: &TrainingDashboardProps) -> Html {
+    let stats = &props.stats;
+
+    let loss_points: Vec<(f32, f32)> = stats.loss_history.iter().map(|(i, l)| (*i as f32, *l)).collect();
+    let splat_points: Vec<(f32, f32)> = stats.splat_history.iter().map(|(i, n)| (*i as f32, *n as f32)).collect();
+
+    html! {
+        <div class="absolute top-4 right-4 z-10 w-72 bg-black/70 text-white text-xs rounded shadow p-3 space-y-2">
+            if let Some(err) = &stats.error {
+                <div class="text-red-400">{ err.as_str() }</div>
+            }
+            if let Some(reason) = &stats.finished {
+                <div class="text-green-400">{ format!("Finished: {reason}") }</div>
+            }
+            if let Some(latest) = &stats.latest {
+                <>
+                <div class="flex justify-between">
+                    <span>{ format!("Iteration {} / {}", latest.iter, stats.total_steps) }</span>
+                    <span>{ format_duration(latest.elapsed_secs) }</span>
+                </div>
+                <div class="flex justify-between">
+                    <span>{ format!("{} splats", latest.num_splats) }</span>
+                    <span>
+                        { stats.eta_secs().map(|eta| format!("ETA {}", format_duration(eta))).unwrap_or_default() }
+                    </span>
+                </div>
+
+                <div>{ format!("Loss {:.5}", latest.loss) }</div>
+                { line_chart(&loss_points, "#f87171") }
+
+                <div>{ "Splat count" }</div>
+                { line_chart(&splat_points, "#60a5fa") }
+
+                <div class="grid grid-cols-2 gap-x-2">
+                    <span>{ format!("lr mean {:.2e}", latest.lr.mean) }</span>
+                    <span>{ format!("lr rot {:.2e}", latest.lr.rotation) }</span>
+                    <span>{ format!("lr scale {:.2e}", latest.lr.scale) }</span>
+                    <span>{ format!("lr sh {:.2e}", latest.lr.coeffs) }</span>
+                    <span>{ format!("lr opac {:.2e}", latest.lr.opacity) }</span>
+                    if let Some(lr) = latest.lr.camera {
+                        <span>{ format!("lr camera {:.2e}", lr) }</span>
+                    }
+                    if let Some(lr) = latest.lr.background {
+                        <span>{ format!("lr background {:.2e}", lr) }</span>
+                    }
+                </div>
+                </>
+            } else {
+                <div>{ "Waiting for training to start…" }</div>
+            }
+
+            if !stats.evals.is_empty() {
+                <div>
+                    <div class="font-semibold">{ "Eval" }</div>
+                    if let Some(split) = stats.evals.last().map(|eval| &eval.split).filter(|split| !split.is_empty()) {
+                        <div class="text-xs opacity-70">{ split }</div>
+                    }
+                    {
+                        for stats.evals.iter().map(|eval| html! {
+                            <div>{ format!("#{}: PSNR {:.2} SSIM {:.4}", eval.iter, eval.psnr, eval.ssim) }</div>
+                        })
+                    }
+                </div>
+            }
+
+            if !stats.rollbacks.is_empty() {
+                <div class="text-yellow-400">
+                    <div class="font-semibold">{ "Rollbacks" }</div>
+                    {
+                        for stats.rollbacks.iter().map(|rollback| html! {
+                            <div>{ format!("#{} to #{}: {}", rollback.iter, rollback.rolled_back_to, rollback.reason) }</div>
+                        })
+                    }
+                </div>
+            }
+
+            if !stats.refines.is_empty() {
+                <div>
+                    <div class="font-semibold">{ "Refines" }</div>
+                    {
+                        for stats.refines.iter().rev().take(MAX_REFINE_EVENTS).map(|refine| html! {
+                            <div>{ format!("#{}: +{} -{}", refine.iter, refine.num_added, refine.num_pruned) }</div>
+                        })
+                    }
+                </div>
+            }
+        </div>
+    }
+}
+
+// Draws the points as a polyline scaled to fill the chart.
+fn line_chart(points: &[(f32, f32)], color: &str) -> Html {
+    const WIDTH: f32 = 100.0;
+    const HEIGHT: f32 = 30.0;
+
+    let (min_x, max_x, min_y, max_y) = points.iter().fold(
+        (f32::INFINITY, f32::NEG_INFINITY, f32::INFINITY, f32::NEG_INFINITY),
+        |(min_x, max_x, min_y, max_y), (x, y)| (min_x.min(*x), max_x.max(*x), min_y.min(*y), max_y.max(*y)),
+    );
+    let range_x = (max_x - min_x).max(f32::EPSILON);
+    let range_y = (max_y - min_y).max(f32::EPSILON);
+
+    let polyline = points
+        .iter()
+        .map(|(x, y)| {
+            let px = (x - min_x) / range_x * WIDTH;
+            let py = HEIGHT - (y - min_y) / range_y * HEIGHT;
+            format!("{px:.2},{py:.2}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    html! {
+        <svg viewBox={format!("0 0 {WIDTH} {HEIGHT}")} preserveAspectRatio="none" class="w-full h-12 bg-white/5 rounded">
+            <polyline points={polyline} fill="none" stroke={color.to_owned()} stroke-width="1" vector-effect="non-scaling-stroke" />
+        </svg>
+    }
+}
+
+fn format_duration(secs: f32) -> String {
+    let secs = secs.max(0.0) as u32;
+    let (h, m, s) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if h > 0 {
+        format!("{h}h {m:02}m {s:02}s")
+    } else {
+        format!("{m:02}m {s:02}s")
+    }
+}
@@ -4,10 +4,11 @@ use std::sync::{Arc, Mutex, RwLock};
 use futures_util::TryStreamExt;
 use glam::{Quat, Vec2, Vec3};
 use gloo::render::request_animation_frame;
-use gloo_console::{error, log, warn};
+use gloo_console::{error, log};
 use wasm_bindgen::prelude::Closure;
 use web_sys::{window, HtmlCanvasElement};
 use web_cmn::pipeline::WiredPipelineMessage;
+use web_cmn::scene::{SceneLayoutResponse, TrainingCamera};
 use web_cmn::splats::RawSplats;
 use crate::error::{FrontendError, Result};
 use std::borrow::BorrowMut;
@@ -20,9 +21,14 @@ pub struct ViewerState {
     splatter: Splatter,
     camera: websplat::camera::Camera,
     mouse_down: bool,
+    dragged: bool,
     last_mouse_pos: Option<(f32, f32)>,
+    training_cameras: Vec<TrainingCamera>,
 }
 
+// Max distance in pixels between a click and a camera for it to be picked.
+const CAMERA_PICK_RADIUS: f32 = 16.0;
+
 impl ViewerState {
     pub async fn new(canvas: HtmlCanvasElement) -> Result<Self> {
         let (width, height) = (canvas.width(), canvas.height());
@@ -50,7 +56,9 @@ impl ViewerState {
                     splatter,
                     camera,
                     mouse_down: false,
+                    dragged: false,
                     last_mouse_pos: None,
+                    training_cameras: vec![],
                 })
             }
             Err(err) => {
@@ -65,15 +73,27 @@ impl ViewerState {
             WiredPipelineMessage::TrainStep(mut splats) => {
                 self.splatter.set_splats(&self.ctx, &splats);
             }
-            _ => {
-                let msg = format!("Unhandled pipeline message: {:?}", msg);
-                warn!(msg);
+            WiredPipelineMessage::Error(err) => {
+                error!(format!("Training failed: {}", err));
             }
+            // Stats are shown by the training dashboard.
+            _ => {}
         }
     }
 
+    pub fn set_scene_layout(&mut self, layout: SceneLayoutResponse) {
+        self.splatter.set_training_cameras(&self.ctx, &layout.cameras);
+        self.splatter.set_sparse_points(&self.ctx, &layout.points);
+        self.training_cameras = layout.cameras;
+    }
+
+    pub fn set_overlay_visible(&mut self, visible: bool) {
+        self.splatter.set_overlay_visible(visible);
+    }
+
     pub fn handle_mouse_down(&mut self) {
         self.mouse_down = true;
+        self.dragged = false;
     }
 
     pub fn handle_mouse_up(&mut self) {
@@ -89,12 +109,39 @@ impl ViewerState {
         if let Some((last_x, last_y)) = self.last_mouse_pos {
             let dx = x - last_x;
             let dy = y - last_y;
+            if dx != 0.0 || dy != 0.0 {
+                self.dragged = true;
+            }
             self.camera.orbit(Vec2::new(dx, dy));
         }
 
         self.last_mouse_pos = Some((x, y));
     }
 
+    /// Snaps the camera to the training view closest to the clicked position, if any.
+    /// Returns the image name of the view that was picked.
+    pub fn handle_click(&mut self, x: f32, y: f32, width: f32, height: f32) -> Option<String> {
+        if self.dragged {
+            return None;
+        }
+
+        let click = Vec2::new(x, y);
+
+        let picked = self
+            .training_cameras
+            .iter()
+            .filter_map(|cam| {
+                let screen = self.camera.project(Vec3::from_array(cam.position), width, height)?;
+                Some((cam, screen.distance(click)))
+            })
+            .filter(|(_, dist)| *dist <= CAMERA_PICK_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(cam, _)| cam.clone())?;
+
+        self.camera.snap_to(&picked);
+        Some(picked.image_name)
+    }
+
     pub fn handle_mouse_wheel(&mut self, zoom: f32) {
         // zoom is usually in "scroll delta" units
         self.camera.zoom(-zoom);
@@ -7,6 +7,12 @@ pub enum FrontendError {
     #[error("Bad Request: {0}")]
     BadRequest(#[from] gloo_net::Error),
     
+    #[error("Server error: {0}")]
+    ServerError(String),
+
+    #[error("Failed to read file: {0}")]
+    FileRead(String),
+
     #[error("Viewer error: {0}")]
     ViewerError(&'static str),
     
@@ -1 +1,15 @@
-pub mod scene;
\ No newline at end of file
+pub mod scene;
+pub mod upload;
+
+use gloo_net::http::Response;
+use crate::error::{FrontendError, Result};
+
+// The backend answers errors with a plain text message.
+pub(crate) async fn check_response(response: Response) -> Result<Response> {
+    if response.ok() {
+        Ok(response)
+    } else {
+        let message = response.text().await.unwrap_or_else(|_| response.status_text());
+        Err(FrontendError::ServerError(message))
+    }
+}
@@ -1,7 +1,9 @@
 use gloo_console::log;
 use gloo_net::http::Request;
-use web_cmn::scene::SceneResponse;
-use crate::error::{FrontendError, Result};
+use web_cmn::artifact::ArtifactResponse;
+use web_cmn::scene::{RenameSceneRequest, SceneLayoutResponse, SceneResponse, UpdateSceneRequest};
+use crate::error::Result;
+use super::check_response;
 
 pub async fn fetch_scenes() -> Result<Vec<SceneResponse>> {
     log!("Fetching scenes");
@@ -9,4 +11,47 @@ pub async fn fetch_scenes() -> Result<Vec<SceneResponse>> {
         .send()
         .await?;
     Ok(response.json::<Vec<SceneResponse>>().await?)
-}
\ No newline at end of file
+}
+
+pub async fn fetch_scene_layout(name: &str) -> Result<SceneLayoutResponse> {
+    log!("Fetching scene layout for", name);
+    let response = Request::get(&format!("/api/scene/{name}/layout"))
+        .send()
+        .await?;
+    Ok(response.json::<SceneLayoutResponse>().await?)
+}
+
+pub async fn fetch_artifacts(name: &str) -> Result<Vec<ArtifactResponse>> {
+    log!("Fetching artifacts for", name);
+    let response = Request::get(&format!("/api/scene/{name}/artifacts"))
+        .send()
+        .await?;
+    Ok(check_response(response).await?.json::<Vec<ArtifactResponse>>().await?)
+}
+
+pub async fn rename_scene(name: &str, new_name: &str) -> Result<SceneResponse> {
+    log!("Renaming scene", name, "to", new_name);
+    let response = Request::post(&format!("/api/scene/{name}/rename"))
+        .json(&RenameSceneRequest { name: new_name.to_owned() })?
+        .send()
+        .await?;
+    Ok(check_response(response).await?.json::<SceneResponse>().await?)
+}
+
+pub async fn update_scene(name: &str, request: &UpdateSceneRequest) -> Result<SceneResponse> {
+    log!("Updating scene", name);
+    let response = Request::patch(&format!("/api/scene/{name}"))
+        .json(request)?
+        .send()
+        .await?;
+    Ok(check_response(response).await?.json::<SceneResponse>().await?)
+}
+
+pub async fn delete_scene(name: &str) -> Result<()> {
+    log!("Deleting scene", name);
+    let response = Request::delete(&format!("/api/scene/{name}"))
+        .send()
+        .await?;
+    check_response(response).await?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use gloo::timers::future::TimeoutFuture;
+use gloo_console::{log, warn};
+use gloo_net::http::Request;
+use sha2::{Digest, Sha256};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::js_sys::Uint8Array;
+use web_cmn::scene::SceneResponse;
+use web_cmn::upload::{CreateUploadRequest, FinalizeUploadRequest, UploadStatus};
+use crate::error::{FrontendError, Result};
+use super::check_response;
+
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+// Consecutive failures before giving up on the upload.
+const MAX_RETRIES: u32 = 5;
+// Wait before the first retry, doubled after each consecutive failure.
+const RETRY_DELAY_MS: u32 = 500;
+
+/// Uploads a zip in chunks, resuming from what the backend has after a failed request.
+/// `on_progress` is called with the number of bytes the backend has received.
+pub async fn upload_zip(name: &str, file: &web_sys::File, on_progress: impl Fn(u64, u64)) -> Result<SceneResponse> {
+    let size = file.size() as u64;
+    let request = CreateUploadRequest {
+        name: name.to_owned(),
+        file_name: file.name(),
+        size,
+    };
+    let response = Request::post("/api/uploads").json(&request)?.send().await?;
+    let session = check_response(response).await?.json::<UploadStatus>().await?;
+    log!("Started upload", &session.id);
+
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    let mut retries = 0;
+    while offset < size {
+        let end = (offset + CHUNK_SIZE).min(size);
+        let chunk = read_range(file, offset, end).await?;
+
+        match put_chunk(&session.id, offset, &chunk).await {
+            Ok(status) if status.received == end => {
+                hasher.update(&chunk);
+                offset = end;
+                retries = 0;
+            }
+            result => {
+                retries += 1;
+                if retries > MAX_RETRIES {
+                    return Err(result.err().unwrap_or_else(|| FrontendError::ServerError("Upload went out of sync".into())));
+                }
+                warn!(format!("Chunk at {offset} failed, resuming"));
+                TimeoutFuture::new(RETRY_DELAY_MS << (retries - 1)).await;
+
+                // Part of the chunk may have arrived, continue from wherever the backend is.
+                // When the status can't be fetched either, the chunk is sent again and counts as another retry.
+                match get_status(&session.id).await {
+                    Ok(status) if status.received != offset => {
+                        offset = status.received;
+                        hasher = hash_prefix(file, offset).await?;
+                    }
+                    Ok(_) => {}
+                    Err(err) => warn!(format!("Failed to get the upload status: {err}")),
+                }
+            }
+        }
+        on_progress(offset, size);
+    }
+
+    let request = FinalizeUploadRequest { checksum: format!("{:x}", hasher.finalize()) };
+    let response = Request::post(&format!("/api/uploads/{}/finalize", session.id))
+        .json(&request)?
+        .send()
+        .await?;
+    Ok(check_response(response).await?.json::<SceneResponse>().await?)
+}
+
+async fn get_status(id: &str) -> Result<UploadStatus> {
+    let response = Request::get(&format!("/api/uploads/{id}")).send().await?;
+    Ok(check_response(response).await?.json::<UploadStatus>().await?)
+}
+
+async fn put_chunk(id: &str, offset: u64, chunk: &[u8]) -> Result<UploadStatus> {
+    let response = Request::put(&format!("/api/uploads/{id}?offset={offset}"))
+        .body(Uint8Array::from(chunk))?
+        .send()
+        .await?;
+    Ok(check_response(response).await?.json::<UploadStatus>().await?)
+}
+
+async fn read_range(file: &web_sys::File, start: u64, end: u64) -> Result<Vec<u8>> {
+    let to_err = |e: wasm_bindgen::JsValue| FrontendError::FileRead(format!("{e:?}"));
+    let blob = file.slice_with_f64_and_f64(start as f64, end as f64).map_err(to_err)?;
+    let buffer = JsFuture::from(blob.array_buffer()).await.map_err(to_err)?;
+    Ok(Uint8Array::new(&buffer).to_vec())
+}
+
+// Hashes the first `len` bytes of the file, used when resuming halfway through.
+async fn hash_prefix(file: &web_sys::File, len: u64) -> Result<Sha256> {
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    while offset < len {
+        let end = (offset + CHUNK_SIZE).min(len);
+        hasher.update(read_range(file, offset, end).await?);
+        offset = end;
+    }
+    Ok(hasher)
+}
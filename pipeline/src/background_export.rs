@@ -0,0 +1,54 @@
+use anyhow::Result;
+use dataset::{ColorSpace, SceneTransform};
+use burn::prelude::Backend;
+use std::path::Path;
+use train::background::BackgroundModel;
+
+/// Saves the learned background as a linear equirectangular `.hdr` map, in the coordinates of the
+/// source data like exported splats: the top row looks along +Y of the source.
+#[cfg(not(target_family = "wasm"))]
+pub async fn export_background_to_disk<B: Backend>(
+    background: BackgroundModel<B>,
+    transform: &SceneTransform,
+    color_space: ColorSpace,
+    path: &Path,
+) -> Result<()> {
+    use image::Rgb32FImage;
+
+    // Size of the exported map, the SH background has little detail.
+    const EXPORT_WIDTH: u32 = 512;
+    const EXPORT_HEIGHT: u32 = 256;
+
+    let mut data = background
+        .equirect(EXPORT_WIDTH, EXPORT_HEIGHT, transform.rotation)
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
+    if color_space == ColorSpace::Srgb {
+        for c in &mut data {
+            *c = dataset::color::srgb_to_linear(*c);
+        }
+    }
+
+    let img: image::DynamicImage = Rgb32FImage::from_raw(EXPORT_WIDTH, EXPORT_HEIGHT, data)
+        .expect("Failed to create image from tensor")
+        .into();
+
+    let parent = path.parent().expect("Export must have a filename");
+    tokio::fs::create_dir_all(parent).await?;
+    log::info!("Exporting background to {path:?}");
+    img.save(path)?;
+    Ok(())
+}
+
+// TODO: Maybe figure out how to do this on WASM.
+#[cfg(target_family = "wasm")]
+pub async fn export_background_to_disk<B: Backend>(
+    _background: BackgroundModel<B>,
+    _transform: &SceneTransform,
+    _color_space: ColorSpace,
+    _path: &Path,
+) -> Result<()> {
+    Ok(())
+}
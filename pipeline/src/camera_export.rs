@@ -0,0 +1,49 @@
+use anyhow::Result;
+use dataset::SceneTransform;
+use render::camera::Camera;
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct CameraRecord<'a> {
+    image: &'a str,
+    position: [f32; 3],
+    /// Local to world rotation, scalar first.
+    rotation: [f32; 4],
+    fov_x: f64,
+    fov_y: f64,
+    center_uv: [f32; 2],
+}
+
+/// Writes the cameras of the views as json, mapped back to the coordinates of the source data like
+/// exported splats. `images` are the image paths of the views, in the same order.
+pub async fn export_cameras_to_disk(
+    cameras: &[Camera],
+    images: &[String],
+    transform: &SceneTransform,
+    path: &Path,
+) -> Result<()> {
+    let inverse = transform.inverse();
+    let records: Vec<_> = cameras
+        .iter()
+        .zip(images)
+        .map(|(camera, image)| {
+            let camera = inverse.transform_camera(camera);
+            let rotation = camera.rotation;
+            CameraRecord {
+                image,
+                position: camera.position.to_array(),
+                rotation: [rotation.w, rotation.x, rotation.y, rotation.z],
+                fov_x: camera.fov_x,
+                fov_y: camera.fov_y,
+                center_uv: camera.center_uv.to_array(),
+            }
+        })
+        .collect();
+
+    let parent = path.parent().expect("Export must have a filename");
+    tokio::fs::create_dir_all(parent).await?;
+    log::info!("Exporting cameras to {path:?}");
+    tokio::fs::write(path, serde_json::to_vec_pretty(&records)?).await?;
+    Ok(())
+}
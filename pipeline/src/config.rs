@@ -31,4 +31,28 @@ pub struct PipelineConfig {
     /// Filename of exported ply file
     #[config(default = "String::from(\"export_{iter}.ply\")")]
     pub export_name: String,
+
+    /// Stop when the eval PSNR hasn't improved for this many evals.
+    pub stop_plateau_evals: Option<u32>,
+
+    /// Smallest PSNR gain in dB that counts as an improvement.
+    #[config(default = 0.05)]
+    pub stop_plateau_min_delta: f32,
+
+    /// Stop once the eval PSNR reaches this. When a target SSIM is set too, both have to be reached.
+    pub stop_target_psnr: Option<f32>,
+
+    /// Stop once the eval SSIM reaches this. When a target PSNR is set too, both have to be reached.
+    pub stop_target_ssim: Option<f32>,
+
+    /// Stop after the training loop ran for this many seconds, evals & exports included.
+    pub stop_time_budget_secs: Option<u64>,
+
+    /// Stop when, after growth stopped, the splat count stayed within `stop_splat_count_tolerance`
+    /// over this many refines. Not supported with the ADC refine strategy, which stops refining when growth stops.
+    pub stop_splat_count_refines: Option<u32>,
+
+    /// Relative change of the splat count that still counts as stable.
+    #[config(default = 0.01)]
+    pub stop_splat_count_tolerance: f32,
 }
\ No newline at end of file
@@ -1,34 +1,45 @@
 use anyhow::Result;
 use train::eval::EvalSample;
 use burn::prelude::Backend;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[allow(unused)]
-pub async fn eval_save_to_disk<B: Backend>(sample: &EvalSample<B>, path: &Path) -> Result<()> {
-    // TODO: Maybe figure out how to do this on WASM.
-    #[cfg(not(target_family = "wasm"))]
-    {
-        use image::Rgb32FImage;
-        log::info!("Saving eval image to disk.");
+/// Saves the linear render of an eval view to `path` (a `.hdr` file), with a tone-mapped `.png`
+/// preview next to it. Returns the paths of the files written.
+#[cfg(not(target_family = "wasm"))]
+pub async fn eval_save_to_disk<B: Backend>(sample: &EvalSample<B>, path: &Path) -> Result<Vec<PathBuf>> {
+    use image::{Rgb32FImage, RgbImage};
+    log::info!("Saving eval image to disk.");
 
-        let img = sample.rendered.clone();
-        let [h, w, _] = [img.dims()[0], img.dims()[1], img.dims()[2]];
-        let data = sample
-            .rendered
-            .clone()
-            .into_data_async()
-            .await
-            .into_vec::<f32>()
-            .expect("Wrong type");
+    let img = sample.rendered_linear.clone();
+    let [h, w, _] = [img.dims()[0], img.dims()[1], img.dims()[2]];
+    let data = img
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Wrong type");
 
-        let img: image::DynamicImage = Rgb32FImage::from_raw(w as u32, h as u32, data)
-            .expect("Failed to create image from tensor")
-            .into();
+    let preview: Vec<u8> = dataset::color::tone_map_to_srgb(&data)
+        .into_iter()
+        .map(|c| (c * 255.0).round() as u8)
+        .collect();
+    let preview = RgbImage::from_raw(w as u32, h as u32, preview)
+        .expect("Failed to create image from tensor");
 
-        let parent = path.parent().expect("Eval must have a filename");
-        tokio::fs::create_dir_all(parent).await?;
-        log::info!("Saving eval view to {path:?}");
-        img.save(path)?;
-    }
-    Ok(())
-}
\ No newline at end of file
+    let img: image::DynamicImage = Rgb32FImage::from_raw(w as u32, h as u32, data)
+        .expect("Failed to create image from tensor")
+        .into();
+
+    let parent = path.parent().expect("Eval must have a filename");
+    tokio::fs::create_dir_all(parent).await?;
+    log::info!("Saving eval view to {path:?}");
+    img.save(path)?;
+    let preview_path = path.with_extension("png");
+    preview.save(&preview_path)?;
+    Ok(vec![path.to_path_buf(), preview_path])
+}
+
+// TODO: Maybe figure out how to do this on WASM.
+#[cfg(target_family = "wasm")]
+pub async fn eval_save_to_disk<B: Backend>(_sample: &EvalSample<B>, _path: &Path) -> Result<Vec<PathBuf>> {
+    Ok(vec![])
+}
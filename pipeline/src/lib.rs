@@ -1,7 +1,8 @@
 #![recursion_limit = "256"]
 
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::Mutex;
 use async_fn_stream::try_fn_stream;
 use burn_cubecl::cubecl::Runtime;
 use burn_wgpu::{WgpuDevice, WgpuRuntime};
@@ -9,14 +10,17 @@ use futures::{Stream, StreamExt};
 use futures::stream::BoxStream;
 use tokio::sync::mpsc::UnboundedSender;
 use dataset::LoadConfig;
+use render::gaussian_splats::Splats;
+use render::MainBackend;
 use scene_source::Source;
-use train::config::TrainConfig;
 use crate::config::PipelineConfig;
 use crate::pipeline_stream::PipelineStream;
 use crate::view_stream::ViewStream;
 
+pub use crate::config::PipelineConfig;
 pub use crate::error::PipelineError;
-pub use crate::message::PipelineMessage;
+pub use crate::message::{ArtifactKind, PipelineMessage, StopReason};
+pub use train::config::TrainConfig;
 
 mod train_stream;
 mod message;
@@ -25,32 +29,99 @@ mod error;
 mod pipeline_stream;
 mod config;
 mod eval_export;
+mod background_export;
+mod camera_export;
+mod splat_export;
+mod stopping;
 
 pub struct Pipeline {
     device: WgpuDevice,
-    source: Source
+    source: Source,
+    export_path: PathBuf,
+    image_cache_dir: Option<PathBuf>,
+    load_config: LoadConfig,
+    pipeline_config: PipelineConfig,
+    train_config: TrainConfig,
+    // Behind a mutex so the pipeline can be shared, splats aren't Sync.
+    initial_splats: Mutex<Option<Splats<MainBackend>>>,
 }
 
 impl Pipeline {
-    pub fn new(source: Source) -> Result<Self, anyhow::Error> {
+    /// Creates a pipeline training on the source. Eval images and exported splats are written to `export_path`.
+    pub fn new(source: Source, export_path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
         let device = WgpuDevice::default();
         
         Ok(Self {
             device,
-            source
+            source,
+            export_path: export_path.into(),
+            image_cache_dir: None,
+            load_config: LoadConfig::new(),
+            pipeline_config: PipelineConfig::new().with_eval_save_to_disk(true),
+            train_config: TrainConfig::new(),
+            initial_splats: Mutex::new(None),
         })
     }
 
+    /// Keeps decoded images in this directory, so later runs on the same images skip decoding.
+    pub fn with_image_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.image_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Loads the dataset with this config instead of the defaults. The directory of
+    /// [`Self::with_image_cache`] takes precedence over the disk cache of the config.
+    pub fn with_load_config(mut self, config: LoadConfig) -> Self {
+        self.load_config = config;
+        self
+    }
+
+    /// Evaluates, exports & stops as set in this config instead of the defaults. The export path
+    /// given to [`Self::new`] takes precedence over the one of the config.
+    pub fn with_pipeline_config(mut self, config: PipelineConfig) -> Self {
+        self.pipeline_config = config;
+        self
+    }
+
+    /// Trains with this config instead of the defaults.
+    pub fn with_train_config(mut self, config: TrainConfig) -> Self {
+        self.train_config = config;
+        self
+    }
+
+    /// Continues training from these splats instead of the splats of the source, e.g. to fine-tune an
+    /// existing scene on new views. The splats are in the coordinates & sRGB colors of the source data, like exported plys.
+    pub fn with_initial_splats(mut self, splats: Splats<MainBackend>) -> Self {
+        self.initial_splats = Mutex::new(Some(splats));
+        self
+    }
+
+    /// Like [`Self::with_initial_splats`], with the splats read from a ply like the ones the pipeline exports.
+    pub fn with_initial_ply(self, bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let splats = splat_export::splats_from_ply(bytes, &self.device)?;
+        Ok(self.with_initial_splats(splats))
+    }
+
     pub fn launch(&mut self) -> impl Stream<Item = Result<PipelineMessage, anyhow::Error>> + 'static
     {
         let device = self.device.clone();
         let source = self.source.clone();
+        let export_path = self.export_path.clone();
+        let image_cache_dir = self.image_cache_dir.clone();
+        let load_config = self.load_config.clone();
+        let pipeline_config = self.pipeline_config.clone();
+        let train_config = self.train_config.clone();
+        let initial_splats = self.initial_splats.lock().expect("Initial splats lock poisoned").clone();
 
-        process_stream(source, device)
+        process_stream(source, export_path, image_cache_dir, load_config, pipeline_config, train_config, initial_splats, device)
     }
 }
 
-fn process_stream(source: Source, device: WgpuDevice) -> impl Stream<Item = Result<PipelineMessage, anyhow::Error>> + 'static {
+fn process_stream(source: Source, export_path: PathBuf, image_cache_dir: Option<PathBuf>, mut load_config: LoadConfig,
+                  mut pipeline_config: PipelineConfig, train_config: TrainConfig,
+                  initial_splats: Option<Splats<MainBackend>>, device: WgpuDevice)
+    -> impl Stream<Item = Result<PipelineMessage, anyhow::Error>> + 'static
+{
     try_fn_stream(|emitter| async move {
         log::info!("Starting process with source {source:?}");
         emitter.emit(PipelineMessage::NewSource).await;
@@ -59,12 +130,11 @@ fn process_stream(source: Source, device: WgpuDevice) -> impl Stream<Item = Resu
         // Start with memory cleared out.
         client.memory_cleanup();
 
-        let mut load_config = LoadConfig::new();
-        load_config.eval_split_every = Some(8);
-        let mut pipeline_config = PipelineConfig::new();
-        pipeline_config.export_path = String::from("eval");
-        let train_config = TrainConfig::new();
-        train_stream::run(source, load_config, pipeline_config, train_config, device, emitter).await?;
+        if let Some(dir) = image_cache_dir {
+            load_config.image_cache.disk_path = Some(dir.to_string_lossy().to_string());
+        }
+        pipeline_config.export_path = export_path.to_string_lossy().to_string();
+        train_stream::run(source, load_config, pipeline_config, train_config, initial_splats, device, emitter).await?;
 
         log::info!("Completed train stream");
         Ok(())
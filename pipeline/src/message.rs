@@ -1,9 +1,59 @@
+use std::fmt;
+use std::path::PathBuf;
 use std::time::Duration;
 use glam::Vec3;
 use render::gaussian_splats::Splats;
 use render::MainBackend;
 use serde::{Deserialize, Serialize};
-use train::msg::{RefineStats, TrainStepStats};
+use train::msg::{DivergenceStats, RefineStats, TrainStepStats};
+
+/// What kind of file the pipeline wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    /// A rendered eval view.
+    EvalImage,
+    /// The splats exported as a ply.
+    Splats,
+    /// The refined training cameras as json.
+    Cameras,
+    /// The learned background as an equirectangular hdr.
+    Background,
+}
+
+/// Why training finished.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// All steps are done.
+    Completed,
+    /// The eval PSNR stopped improving.
+    Plateau { evals: u32, best_psnr: f32 },
+    /// The eval scores reached their targets.
+    TargetReached { psnr: f32, ssim: f32 },
+    /// The training loop used up its time budget.
+    TimeBudget { elapsed: Duration },
+    /// The splat count stopped changing after growth stopped.
+    SplatCountStable { count: u32 },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Completed => write!(f, "all steps done"),
+            StopReason::Plateau { evals, best_psnr } => {
+                write!(f, "PSNR didn't improve on {best_psnr:.2} for {evals} evals")
+            }
+            StopReason::TargetReached { psnr, ssim } => {
+                write!(f, "target reached with PSNR {psnr:.2} and SSIM {ssim:.4}")
+            }
+            StopReason::TimeBudget { elapsed } => {
+                write!(f, "time budget used up after {}s", elapsed.as_secs())
+            }
+            StopReason::SplatCountStable { count } => {
+                write!(f, "splat count stable at {count}")
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum PipelineMessage {
@@ -11,6 +61,11 @@ pub enum PipelineMessage {
     StartLoading {
         training: bool,
     },
+    /// Data is loaded and the training loop is about to start.
+    TrainingStarted {
+        start_iter: u32,
+        total_steps: u32,
+    },
     ViewSplats {
         up_axis: Option<Vec3>,
         splats: Box<Splats<MainBackend>>,
@@ -32,12 +87,27 @@ pub enum PipelineMessage {
         cur_splat_count: u32,
         iter: u32,
     },
+    /// Training diverged after step `iter` and was rolled back to an earlier snapshot.
+    Diverged {
+        stats: Box<DivergenceStats>,
+        iter: u32,
+    },
     /// Eval was run successfully with these results.
     #[allow(unused)]
     EvalResult {
         iter: u32,
         avg_psnr: f32,
         avg_ssim: f32,
+        /// Describes the eval split, scores are only comparable between runs with the same split.
+        split: String,
+    },
+    /// A file was written to the export path.
+    ArtifactSaved {
+        kind: ArtifactKind,
+        iter: u32,
+        path: PathBuf,
+    },
+    Finished {
+        reason: StopReason,
     },
-    Finished
 }
\ No newline at end of file
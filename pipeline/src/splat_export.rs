@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Result};
+use burn::prelude::Backend;
+use burn::tensor::{s, Tensor, TensorData};
+use dataset::{ColorSpace, SceneTransform};
+use glam::{Quat, Vec3};
+use render::gaussian_splats::Splats;
+use render::sh::{sh_coeffs_for_degree, ShRotation, MAX_SH_DEGREE, SH_C0};
+use std::path::Path;
+
+// Splat parameters on the host, read back from the GPU or from a ply.
+struct HostSplats {
+    num_splats: usize,
+    coeffs_per_channel: usize,
+    means: Vec<f32>,
+    rotations: Vec<f32>,
+    log_scales: Vec<f32>,
+    raw_opacity: Vec<f32>,
+    sh_coeffs: Vec<f32>,
+}
+
+impl HostSplats {
+    async fn read<B: Backend>(splats: Splats<B>) -> Result<Self> {
+        let to_vec = |data: burn::tensor::TensorData| data.into_vec::<f32>().map_err(|e| anyhow!("{e:?}"));
+
+        let num_splats = splats.num_splats() as usize;
+        let coeffs_per_channel = splats.sh_coeffs.dims()[1];
+        // Take the tensors out first, the params can't be held across an await.
+        let tensors = (
+            splats.means.val(),
+            splats.rotations_normed(),
+            splats.log_scales.val(),
+            splats.raw_opacity.val(),
+            splats.sh_coeffs.val(),
+        );
+        drop(splats);
+        let (means, rotations, log_scales, raw_opacity, sh_coeffs) = tensors;
+
+        Ok(Self {
+            num_splats,
+            coeffs_per_channel,
+            means: to_vec(means.into_data_async().await)?,
+            rotations: to_vec(rotations.into_data_async().await)?,
+            log_scales: to_vec(log_scales.into_data_async().await)?,
+            raw_opacity: to_vec(raw_opacity.into_data_async().await)?,
+            sh_coeffs: to_vec(sh_coeffs.into_data_async().await)?,
+        })
+    }
+
+    fn into_splats<B: Backend>(self, device: &B::Device) -> Splats<B> {
+        let n = self.num_splats;
+        Splats::from_tensor_data(
+            Tensor::from_data(TensorData::new(self.means, [n, 3]), device),
+            Tensor::from_data(TensorData::new(self.rotations, [n, 4]), device),
+            Tensor::from_data(TensorData::new(self.log_scales, [n, 3]), device),
+            Tensor::from_data(TensorData::new(self.sh_coeffs, [n, self.coeffs_per_channel, 3]), device),
+            Tensor::from_data(TensorData::new(self.raw_opacity, [n]), device),
+        )
+    }
+
+    fn to_ply(&self) -> Vec<u8> {
+        let coeffs_per_channel = self.coeffs_per_channel;
+        let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+        header += &format!("element vertex {}\n", self.num_splats);
+        let properties = ply_properties(coeffs_per_channel);
+        for property in &properties {
+            header += &format!("property float {property}\n");
+        }
+        header += "end_header\n";
+
+        let mut bytes = header.into_bytes();
+        bytes.reserve(self.num_splats * properties.len() * 4);
+        let mut push = |v: f32| bytes.extend_from_slice(&v.to_le_bytes());
+
+        for i in 0..self.num_splats {
+            self.means[i * 3..i * 3 + 3].iter().for_each(|v| push(*v));
+
+            // Coefficients are stored [coeff, channel], the ply wants the dc term first and the rest channel major.
+            let coeffs = &self.sh_coeffs[i * coeffs_per_channel * 3..(i + 1) * coeffs_per_channel * 3];
+            (0..3).for_each(|c| push(coeffs[c]));
+            for c in 0..3 {
+                (1..coeffs_per_channel).for_each(|k| push(coeffs[k * 3 + c]));
+            }
+
+            push(self.raw_opacity[i]);
+            self.log_scales[i * 3..i * 3 + 3].iter().for_each(|v| push(*v));
+            self.rotations[i * 4..i * 4 + 4].iter().for_each(|v| push(*v));
+        }
+        bytes
+    }
+
+    fn from_ply(bytes: &[u8]) -> Result<Self> {
+        const END_HEADER: &[u8] = b"end_header\n";
+        let header_len = bytes
+            .windows(END_HEADER.len())
+            .position(|w| w == END_HEADER)
+            .ok_or_else(|| anyhow!("Ply has no end_header"))?
+            + END_HEADER.len();
+        let header = std::str::from_utf8(&bytes[..header_len])?;
+
+        let mut lines = header.lines().map(str::trim);
+        if lines.next() != Some("ply") {
+            return Err(anyhow!("Not a ply file"));
+        }
+        let mut num_splats = None;
+        let mut properties = vec![];
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "binary_little_endian", _] => {}
+                ["format", format, _] => return Err(anyhow!("Unsupported ply format {format}")),
+                ["element", "vertex", count] => num_splats = Some(count.parse::<usize>()?),
+                // Other elements, like faces, come after the vertices and are ignored.
+                ["element", ..] if num_splats.is_some() => break,
+                ["element", name, ..] => return Err(anyhow!("Unexpected ply element {name} before the vertices")),
+                ["property", "float", name] => properties.push(name.to_string()),
+                ["property", ty, name] => return Err(anyhow!("Property {name} is {ty}, only floats are supported")),
+                ["property", ..] => return Err(anyhow!("Unsupported ply property: {line}")),
+                _ => {}
+            }
+        }
+        let num_splats = num_splats.ok_or_else(|| anyhow!("Ply has no vertices"))?;
+
+        let rest_count = properties.iter().filter(|p| p.starts_with("f_rest_")).count();
+        let coeffs_per_channel = rest_count / 3 + 1;
+        if rest_count % 3 != 0 || !(0..=MAX_SH_DEGREE).any(|d| sh_coeffs_for_degree(d) as usize == coeffs_per_channel) {
+            return Err(anyhow!("Ply has an unsupported number of SH coefficients ({rest_count})"));
+        }
+        // Find each property the splats need, in the order they are written.
+        let offsets = ply_properties(coeffs_per_channel)
+            .iter()
+            .map(|name| {
+                properties
+                    .iter()
+                    .position(|p| p == name)
+                    .ok_or_else(|| anyhow!("Ply is missing property {name}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let stride = properties.len() * 4;
+        let data = &bytes[header_len..];
+        if data.len() < num_splats * stride {
+            return Err(anyhow!("Ply is truncated, expected {num_splats} vertices"));
+        }
+
+        let mut host = Self {
+            num_splats,
+            coeffs_per_channel,
+            means: Vec::with_capacity(num_splats * 3),
+            rotations: Vec::with_capacity(num_splats * 4),
+            log_scales: Vec::with_capacity(num_splats * 3),
+            raw_opacity: Vec::with_capacity(num_splats),
+            sh_coeffs: vec![0.0; num_splats * coeffs_per_channel * 3],
+        };
+        for (i, vertex) in data.chunks_exact(stride).take(num_splats).enumerate() {
+            let mut values = offsets.iter().map(|&o| {
+                f32::from_le_bytes([vertex[o * 4], vertex[o * 4 + 1], vertex[o * 4 + 2], vertex[o * 4 + 3]])
+            });
+            let mut take = |n: usize| values.by_ref().take(n).collect::<Vec<_>>();
+
+            host.means.extend(take(3));
+            let coeffs = &mut host.sh_coeffs[i * coeffs_per_channel * 3..(i + 1) * coeffs_per_channel * 3];
+            coeffs[..3].copy_from_slice(&take(3));
+            for c in 0..3 {
+                for (k, v) in (1..coeffs_per_channel).zip(take(coeffs_per_channel - 1)) {
+                    coeffs[k * 3 + c] = v;
+                }
+            }
+            host.raw_opacity.extend(take(1));
+            host.log_scales.extend(take(3));
+            host.rotations.extend(take(4));
+        }
+        Ok(host)
+    }
+
+    fn transform(&mut self, transform: &SceneTransform) {
+        if transform.is_identity() {
+            return;
+        }
+        let coeffs_per_channel = self.coeffs_per_channel;
+        let log_scale = transform.scale.ln();
+        let sh_rotation = ShRotation::new(transform.rotation, render::sh::sh_degree_from_coeffs(coeffs_per_channel as u32));
+
+        for i in 0..self.num_splats {
+            let mean = transform.transform_point(Vec3::from_slice(&self.means[i * 3..i * 3 + 3]));
+            self.means[i * 3..i * 3 + 3].copy_from_slice(&mean.to_array());
+
+            // Rotations are stored scalar first.
+            let r = &mut self.rotations[i * 4..i * 4 + 4];
+            let rotation = (transform.rotation * Quat::from_xyzw(r[1], r[2], r[3], r[0])).normalize();
+            r.copy_from_slice(&[rotation.w, rotation.x, rotation.y, rotation.z]);
+
+            self.log_scales[i * 3..i * 3 + 3].iter_mut().for_each(|v| *v += log_scale);
+            sh_rotation.apply(&mut self.sh_coeffs[i * coeffs_per_channel * 3..(i + 1) * coeffs_per_channel * 3]);
+        }
+    }
+}
+
+// Maps the colors of the splats through a transfer function. The base color goes through `transfer`,
+// the higher bands are scaled by its slope at the base color so the view dependent variation keeps its size.
+fn map_colors<B: Backend>(
+    splats: Splats<B>,
+    transfer: impl Fn(Tensor<B, 3>) -> Tensor<B, 3>,
+    slope: impl Fn(Tensor<B, 3>) -> Tensor<B, 3>,
+) -> Splats<B> {
+    let [_, coeffs_per_channel, _] = splats.sh_coeffs.dims();
+    let coeffs = splats.sh_coeffs.val();
+    let base = (coeffs.clone().slice(s![.., 0..1]) * SH_C0 + 0.5).clamp(0.0, 1.0);
+
+    let dc = (transfer(base.clone()) - 0.5) / SH_C0;
+    let coeffs = if coeffs_per_channel > 1 {
+        Tensor::cat(vec![dc, coeffs.slice(s![.., 1..]) * slope(base)], 1)
+    } else {
+        dc
+    };
+
+    Splats::from_tensor_data(
+        splats.means.val(),
+        splats.rotation.val(),
+        splats.log_scales.val(),
+        coeffs,
+        splats.raw_opacity.val(),
+    )
+}
+
+// Properties of a splat in the ply, in the order they are written.
+fn ply_properties(coeffs_per_channel: usize) -> Vec<String> {
+    let mut properties = Vec::from(["x", "y", "z", "f_dc_0", "f_dc_1", "f_dc_2"].map(String::from));
+    properties.extend((0..(coeffs_per_channel - 1) * 3).map(|i| format!("f_rest_{i}")));
+    properties.extend(["opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3"].map(String::from));
+    properties
+}
+
+/// Converts the colors of splats trained in `color_space` to sRGB, which plys and the viewer expect.
+pub fn splats_to_srgb<B: Backend>(splats: Splats<B>, color_space: ColorSpace) -> Splats<B> {
+    if color_space != ColorSpace::Linear {
+        return splats;
+    }
+    map_colors(
+        splats,
+        |c| {
+            let high = c.clone().powf_scalar(1.0 / 2.4) * 1.055 - 0.055;
+            high.mask_where(c.clone().lower_equal_elem(0.0031308), c * 12.92)
+        },
+        |c| {
+            let high = c.clone().clamp_min(0.0031308).powf_scalar(1.0 / 2.4 - 1.0) * (1.055 / 2.4);
+            high.mask_fill(c.lower_equal_elem(0.0031308), 12.92)
+        },
+    )
+}
+
+/// Converts the colors of sRGB splats, like an exported ply, to `color_space`. Inverse of [`splats_to_srgb`].
+pub fn splats_from_srgb<B: Backend>(splats: Splats<B>, color_space: ColorSpace) -> Splats<B> {
+    if color_space != ColorSpace::Linear {
+        return splats;
+    }
+    map_colors(
+        splats,
+        |c| {
+            let high = ((c.clone() + 0.055) / 1.055).powf_scalar(2.4);
+            high.mask_where(c.clone().lower_equal_elem(0.04045), c / 12.92)
+        },
+        |c| {
+            let high = ((c.clone() + 0.055) / 1.055).powf_scalar(1.4) * (2.4 / 1.055);
+            high.mask_fill(c.lower_equal_elem(0.04045), 1.0 / 12.92)
+        },
+    )
+}
+
+/// Moves splats given in the original coordinates of a dataset, like an exported ply, into the frame
+/// the dataset is trained in. `transform` maps the original coordinates to that frame.
+pub async fn splats_to_frame<B: Backend>(splats: Splats<B>, transform: &SceneTransform) -> Result<Splats<B>> {
+    if transform.is_identity() {
+        return Ok(splats);
+    }
+    let device = splats.device();
+    let mut host = HostSplats::read(splats).await?;
+    host.transform(transform);
+    Ok(host.into_splats(&device))
+}
+
+/// Encodes the splats as a binary ply, using the property layout of the reference 3DGS implementation.
+/// Colors are written as they are, convert them with [`splats_to_srgb`] first.
+///
+/// The splats are trained in the frame of the dataset, `transform` maps the original coordinates to that
+/// frame. Its inverse is applied so the ply lines up with the source data.
+pub async fn splats_to_ply<B: Backend>(splats: Splats<B>, transform: &SceneTransform) -> Result<Vec<u8>> {
+    let mut host = HostSplats::read(splats).await?;
+    host.transform(&transform.inverse());
+    Ok(host.to_ply())
+}
+
+/// Decodes splats from a binary ply with the property layout of the reference 3DGS implementation, like
+/// the plys written by [`splats_to_ply`].
+pub fn splats_from_ply<B: Backend>(bytes: &[u8], device: &B::Device) -> Result<Splats<B>> {
+    Ok(HostSplats::from_ply(bytes)?.into_splats(device))
+}
+
+/// Writes the splats as a ply, with their colors converted from `color_space` to sRGB.
+pub async fn export_splats_to_disk<B: Backend>(
+    splats: Splats<B>,
+    transform: &SceneTransform,
+    color_space: ColorSpace,
+    path: &Path,
+) -> Result<()> {
+    let bytes = splats_to_ply(splats_to_srgb(splats, color_space), transform).await?;
+
+    let parent = path.parent().expect("Export must have a filename");
+    tokio::fs::create_dir_all(parent).await?;
+    log::info!("Exporting splats to {path:?}");
+    tokio::fs::write(path, bytes).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_splats(num_splats: usize, coeffs_per_channel: usize) -> HostSplats {
+        let values = |len: usize, offset: f32| (0..len).map(|i| i as f32 * 0.25 - offset).collect::<Vec<_>>();
+        HostSplats {
+            num_splats,
+            coeffs_per_channel,
+            means: values(num_splats * 3, 1.0),
+            rotations: values(num_splats * 4, 0.5),
+            log_scales: values(num_splats * 3, 3.0),
+            raw_opacity: values(num_splats, 0.1),
+            sh_coeffs: values(num_splats * coeffs_per_channel * 3, 2.0),
+        }
+    }
+
+    fn assert_same(a: &HostSplats, b: &HostSplats) {
+        assert_eq!(a.num_splats, b.num_splats, "Splat count differs");
+        assert_eq!(a.coeffs_per_channel, b.coeffs_per_channel, "SH degree differs");
+        assert_eq!(a.means, b.means, "Means differ");
+        assert_eq!(a.rotations, b.rotations, "Rotations differ");
+        assert_eq!(a.log_scales, b.log_scales, "Scales differ");
+        assert_eq!(a.raw_opacity, b.raw_opacity, "Opacities differ");
+        assert_eq!(a.sh_coeffs, b.sh_coeffs, "SH coefficients differ");
+    }
+
+    #[test]
+    fn exported_ply_round_trips() {
+        for coeffs_per_channel in [1, 4, 16] {
+            let splats = test_splats(5, coeffs_per_channel);
+            let decoded = HostSplats::from_ply(&splats.to_ply()).expect("Exported ply should import");
+            assert_same(&splats, &decoded);
+        }
+    }
+
+    #[test]
+    fn imports_reordered_properties() {
+        // Other tools may write extra properties, or the properties in another order.
+        let header = "ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty float nx\n";
+        let order = ["rot_0", "rot_1", "rot_2", "rot_3", "x", "y", "z", "scale_0", "scale_1", "scale_2", "opacity", "f_dc_0", "f_dc_1", "f_dc_2"];
+        let mut bytes = header.to_string();
+        order.iter().for_each(|p| bytes += &format!("property float {p}\n"));
+        bytes += "end_header\n";
+        let mut bytes = bytes.into_bytes();
+        for v in [9.0f32, 1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, -1.0, -2.0, -3.0, 0.5, 0.1, 0.2, 0.3] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let splats = HostSplats::from_ply(&bytes).expect("Ply should import");
+        assert_eq!(splats.means, vec![1.0, 2.0, 3.0]);
+        assert_eq!(splats.rotations, vec![1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(splats.log_scales, vec![-1.0, -2.0, -3.0]);
+        assert_eq!(splats.raw_opacity, vec![0.5]);
+        assert_eq!(splats.sh_coeffs, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn rejects_bad_plys() {
+        let bytes = test_splats(2, 4).to_ply();
+        assert!(HostSplats::from_ply(&bytes[..bytes.len() - 1]).is_err(), "Truncated data");
+        assert!(HostSplats::from_ply(b"ply\nformat ascii 1.0\nelement vertex 0\nend_header\n").is_err(), "Ascii");
+
+        let text = String::from_utf8_lossy(&bytes);
+        let header_len = text.find("end_header\n").expect("Ply has a header");
+        let mut missing = text[..header_len].replace("property float opacity\n", "").into_bytes();
+        missing.extend_from_slice(&bytes[header_len..]);
+        assert!(HostSplats::from_ply(&missing).is_err(), "Missing property");
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+use train::config::{RefineStrategyConfig, TrainConfig};
+use crate::config::PipelineConfig;
+use crate::message::StopReason;
+
+/// Checks the optional stopping rules of the pipeline config as training goes.
+pub(crate) struct EarlyStopping {
+    plateau_evals: Option<u32>,
+    plateau_min_delta: f32,
+    target_psnr: Option<f32>,
+    target_ssim: Option<f32>,
+    time_budget: Option<Duration>,
+    splat_count_refines: Option<u32>,
+    splat_count_tolerance: f32,
+    growth_stop_iter: u32,
+
+    start: Instant,
+    best_psnr: f32,
+    evals_since_best: u32,
+    // Splat counts after the refines since growth stopped.
+    splat_counts: Vec<u32>,
+}
+
+impl EarlyStopping {
+    pub(crate) fn new(config: &PipelineConfig, train_config: &TrainConfig) -> anyhow::Result<Self> {
+        // The splat count is only recorded on refines after growth stops, which ADC doesn't do.
+        let refines_after_growth = !train_config.disable_refine
+            && !matches!(train_config.refine_strategy, RefineStrategyConfig::Adc);
+        if config.stop_splat_count_refines.is_some() && !refines_after_growth {
+            anyhow::bail!(
+                "Stopping on a stable splat count needs a refine strategy that keeps refining after growth stops"
+            );
+        }
+
+        Ok(Self {
+            plateau_evals: config.stop_plateau_evals,
+            plateau_min_delta: config.stop_plateau_min_delta,
+            target_psnr: config.stop_target_psnr,
+            target_ssim: config.stop_target_ssim,
+            time_budget: config.stop_time_budget_secs.map(Duration::from_secs),
+            splat_count_refines: config.stop_splat_count_refines,
+            splat_count_tolerance: config.stop_splat_count_tolerance,
+            growth_stop_iter: train_config.growth_stop_iter,
+            start: Instant::now(),
+            best_psnr: f32::NEG_INFINITY,
+            evals_since_best: 0,
+            splat_counts: vec![],
+        })
+    }
+
+    /// Checks the rules that apply after every step.
+    pub(crate) fn after_step(&mut self, iter: u32, refined_count: Option<u32>) -> Option<StopReason> {
+        if let Some(budget) = self.time_budget {
+            let elapsed = self.start.elapsed();
+            if elapsed >= budget {
+                return Some(StopReason::TimeBudget { elapsed });
+            }
+        }
+
+        let (Some(refines), Some(count)) = (self.splat_count_refines, refined_count) else {
+            return None;
+        };
+        if iter < self.growth_stop_iter {
+            return None;
+        }
+        self.splat_counts.push(count);
+        let window = refines as usize + 1;
+        if self.splat_counts.len() < window {
+            return None;
+        }
+        let recent = &self.splat_counts[self.splat_counts.len() - window..];
+        let min = *recent.iter().min()? as f32;
+        let max = *recent.iter().max()? as f32;
+        (max - min <= max * self.splat_count_tolerance).then_some(StopReason::SplatCountStable { count })
+    }
+
+    /// Checks the rules on the eval scores.
+    pub(crate) fn after_eval(&mut self, psnr: f32, ssim: f32) -> Option<StopReason> {
+        let targets_set = self.target_psnr.is_some() || self.target_ssim.is_some();
+        let reached = self.target_psnr.is_none_or(|target| psnr >= target)
+            && self.target_ssim.is_none_or(|target| ssim >= target);
+        if targets_set && reached {
+            return Some(StopReason::TargetReached { psnr, ssim });
+        }
+
+        if psnr > self.best_psnr + self.plateau_min_delta {
+            self.best_psnr = psnr;
+            self.evals_since_best = 0;
+        } else {
+            self.evals_since_best += 1;
+        }
+        let evals = self.plateau_evals?;
+        (self.evals_since_best >= evals).then_some(StopReason::Plateau {
+            evals,
+            best_psnr: self.best_psnr,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stopping(config: PipelineConfig) -> EarlyStopping {
+        EarlyStopping::new(&config, &TrainConfig::new().with_growth_stop_iter(100))
+            .expect("Config should be valid")
+    }
+
+    #[test]
+    fn no_rules_never_stop() {
+        let mut stopping = stopping(PipelineConfig::new());
+        for iter in 0..1000 {
+            assert_eq!(stopping.after_step(iter, Some(10)), None);
+        }
+        for _ in 0..100 {
+            assert_eq!(stopping.after_eval(20.0, 0.5), None);
+        }
+    }
+
+    #[test]
+    fn stops_on_plateau() {
+        let mut stopping = stopping(PipelineConfig::new().with_stop_plateau_evals(Some(2)));
+        assert_eq!(stopping.after_eval(20.0, 0.5), None);
+        assert_eq!(stopping.after_eval(21.0, 0.5), None);
+        // Gains below the min delta don't count.
+        assert_eq!(stopping.after_eval(21.01, 0.5), None);
+        assert_eq!(
+            stopping.after_eval(20.5, 0.5),
+            Some(StopReason::Plateau { evals: 2, best_psnr: 21.0 })
+        );
+    }
+
+    #[test]
+    fn stops_when_all_targets_are_reached() {
+        let config = PipelineConfig::new()
+            .with_stop_target_psnr(Some(25.0))
+            .with_stop_target_ssim(Some(0.8));
+        let mut stopping = stopping(config);
+        assert_eq!(stopping.after_eval(26.0, 0.7), None);
+        assert_eq!(stopping.after_eval(24.0, 0.9), None);
+        assert_eq!(
+            stopping.after_eval(25.0, 0.8),
+            Some(StopReason::TargetReached { psnr: 25.0, ssim: 0.8 })
+        );
+    }
+
+    #[test]
+    fn stops_when_time_is_up() {
+        let mut stopping = stopping(PipelineConfig::new().with_stop_time_budget_secs(Some(0)));
+        assert!(matches!(stopping.after_step(1, None), Some(StopReason::TimeBudget { .. })));
+    }
+
+    #[test]
+    fn stops_on_stable_splat_count_after_growth() {
+        let config = PipelineConfig::new()
+            .with_stop_splat_count_refines(Some(2))
+            .with_stop_splat_count_tolerance(0.01);
+        let mut stopping = stopping(config);
+
+        // Counts while growing and steps without a refine don't count.
+        assert_eq!(stopping.after_step(50, Some(1000)), None);
+        assert_eq!(stopping.after_step(60, Some(1000)), None);
+        assert_eq!(stopping.after_step(150, None), None);
+
+        assert_eq!(stopping.after_step(200, Some(1000)), None);
+        assert_eq!(stopping.after_step(300, Some(1100)), None);
+        assert_eq!(stopping.after_step(400, Some(1100)), None);
+        assert_eq!(
+            stopping.after_step(500, Some(1095)),
+            Some(StopReason::SplatCountStable { count: 1095 })
+        );
+    }
+
+    #[test]
+    fn splat_count_rule_needs_refines_after_growth() {
+        let config = PipelineConfig::new().with_stop_splat_count_refines(Some(3));
+        let adc = TrainConfig::new().with_refine_strategy(RefineStrategyConfig::Adc);
+        assert!(EarlyStopping::new(&config, &adc).is_err(), "ADC stops refining with growth");
+        let no_refine = TrainConfig::new().with_disable_refine(true);
+        assert!(EarlyStopping::new(&config, &no_refine).is_err(), "Refining is disabled");
+
+        let mcmc = TrainConfig::new().with_refine_strategy(RefineStrategyConfig::Mcmc(train::config::McmcConfig::new()));
+        assert!(EarlyStopping::new(&config, &mcmc).is_ok(), "MCMC keeps relocating");
+        assert!(EarlyStopping::new(&PipelineConfig::new(), &adc).is_ok(), "The rule isn't used");
+    }
+}
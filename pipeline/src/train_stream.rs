@@ -16,13 +16,20 @@ use scene_source::Source;
 use train::config::TrainConfig;
 use train::eval::eval_stats;
 use train::train::SplatTrainer;
+use crate::background_export::export_background_to_disk;
 use crate::config::PipelineConfig;
+use crate::camera_export::export_cameras_to_disk;
 use crate::eval_export::eval_save_to_disk;
-use crate::message::PipelineMessage;
+use crate::message::{ArtifactKind, PipelineMessage, StopReason};
 use crate::pipeline_stream::*;
+use crate::splat_export::{export_splats_to_disk, splats_from_srgb, splats_to_frame, splats_to_srgb};
+use crate::stopping::EarlyStopping;
 use crate::PipelineError;
 
-pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: PipelineConfig, train_config: TrainConfig, device: WgpuDevice,
+/// Trains on the source. When `provided_splats` are given, training continues from those instead of
+/// the splats of the dataset. They are in the coordinates & sRGB colors of the source data, like exported plys.
+pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: PipelineConfig, train_config: TrainConfig,
+                 provided_splats: Option<Splats<MainBackend>>, device: WgpuDevice,
                  emitter: TryStreamEmitter<PipelineMessage, anyhow::Error>) -> anyhow::Result<()> {
     let (mut splat_stream, dataset) = dataset::load_dataset(
         source.clone(), load_config.clone(), &device).await?;
@@ -30,19 +37,34 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
     let mut initial_splats = None;
 
     let estimated_up = dataset.estimate_up();
+    // Splats are trained in the color space of the dataset, the viewer and exports use sRGB.
+    let color_space = dataset.color_space;
 
-    while let Some(message) = splat_stream.next().await {
-        let message = message?;
-        let msg = PipelineMessage::ViewSplats {
-            // If the metadata has an up axis prefer that, otherwise estimate
-            // the up direction.
-            up_axis: message.meta.up_axis.or(Some(estimated_up)),
-            splats: Box::new(message.splats.clone()),
-            frame: 0,
-            total_frames: 0,
-        };
-        emitter.emit(msg).await;
-        initial_splats = Some(message.splats);
+    if let Some(splats) = provided_splats {
+        let splats = splats_to_frame(splats, &dataset.transform).await?;
+        emitter
+            .emit(PipelineMessage::ViewSplats {
+                up_axis: Some(estimated_up),
+                splats: Box::new(splats.clone()),
+                frame: 0,
+                total_frames: 0,
+            })
+            .await;
+        initial_splats = Some(splats_from_srgb(splats, color_space));
+    } else {
+        while let Some(message) = splat_stream.next().await {
+            let message = message?;
+            let msg = PipelineMessage::ViewSplats {
+                // If the metadata has an up axis prefer that, otherwise estimate
+                // the up direction.
+                up_axis: message.meta.up_axis.or(Some(estimated_up)),
+                splats: Box::new(splats_to_srgb(message.splats.clone(), color_space)),
+                frame: 0,
+                total_frames: 0,
+            };
+            emitter.emit(msg).await;
+            initial_splats = Some(message.splats);
+        }
     }
 
     let pipeline_config = &pipeline_config;
@@ -68,34 +90,76 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
         Splats::from_random_config(&config, adjusted_bounds, &mut rng, &device)
     };
 
-    let splats = splats.with_sh_degree(train_config.sh_degree);
+    // Start at the scheduled degree, but keep the bands of provided splats that are still trained.
+    let sh_degree = if train_config.freeze_sh {
+        splats.sh_degree()
+    } else {
+        train_config
+            .sh_degree_at(pipeline_config.start_iter)
+            .max(splats.sh_degree().min(train_config.sh_degree))
+    };
+    let splats = splats.with_sh_degree(sh_degree);
     let mut splats = splats.into_autodiff();
 
+    // Exports are mapped back to the coordinates of the source data.
+    let scene_transform = dataset.transform;
+    let eval_split = match &dataset.eval {
+        Some(eval) => format!("{} ({} views)", dataset.eval_split.describe(), eval.views.len()),
+        None => dataset.eval_split.describe(),
+    };
     let mut eval_scene = dataset.eval;
     let scene_extent = dataset.train.estimate_extent().unwrap_or(1.0);
 
     let mut train_duration = Duration::from_secs(0);
-    let mut dataloader = SceneLoader::new(&dataset.train, 42, &device);
-    let mut trainer = SplatTrainer::new(&train_config, &device);
+    let mut dataloader = SceneLoader::new(
+        &dataset.train,
+        pipeline_config.seed,
+        dataset.color_space,
+        &load_config.loader,
+        &load_config.image_cache,
+        &device,
+    );
+    let mut trainer = SplatTrainer::new(&train_config, dataset.train.views.len(), &device);
+    let train_cameras: Vec<_> = dataset.train.views.iter().map(|view| view.camera.clone()).collect();
+    let train_images: Vec<_> = dataset.train.views.iter().map(|view| view.image.path.to_string_lossy().to_string()).collect();
+    let mut stopping = EarlyStopping::new(pipeline_config, &train_config)?;
+    let mut stop_reason = None;
 
     log::info!("Start training loop.");
+    emitter
+        .emit(PipelineMessage::TrainingStarted {
+            start_iter: pipeline_config.start_iter,
+            total_steps: train_config.total_steps,
+        })
+        .await;
     for iter in pipeline_config.start_iter..train_config.total_steps {
         log::info!("Training iteration {} of {}", iter + 1, train_config.total_steps);
 
         let step_time = Instant::now();
 
-        let batch = dataloader.next_batch().await;
-        let (new_splats, stats) = trainer.step(scene_extent, iter, &batch, splats);
+        dataloader.set_downscale(train_config.downscale_at(iter));
+        let batches = dataloader.next_batches(train_config.batch_size as usize).await?;
+        let (new_splats, stats) = trainer.step(scene_extent, iter, &batches, splats);
+        let (new_splats, divergence) = trainer
+            .check_divergence(iter, new_splats, stats.loss.clone())
+            .await?;
         splats = new_splats;
-        let (new_splats, refine) = trainer.refine_if_needed(iter, splats).await;
+        if dataloader.wants_feedback() {
+            // Reading back the loss syncs with the GPU, so only do it when the sampler uses it.
+            for (view_index, loss) in &stats.view_losses {
+                let loss = loss.clone().into_scalar_async().await;
+                dataloader.report_loss(*view_index, loss);
+            }
+        }
+        let (new_splats, refine) = trainer.refine_if_needed(iter, scene_extent, splats).await;
         splats = new_splats;
 
-        #[allow(unused)]
         let export_path = Path::new(&pipeline_config.export_path).to_owned();
 
         // We just finished iter 'iter', now starting iter + 1.
         let iter = iter + 1;
-        let is_last_step = iter == train_config.total_steps;
+        stop_reason = stopping.after_step(iter, refine.as_ref().map(|_| splats.num_splats()));
+        let mut is_last_step = iter == train_config.total_steps || stop_reason.is_some();
 
         // Check if we want to evaluate _next iteration_. Small detail, but this ensures we evaluate
         // before doing a refine.
@@ -106,6 +170,7 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
                 let mut count = 0;
 
                 log::info!("Running evaluation for iteration {iter}");
+                let background = trainer.background();
 
                 for (i, view) in eval_scene.views.iter().enumerate() {
                     let eval_img = view.image.load().await?;
@@ -116,9 +181,11 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
 
                         eval_stats(
                             splats,
+                            background.clone(),
                             &view.camera,
                             eval_img,
                             view.image.is_masked(),
+                            color_space,
                             &device,
                         ).await.context("Failed to run eval for sample.")?
                     };
@@ -135,7 +202,15 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
                         let path = Path::new(&export_path)
                             .join(format!("eval_{iter}"))
                             .join(format!("{img_name}.hdr"));
-                        eval_save_to_disk(&sample, &path).await?;
+                        for path in eval_save_to_disk(&sample, &path).await? {
+                            emitter
+                                .emit(PipelineMessage::ArtifactSaved {
+                                    kind: ArtifactKind::EvalImage,
+                                    iter,
+                                    path,
+                                })
+                                .await;
+                        }
                     }
                 }
 
@@ -146,9 +221,51 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
                     iter,
                     avg_psnr: psnr,
                     avg_ssim: ssim,
+                    split: eval_split.clone(),
                 };
 
                 emitter.emit(message).await;
+
+                if stop_reason.is_none() {
+                    stop_reason = stopping.after_eval(psnr, ssim);
+                    is_last_step |= stop_reason.is_some();
+                }
+            }
+        }
+
+        if iter % pipeline_config.export_every == 0 || is_last_step {
+            let path = export_path.join(pipeline_config.export_name.replace("{iter}", &iter.to_string()));
+            export_splats_to_disk(splats.valid(), &scene_transform, color_space, &path).await?;
+            emitter
+                .emit(PipelineMessage::ArtifactSaved {
+                    kind: ArtifactKind::Splats,
+                    iter,
+                    path,
+                })
+                .await;
+
+            if let Some(cameras) = trainer.refined_cameras(&train_cameras, scene_extent).await {
+                let path = export_path.join(format!("cameras_{iter}.json"));
+                export_cameras_to_disk(&cameras, &train_images, &scene_transform, &path).await?;
+                emitter
+                    .emit(PipelineMessage::ArtifactSaved {
+                        kind: ArtifactKind::Cameras,
+                        iter,
+                        path,
+                    })
+                    .await;
+            }
+
+            if let Some(background) = trainer.background() {
+                let path = export_path.join(format!("background_{iter}.hdr"));
+                export_background_to_disk(background, &scene_transform, color_space, &path).await?;
+                emitter
+                    .emit(PipelineMessage::ArtifactSaved {
+                        kind: ArtifactKind::Background,
+                        iter,
+                        path,
+                    })
+                    .await;
             }
         }
 
@@ -158,6 +275,14 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
         train_duration += step_time.elapsed();
 
         // Emit some messages. Important to not count these in the training time (as this might pause).
+        if let Some(stats) = divergence {
+            emitter
+                .emit(PipelineMessage::Diverged {
+                    stats: Box::new(stats),
+                    iter,
+                })
+                .await;
+        }
         if let Some(stats) = refine {
             emitter
                 .emit(PipelineMessage::RefineStep {
@@ -172,15 +297,21 @@ pub async fn run(source: Source, load_config: LoadConfig, pipeline_config: Pipel
         const UPDATE_EVERY: u32 = 100;
         if iter % UPDATE_EVERY == 0 || is_last_step {
             let message = PipelineMessage::TrainStep {
-                splats: Box::new(splats.valid()),
+                splats: Box::new(splats_to_srgb(splats.valid(), color_space)),
                 stats: Box::new(stats),
                 iter,
                 total_elapsed: train_duration,
             };
             emitter.emit(message).await;
         }
+
+        if is_last_step {
+            break;
+        }
     }
 
-    emitter.emit(PipelineMessage::Finished).await;
+    let reason = stop_reason.unwrap_or(StopReason::Completed);
+    log::info!("Training finished: {reason}");
+    emitter.emit(PipelineMessage::Finished { reason }).await;
     Ok(())
 }
\ No newline at end of file
@@ -39,10 +39,12 @@ pub trait SplatForwardDiff<B: Backend> {
     /// Render splats to a buffer.
     ///
     /// This projects the gaussians, sorts them, and rasterizes them to a buffer, in a
-    /// differentiable way.
+    /// differentiable way. The `view` (see [`SplatForward::render_splats`]) receives the gradients
+    /// of the view matrix & focal length.
     #[allow(clippy::too_many_arguments)]
     fn render_splats(
         camera: &Camera,
+        view: Option<FloatTensor<B>>,
         img_size: glam::UVec2,
         means: FloatTensor<B>,
         log_scales: FloatTensor<B>,
@@ -61,6 +63,7 @@ pub trait SplatBackwardOps<B: Backend> {
     fn render_splats_bwd(
         state: GaussianBackwardState<B>,
         v_output: FloatTensor<B>,
+        camera_grads: bool,
     ) -> SplatGrads<B>;
 }
 
@@ -68,6 +71,7 @@ impl SplatBackwardOps<Self> for MainBackendBase {
     fn render_splats_bwd(
         state: GaussianBackwardState<Self>,
         v_output: FloatTensor<Self>,
+        camera_grads: bool,
     ) -> SplatGrads<Self> {
         render_backward(
             v_output,
@@ -82,6 +86,7 @@ impl SplatBackwardOps<Self> for MainBackendBase {
             state.tile_offsets,
             state.final_idx,
             state.sh_degree,
+            camera_grads,
         )
     }
 }
@@ -105,7 +110,7 @@ pub struct GaussianBackwardState<B: Backend> {
 #[derive(Debug)]
 struct RenderBackwards;
 
-const NUM_BWD_ARGS: usize = 6;
+const NUM_BWD_ARGS: usize = 7;
 
 // Implement gradient registration when rendering backwards.
 impl<B: Backend + SplatBackwardOps<B>> Backward<B, NUM_BWD_ARGS> for RenderBackwards {
@@ -132,9 +137,10 @@ impl<B: Backend + SplatBackwardOps<B>> Backward<B, NUM_BWD_ARGS> for RenderBackw
         quats_parent,
         coeffs_parent,
         raw_opacity_parent,
+        view_parent,
         ] = ops.parents;
 
-        let v_tens = B::render_splats_bwd(state, v_output);
+        let v_tens = B::render_splats_bwd(state, v_output, view_parent.is_some());
 
         if let Some(node) = mean_parent {
             grads.register::<B>(node.id, v_tens.v_means);
@@ -160,6 +166,10 @@ impl<B: Backend + SplatBackwardOps<B>> Backward<B, NUM_BWD_ARGS> for RenderBackw
         if let Some(node) = raw_opacity_parent {
             grads.register::<B>(node.id, v_tens.v_raw_opac);
         }
+
+        if let (Some(node), Some(v_view)) = (view_parent, v_tens.v_view) {
+            grads.register::<B>(node.id, v_view);
+        }
     }
 }
 
@@ -175,6 +185,7 @@ SplatForwardDiff<Self> for Autodiff<B, C>
 {
     fn render_splats(
         camera: &Camera,
+        view: Option<FloatTensor<Self>>,
         img_size: glam::UVec2,
         means: FloatTensor<Self>,
         log_scales: FloatTensor<Self>,
@@ -188,6 +199,11 @@ SplatForwardDiff<Self> for Autodiff<B, C>
         let device =
             Tensor::<Self, 2>::from_primitive(TensorPrimitive::Float(means.clone())).device();
         let refine_weight_holder = Tensor::<Self, 1>::zeros([1], &device).require_grad();
+        // Without a view, an untracked placeholder stands in for its node.
+        let view_node = match &view {
+            Some(view) => view.node.clone(),
+            None => Tensor::<Self, 1>::zeros([1], &device).into_primitive().tensor().node,
+        };
 
         // Prepare backward pass, and check if we even need to do it. Store nodes that need gradients.
         let prep_nodes = RenderBackwards
@@ -198,6 +214,7 @@ SplatForwardDiff<Self> for Autodiff<B, C>
                 quats.node.clone(),
                 sh_coeffs.node.clone(),
                 raw_opacity.node.clone(),
+                view_node,
             ])
             .compute_bound()
             .stateful();
@@ -205,6 +222,7 @@ SplatForwardDiff<Self> for Autodiff<B, C>
         // Render complete forward pass.
         let (out_img, aux) = <B as SplatForward<B>>::render_splats(
             camera,
+            view.map(|view| view.into_primitive()),
             img_size,
             means.clone().into_primitive(),
             log_scales.clone().into_primitive(),
@@ -271,11 +289,13 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
     fn render_splats_bwd(
         state: GaussianBackwardState<Self>,
         v_output: FloatTensor<Self>,
+        camera_grads: bool,
     ) -> SplatGrads<Self> {
         #[derive(Debug)]
         struct CustomOp {
             desc: CustomOpIr,
             sh_degree: u32,
+            camera_grads: bool,
         }
 
         impl<BT: BoolElement> Operation<FusionCubeRuntime<WgpuRuntime, BT>> for CustomOp {
@@ -283,8 +303,19 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                 &self,
                 h: &mut HandleContainer<FusionHandle<FusionCubeRuntime<WgpuRuntime, BT>>>,
             ) {
-                let (
-                    [
+                let inputs: &[_; 12] = self
+                    .desc
+                    .inputs
+                    .as_slice()
+                    .try_into()
+                    .expect("Wrong number of inputs of render_splat_bwd");
+                // The view gradient is an optional last output.
+                let [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_refine, v_view @ ..] =
+                    self.desc.outputs.as_slice()
+                else {
+                    panic!("Missing outputs of render_splat_bwd");
+                };
+                let [
                     v_output,
                     means,
                     quats,
@@ -297,9 +328,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                     tile_offsets,
                     compact_gid_from_isect,
                     global_from_compact_gid,
-                    ],
-                    [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_refine],
-                ) = self.desc.as_fixed();
+                ] = inputs;
 
                 let inner_state = GaussianBackwardState {
                     means: h.get_float_tensor::<MainBackendBase>(means),
@@ -322,6 +351,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                     <MainBackendBase as SplatBackwardOps<MainBackendBase>>::render_splats_bwd(
                         inner_state,
                         h.get_float_tensor::<MainBackendBase>(v_output),
+                        self.camera_grads,
                     );
 
                 // // Register output.
@@ -331,6 +361,9 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                 h.register_float_tensor::<MainBackendBase>(&v_coeffs.id, grads.v_coeffs);
                 h.register_float_tensor::<MainBackendBase>(&v_raw_opac.id, grads.v_raw_opac);
                 h.register_float_tensor::<MainBackendBase>(&v_refine.id, grads.v_refine_weight);
+                if let (Some(v_view), Some(grad)) = (v_view.first(), grads.v_view) {
+                    h.register_float_tensor::<MainBackendBase>(&v_view.id, grad);
+                }
             }
         }
 
@@ -345,6 +378,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
             v_coeffs: client.tensor_uninitialized(vec![num_points, coeffs, 3], DType::F32),
             v_raw_opac: client.tensor_uninitialized(vec![num_points], DType::F32),
             v_refine_weight: client.tensor_uninitialized(vec![num_points, 2], DType::F32),
+            v_view: camera_grads.then(|| client.tensor_uninitialized(vec![18], DType::F32)),
         };
 
         let input_tensors = [
@@ -362,14 +396,18 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
             state.global_from_compact_gid,
         ];
 
-        let output_tensors = [
+        let output_tensors: Vec<_> = [
             &grads.v_means,
             &grads.v_quats,
             &grads.v_scales,
             &grads.v_coeffs,
             &grads.v_raw_opac,
             &grads.v_refine_weight,
-        ];
+        ]
+        .into_iter()
+        .chain(&grads.v_view)
+        .map(|t| t.to_ir_out())
+        .collect();
 
         let mut stream = OperationStreams::default();
         for inp in &input_tensors {
@@ -379,7 +417,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
         let desc = CustomOpIr::new(
             "render_splat_bwd",
             &input_tensors.map(|t| t.into_ir()),
-            &output_tensors.map(|t| t.to_ir_out()),
+            &output_tensors,
         );
 
         client.register(
@@ -389,6 +427,7 @@ impl SplatBackwardOps<Self> for Fusion<MainBackendBase> {
                 // state,
                 desc,
                 sh_degree: state.sh_degree,
+                camera_grads,
             },
         );
         grads
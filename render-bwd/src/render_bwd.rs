@@ -11,7 +11,7 @@ use burn_cubecl::cubecl::server::Bindings;
 use glam::uvec2;
 
 kernel_source_gen!(GatherGrads {}, gather_grads);
-kernel_source_gen!(ProjectBackwards {}, project_backwards);
+kernel_source_gen!(ProjectBackwards { camera_grads }, project_backwards);
 kernel_source_gen!(RasterizeBackwards { hard_float }, rasterize_backwards);
 
 #[derive(Debug, Clone)]
@@ -22,6 +22,8 @@ pub struct SplatGrads<B: Backend> {
     pub v_coeffs: FloatTensor<B>,
     pub v_raw_opac: FloatTensor<B>,
     pub v_refine_weight: FloatTensor<B>,
+    /// Gradient of the view matrix & focal length [18], when camera gradients were requested.
+    pub v_view: Option<FloatTensor<B>>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -40,6 +42,7 @@ pub(crate) fn render_backward(
     tile_offsets: CubeTensor<WgpuRuntime>,
     final_index: CubeTensor<WgpuRuntime>,
     sh_degree: u32,
+    camera_grads: bool,
 ) -> SplatGrads<MainBackendBase> {
     let device = &out_img.device;
     let img_dimgs = out_img.shape.dims;
@@ -122,27 +125,40 @@ pub(crate) fn render_backward(
         );
     }
 
+    // Gradients of the view matrix & focal length per visible splat, summed below.
+    let v_camera = camera_grads
+        .then(|| MainBackendBase::float_zeros([num_points, 18].into(), device));
+
+    let mut buffers = vec![
+        uniforms_buffer.handle.binding(),
+        means.handle.binding(),
+        log_scales.handle.binding(),
+        quats.handle.binding(),
+        global_from_compact_gid.handle.binding(),
+        v_grads.handle.binding(),
+        v_means.handle.clone().binding(),
+        v_scales.handle.clone().binding(),
+        v_quats.handle.clone().binding(),
+    ];
+    if let Some(v_camera) = &v_camera {
+        buffers.push(v_camera.handle.clone().binding());
+    }
+
     tracing::trace_span!("ProjectBackwards", sync_burn = true).in_scope(||
         // SAFETY: Kernel has to contain no OOB indexing, bounded loops.
         unsafe {
         client.execute_unchecked(
-            ProjectBackwards::task(),
+            ProjectBackwards::task(camera_grads),
             calc_cube_count([num_points as u32], ProjectBackwards::WORKGROUP_SIZE),
-            Bindings::new().with_buffers(
-            vec![
-                uniforms_buffer.handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
-                global_from_compact_gid.handle.binding(),
-                v_grads.handle.binding(),
-                v_means.handle.clone().binding(),
-                v_scales.handle.clone().binding(),
-                v_quats.handle.clone().binding(),
-            ]),
+            Bindings::new().with_buffers(buffers),
         );
     });
 
+    let v_view = v_camera.map(|v_camera| {
+        let v_camera = MainBackendBase::float_sum_dim(v_camera, 0);
+        MainBackendBase::float_reshape(v_camera, [18].into())
+    });
+
     SplatGrads {
         v_means,
         v_quats,
@@ -150,5 +166,6 @@ pub(crate) fn render_backward(
         v_coeffs,
         v_raw_opac: v_opac,
         v_refine_weight,
+        v_view,
     }
 }
@@ -15,6 +15,11 @@
 @group(0) @binding(7) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
 @group(0) @binding(8) var<storage, read_write> v_quats: array<vec4f>;
 
+#ifdef CAMERA_GRADS
+// Gradient of the view matrix (column major) & focal length per visible splat, summed afterwards.
+@group(0) @binding(9) var<storage, read_write> v_camera: array<f32>;
+#endif
+
 fn normalize_vjp(quat: vec4f) -> mat4x4f {
     let quat_sqr = quat * quat;
     let quat_len_sqr = dot(quat, quat);
@@ -146,6 +151,43 @@ fn persp_proj_vjp(
     return v_mean3d;
 }
 
+// Gradient of the focal length (fx, fy), through the projected mean & the jacobian.
+fn focal_vjp(
+    J: mat3x2f,
+    mean3d: vec3f,
+    cov3d: mat3x3f,
+    focal: vec2f,
+    pixel_center: vec2f,
+    img_size: vec2u,
+    v_cov2d: mat2x2f,
+    v_mean2d: vec2f,
+) -> vec2f {
+    let rz = 1.0 / mean3d.z;
+    let rz2 = rz * rz;
+
+    // mean2d = focal * mean.xy * rz + pixel_center
+    var v_focal = v_mean2d * mean3d.xy * rz;
+
+    // J_00 = fx * rz, J_11 = fy * rz
+    let v_J = v_cov2d * J * transpose(cov3d) + transpose(v_cov2d) * J * cov3d;
+    v_focal += vec2f(v_J[0][0], v_J[1][1]) * rz;
+
+    // J_02 = -fx * tx * rz2. Outside of the frustum tx is clipped to a limit that scales
+    // with 1 / fx, so J_02 doesn't depend on the focal length there.
+    let tan_fov = 0.5 * vec2f(img_size.xy) / focal;
+    let lims_pos = (vec2f(img_size.xy) - pixel_center) / focal + 0.3f * tan_fov;
+    let lims_neg = pixel_center / focal + 0.3f * tan_fov;
+    let ndc = mean3d.xy * rz;
+
+    if (ndc.x <= lims_pos.x && ndc.x >= -lims_neg.x) {
+        v_focal.x += -mean3d.x * rz2 * v_J[2][0];
+    }
+    if (ndc.y <= lims_pos.y && ndc.y >= -lims_neg.y) {
+        v_focal.y += -mean3d.y * rz2 * v_J[2][1];
+    }
+    return v_focal;
+}
+
 @compute
 @workgroup_size(256, 1, 1)
 fn main(@builtin(global_invocation_id) gid: vec3u) {
@@ -204,16 +246,27 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // for D = W * X, G = df/dD
     // df/dW = G * XT, df/dX = WT * G
 
-    // TODO: Camera gradient is not done yet.
-    // var v_R = outer_product(v_mean_c, mean);
     let v_mean = transpose(R) * v_mean_c;
+    let v_covar = transpose(R) * v_covar_c * R;
 
+#ifdef CAMERA_GRADS
+    // mean_c = R * mean + t -> df/dR = v_mean_c * mean^T, df/dt = v_mean_c
+    var v_R = mat3x3f(v_mean_c * mean.x, v_mean_c * mean.y, v_mean_c * mean.z);
     // covar_world_to_cam_vjp
-    // TODO: Camera gradient is not done yet.
-    // v_R += v_covar_c * R * transpose(covar) +
-    //        transpose(v_covar_c) * R * covar;
-
-    let v_covar = transpose(R) * v_covar_c * R;
+    v_R += v_covar_c * R * transpose(covar) +
+           transpose(v_covar_c) * R * covar;
+    let v_focal = focal_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+
+    let base = compact_gid * 18;
+    for (var i = 0; i < 3; i++) {
+        for (var j = 0; j < 3; j++) {
+            v_camera[base + i * 4 + j] = v_R[i][j];
+        }
+        v_camera[base + 12 + i] = v_mean_c[i];
+    }
+    v_camera[base + 16] = v_focal.x;
+    v_camera[base + 17] = v_focal.y;
+#endif
 
     // quat_scale_to_covar_vjp
     // TODO: Merge with cov calculation.
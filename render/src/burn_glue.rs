@@ -21,6 +21,7 @@ use crate::{
 impl SplatForward<Self> for MainBackendBase {
     fn render_splats(
         camera: &Camera,
+        view: Option<FloatTensor<Self>>,
         img_size: glam::UVec2,
         means: FloatTensor<Self>,
         log_scales: FloatTensor<Self>,
@@ -31,7 +32,8 @@ impl SplatForward<Self> for MainBackendBase {
         bwd_info: bool,
     ) -> (FloatTensor<Self>, RenderAux<Self>) {
         render_forward(
-            camera, img_size, means, log_scales, quats, sh_coeffs, opacity, background, bwd_info,
+            camera, view, img_size, means, log_scales, quats, sh_coeffs, opacity, background,
+            bwd_info,
         )
     }
 }
@@ -39,6 +41,7 @@ impl SplatForward<Self> for MainBackendBase {
 impl SplatForward<Self> for Fusion<MainBackendBase> {
     fn render_splats(
         cam: &Camera,
+        view: Option<FloatTensor<Self>>,
         img_size: glam::UVec2,
         means: FloatTensor<Self>,
         log_scales: FloatTensor<Self>,
@@ -62,9 +65,18 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
                 &self,
                 h: &mut HandleContainer<FusionHandle<FusionCubeRuntime<WgpuRuntime, BT>>>,
             ) {
-                let (inputs, outputs) = self.desc.as_fixed();
-
-                let [means, log_scales, quats, sh_coeffs, opacity] = inputs;
+                // The view is an optional last input.
+                let [means, log_scales, quats, sh_coeffs, opacity, view @ ..] =
+                    self.desc.inputs.as_slice()
+                else {
+                    panic!("Missing inputs of render_splats");
+                };
+                let outputs: &[_; 8] = self
+                    .desc
+                    .outputs
+                    .as_slice()
+                    .try_into()
+                    .expect("Wrong number of outputs of render_splats");
                 let [
                 projected_splats,
                 uniforms_buffer,
@@ -78,6 +90,8 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
 
                 let (img, aux) = MainBackendBase::render_splats(
                     &self.cam,
+                    view.first()
+                        .map(|view| h.get_float_tensor::<MainBackendBase>(view)),
                     self.img_size,
                     h.get_float_tensor::<MainBackendBase>(means),
                     h.get_float_tensor::<MainBackendBase>(log_scales),
@@ -150,7 +164,10 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
         };
 
         let mut stream = OperationStreams::default();
-        let input_tensors = [means, log_scales, quats, sh_coeffs, opacity];
+        let input_tensors: Vec<_> = [means, log_scales, quats, sh_coeffs, opacity]
+            .into_iter()
+            .chain(view)
+            .collect();
         let output_tensors = [
             &aux.projected_splats,
             &aux.uniforms_buffer,
@@ -166,7 +183,7 @@ impl SplatForward<Self> for Fusion<MainBackendBase> {
         }
         let desc = CustomOpIr::new(
             "render_splats",
-            &input_tensors.map(|t| t.into_ir()),
+            &input_tensors.into_iter().map(|t| t.into_ir()).collect::<Vec<_>>(),
             &output_tensors.map(|t| t.to_ir_out()),
         );
         let op = CustomOp {
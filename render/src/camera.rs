@@ -40,6 +40,19 @@ impl Camera {
         )
     }
 
+    /// The camera that sees the `size` pixels at `offset` of an `img_size` image, with the same
+    /// focal length in pixels.
+    pub fn crop(&self, img_size: glam::UVec2, offset: glam::UVec2, size: glam::UVec2) -> Self {
+        let focal = self.focal(img_size);
+        let center = self.center(img_size) - offset.as_vec2();
+        Self {
+            fov_x: focal_to_fov(focal.x as f64, size.x),
+            fov_y: focal_to_fov(focal.y as f64, size.y),
+            center_uv: center / size.as_vec2(),
+            ..self.clone()
+        }
+    }
+
     pub fn local_to_world(&self) -> Affine3A {
         Affine3A::from_rotation_translation(self.rotation, self.position)
     }
@@ -56,4 +69,33 @@ pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
 // Converts focal length to field of view
 pub fn focal_to_fov(focal: f64, pixels: u32) -> f64 {
     2.0 * f64::atan((pixels as f64) / (2.0 * focal))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_keeps_focal_and_principal_point() {
+        let camera = Camera::new(
+            glam::Vec3::ZERO,
+            glam::Quat::IDENTITY,
+            1.2,
+            0.8,
+            glam::vec2(0.45, 0.55),
+        );
+        let img_size = glam::uvec2(4000, 3000);
+        let offset = glam::uvec2(1200, 700);
+        let size = glam::uvec2(512, 256);
+        let crop = camera.crop(img_size, offset, size);
+
+        assert!(camera.focal(img_size).abs_diff_eq(crop.focal(size), 1e-2));
+        let center = camera.center(img_size) - offset.as_vec2();
+        assert!(center.abs_diff_eq(crop.center(size), 1e-3));
+
+        // Cropping the whole image changes nothing.
+        let full = camera.crop(img_size, glam::UVec2::ZERO, img_size);
+        assert!((full.fov_x - camera.fov_x).abs() < 1e-5);
+        assert!(full.center_uv.abs_diff_eq(camera.center_uv, 1e-6));
+    }
+}
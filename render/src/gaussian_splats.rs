@@ -312,6 +312,7 @@ impl<B: Backend + SplatForward<B>> Splats<B> {
 
         let (img, aux) = B::render_splats(
             camera,
+            None,
             img_size,
             self.means.val().into_primitive().tensor(),
             scales.into_primitive().tensor(),
@@ -44,8 +44,11 @@ pub trait SplatForward<B: Backend> {
     /// The [`xy_grad_dummy`] variable is only used to carry screenspace xy gradients.
     /// This function can optionally render a "u32" buffer, which is a packed RGBA (8 bits per channel)
     /// buffer. This is useful when the results need to be displayed immediately.
+    /// A `view` tensor [18] holds a view matrix (column major) & focal length that replace the ones
+    /// of `camera`, for cameras that are being optimized and live on the GPU.
     fn render_splats(
         camera: &Camera,
+        view: Option<FloatTensor<B>>,
         img_size: glam::UVec2,
         means: FloatTensor<B>,
         log_scales: FloatTensor<B>,
@@ -15,7 +15,7 @@ use kernel::create_uniform_buffer;
 use kernel::{CubeCount, calc_cube_count};
 use prefix_sum::prefix_sum;
 use sort::radix_argsort;
-use burn::tensor::{DType, Int, s};
+use burn::tensor::{DType, Int, TensorPrimitive, s};
 use burn::tensor::{
     Tensor,
     ops::{FloatTensorOps, IntTensorOps},
@@ -59,8 +59,51 @@ pub fn max_intersections(img_size: glam::UVec2, num_splats: u32) -> u32 {
         .min(INTERSECTS_UPPER_BOUND)
 }
 
+// Writes the view matrix & focal length of `view` ([18], the matrix column major), and the camera
+// position they imply, over those of the camera in the uniforms.
+fn write_view(
+    uniforms_buffer: CubeTensor<WgpuRuntime>,
+    view: CubeTensor<WgpuRuntime>,
+) -> CubeTensor<WgpuRuntime> {
+    let view = Tensor::<MainBackendBase, 1>::from_primitive(TensorPrimitive::Float(view));
+
+    // Rows are the columns of the view matrix, so this block is the transposed rotation.
+    let cols = view.clone().slice(s![0..16]).reshape([4, 4]);
+    let rot_t = cols.clone().slice(s![0..3, 0..3]);
+    let translation = cols.slice(s![3..4, 0..3]);
+    // The camera sits at -R^T * t.
+    let position = translation.matmul(rot_t.transpose()).neg().reshape([3]);
+
+    // The uniforms are stored as i32, copy the bits of the floats over.
+    let as_bits = |values: Tensor<MainBackendBase, 1>| CubeTensor {
+        dtype: DType::I32,
+        ..into_contiguous(values.into_primitive().tensor())
+    };
+
+    let focal_offset = offset_of!(shaders::helpers::RenderUniforms, focal) / 4;
+    let position_offset = offset_of!(shaders::helpers::RenderUniforms, camera_position) / 4;
+    let uniforms_buffer = MainBackendBase::int_slice_assign(
+        uniforms_buffer,
+        &[0..16],
+        as_bits(view.clone().slice(s![0..16])),
+    );
+    let uniforms_buffer = MainBackendBase::int_slice_assign(
+        uniforms_buffer,
+        &[focal_offset..focal_offset + 2],
+        as_bits(view.slice(s![16..18])),
+    );
+    MainBackendBase::int_slice_assign(
+        uniforms_buffer,
+        &[position_offset..position_offset + 3],
+        as_bits(position),
+    )
+}
+
+/// Renders the splats as seen by `camera`. When `view` is given, its view matrix & focal length
+/// are used instead of the ones of `camera`, see [`crate::SplatForward::render_splats`].
 pub(crate) fn render_forward(
     camera: &Camera,
+    view: Option<CubeTensor<WgpuRuntime>>,
     img_size: glam::UVec2,
     means: CubeTensor<WgpuRuntime>,
     log_scales: CubeTensor<WgpuRuntime>,
@@ -135,6 +178,10 @@ pub(crate) fn render_forward(
     // Nb: This contains both static metadata and some dynamic data so can't pass this as metadata to execute. In the future
     // should separate the two.
     let uniforms_buffer = create_uniform_buffer(uniforms, device, &client);
+    let uniforms_buffer = match view {
+        Some(view) => write_view(uniforms_buffer, into_contiguous(view)),
+        None => uniforms_buffer,
+    };
 
     let client = &means.client.clone();
 
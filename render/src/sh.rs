@@ -1,7 +1,12 @@
 use crate::shaders;
 
-use glam::Vec3;
-const SH_C0: f32 = shaders::project_visible::SH_C0;
+use glam::{Quat, Vec3};
+
+/// Weight of the constant SH basis function.
+pub const SH_C0: f32 = shaders::project_visible::SH_C0;
+
+/// Highest SH degree the renderer evaluates.
+pub const MAX_SH_DEGREE: u32 = 4;
 
 pub const fn sh_coeffs_for_degree(degree: u32) -> u32 {
     (degree + 1).pow(2)
@@ -28,4 +33,193 @@ pub fn rgb_to_sh(rgb: Vec3) -> Vec3 {
         channel_to_sh(rgb.y),
         channel_to_sh(rgb.z),
     )
-}
\ No newline at end of file
+}
+/// Evaluates the basis functions of one band at a unit direction.
+/// Matches the (Sloan) basis `sh_coeffs_to_color` uses in `project_visible.wgsl`.
+/// `band` is clamped to [`MAX_SH_DEGREE`].
+fn sh_band(band: u32, dir: Vec3) -> Vec<f32> {
+    let (x, y, z) = (dir.x, dir.y, dir.z);
+    let z2 = z * z;
+    let c1 = x * x - y * y;
+    let s1 = 2.0 * x * y;
+    let c2 = x * c1 - y * s1;
+    let s2 = x * s1 + y * c1;
+    let sh6 = 0.946_174_7 * z2 - 0.315_391_57;
+    let sh12 = z * (1.865_881_7 * z2 - 1.119_529);
+
+    match band.min(MAX_SH_DEGREE) {
+        0 => vec![SH_C0],
+        1 => {
+            let a = 0.488_602_5;
+            vec![-a * y, a * z, -a * x]
+        }
+        2 => {
+            let b = -1.092_548_4 * z;
+            let a = 0.546_274_2;
+            vec![a * s1, b * y, sh6, b * x, a * c1]
+        }
+        3 => {
+            let c = -2.285_229 * z2 + 0.457_045_8;
+            let b = 1.445_305_7 * z;
+            let a = -0.590_043_6;
+            vec![a * s2, b * s1, c * y, sh12, c * x, b * c1, a * c2]
+        }
+        _ => {
+            let d = z * (-4.683_326 * z2 + 2.007_139_6);
+            let c = 3.311_611_4 * z2 - 0.473_087_35;
+            let b = -1.770_130_8 * z;
+            let a = 0.625_835_7;
+            let c3 = x * c2 - y * s2;
+            let s3 = x * s2 + y * c2;
+            let sh20 = 1.984_313_5 * z * sh12 - 1.006_230_6 * sh6;
+            vec![a * s3, b * s2, c * s1, d * y, sh20, d * x, c * c1, b * c2, a * c3]
+        }
+    }
+}
+
+/// Rotates sh coefficients, so that splats keep their look when the scene is rotated.
+///
+/// The coefficients within a band mix linearly under rotation. The mixing matrix of each band is
+/// fitted by evaluating the basis at a set of directions before & after rotating them.
+/// Bands above [`MAX_SH_DEGREE`] aren't rendered, and are left as they are.
+pub struct ShRotation {
+    // Per band, row major (2l + 1)^2 matrix mapping old to new coefficients.
+    bands: Vec<Vec<f32>>,
+}
+
+impl ShRotation {
+    pub fn new(rotation: Quat, degree: u32) -> Self {
+        const SAMPLES: usize = 64;
+
+        // Spread the sample directions evenly over the sphere.
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+        let dirs: Vec<Vec3> = (0..SAMPLES)
+            .map(|i| {
+                let y = 1.0 - 2.0 * (i as f32 + 0.5) / SAMPLES as f32;
+                let r = (1.0 - y * y).sqrt();
+                let theta = golden_angle * i as f32;
+                glam::vec3(r * theta.cos(), y, r * theta.sin())
+            })
+            .collect();
+
+        let inv_rotation = rotation.inverse();
+        let bands = (1..=degree.min(MAX_SH_DEGREE))
+            .map(|band| {
+                let n = 2 * band as usize + 1;
+                // The rotated function at dir is the original one at inv_rotation * dir. Find M with
+                // basis(inv_rotation * dir) = M * basis(dir) in the least squares sense.
+                let mut gram = vec![0.0f64; n * n];
+                let mut cross = vec![0.0f64; n * n];
+                for &dir in &dirs {
+                    let orig = sh_band(band, dir);
+                    let rotated = sh_band(band, inv_rotation * dir);
+                    for (i, (&o_i, &r_i)) in orig.iter().zip(&rotated).enumerate() {
+                        for (j, &o_j) in orig.iter().enumerate() {
+                            gram[i * n + j] += (o_i * o_j) as f64;
+                            cross[i * n + j] += (r_i * o_j) as f64;
+                        }
+                    }
+                }
+                // M = cross * gram^-1, gram is symmetric so solve gram * M^T = cross^T.
+                let m_t = solve(gram, transpose(&cross, n), n);
+                // New coefficients are M^T * old, keep that matrix.
+                m_t.into_iter().map(|v| v as f32).collect()
+            })
+            .collect();
+
+        Self { bands }
+    }
+
+    /// Rotates the coefficients of one splat, laid out as [coeff, channel].
+    pub fn apply(&self, coeffs: &mut [f32]) {
+        for (band, matrix) in self.bands.iter().enumerate() {
+            let band = band as u32 + 1;
+            let n = 2 * band as usize + 1;
+            let start = band.pow(2) as usize;
+            if coeffs.len() < (start + n) * 3 {
+                break;
+            }
+
+            for c in 0..3 {
+                let old: Vec<f32> = (0..n).map(|i| coeffs[(start + i) * 3 + c]).collect();
+                for i in 0..n {
+                    coeffs[(start + i) * 3 + c] = (0..n).map(|j| matrix[i * n + j] * old[j]).sum();
+                }
+            }
+        }
+    }
+}
+
+fn transpose(matrix: &[f64], n: usize) -> Vec<f64> {
+    (0..n * n).map(|k| matrix[(k % n) * n + k / n]).collect()
+}
+
+// Solves a * x = b for a square system with n columns on the right hand side, using
+// Gauss-Jordan elimination with partial pivoting.
+fn solve(mut a: Vec<f64>, mut b: Vec<f64>, n: usize) -> Vec<f64> {
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&r1, &r2| a[r1 * n + col].abs().total_cmp(&a[r2 * n + col].abs()))
+            .unwrap_or(col);
+        for k in 0..n {
+            a.swap(col * n + k, pivot * n + k);
+            b.swap(col * n + k, pivot * n + k);
+        }
+
+        let diag = a[col * n + col];
+        for k in 0..n {
+            a[col * n + k] /= diag;
+            b[col * n + k] /= diag;
+        }
+        for row in (0..n).filter(|&row| row != col) {
+            let factor = a[row * n + col];
+            for k in 0..n {
+                a[row * n + k] -= factor * a[col * n + k];
+                b[row * n + k] -= factor * b[col * n + k];
+            }
+        }
+    }
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(coeffs: &[f32], degree: u32, dir: Vec3) -> f32 {
+        (0..=degree)
+            .flat_map(|band| sh_band(band, dir))
+            .enumerate()
+            .map(|(i, basis)| basis * coeffs[i * 3])
+            .sum()
+    }
+
+    #[test]
+    fn rotated_coefficients_follow_the_rotation() {
+        let degree = 4;
+        let num_coeffs = sh_coeffs_for_degree(degree) as usize;
+        let coeffs: Vec<f32> = (0..num_coeffs * 3).map(|i| ((i * 7919) % 13) as f32 / 13.0 - 0.5).collect();
+        let rotation = Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.2, 2.0);
+
+        let mut rotated = coeffs.clone();
+        ShRotation::new(rotation, degree).apply(&mut rotated);
+
+        for dir in [Vec3::X, Vec3::Y, glam::vec3(0.3, -0.5, 0.8).normalize(), glam::vec3(-0.9, 0.1, -0.2).normalize()] {
+            let expected = eval(&coeffs, degree, dir);
+            let actual = eval(&rotated, degree, rotation * dir);
+            assert!((expected - actual).abs() < 1e-4, "{expected} != {actual}");
+        }
+    }
+
+    #[test]
+    fn rotation_ignores_bands_above_the_max_degree() {
+        let num_coeffs = sh_coeffs_for_degree(MAX_SH_DEGREE + 1) as usize;
+        let coeffs: Vec<f32> = (0..num_coeffs * 3).map(|i| i as f32).collect();
+
+        let mut rotated = coeffs.clone();
+        ShRotation::new(Quat::from_rotation_z(0.7), MAX_SH_DEGREE + 1).apply(&mut rotated);
+
+        let start = sh_coeffs_for_degree(MAX_SH_DEGREE) as usize * 3;
+        assert_eq!(&rotated[start..], &coeffs[start..], "Bands above the max degree should be kept");
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, SceneSourceError>;
@@ -7,12 +8,18 @@ pub enum SceneSourceError {
     #[error("File system error")]
     Filesystem,
 
-    #[error("File IO error")]
+    #[error("File IO error: {0}")]
     File(#[from] std::io::Error),
 
-    #[error("Zip error")]
+    #[error("Zip error: {0}")]
     Zip(#[from] zip::result::ZipError),
 
+    #[error("Duplicate path found: {}. Paths must be unique (case non-sensitive)", .0.display())]
+    DuplicatePath(PathBuf),
+
+    #[error("Unsupported source: {0}")]
+    Unsupported(&'static str),
+
     #[error("Unknown source")]
     UnknownSource,
-}
\ No newline at end of file
+}
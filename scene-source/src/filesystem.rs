@@ -28,7 +28,9 @@ pub struct Dir {
 
 #[derive(Clone)]
 enum Container {
-    Zip(ZipArchive<Cursor<ZipData>>)
+    Zip(ZipArchive<Cursor<ZipData>>),
+    /// Files are read from disk, relative to this directory.
+    Dir(PathBuf),
 }
 
 #[derive(Clone)]
@@ -56,7 +58,7 @@ impl Filesystem {
             Box::new(AsyncReadExt::chain(Cursor::new(peek.clone()), data));
 
         if peek.as_slice().starts_with(b"ply") {
-            todo!();
+            Err(SceneSourceError::Unsupported("ply files"))
         } else if peek.starts_with(b"PK") {
             let mut bytes = vec![];
             reader.read_to_end(&mut bytes).await?;
@@ -65,16 +67,39 @@ impl Filesystem {
             }))?;
             let file_names: Vec<_> = archive.file_names().map(PathBuf::from).collect();
             Ok(Self {
-                lookup: lookup_from_paths(&file_names),
+                lookup: lookup_from_paths(&file_names)?,
                 container: Container::Zip(archive),
             })
         } else if peek.starts_with(b"<!DOCTYPE html>") {
-            todo!();
+            Err(SceneSourceError::Unsupported("web pages"))
         } else {
             Err(SceneSourceError::UnknownSource)
         }
     }
 
+    /// Indexes all files below the directory. Files are only read when requested.
+    pub async fn from_dir(root: impl Into<PathBuf>) -> Result<Filesystem> {
+        let root = root.into();
+        let mut file_names = vec![];
+        let mut pending = vec![PathBuf::new()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = tokio::fs::read_dir(root.join(&dir)).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = dir.join(entry.file_name());
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else {
+                    file_names.push(path);
+                }
+            }
+        }
+
+        Ok(Self {
+            lookup: lookup_from_paths(&file_names)?,
+            container: Container::Dir(root),
+        })
+    }
+
     pub fn files_with_extension<'a>(
         &'a self,
         extension: &'a str,
@@ -110,6 +135,10 @@ impl Filesystem {
         })
     }
 
+    pub fn has_file(&self, path: &Path) -> bool {
+        self.lookup.contains_key(&PathKey::from_path(path))
+    }
+
     pub async fn reader_at_path(&self, path: &Path) -> io::Result<Box<dyn AsyncRead + Unpin + Send>> {
         let key = PathKey::from_path(path);
         let path = self.lookup.get(&key).ok_or_else(|| {
@@ -121,14 +150,16 @@ impl Filesystem {
 
         match &self.container {
             Container::Zip(archive) => {
-                let name = path
-                    .to_str()
-                    .expect("Invalid UTF-8 in zip file")
-                    .replace('\\', "/");
+                // Zip names are read as UTF-8, so the lookup path converts back losslessly.
+                let name = path.to_string_lossy().replace('\\', "/");
                 let mut buffer = vec![];
                 archive.clone().by_name(&name)?.read_to_end(&mut buffer)?;
                 Ok(Box::new(Cursor::new(buffer)))
             }
+            Container::Dir(root) => {
+                let file = tokio::fs::File::open(root.join(path)).await?;
+                Ok(Box::new(file))
+            }
         }
     }
 }
@@ -147,8 +178,7 @@ impl PathKey {
     fn from_path(path: &Path) -> Self {
         let key = path
             .clean()
-            .to_str()
-            .expect("Path is not valid ascii")
+            .to_string_lossy()
             .to_lowercase()
             .replace('\\', "/");
         let key = if key.starts_with('/') {
@@ -160,7 +190,7 @@ impl PathKey {
     }
 }
 
-fn lookup_from_paths(paths: &[PathBuf]) -> HashMap<PathKey, PathBuf> {
+fn lookup_from_paths(paths: &[PathBuf]) -> Result<HashMap<PathKey, PathBuf>> {
     let mut result = HashMap::new();
     for path in paths {
         let path = path.clean();
@@ -170,12 +200,10 @@ fn lookup_from_paths(paths: &[PathBuf]) -> HashMap<PathKey, PathBuf> {
         // so just skip them.
         if path.extension().is_some() && !path.components().any(|c| c.as_os_str() == "__MACOSX") {
             let key = PathKey::from_path(&path);
-            assert!(
-                result.insert(key, path.clone()).is_none(),
-                "Duplicate path found: {}. Paths must be unique (case non-sensitive)",
-                path.display()
-            );
+            if result.insert(key, path.clone()).is_some() {
+                return Err(SceneSourceError::DuplicatePath(path));
+            }
         }
     }
-    result
+    Ok(result)
 }
\ No newline at end of file
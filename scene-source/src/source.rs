@@ -2,7 +2,7 @@ use crate::filesystem::File;
 use serde::{Deserialize, Serialize};
 use tokio::stream;
 use tokio_util::io::StreamReader;
-use crate::error::Result;
+use crate::error::{Result, SceneSourceError};
 use crate::filesystem::Filesystem;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -19,12 +19,8 @@ impl Source {
                 let file = tokio::fs::File::open(&path).await?;
                 Filesystem::from_reader(file).await
             },
-            Source::Dir { path } => {
-                todo!();
-            },
-            Source::Url { url } => {
-                todo!();
-            },
+            Source::Dir { path } => Filesystem::from_dir(path).await,
+            Source::Url { .. } => Err(SceneSourceError::Unsupported("urls")),
         }
     }
 }
@@ -0,0 +1,247 @@
+use burn::{
+    module::{Module, Param, ParamId},
+    prelude::Backend,
+    tensor::{Tensor, backend::AutodiffBackend},
+};
+use glam::{Mat3, Quat, UVec2, Vec3};
+use render::{
+    camera::Camera,
+    sh::{SH_C0, sh_coeffs_for_degree, sh_degree_from_coeffs},
+};
+
+const SH_C1: f32 = 0.488_602_5;
+const SH_C2: [f32; 5] = [1.092_548_4, -1.092_548_4, 0.315_391_6, -1.092_548_4, 0.546_274_2];
+const SH_C3: [f32; 7] = [
+    -0.590_043_6,
+    2.890_611_4,
+    -0.457_045_8,
+    0.373_176_3,
+    -0.457_045_8,
+    1.445_305_7,
+    -0.590_043_6,
+];
+
+/// A learned background behind the splats, like a sky: a color for each view direction, stored as
+/// spherical harmonics. Composited under the splats, so distant parts of the scene don't have to be
+/// explained by huge splats.
+#[derive(Module, Debug)]
+pub struct BackgroundModel<B: Backend> {
+    // [coeffs, 3], offset by 0.5 like the colors of splats.
+    pub(crate) coeffs: Param<Tensor<B, 2>>,
+}
+
+impl<B: Backend> BackgroundModel<B> {
+    /// A uniform gray background. Degrees above 3 are clamped to 3.
+    pub fn new(sh_degree: u32, device: &B::Device) -> Self {
+        let coeff_count = sh_coeffs_for_degree(sh_degree.min(3)) as usize;
+        Self {
+            coeffs: Param::initialized(
+                ParamId::new(),
+                Tensor::zeros([coeff_count, 3], device).require_grad(),
+            ),
+        }
+    }
+
+    pub fn sh_degree(&self) -> u32 {
+        sh_degree_from_coeffs(self.coeffs.dims()[0] as u32)
+    }
+
+    /// Colors seen along the unit directions `dirs` [n, 3], as [n, 3].
+    pub fn colors(&self, dirs: Tensor<B, 2>) -> Tensor<B, 2> {
+        let basis = sh_basis(dirs, self.sh_degree());
+        (basis.matmul(self.coeffs.val()) + 0.5).clamp_min(0.0)
+    }
+
+    /// The background seen by each pixel of the camera, as [h, w, 3].
+    pub fn render(&self, camera: &Camera, img_size: UVec2) -> Tensor<B, 3> {
+        let device = self.coeffs.device();
+        let focal = camera.focal(img_size);
+        let center = camera.center(img_size);
+        let [w, h] = [img_size.x as usize, img_size.y as usize];
+
+        // Rays through the pixel centers, in the frame of the camera.
+        let xs: Vec<f32> = (0..img_size.x)
+            .map(|x| (x as f32 + 0.5 - center.x) / focal.x)
+            .collect();
+        let ys: Vec<f32> = (0..img_size.y)
+            .map(|y| (y as f32 + 0.5 - center.y) / focal.y)
+            .collect();
+        let x = Tensor::<B, 1>::from_floats(xs.as_slice(), &device)
+            .reshape([1, w, 1])
+            .repeat_dim(0, h);
+        let y = Tensor::<B, 1>::from_floats(ys.as_slice(), &device)
+            .reshape([h, 1, 1])
+            .repeat_dim(1, w);
+        let z = Tensor::ones([h, w, 1], &device);
+        let local = Tensor::cat(vec![x, y, z], 2).reshape([h * w, 3]);
+
+        // Columns of the rotation as rows, which is the transpose.
+        let rot_t = Tensor::<B, 1>::from_floats(Mat3::from_quat(camera.rotation).to_cols_array(), &device)
+            .reshape([3, 3]);
+        let dirs = local.matmul(rot_t);
+        let dirs = dirs.clone() / dirs.powf_scalar(2.0).sum_dim(1).sqrt();
+
+        self.colors(dirs).reshape([h, w, 3])
+    }
+
+    /// The whole background as an equirectangular map of [height, width, 3]. The top row looks
+    /// along +Y, the center column along +Z. Directions are rotated by `rotation` before looking
+    /// up their color.
+    pub fn equirect(&self, width: u32, height: u32, rotation: Quat) -> Tensor<B, 3> {
+        let device = self.coeffs.device();
+        let mut dirs = Vec::with_capacity((width * height * 3) as usize);
+        for v in 0..height {
+            let polar = std::f32::consts::PI * (v as f32 + 0.5) / height as f32;
+            for u in 0..width {
+                let azimuth = std::f32::consts::TAU * (u as f32 + 0.5) / width as f32 - std::f32::consts::PI;
+                let dir = Vec3::new(
+                    polar.sin() * azimuth.sin(),
+                    polar.cos(),
+                    polar.sin() * azimuth.cos(),
+                );
+                dirs.extend((rotation * dir).to_array());
+            }
+        }
+        let dirs = Tensor::<B, 1>::from_floats(dirs.as_slice(), &device)
+            .reshape([(width * height) as usize, 3]);
+        self.colors(dirs).reshape([height as usize, width as usize, 3])
+    }
+
+    pub(crate) fn into_autodiff<BDiff: AutodiffBackend<InnerBackend = B>>(
+        self,
+    ) -> BackgroundModel<BDiff> {
+        let (coeffs_id, coeffs, _) = self.coeffs.consume();
+        BackgroundModel {
+            coeffs: Param::initialized(coeffs_id, Tensor::from_inner(coeffs).require_grad()),
+        }
+    }
+}
+
+/// Lays the background under an RGBA render on a black background, as [h, w, 4].
+pub fn composite<B: Backend>(render: Tensor<B, 3>, background: Tensor<B, 3>) -> Tensor<B, 3> {
+    let [h, w, _] = render.dims();
+    let rgb = render.clone().slice([0..h, 0..w, 0..3]);
+    let alpha = render.slice([0..h, 0..w, 3..4]);
+    let rgb = rgb + (alpha.ones_like() - alpha.clone()) * background;
+    Tensor::cat(vec![rgb, alpha], 2)
+}
+
+// Real SH basis functions of the unit directions `dirs` [n, 3], as [n, coeffs].
+fn sh_basis<B: Backend>(dirs: Tensor<B, 2>, degree: u32) -> Tensor<B, 2> {
+    let n = dirs.dims()[0];
+    let c = |i: usize| dirs.clone().slice([0..n, i..i + 1]);
+    let (x, y, z) = (c(0), c(1), c(2));
+
+    let mut basis = vec![x.ones_like() * SH_C0];
+    if degree >= 1 {
+        basis.extend([y.clone() * -SH_C1, z.clone() * SH_C1, x.clone() * -SH_C1]);
+    }
+    if degree >= 2 {
+        let (xx, yy, zz) = (x.clone() * x.clone(), y.clone() * y.clone(), z.clone() * z.clone());
+        basis.extend([
+            x.clone() * y.clone() * SH_C2[0],
+            y.clone() * z.clone() * SH_C2[1],
+            (zz.clone() * 2.0 - xx.clone() - yy.clone()) * SH_C2[2],
+            x.clone() * z.clone() * SH_C2[3],
+            (xx.clone() - yy.clone()) * SH_C2[4],
+        ]);
+        if degree >= 3 {
+            basis.extend([
+                y.clone() * (xx.clone() * 3.0 - yy.clone()) * SH_C3[0],
+                x.clone() * y.clone() * z.clone() * SH_C3[1],
+                y.clone() * (zz.clone() * 4.0 - xx.clone() - yy.clone()) * SH_C3[2],
+                z.clone() * (zz.clone() * 2.0 - xx.clone() * 3.0 - yy.clone() * 3.0) * SH_C3[3],
+                x.clone() * (zz.clone() * 4.0 - xx.clone() - yy.clone()) * SH_C3[4],
+                z * (xx.clone() - yy.clone()) * SH_C3[5],
+                x * (xx - yy * 3.0) * SH_C3[6],
+            ]);
+        }
+    }
+    Tensor::cat(basis, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::wgpu::WgpuDevice;
+    use render::MainBackend;
+
+    fn to_vec<const D: usize>(tensor: Tensor<MainBackend, D>) -> Vec<f32> {
+        tensor.into_data().into_vec::<f32>().expect("Wrong type")
+    }
+
+    #[test]
+    fn composite_blends_by_alpha() {
+        let device = WgpuDevice::default();
+        let background = Tensor::<MainBackend, 1>::from_floats([0.2, 0.4, 0.6], &device)
+            .reshape([1, 1, 3])
+            .repeat_dim(1, 2);
+        let render = Tensor::<MainBackend, 1>::from_floats(
+            [0.9, 0.8, 0.7, 1.0, 0.0, 0.0, 0.0, 0.0],
+            &device,
+        )
+        .reshape([1, 2, 4]);
+
+        let out = to_vec(composite(render, background));
+        let assert_pixel = |pixel: &[f32], expected: [f32; 4]| {
+            for (a, e) in pixel.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-6, "Pixel {pixel:?} should be {expected:?}");
+            }
+        };
+        // An opaque pixel keeps the render, a transparent one shows the background.
+        assert_pixel(&out[0..4], [0.9, 0.8, 0.7, 1.0]);
+        assert_pixel(&out[4..8], [0.2, 0.4, 0.6, 0.0]);
+    }
+
+    #[test]
+    fn sh_basis_constant_term() {
+        let device = WgpuDevice::default();
+        let dirs = Tensor::<MainBackend, 1>::from_floats([1.0, 0.0, 0.0, 0.0, 0.6, 0.8], &device)
+            .reshape([2, 3]);
+        let basis = sh_basis(dirs, 2);
+        assert_eq!(basis.dims(), [2, 9]);
+
+        let basis = to_vec(basis);
+        // The first term doesn't depend on the direction.
+        assert!((basis[0] - SH_C0).abs() < 1e-6);
+        assert!((basis[9] - SH_C0).abs() < 1e-6);
+
+        // With only the constant term, the color is the same everywhere.
+        let mut model = BackgroundModel::<MainBackend>::new(0, &device);
+        model.coeffs = model.coeffs.map(|c| c + 0.25);
+        let colors = to_vec(model.equirect(8, 4, Quat::IDENTITY));
+        let expected = 0.25 * SH_C0 + 0.5;
+        assert!(colors.iter().all(|c| (c - expected).abs() < 1e-5), "Colors {colors:?} aren't uniform");
+    }
+
+    #[test]
+    fn equirect_top_row_looks_up() {
+        let device = WgpuDevice::default();
+        // Degree 1, only the red channel of the y band set: red grows looking along +Y.
+        let mut model = BackgroundModel::<MainBackend>::new(1, &device);
+        let coeffs = Tensor::<MainBackend, 1>::from_floats(
+            [0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            &device,
+        )
+        .reshape([4, 3]);
+        model.coeffs = model.coeffs.map(|_| coeffs);
+
+        let (width, height) = (8, 4);
+        let red_of_row = |rotation: Quat, row: usize| {
+            let map = to_vec(model.equirect(width, height, rotation));
+            map.chunks_exact(3)
+                .skip(row * width as usize)
+                .take(width as usize)
+                .map(|rgb| rgb[0])
+                .sum::<f32>()
+                / width as f32
+        };
+
+        // -SH_C1 * y with a coefficient of -1 is brightest along +Y.
+        assert!(red_of_row(Quat::IDENTITY, 0) > 0.5 + 0.8 * SH_C1);
+        assert!(red_of_row(Quat::IDENTITY, height as usize - 1) < 0.5 - 0.8 * SH_C1);
+        // Turning +Y to -Y flips the map.
+        let flip = Quat::from_rotation_x(std::f32::consts::PI);
+        assert!(red_of_row(flip, 0) < 0.5 - 0.8 * SH_C1);
+    }
+}
@@ -0,0 +1,240 @@
+use burn::{
+    backend::Autodiff,
+    backend::wgpu::WgpuDevice,
+    module::{Module, Param, ParamId},
+    prelude::Backend,
+    tensor::Tensor,
+};
+use glam::{Mat3, Quat, Vec3};
+use render::{MainBackend, camera::Camera};
+
+type DiffTensor<const D: usize> = Tensor<Autodiff<MainBackend>, D>;
+
+// Nr. of values per view: rotation vector, translation & log focal scale.
+const PARAMS_PER_VIEW: usize = 7;
+
+/// Learned corrections of the training cameras. Each view has a small rotation & translation in the
+/// frame of its camera, and a log scale of its focal length.
+///
+/// The corrected view matrix & focal length are built on the GPU and passed to the renderer, which
+/// has gradients for them.
+#[derive(Module, Debug)]
+pub(crate) struct CameraAdjustments<B: Backend> {
+    // [views, 7]: rotation vector, translation relative to the scene extent, log focal scale.
+    pub(crate) params: Param<Tensor<B, 2>>,
+}
+
+/// Corrections of a single view, read back from the GPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ViewAdjustment {
+    pub(crate) rotation: Vec3,
+    pub(crate) translation: Vec3,
+    pub(crate) log_focal: f32,
+}
+
+impl ViewAdjustment {
+    fn from_slice(values: &[f32], scene_extent: f32) -> Self {
+        Self {
+            rotation: Vec3::from_slice(&values[0..3]),
+            translation: Vec3::from_slice(&values[3..6]) * scene_extent,
+            log_focal: values[6],
+        }
+    }
+
+    /// The camera with this correction applied.
+    pub(crate) fn apply(&self, camera: &Camera) -> Camera {
+        let focal_fov = |fov: f64| 2.0 * ((fov * 0.5).tan() * (-self.log_focal as f64).exp()).atan();
+        Camera {
+            position: camera.position + camera.rotation * self.translation,
+            rotation: (camera.rotation * Quat::from_scaled_axis(self.rotation)).normalize(),
+            fov_x: focal_fov(camera.fov_x),
+            fov_y: focal_fov(camera.fov_y),
+            ..camera.clone()
+        }
+    }
+}
+
+impl CameraAdjustments<Autodiff<MainBackend>> {
+    pub(crate) fn new(num_views: usize, device: &WgpuDevice) -> Self {
+        Self {
+            params: Param::initialized(
+                ParamId::new(),
+                Tensor::zeros([num_views, PARAMS_PER_VIEW], device).require_grad(),
+            ),
+        }
+    }
+
+    /// Reads back the corrections of the views.
+    pub(crate) async fn read(&self, scene_extent: f32) -> Vec<ViewAdjustment> {
+        self.params
+            .val()
+            .inner()
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Failed to read camera adjustments")
+            .chunks_exact(PARAMS_PER_VIEW)
+            .map(|values| ViewAdjustment::from_slice(values, scene_extent))
+            .collect()
+    }
+
+    pub(crate) fn values(&self) -> Tensor<MainBackend, 2> {
+        self.params.val().inner()
+    }
+
+    /// Sets the corrections back to earlier `values`.
+    pub(crate) fn restore(&mut self, values: Tensor<MainBackend, 2>) {
+        self.params = Param::initialized(self.params.id, Tensor::from_inner(values).require_grad());
+    }
+
+    /// View matrix (column major) & focal length [18] of `camera` with the correction of its view,
+    /// to render with. Only the corrections that are refined get gradients, the rest is masked out.
+    pub(crate) fn view(
+        &self,
+        view_index: usize,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        scene_extent: f32,
+        refine_poses: bool,
+        refine_focal: bool,
+    ) -> DiffTensor<1> {
+        let device = self.params.val().device();
+        let pose = if refine_poses { 1.0 } else { 0.0 };
+        let focal = if refine_focal { 1.0 } else { 0.0 };
+        let mask = Tensor::<_, 1>::from_floats([pose, pose, pose, pose, pose, pose, focal], &device);
+        let params = self
+            .params
+            .val()
+            .slice([view_index..view_index + 1])
+            .reshape([PARAMS_PER_VIEW])
+            * mask;
+
+        let world_to_local = camera.world_to_local();
+        // The columns of the rotation end up as rows, so transpose them back.
+        let base_rot = Tensor::<_, 1>::from_floats(
+            Mat3::from(world_to_local.matrix3).to_cols_array(),
+            &device,
+        )
+        .reshape([3, 3])
+        .transpose();
+        let base_translation =
+            Tensor::<_, 1>::from_floats(Vec3::from(world_to_local.translation).to_array(), &device)
+                .reshape([3, 1]);
+
+        // The camera rotates by `rotation` and moves by `translation` in its own frame, so the
+        // world to camera transform becomes R_c^T * R and R_c^T * (t - translation).
+        let rotation = rotation_from_vector(params.clone().slice([0..3])).transpose();
+        let translation = params.clone().slice([3..6]).reshape([3, 1]) * scene_extent;
+        let rot = rotation.clone().matmul(base_rot);
+        let t = rotation.matmul(base_translation - translation);
+
+        // Rows of the transposed matrix are the columns of the matrix.
+        let bottom = Tensor::<_, 1>::from_floats([0.0, 0.0, 0.0, 1.0], &device).reshape([1, 4]);
+        let viewmat = Tensor::cat(vec![Tensor::cat(vec![rot, t], 1), bottom], 0)
+            .transpose()
+            .reshape([16]);
+        let focal = Tensor::<_, 1>::from_floats(camera.focal(img_size).to_array(), &device)
+            * params.slice([6..7]).exp();
+        Tensor::cat(vec![viewmat, focal], 0)
+    }
+}
+
+// Rotation matrix of the rotation vector `phi` [3], by Rodrigues' formula.
+fn rotation_from_vector(phi: DiffTensor<1>) -> DiffTensor<2> {
+    let device = phi.device();
+    // Offset keeps the gradient finite at zero rotation.
+    let theta = (phi.clone().powf_scalar(2.0).sum() + 1e-12).sqrt();
+    let half_sin = (theta.clone() * 0.5).sin();
+
+    let c = |i: usize| phi.clone().slice([i..i + 1]);
+    let (x, y, z) = (c(0), c(1), c(2));
+    let zero = Tensor::zeros([1], &device);
+    let skew = Tensor::cat(
+        vec![
+            zero.clone(), z.clone().neg(), y.clone(),
+            z, zero.clone(), x.clone().neg(),
+            y.neg(), x, zero,
+        ],
+        0,
+    )
+    .reshape([3, 3]);
+
+    // 1 - cos(theta) written as 2 sin^2(theta / 2), which is accurate for small angles.
+    let a = (theta.clone().sin() / theta.clone()).reshape([1, 1]);
+    let b = (half_sin.powf_scalar(2.0) * 2.0 / theta.powf_scalar(2.0)).reshape([1, 1]);
+    Tensor::eye(3, &device) + skew.clone() * a + skew.clone().matmul(skew) * b
+}
+
+/// Corrected copies of the `cameras`, by the adjustments of their views.
+pub(crate) fn apply_adjustments(cameras: &[Camera], adjustments: &[ViewAdjustment]) -> Vec<Camera> {
+    cameras
+        .iter()
+        .zip(adjustments)
+        .map(|(camera, adjustment)| adjustment.apply(camera))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjustment_moves_camera_in_its_frame() {
+        let camera = Camera::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.5),
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        );
+
+        let adjustment = ViewAdjustment::from_slice(&[0.0, 0.1, 0.0, 0.0, 0.0, 0.5, 0.2], 2.0);
+        let adjusted = adjustment.apply(&camera);
+        // Half the extent forward along the view axis of the camera.
+        assert!(adjusted.position.distance(camera.position + camera.rotation * Vec3::Z) < 1e-5);
+        assert!(adjusted.rotation.angle_between(Quat::from_rotation_y(0.6)) < 1e-5);
+        // A longer focal length narrows the view.
+        assert!(adjusted.fov_x < camera.fov_x && adjusted.fov_y < camera.fov_y);
+        let focal = |fov: f64| 1.0 / (fov * 0.5).tan();
+        assert!((focal(adjusted.fov_x) / focal(camera.fov_x) - 0.2f64.exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn view_matches_adjusted_camera() {
+        let device = WgpuDevice::default();
+        let camera = Camera::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_rotation_y(0.5),
+            0.8,
+            0.6,
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(64, 48);
+        let values = [0.05, 0.1, -0.02, 0.1, 0.0, 0.3, 0.2];
+
+        let mut cameras = CameraAdjustments::new(1, &device);
+        cameras.restore(Tensor::<MainBackend, 1>::from_floats(values, &device).reshape([1, 7]));
+        let view = |refine_poses, refine_focal| {
+            cameras
+                .view(0, &camera, img_size, 2.0, refine_poses, refine_focal)
+                .into_data()
+                .into_vec::<f32>()
+                .expect("Failed to read view")
+        };
+        let expect = |camera: &Camera| {
+            let mut expected = glam::Mat4::from(camera.world_to_local()).to_cols_array().to_vec();
+            expected.extend(camera.focal(img_size).to_array());
+            expected
+        };
+        let assert_close = |actual: &[f32], expected: &[f32]| {
+            for (a, e) in actual.iter().zip(expected) {
+                assert!((a - e).abs() < 1e-3, "View {actual:?} should be {expected:?}");
+            }
+        };
+
+        let adjusted = ViewAdjustment::from_slice(&values, 2.0).apply(&camera);
+        assert_close(&view(true, true), &expect(&adjusted));
+        // Without refinement, the corrections are left out.
+        assert_close(&view(false, false), &expect(&camera));
+    }
+}
@@ -2,13 +2,32 @@ use burn::prelude::Config;
 
 #[derive(Config, Debug)]
 pub struct TrainConfig {
+    /// Final degree of the spherical harmonics.
     #[config(default = 3)]
     pub sh_degree: u32,
 
+    /// Training starts with only the base color, and the SH degree goes up by one at each of
+    /// these steps until `sh_degree`. When empty, all bands are trained from the start.
+    #[config(default = "vec![1000, 2000, 3000]")]
+    pub sh_degree_steps: Vec<u32>,
+
     /// Total number of steps to train for.
     #[config(default = 30000)]
     pub total_steps: u32,
 
+    /// Factor the training images are downscaled by at the start of training. The factor is
+    /// halved at each of `downscale_steps`, until training is at full resolution.
+    #[config(default = 1)]
+    pub start_downscale: u32,
+
+    /// Steps at which the training resolution doubles.
+    #[config(default = "vec![]")]
+    pub downscale_steps: Vec<u32>,
+
+    /// Nr. of views rendered each step. Their losses are averaged before a single optimizer step.
+    #[config(default = 1)]
+    pub batch_size: u32,
+
     /// Max nr. of splats. This is an upper bound, but the actual final number of splats might be lower than this.
     #[config(default = 10000000)]
     pub max_splats: u32,
@@ -17,9 +36,9 @@ pub struct TrainConfig {
     #[config(default = 4e-5)]
     pub lr_mean: f64,
 
-    /// End learning rate for the mean parameters.
-    #[config(default = 4e-7)]
-    pub lr_mean_end: f64,
+    /// Schedule of the mean learning rate.
+    #[config(default = "LrSchedule::Exponential { end: 4e-7 }")]
+    pub lr_mean_schedule: LrSchedule,
 
     /// How much noise to add to the mean parameters of low opacity gaussians.
     #[config(default = 1e4)]
@@ -29,6 +48,10 @@ pub struct TrainConfig {
     #[config(default = 3e-3)]
     pub lr_coeffs_dc: f64,
 
+    /// Schedule of the SH coefficient learning rate.
+    #[config(default = "LrSchedule::Constant")]
+    pub lr_coeffs_schedule: LrSchedule,
+
     /// How much to divide the learning rate by for higher SH orders.
     #[config(default = 20.0)]
     pub lr_coeffs_sh_scale: f32,
@@ -37,18 +60,80 @@ pub struct TrainConfig {
     #[config(default = 3e-2)]
     pub lr_opac: f64,
 
+    /// Schedule of the opacity learning rate.
+    #[config(default = "LrSchedule::Constant")]
+    pub lr_opac_schedule: LrSchedule,
+
     /// Learning rate for the scale parameters.
     #[config(default = 1e-2)]
     pub lr_scale: f64,
 
-    /// Learning rate for the scale parameters.
-    #[config(default = 6e-3)]
-    pub lr_scale_end: f64,
+    /// Schedule of the scale learning rate.
+    #[config(default = "LrSchedule::Exponential { end: 6e-3 }")]
+    pub lr_scale_schedule: LrSchedule,
 
     /// Learning rate for the rotation parameters.
     #[config(default = 1e-3)]
     pub lr_rotation: f64,
 
+    /// Schedule of the rotation learning rate.
+    #[config(default = "LrSchedule::Constant")]
+    pub lr_rotation_schedule: LrSchedule,
+
+    /// Keep the means as they are.
+    #[config(default = false)]
+    pub freeze_means: bool,
+
+    /// Keep the rotations as they are.
+    #[config(default = false)]
+    pub freeze_rotation: bool,
+
+    /// Keep the scales as they are.
+    #[config(default = false)]
+    pub freeze_scales: bool,
+
+    /// Keep the SH coefficients as they are. The SH degree isn't raised either.
+    #[config(default = false)]
+    pub freeze_sh: bool,
+
+    /// Keep the opacities as they are.
+    #[config(default = false)]
+    pub freeze_opacity: bool,
+
+    /// Never add, remove or move splats outside of the optimizer. Refining ignores the freeze
+    /// flags, so set this as well to keep frozen parameters exactly as they are.
+    #[config(default = false)]
+    pub disable_refine: bool,
+
+    /// Learn a correction of the pose of each training camera.
+    #[config(default = false)]
+    pub refine_poses: bool,
+
+    /// Learn a correction of the focal length of each training camera.
+    #[config(default = false)]
+    pub refine_focal: bool,
+
+    /// Learning rate of the camera corrections. Translations are relative to the scene extent.
+    #[config(default = 1e-4)]
+    pub lr_camera: f64,
+
+    /// Learn a background behind the splats, like a sky, instead of training against random
+    /// background colors. Only used for views without transparency.
+    #[config(default = false)]
+    pub learn_background: bool,
+
+    /// SH degree of the learned background, at most 3.
+    #[config(default = 3)]
+    pub background_sh_degree: u32,
+
+    /// Learning rate of the learned background.
+    #[config(default = 5e-3)]
+    pub lr_background: f64,
+
+    /// How splats are added, removed & moved during training.
+    #[config(default = "RefineStrategyConfig::Default")]
+    pub refine_strategy: RefineStrategyConfig,
+
     /// Frequency of 'refinement' where gaussians are replaced and densified. This should
     /// roughly be the number of images it takes to properly "cover" your scene.
     #[config(default = 150)]
@@ -67,6 +152,19 @@ pub struct TrainConfig {
     #[config(default = 12500)]
     pub growth_stop_iter: u32,
 
+    /// Mean screen space gradient above which splats are cloned or split, for the ADC strategy.
+    #[config(default = 0.0002)]
+    pub densify_grad_threshold: f32,
+
+    /// Splats smaller than this fraction of the scene extent are cloned, larger ones are split,
+    /// for the ADC strategy.
+    #[config(default = 0.01)]
+    pub percent_dense: f32,
+
+    /// Reset the opacity of all splats every this many steps while growing, for the ADC strategy.
+    #[config(default = 3000)]
+    pub opacity_reset_interval: u32,
+
     /// Weight of SSIM loss (compared to l1 loss)
     #[config(default = 0.2)]
     pub ssim_weight: f32,
@@ -78,4 +176,191 @@ pub struct TrainConfig {
     /// Weight of l1 loss on alpha if input view has transparency.
     #[config(default = 0.1)]
     pub match_alpha_weight: f32,
-}
\ No newline at end of file
+
+    /// Train on random square patches of this many pixels instead of whole images, so high
+    /// resolution views fit in memory. Raise the max resolution of the dataset to train at native resolution.
+    pub patch_size: Option<u32>,
+
+    /// Nr. of patches taken from the view each step. Their losses are averaged.
+    #[config(default = 1)]
+    pub patches_per_step: u32,
+
+    /// Check the loss & splats for divergence every this many steps, and before each refine.
+    /// Each check waits on the GPU, so keep this coarse. 0 disables the checks.
+    #[config(default = 100)]
+    pub divergence_check_every: u32,
+
+    /// Keep an in-memory copy of the splats & optimizer state every this many steps, which
+    /// training rolls back to when it diverges.
+    #[config(default = 500)]
+    pub snapshot_every: u32,
+
+    /// A loss this many mean deviations above the running average loss counts as divergence.
+    #[config(default = 20.0)]
+    pub divergence_spike_factor: f32,
+
+    /// All learning rates are multiplied by this after each rollback.
+    #[config(default = 0.5)]
+    pub divergence_lr_decay: f64,
+
+    /// Training fails after this many rollbacks.
+    #[config(default = 5)]
+    pub max_rollbacks: u32,
+}
+
+impl TrainConfig {
+    /// Factor the training images are downscaled by at step `iter`.
+    pub fn downscale_at(&self, iter: u32) -> u32 {
+        let halvings = self.downscale_steps.iter().filter(|&&step| step <= iter).count() as u32;
+        self.start_downscale.checked_shr(halvings).unwrap_or(0).max(1)
+    }
+
+    /// SH degree that is trained at step `iter`.
+    pub fn sh_degree_at(&self, iter: u32) -> u32 {
+        if self.sh_degree_steps.is_empty() {
+            return self.sh_degree;
+        }
+        let raised = self.sh_degree_steps.iter().filter(|&&step| step <= iter).count() as u32;
+        raised.min(self.sh_degree)
+    }
+}
+
+/// How a learning rate changes over training, starting from the configured rate.
+#[derive(Config, Debug, PartialEq)]
+pub enum LrSchedule {
+    /// Keep the start rate.
+    Constant,
+    /// Decay exponentially to `end` at the last step.
+    Exponential { end: f64 },
+    /// Decay along half a cosine to `end` at the last step.
+    Cosine { end: f64 },
+    /// Increase linearly from zero over `steps`, then decay along half a cosine to `end`.
+    Warmup { steps: u32, end: f64 },
+    /// Multiply the rate by `gamma` every `every` steps.
+    Step { every: u32, gamma: f64 },
+}
+
+impl LrSchedule {
+    /// Learning rate at step `iter` of `total_steps`, for a schedule starting at `start`.
+    pub fn lr_at(&self, start: f64, iter: u32, total_steps: u32) -> f64 {
+        let progress = |from: u32| {
+            let span = total_steps.saturating_sub(from).max(1);
+            (iter.saturating_sub(from) as f64 / span as f64).clamp(0.0, 1.0)
+        };
+        let cosine = |end: f64, t: f64| end + (start - end) * 0.5 * (1.0 + (std::f64::consts::PI * t).cos());
+
+        match *self {
+            LrSchedule::Constant => start,
+            LrSchedule::Exponential { end } => start * (end / start).powf(progress(0)),
+            LrSchedule::Cosine { end } => cosine(end, progress(0)),
+            LrSchedule::Warmup { steps, .. } if iter < steps => {
+                start * (iter + 1) as f64 / steps as f64
+            }
+            LrSchedule::Warmup { steps, end } => cosine(end, progress(steps)),
+            LrSchedule::Step { every, gamma } => start * gamma.powi((iter / every.max(1)) as i32),
+        }
+    }
+}
+
+#[derive(Config, Debug)]
+pub enum RefineStrategyConfig {
+    /// Prune transparent splats and replace them by splitting splats sampled by opacity. While
+    /// growing, also split splats with a large screen space gradient.
+    Default,
+    /// Adaptive density control of the original 3DGS paper: clone small splats & split large splats
+    /// with a high mean screen space gradient, and periodically reset the opacity.
+    Adc,
+    /// 3DGS-MCMC: transparent splats are relocated onto splats sampled by opacity, and until
+    /// `growth_stop_iter` the nr. of splats grows by a fixed rate up to `max_splats`.
+    Mcmc(McmcConfig),
+}
+
+#[derive(Config, Debug)]
+pub struct McmcConfig {
+    /// Splats with a lower opacity are relocated.
+    #[config(default = 0.005)]
+    pub min_opacity: f32,
+
+    /// Fraction the nr. of splats grows by each refine, while growing.
+    #[config(default = 0.05)]
+    pub growth_rate: f32,
+
+    /// Scale of the noise added to the means, relative to the mean learning rate.
+    #[config(default = 5e5)]
+    pub noise_lr: f32,
+
+    /// Weight of the l1 loss on opacities, which frees up splats to relocate.
+    #[config(default = 0.01)]
+    pub opac_reg_weight: f32,
+
+    /// Weight of the l1 loss on scales.
+    #[config(default = 0.01)]
+    pub scale_reg_weight: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lr_schedules() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+
+        assert!(close(LrSchedule::Constant.lr_at(1e-3, 500, 1000), 1e-3));
+
+        let exp = LrSchedule::Exponential { end: 1e-4 };
+        assert!(close(exp.lr_at(1e-2, 0, 1000), 1e-2));
+        assert!(close(exp.lr_at(1e-2, 500, 1000), 1e-3));
+        assert!(close(exp.lr_at(1e-2, 1000, 1000), 1e-4));
+
+        let cos = LrSchedule::Cosine { end: 0.0 };
+        assert!(close(cos.lr_at(1.0, 500, 1000), 0.5));
+        assert!(close(cos.lr_at(1.0, 1000, 1000), 0.0));
+
+        let warmup = LrSchedule::Warmup { steps: 100, end: 0.0 };
+        assert!(close(warmup.lr_at(1.0, 49, 1100), 0.5));
+        assert!(close(warmup.lr_at(1.0, 100, 1100), 1.0));
+        assert!(close(warmup.lr_at(1.0, 600, 1100), 0.5));
+
+        let step = LrSchedule::Step { every: 100, gamma: 0.5 };
+        assert!(close(step.lr_at(1.0, 99, 1000), 1.0));
+        assert!(close(step.lr_at(1.0, 250, 1000), 0.25));
+    }
+
+    #[test]
+    fn downscale_schedule() {
+        let config = TrainConfig::new().with_start_downscale(4).with_downscale_steps(vec![100, 200]);
+        assert_eq!(config.downscale_at(0), 4);
+        assert_eq!(config.downscale_at(99), 4);
+        assert_eq!(config.downscale_at(100), 2);
+        assert_eq!(config.downscale_at(200), 1);
+        assert_eq!(config.downscale_at(100_000), 1);
+
+        // Extra steps stay at full resolution, odd factors round down.
+        let odd = config.clone().with_start_downscale(3).with_downscale_steps(vec![10, 20, 30, 40]);
+        assert_eq!(odd.downscale_at(10), 1);
+        assert_eq!(odd.downscale_at(40), 1);
+        // Shifting by the width of the integer doesn't wrap around.
+        let many = config.with_downscale_steps((0..40).collect());
+        assert_eq!(many.downscale_at(39), 1);
+    }
+
+    #[test]
+    fn sh_degree_schedule() {
+        let config = TrainConfig::new().with_sh_degree(3).with_sh_degree_steps(vec![100, 200, 300]);
+        assert_eq!(config.sh_degree_at(0), 0);
+        assert_eq!(config.sh_degree_at(99), 0);
+        assert_eq!(config.sh_degree_at(100), 1);
+        assert_eq!(config.sh_degree_at(299), 2);
+        assert_eq!(config.sh_degree_at(300), 3);
+        assert_eq!(config.sh_degree_at(100_000), 3);
+
+        // More steps than bands stop at the final degree.
+        let clamped = config.clone().with_sh_degree(1);
+        assert_eq!(clamped.sh_degree_at(200), 1);
+        assert_eq!(clamped.sh_degree_at(300), 1);
+
+        let unscheduled = config.with_sh_degree_steps(vec![]);
+        assert_eq!(unscheduled.sh_degree_at(0), 3);
+    }
+}
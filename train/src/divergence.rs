@@ -0,0 +1,168 @@
+use crate::{background::BackgroundModel, config::TrainConfig, msg::DivergenceReason, refine::OptimizerRecord};
+
+use burn::tensor::Tensor;
+use render::{MainBackend, gaussian_splats::Splats};
+
+// Nr. of checks before loss spikes are detected, the running average means little before that.
+const WARMUP_CHECKS: u32 = 10;
+// Smoothing of the running average & deviation of the loss.
+const SMOOTHING: f32 = 0.9;
+// Floor of the deviation, so a loss that barely changes doesn't make any bump a spike.
+const MIN_DEVIATION: f32 = 1e-3;
+
+/// Splats, optimizer state & learned extras after a step that passed the divergence check.
+pub(crate) struct Snapshot {
+    pub(crate) iter: u32,
+    pub(crate) splats: Splats<MainBackend>,
+    pub(crate) optimizer: Option<OptimizerRecord>,
+    pub(crate) cameras: Option<Tensor<MainBackend, 2>>,
+    pub(crate) background: Option<BackgroundModel<MainBackend>>,
+}
+
+/// Detects divergence from the checked losses, and keeps the snapshot to roll back to.
+pub(crate) struct DivergenceGuard {
+    check_every: u32,
+    snapshot_every: u32,
+    spike_factor: f32,
+    checks: u32,
+    avg_loss: f32,
+    avg_deviation: f32,
+    snapshot: Option<Snapshot>,
+}
+
+impl DivergenceGuard {
+    pub(crate) fn new(config: &TrainConfig) -> Self {
+        Self {
+            check_every: config.divergence_check_every,
+            snapshot_every: config.snapshot_every,
+            spike_factor: config.divergence_spike_factor,
+            checks: 0,
+            avg_loss: 0.0,
+            avg_deviation: 0.0,
+            snapshot: None,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.check_every > 0
+    }
+
+    pub(crate) fn should_check(&self, iter: u32) -> bool {
+        self.enabled() && iter % self.check_every == 0
+    }
+
+    /// Forgets the running average, for when the loss is expected to jump, like after a refine.
+    pub(crate) fn restart(&mut self) {
+        self.checks = 0;
+    }
+
+    /// Records the loss of a checked step. Returns why the step diverged, if it did.
+    pub(crate) fn check(&mut self, loss: f32, params_finite: bool) -> Option<DivergenceReason> {
+        if !loss.is_finite() {
+            return Some(DivergenceReason::NonFiniteLoss);
+        }
+        if !params_finite {
+            return Some(DivergenceReason::NonFiniteParams);
+        }
+        if self.checks >= WARMUP_CHECKS
+            && loss - self.avg_loss > self.spike_factor * self.avg_deviation.max(MIN_DEVIATION)
+        {
+            return Some(DivergenceReason::LossSpike {
+                loss,
+                average: self.avg_loss,
+            });
+        }
+
+        if self.checks == 0 {
+            self.avg_loss = loss;
+            self.avg_deviation = 0.0;
+        } else {
+            let deviation = (loss - self.avg_loss).abs();
+            self.avg_deviation = self.avg_deviation * SMOOTHING + deviation * (1.0 - SMOOTHING);
+            self.avg_loss = self.avg_loss * SMOOTHING + loss * (1.0 - SMOOTHING);
+        }
+        self.checks += 1;
+        None
+    }
+
+    pub(crate) fn wants_snapshot(&self, iter: u32) -> bool {
+        self.snapshot
+            .as_ref()
+            .is_none_or(|snapshot| iter >= snapshot.iter + self.snapshot_every)
+    }
+
+    pub(crate) fn store(&mut self, snapshot: Snapshot) {
+        self.snapshot = Some(snapshot);
+    }
+
+    pub(crate) fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_ref()
+    }
+}
+
+/// Indices of the splats with a non-finite parameter.
+#[cfg(feature = "debug-validation")]
+pub(crate) async fn non_finite_splats(
+    splats: &Splats<burn::backend::Autodiff<MainBackend>>,
+) -> Vec<u32> {
+    use burn::tensor::Bool;
+
+    let [n, coeffs, _] = splats.sh_coeffs.dims();
+    let non_finite =
+        |x: Tensor<MainBackend, 2>| -> Tensor<MainBackend, 2, Bool> { x.is_finite().bool_not().any_dim(1) };
+    let invalid = non_finite(splats.means.val().inner())
+        .bool_or(non_finite(splats.rotation.val().inner()))
+        .bool_or(non_finite(splats.log_scales.val().inner()))
+        .bool_or(non_finite(splats.sh_coeffs.val().inner().reshape([n, coeffs * 3])))
+        .bool_or(non_finite(splats.raw_opacity.val().inner().unsqueeze_dim(1)));
+
+    invalid
+        .squeeze::<1>(1)
+        .argwhere_async()
+        .await
+        .into_data_async()
+        .await
+        .iter::<i32>()
+        .map(|i| i as u32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard() -> DivergenceGuard {
+        DivergenceGuard::new(&TrainConfig::new().with_divergence_spike_factor(10.0))
+    }
+
+    #[test]
+    fn non_finite_values_diverge() {
+        let mut guard = guard();
+        assert_eq!(guard.check(f32::NAN, true), Some(DivergenceReason::NonFiniteLoss));
+        assert_eq!(guard.check(f32::INFINITY, true), Some(DivergenceReason::NonFiniteLoss));
+        assert_eq!(guard.check(0.1, false), Some(DivergenceReason::NonFiniteParams));
+        assert_eq!(guard.check(0.1, true), None);
+    }
+
+    #[test]
+    fn detects_spikes_after_warmup() {
+        let mut guard = guard();
+        // Large values during warmup are only recorded.
+        assert_eq!(guard.check(5.0, true), None);
+        guard.restart();
+
+        for i in 0..WARMUP_CHECKS * 2 {
+            let loss = if i % 2 == 0 { 0.1 } else { 0.12 };
+            assert_eq!(guard.check(loss, true), None);
+        }
+        // Within the usual variation.
+        assert_eq!(guard.check(0.15, true), None);
+        assert!(matches!(
+            guard.check(1.0, true),
+            Some(DivergenceReason::LossSpike { .. })
+        ));
+
+        guard.restart();
+        assert_eq!(guard.check(1.0, true), None);
+    }
+}
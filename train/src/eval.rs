@@ -1,4 +1,5 @@
 use anyhow::Result;
+use dataset::ColorSpace;
 use dataset::scene::{SceneView, sample_to_tensor, view_to_sample_image};
 use render::SplatForward;
 use render::gaussian_splats::Splats;
@@ -8,11 +9,15 @@ use burn::tensor::{Tensor, TensorPrimitive, s};
 use glam::Vec3;
 use image::DynamicImage;
 use render::camera::Camera;
+use crate::background::{BackgroundModel, composite};
 use crate::ssim::Ssim;
 
 pub struct EvalSample<B: Backend> {
     pub gt_img: DynamicImage,
+    /// The render encoded to sRGB & quantized to 8 bits, as compared against the ground truth.
     pub rendered: Tensor<B, 3>,
+    /// The render in linear space, with the full range of HDR scenes.
+    pub rendered_linear: Tensor<B, 3>,
     pub psnr: Tensor<B, 1>,
     pub ssim: Tensor<B, 1>,
     pub aux: RenderAux<B>,
@@ -20,15 +25,21 @@ pub struct EvalSample<B: Backend> {
 
 pub async fn eval_stats<B: Backend + SplatForward<B>>(
     splats: Splats<B>,
+    background: Option<BackgroundModel<B>>,
     gt_cam: &Camera,
     gt_img: DynamicImage,
     alpha_is_mask: bool,
+    color_space: ColorSpace,
     device: &B::Device,
 ) -> Result<EvalSample<B>> {
     // Compare MSE in RGB only.
     let res = glam::uvec2(gt_img.width(), gt_img.height());
 
-    let gt_tensor = sample_to_tensor(&view_to_sample_image(gt_img.clone(), alpha_is_mask), device);
+    // Metrics are always computed on sRGB values, so they're comparable to other results.
+    let gt_tensor = sample_to_tensor(
+        &view_to_sample_image(gt_img.clone(), alpha_is_mask, ColorSpace::Srgb),
+        device,
+    );
 
     let gt_rgb = gt_tensor.slice(s![.., .., 0..3]);
 
@@ -36,6 +47,7 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
     let (img, aux) = {
         let (img, aux) = B::render_splats(
             gt_cam,
+            None,
             res,
             splats.means.val().into_primitive().tensor(),
             splats.log_scales.val().into_primitive().tensor(),
@@ -47,7 +59,17 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
         );
         (Tensor::from_primitive(TensorPrimitive::Float(img)), aux)
     };
+    // Like in training, the learned background only shows behind views without transparency.
+    let img = match background.filter(|_| !gt_img.color().has_alpha()) {
+        Some(background) => composite(img, background.render(gt_cam, res)),
+        None => img,
+    };
     let render_rgb = img.slice(s![.., .., 0..3]);
+    let (render_rgb, rendered_linear) = if color_space == ColorSpace::Linear {
+        (linear_to_srgb(render_rgb.clone()), render_rgb)
+    } else {
+        (render_rgb.clone(), srgb_to_linear(render_rgb))
+    };
 
     // Simulate an 8-bit roundtrip for fair comparison.
     let render_rgb = (render_rgb * 255.0).round() / 255.0;
@@ -63,6 +85,18 @@ pub async fn eval_stats<B: Backend + SplatForward<B>>(
         psnr,
         ssim,
         rendered: render_rgb,
+        rendered_linear,
         aux,
     })
+}
+
+fn linear_to_srgb<B: Backend>(x: Tensor<B, 3>) -> Tensor<B, 3> {
+    let x = x.clamp(0.0, 1.0);
+    let high = x.clone().powf_scalar(1.0 / 2.4) * 1.055 - 0.055;
+    high.mask_where(x.clone().lower_equal_elem(0.0031308), x * 12.92)
+}
+
+fn srgb_to_linear<B: Backend>(x: Tensor<B, 3>) -> Tensor<B, 3> {
+    let high = ((x.clone() + 0.055) / 1.055).powf_scalar(2.4);
+    high.mask_where(x.clone().lower_equal_elem(0.04045), x / 12.92)
 }
\ No newline at end of file
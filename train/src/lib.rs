@@ -1,12 +1,16 @@
 #![recursion_limit = "256"]
 
+pub mod background;
 pub mod config;
 pub mod eval;
 pub mod msg;
 pub mod train;
 
 mod adam_scaled;
+mod camera_refine;
+mod divergence;
 mod multinomial;
 mod quat_vec;
+mod refine;
 mod ssim;
 mod stats;
\ No newline at end of file
@@ -1,3 +1,4 @@
+use std::fmt;
 use burn::prelude::{Backend, Int, Tensor};
 
 #[derive(Clone, Debug)]
@@ -6,6 +7,37 @@ pub struct RefineStats {
     pub num_pruned: u32,
 }
 
+/// Why training was rolled back.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DivergenceReason {
+    NonFiniteLoss,
+    NonFiniteParams,
+    LossSpike { loss: f32, average: f32 },
+}
+
+impl fmt::Display for DivergenceReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DivergenceReason::NonFiniteLoss => write!(f, "non-finite loss"),
+            DivergenceReason::NonFiniteParams => write!(f, "non-finite splat parameters"),
+            DivergenceReason::LossSpike { loss, average } => {
+                write!(f, "loss spiked to {loss:.5} from an average of {average:.5}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DivergenceStats {
+    pub reason: DivergenceReason,
+    /// Step of the snapshot training continues from.
+    pub rolled_back_to: u32,
+    /// Multiplier of all learning rates from now on.
+    pub lr_scale: f64,
+    /// Splats with non-finite parameters. Only gathered with the `debug-validation` feature.
+    pub offending_splats: Vec<u32>,
+}
+
 #[derive(Clone, Debug)]
 pub struct TrainStepStats<B: Backend> {
     pub pred_image: Tensor<B, 3>,
@@ -13,10 +45,16 @@ pub struct TrainStepStats<B: Backend> {
     pub num_intersections: Tensor<B, 1, Int>,
     pub num_visible: Tensor<B, 1, Int>,
     pub loss: Tensor<B, 1>,
+    /// Loss of each view in the batch, by the index of the view in its scene.
+    pub view_losses: Vec<(usize, Tensor<B, 1>)>,
 
     pub lr_mean: f64,
     pub lr_rotation: f64,
     pub lr_scale: f64,
     pub lr_coeffs: f64,
     pub lr_opac: f64,
+    /// Learning rate of the camera corrections, when they are learned.
+    pub lr_camera: Option<f64>,
+    /// Learning rate of the background, when it is learned.
+    pub lr_background: Option<f64>,
 }
\ No newline at end of file
@@ -1,4 +1,14 @@
 pub(crate) fn multinomial_sample(weights: &[f32], n: u32) -> Vec<i32> {
+    #[cfg(feature = "debug-validation")]
+    {
+        let invalid: Vec<_> = (0..weights.len()).filter(|&i| !weights[i].is_finite()).collect();
+        assert!(
+            invalid.is_empty(),
+            "Non-finite sampling weights for {} splats, first ones: {:?}",
+            invalid.len(),
+            &invalid[..invalid.len().min(32)]
+        );
+    }
     let mut rng = rand::rng();
     rand::seq::index::sample_weighted(
         &mut rng,
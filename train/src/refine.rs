@@ -0,0 +1,191 @@
+use crate::{
+    adam_scaled::{AdamScaled, AdamState},
+    config::{RefineStrategyConfig, TrainConfig},
+    msg::RefineStats,
+    stats::RefineRecord,
+};
+
+use async_trait::async_trait;
+use burn::{
+    backend::Autodiff,
+    module::ParamId,
+    optim::record::AdaptorRecord,
+    tensor::{Bool, Tensor, backend::AutodiffBackend},
+};
+use hashbrown::HashMap;
+use render::{MainBackend, gaussian_splats::Splats};
+
+mod adc;
+mod default;
+mod mcmc;
+
+pub(crate) type OptimizerRecord = HashMap<ParamId, AdaptorRecord<AdamScaled, Autodiff<MainBackend>>>;
+
+/// Decides when & how splats are added, removed or moved during training.
+#[async_trait]
+pub(crate) trait RefineStrategy: Send {
+    /// Whether to refine after finishing step `iter`.
+    fn should_refine(&self, iter: u32) -> bool;
+
+    /// Refines the splats, using the stats gathered since the last refine. The optimizer state
+    /// has to be kept in sync with the splats.
+    async fn refine(
+        &mut self,
+        iter: u32,
+        scene_extent: f32,
+        splats: Splats<Autodiff<MainBackend>>,
+        optimizer: &mut OptimizerRecord,
+        stats: RefineRecord<MainBackend>,
+    ) -> (Splats<Autodiff<MainBackend>>, RefineStats);
+
+    /// Extra loss terms, added to the image loss each step.
+    fn regularization(
+        &self,
+        _splats: &Splats<Autodiff<MainBackend>>,
+    ) -> Option<Tensor<Autodiff<MainBackend>, 1>> {
+        None
+    }
+
+    /// Moves the means after each optimizer step, to explore the scene.
+    ///
+    /// `opacities` and `visible` are from before the optimizer step, `train_t` is the fraction
+    /// of training that is done.
+    fn add_noise(
+        &self,
+        splats: Splats<Autodiff<MainBackend>>,
+        _opacities: Tensor<MainBackend, 1>,
+        _visible: Tensor<MainBackend, 1>,
+        _lr_mean: f64,
+        _train_t: f32,
+    ) -> Splats<Autodiff<MainBackend>> {
+        splats
+    }
+}
+
+pub(crate) fn create_strategy(config: &TrainConfig) -> Box<dyn RefineStrategy> {
+    match &config.refine_strategy {
+        RefineStrategyConfig::Default => Box::new(default::DefaultRefine::new(config.clone())),
+        RefineStrategyConfig::Adc => Box::new(adc::AdcRefine::new(config.clone())),
+        RefineStrategyConfig::Mcmc(mcmc) => {
+            Box::new(mcmc::McmcRefine::new(config.clone(), mcmc.clone()))
+        }
+    }
+}
+
+pub(crate) fn map_splats_and_opt(
+    mut splats: Splats<Autodiff<MainBackend>>,
+    record: &mut OptimizerRecord,
+    map_mean: impl FnOnce(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
+    map_rotation: impl FnOnce(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
+    map_scale: impl FnOnce(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
+    map_coeffs: impl FnOnce(Tensor<MainBackend, 3>) -> Tensor<MainBackend, 3>,
+    map_opac: impl FnOnce(Tensor<MainBackend, 1>) -> Tensor<MainBackend, 1>,
+
+    map_opt_mean: impl Fn(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
+    map_opt_rotation: impl Fn(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
+    map_opt_scale: impl Fn(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
+    map_opt_coeffs: impl Fn(Tensor<MainBackend, 3>) -> Tensor<MainBackend, 3>,
+    map_opt_opac: impl Fn(Tensor<MainBackend, 1>) -> Tensor<MainBackend, 1>,
+) -> Splats<Autodiff<MainBackend>> {
+    splats.means = splats
+        .means
+        .map(|x| Tensor::from_inner(map_mean(x.inner())).require_grad());
+    map_opt(splats.means.id, record, &map_opt_mean);
+
+    splats.rotation = splats
+        .rotation
+        .map(|x| Tensor::from_inner(map_rotation(x.inner())).require_grad());
+    map_opt(splats.rotation.id, record, &map_opt_rotation);
+
+    splats.log_scales = splats
+        .log_scales
+        .map(|x| Tensor::from_inner(map_scale(x.inner())).require_grad());
+    map_opt(splats.log_scales.id, record, &map_opt_scale);
+
+    splats.sh_coeffs = splats
+        .sh_coeffs
+        .map(|x| Tensor::from_inner(map_coeffs(x.inner())).require_grad());
+    map_opt(splats.sh_coeffs.id, record, &map_opt_coeffs);
+
+    splats.raw_opacity = splats
+        .raw_opacity
+        .map(|x| Tensor::from_inner(map_opac(x.inner())).require_grad());
+    map_opt(splats.raw_opacity.id, record, &map_opt_opac);
+
+    splats
+}
+
+pub(crate) fn map_opt<B: AutodiffBackend, const D: usize>(
+    param_id: ParamId,
+    record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, B>>,
+    map_opt: &impl Fn(Tensor<B::InnerBackend, D>) -> Tensor<B::InnerBackend, D>,
+) {
+    // Frozen parameters are never stepped, so they have no state.
+    let Some(state) = record.remove(&param_id) else {
+        return;
+    };
+    let mut state: AdamState<_, D> = state.into_state();
+
+    state.momentum = state.momentum.map(|mut moment| {
+        moment.moment_1 = map_opt(moment.moment_1);
+        moment.moment_2 = map_opt(moment.moment_2);
+        moment
+    });
+
+    record.insert(param_id, AdaptorRecord::from_state(state));
+}
+
+// Prunes points based on the given mask.
+//
+// Args:
+//   mask: bool[n]. If True, prune this Gaussian.
+pub(crate) async fn prune_points(
+    mut splats: Splats<Autodiff<MainBackend>>,
+    record: &mut OptimizerRecord,
+    mut refiner: RefineRecord<MainBackend>,
+    prune: Tensor<MainBackend, 1, Bool>,
+) -> (
+    Splats<Autodiff<MainBackend>>,
+    RefineRecord<MainBackend>,
+    u32,
+) {
+    assert_eq!(
+        prune.dims()[0] as u32,
+        splats.num_splats(),
+        "Prune mask must have same number of elements as splats"
+    );
+
+    let prune_count = prune.dims()[0];
+    if prune_count == 0 {
+        return (splats, refiner, 0);
+    }
+
+    let valid_inds = prune.bool_not().argwhere_async().await;
+
+    if valid_inds.dims()[0] == 0 {
+        log::warn!("Trying to create empty splat!");
+        return (splats, refiner, 0);
+    }
+
+    let start_splats = splats.num_splats();
+    let new_points = valid_inds.dims()[0] as u32;
+    if new_points < start_splats {
+        let valid_inds = valid_inds.squeeze(1);
+        splats = map_splats_and_opt(
+            splats,
+            record,
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+            |x| x.select(0, valid_inds.clone()),
+        );
+        refiner = refiner.keep(valid_inds);
+    }
+    (splats, refiner, start_splats - new_points)
+}
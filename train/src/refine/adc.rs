@@ -0,0 +1,326 @@
+use super::{OptimizerRecord, RefineStrategy, map_opt, map_splats_and_opt, prune_points};
+use crate::{
+    config::TrainConfig, msg::RefineStats, quat_vec::quaternion_vec_multiply,
+    stats::RefineRecord,
+};
+
+use async_trait::async_trait;
+use burn::{
+    backend::Autodiff,
+    tensor::{Distribution, Int, Tensor},
+};
+use render::{
+    MainBackend,
+    gaussian_splats::{Splats, inverse_sigmoid},
+};
+
+const MIN_OPACITY: f32 = 0.005;
+// Opacity all splats are capped to on a reset.
+const RESET_OPACITY: f32 = 0.01;
+// Split children are this much smaller than their parent.
+const SPLIT_SCALE_DIV: f32 = 1.6;
+// After the first opacity reset, splats larger than this fraction of the scene are pruned.
+const MAX_WORLD_SCALE: f32 = 0.1;
+
+/// Adaptive density control of the original 3DGS paper (Kerbl et al. 2023).
+pub(crate) struct AdcRefine {
+    config: TrainConfig,
+}
+
+impl AdcRefine {
+    pub(crate) fn new(config: TrainConfig) -> Self {
+        Self { config }
+    }
+
+    fn is_densify_step(&self, iter: u32) -> bool {
+        iter % self.config.refine_every == 0
+    }
+
+    fn is_reset_step(&self, iter: u32) -> bool {
+        self.config.opacity_reset_interval > 0 && iter % self.config.opacity_reset_interval == 0
+    }
+}
+
+#[async_trait]
+impl RefineStrategy for AdcRefine {
+    fn should_refine(&self, iter: u32) -> bool {
+        iter > 0
+            && iter < self.config.growth_stop_iter
+            && (self.is_densify_step(iter) || self.is_reset_step(iter))
+    }
+
+    async fn refine(
+        &mut self,
+        iter: u32,
+        scene_extent: f32,
+        mut splats: Splats<Autodiff<MainBackend>>,
+        optimizer: &mut OptimizerRecord,
+        stats: RefineRecord<MainBackend>,
+    ) -> (Splats<Autodiff<MainBackend>>, RefineStats) {
+        let mut refine_stats = RefineStats {
+            num_added: 0,
+            num_pruned: 0,
+        };
+
+        if self.is_densify_step(iter) {
+            // Prune first, so the gradient stats stay aligned with the splats.
+            let max_scale = splats.scales().inner().max_dim(1).squeeze::<1>(1);
+            let mut prune = splats.opacities().inner().lower_elem(MIN_OPACITY);
+            if self.config.opacity_reset_interval > 0 && iter > self.config.opacity_reset_interval {
+                prune = prune.bool_or(max_scale.greater_elem(MAX_WORLD_SCALE * scene_extent));
+            }
+            let (pruned, stats, pruned_count) = prune_points(splats, optimizer, stats, prune).await;
+            splats = pruned;
+            refine_stats.num_pruned = pruned_count;
+
+            let high_grad = stats
+                .mean_refine_weight()
+                .greater_equal_elem(self.config.densify_grad_threshold);
+            let max_scale = splats.scales().inner().max_dim(1).squeeze::<1>(1);
+            let is_small = max_scale.lower_equal_elem(self.config.percent_dense * scene_extent);
+
+            let clone_inds = high_grad
+                .clone()
+                .bool_and(is_small.clone())
+                .argwhere_async()
+                .await;
+            let split_inds = high_grad.bool_and(is_small.bool_not()).argwhere_async().await;
+
+            // Each clone and each split adds one splat, stay under the max nr. of splats.
+            let budget = self.config.max_splats.saturating_sub(splats.num_splats()) as usize;
+            let clone_count = clone_inds.dims()[0].min(budget);
+            let split_count = split_inds.dims()[0].min(budget - clone_count);
+
+            if clone_count + split_count > 0 {
+                // Slicing an empty index list isn't supported, only pass the kinds that are there.
+                let clone_inds = (clone_count > 0)
+                    .then(|| clone_inds.slice([0..clone_count]).squeeze::<1>(1));
+                let split_inds = (split_count > 0)
+                    .then(|| split_inds.slice([0..split_count]).squeeze::<1>(1));
+                splats = densify(splats, optimizer, clone_inds, split_inds);
+                refine_stats.num_added = (clone_count + split_count) as u32;
+            }
+        }
+
+        if self.is_reset_step(iter) {
+            let max_raw_opacity = inverse_sigmoid(RESET_OPACITY);
+            splats.raw_opacity = splats.raw_opacity.map(|o| {
+                Tensor::from_inner(o.inner().clamp_max(max_raw_opacity)).require_grad()
+            });
+            // The optimizer would push the opacities right back up.
+            map_opt(splats.raw_opacity.id, optimizer, &|x: Tensor<MainBackend, 1>| {
+                x.zeros_like()
+            });
+        }
+
+        (splats, refine_stats)
+    }
+}
+
+// Clones the splats at `clone_inds`, and splits the splats at `split_inds` into two children
+// sampled from the parent with a smaller scale.
+fn densify(
+    mut splats: Splats<Autodiff<MainBackend>>,
+    optimizer: &mut OptimizerRecord,
+    clone_inds: Option<Tensor<MainBackend, 1, Int>>,
+    split_inds: Option<Tensor<MainBackend, 1, Int>>,
+) -> Splats<Autodiff<MainBackend>> {
+    let device = splats.means.device();
+    let count = |inds: &Option<Tensor<MainBackend, 1, Int>>| inds.as_ref().map_or(0, |i| i.dims()[0]);
+    let split_count = count(&split_inds);
+    let add_count = count(&clone_inds) + split_count;
+
+    let mut means = vec![];
+    let mut rotations = vec![];
+    let mut log_scales = vec![];
+    let mut coeffs = vec![];
+    let mut raw_opacities = vec![];
+
+    for inds in [&clone_inds, &split_inds].into_iter().flatten() {
+        means.push(splats.means.val().inner().select(0, inds.clone()));
+        rotations.push(splats.rotation.val().inner().select(0, inds.clone()));
+        log_scales.push(splats.log_scales.val().inner().select(0, inds.clone()));
+        coeffs.push(splats.sh_coeffs.val().inner().select(0, inds.clone()));
+        raw_opacities.push(splats.raw_opacity.val().inner().select(0, inds.clone()));
+    }
+
+    if let Some(split_inds) = split_inds {
+        let split_rots = splats.rotations_normed().inner().select(0, split_inds.clone());
+        let split_scales = splats.scales().inner().select(0, split_inds.clone());
+        let sample = || {
+            quaternion_vec_multiply(
+                split_rots.clone(),
+                Tensor::random([split_count, 3], Distribution::Normal(0.0, 1.0), &device)
+                    * split_scales.clone(),
+            )
+        };
+        let scale_offset = Tensor::ones_like(&split_scales) * -SPLIT_SCALE_DIV.ln();
+
+        // The parent becomes the first child, the second child is added.
+        let split_inds_2d = split_inds.unsqueeze_dim(1).repeat_dim(1, 3);
+        let offset = sample();
+        splats.means = splats.means.map(|m| {
+            Tensor::from_inner(m.inner().scatter(0, split_inds_2d.clone(), offset)).require_grad()
+        });
+        splats.log_scales = splats.log_scales.map(|s| {
+            Tensor::from_inner(s.inner().scatter(0, split_inds_2d, scale_offset.clone()))
+                .require_grad()
+        });
+
+        let second = means.len() - 1;
+        means[second] = means[second].clone() + sample();
+        log_scales[second] = log_scales[second].clone() + scale_offset;
+    }
+
+    let sh_dim = splats.sh_coeffs.dims()[1];
+    map_splats_and_opt(
+        splats,
+        optimizer,
+        |x| Tensor::cat([vec![x], means].concat(), 0),
+        |x| Tensor::cat([vec![x], rotations].concat(), 0),
+        |x| Tensor::cat([vec![x], log_scales].concat(), 0),
+        |x| Tensor::cat([vec![x], coeffs].concat(), 0),
+        |x| Tensor::cat([vec![x], raw_opacities].concat(), 0),
+        |x| Tensor::cat(vec![x, Tensor::zeros([add_count, 3], &device)], 0),
+        |x| Tensor::cat(vec![x, Tensor::zeros([add_count, 4], &device)], 0),
+        |x| Tensor::cat(vec![x, Tensor::zeros([add_count, 3], &device)], 0),
+        |x| Tensor::cat(vec![x, Tensor::zeros([add_count, sh_dim, 3], &device)], 0),
+        |x| Tensor::cat(vec![x, Tensor::zeros([add_count], &device)], 0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TrainConfig;
+    use burn::tensor::TensorData;
+    use burn_wgpu::WgpuDevice;
+    use glam::Vec3;
+
+    // Splats along the x axis, with uniform scales.
+    fn test_splats(
+        scales: &[f32],
+        opacities: &[f32],
+        device: &WgpuDevice,
+    ) -> Splats<Autodiff<MainBackend>> {
+        let means: Vec<Vec3> = (0..scales.len()).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect();
+        let log_scales: Vec<Vec3> = scales.iter().map(|s| Vec3::splat(s.ln())).collect();
+        let raw_opacities: Vec<f32> = opacities.iter().map(|&o| inverse_sigmoid(o)).collect();
+        Splats::from_raw(&means, None, Some(&log_scales), None, Some(&raw_opacities), device)
+    }
+
+    // Refine stats where each splat was visible once with the given gradient.
+    fn test_record(grads: &[f32], device: &WgpuDevice) -> RefineRecord<MainBackend> {
+        let mut record = RefineRecord::new(grads.len() as u32, device);
+        record.refine_weight_sum = Tensor::from_floats(grads, device);
+        record.visible_count = Tensor::ones([grads.len()], device);
+        record
+    }
+
+    fn inds(inds: &[i32], device: &WgpuDevice) -> Tensor<MainBackend, 1, Int> {
+        Tensor::from_data(TensorData::new(inds.to_vec(), [inds.len()]), device)
+    }
+
+    // First scale & x coordinate of each splat.
+    async fn scales_and_x(splats: &Splats<Autodiff<MainBackend>>) -> (Vec<f32>, Vec<f32>) {
+        let read = |t: Tensor<MainBackend, 2>| async move {
+            let values = t.into_data_async().await.into_vec::<f32>().expect("Wrong type");
+            values.into_iter().step_by(3).collect::<Vec<_>>()
+        };
+        (read(splats.scales().inner()).await, read(splats.means.val().inner()).await)
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?} != {expected:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{actual:?} != {expected:?}");
+        }
+    }
+
+    fn test_config() -> TrainConfig {
+        TrainConfig::new()
+            .with_opacity_reset_interval(0)
+            .with_densify_grad_threshold(0.5)
+            .with_percent_dense(0.25)
+    }
+
+    #[tokio::test]
+    async fn densify_clones_and_splits() {
+        let device = WgpuDevice::default();
+        let splats = test_splats(&[0.1, 0.2, 0.4], &[0.5; 3], &device);
+        let splats = densify(
+            splats,
+            &mut OptimizerRecord::new(),
+            Some(inds(&[0], &device)),
+            Some(inds(&[2], &device)),
+        );
+
+        let (scales, xs) = scales_and_x(&splats).await;
+        // Clones are appended as they are. The split parent shrinks in place, its second child is appended.
+        let split_scale = 0.4 / SPLIT_SCALE_DIV;
+        assert_close(&scales, &[0.1, 0.2, split_scale, 0.1, split_scale]);
+        assert_close(&xs[..2], &[0.0, 1.0]);
+        assert_close(&xs[3..4], &[0.0]);
+    }
+
+    #[tokio::test]
+    async fn densify_only_clones() {
+        let device = WgpuDevice::default();
+        let splats = test_splats(&[0.1, 0.2], &[0.5; 2], &device);
+        let splats = densify(splats, &mut OptimizerRecord::new(), Some(inds(&[1], &device)), None);
+
+        let (scales, xs) = scales_and_x(&splats).await;
+        assert_close(&scales, &[0.1, 0.2, 0.2]);
+        assert_close(&xs, &[0.0, 1.0, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn refine_prunes_clones_and_splits() {
+        let device = WgpuDevice::default();
+        let config = test_config();
+        let mut adc = AdcRefine::new(config.clone());
+        assert!(adc.should_refine(config.refine_every), "Should densify every refine_every steps");
+
+        // Small & large splats with high and low gradients, and a transparent splat.
+        let splats = test_splats(&[0.1, 0.1, 0.5, 0.5, 0.1], &[0.5, 0.5, 0.5, 0.5, 0.001], &device);
+        let record = test_record(&[1.0, 0.1, 1.0, 0.1, 1.0], &device);
+        let (splats, stats) = adc
+            .refine(config.refine_every, 1.0, splats, &mut OptimizerRecord::new(), record)
+            .await;
+
+        assert_eq!(stats.num_pruned, 1, "The transparent splat should be pruned");
+        assert_eq!(stats.num_added, 2, "One splat should be cloned and one split");
+        let (scales, _) = scales_and_x(&splats).await;
+        let split_scale = 0.5 / SPLIT_SCALE_DIV;
+        assert_close(&scales, &[0.1, 0.1, split_scale, 0.5, 0.1, split_scale]);
+    }
+
+    #[tokio::test]
+    async fn refine_stays_under_max_splats() {
+        let device = WgpuDevice::default();
+        let config = test_config().with_max_splats(3);
+        let mut adc = AdcRefine::new(config.clone());
+
+        // Room for one more splat, which goes to the clone.
+        let splats = test_splats(&[0.1, 0.5], &[0.5; 2], &device);
+        let record = test_record(&[1.0, 1.0], &device);
+        let (splats, stats) = adc
+            .refine(config.refine_every, 1.0, splats, &mut OptimizerRecord::new(), record)
+            .await;
+
+        assert_eq!(stats.num_added, 1, "Only one splat fits under the max");
+        let (scales, _) = scales_and_x(&splats).await;
+        assert_close(&scales, &[0.1, 0.5, 0.1]);
+    }
+
+    #[test]
+    fn refines_until_growth_stops() {
+        let config = test_config().with_growth_stop_iter(1000).with_opacity_reset_interval(300);
+        let adc = AdcRefine::new(config.clone());
+        assert!(!adc.should_refine(0), "Shouldn't refine before the first step");
+        assert!(adc.should_refine(config.refine_every), "Should densify");
+        assert!(adc.should_refine(300), "Should reset the opacity");
+        assert!(!adc.should_refine(301), "Should wait for the next refine");
+        assert!(!adc.should_refine(config.refine_every * 10), "Should stop after growth stops");
+    }
+}
@@ -0,0 +1,275 @@
+use super::{OptimizerRecord, RefineStrategy, map_splats_and_opt, prune_points};
+use crate::{
+    config::TrainConfig, msg::RefineStats, multinomial::multinomial_sample,
+    quat_vec::quaternion_vec_multiply, stats::RefineRecord,
+};
+
+use async_trait::async_trait;
+use burn::{
+    backend::Autodiff,
+    tensor::{Distribution, Tensor, TensorData, activation::sigmoid},
+};
+use hashbrown::HashSet;
+use render::{
+    MainBackend,
+    gaussian_splats::{Splats, inverse_sigmoid},
+};
+use std::f64::consts::SQRT_2;
+
+const MIN_OPACITY: f32 = 0.99 / 255.0;
+
+fn inv_sigmoid<B: burn::prelude::Backend>(x: Tensor<B, 1>) -> Tensor<B, 1> {
+    (x.clone() / (1.0f32 - x)).log()
+}
+
+/// Prunes transparent splats and replaces them by splitting splats sampled by opacity. While
+/// growing, also splits splats with a large screen space gradient.
+pub(crate) struct DefaultRefine {
+    config: TrainConfig,
+}
+
+impl DefaultRefine {
+    pub(crate) fn new(config: TrainConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl RefineStrategy for DefaultRefine {
+    fn should_refine(&self, iter: u32) -> bool {
+        iter > 0 && iter % self.config.refine_every == 0
+    }
+
+    async fn refine(
+        &mut self,
+        iter: u32,
+        _scene_extent: f32,
+        splats: Splats<Autodiff<MainBackend>>,
+        optimizer: &mut OptimizerRecord,
+        stats: RefineRecord<MainBackend>,
+    ) -> (Splats<Autodiff<MainBackend>>, RefineStats) {
+        let device = splats.means.device();
+
+        // Prune dead splats. This ALWAYS happen even if we're not "refining" anymore.
+        let alpha_mask = splats
+            .raw_opacity
+            .val()
+            .inner()
+            .lower_elem(inverse_sigmoid(MIN_OPACITY));
+
+        let (mut splats, refiner, pruned_count) =
+            prune_points(splats, optimizer, stats, alpha_mask).await;
+        let mut add_indices = HashSet::new();
+
+        // Replace dead gaussians if we're still refining.
+        if pruned_count > 0 {
+            // Sample from random opacities.
+            let resampled_weights = splats.opacities().inner();
+            let resampled_weights = resampled_weights
+                .into_data_async()
+                .await
+                .into_vec::<f32>()
+                .expect("Failed to read weights");
+            let resampled_inds = multinomial_sample(&resampled_weights, pruned_count);
+            add_indices.extend(resampled_inds);
+        }
+
+        if iter < self.config.growth_stop_iter {
+            let above_threshold = refiner
+                .refine_weight_norm
+                .clone()
+                .greater_elem(self.config.growth_grad_threshold)
+                .int();
+            let threshold_count = above_threshold.clone().sum().into_scalar_async().await as u32;
+
+            let grow_count =
+                (threshold_count as f32 * self.config.growth_select_fraction).round() as u32;
+
+            let sample_high_grad = grow_count.saturating_sub(pruned_count);
+
+            // Only grow to the max nr. of splats.
+            let cur_splats = splats.num_splats() + add_indices.len() as u32;
+            let grow_count = sample_high_grad.min(self.config.max_splats.saturating_sub(cur_splats));
+
+            // If still growing, sample from indices which are over the threshold.
+            if grow_count > 0 {
+                let weights = above_threshold.float() * refiner.refine_weight_norm;
+                let weights = weights
+                    .into_data_async()
+                    .await
+                    .into_vec::<f32>()
+                    .expect("Failed to read weights");
+                let growth_inds = multinomial_sample(&weights, grow_count);
+                add_indices.extend(growth_inds);
+            }
+        }
+
+        let refine_count = add_indices.len();
+
+        if refine_count > 0 {
+            let refine_inds = Tensor::from_data(
+                TensorData::new(add_indices.into_iter().collect(), [refine_count]),
+                &device,
+            );
+
+            let cur_means = splats.means.val().inner().select(0, refine_inds.clone());
+            let cur_rots = splats
+                .rotations_normed()
+                .inner()
+                .select(0, refine_inds.clone());
+            let cur_log_scale = splats
+                .log_scales
+                .val()
+                .inner()
+                .select(0, refine_inds.clone());
+            let cur_coeff = splats
+                .sh_coeffs
+                .val()
+                .inner()
+                .select(0, refine_inds.clone());
+            let cur_raw_opac = splats
+                .raw_opacity
+                .val()
+                .inner()
+                .select(0, refine_inds.clone());
+
+            // The amount to offset the scale and opacity should maybe depend on how far away we have sampled these gaussians,
+            // but a fixed amount seems to work ok. The only note is that divide by _less_ than SQRT(2) seems to exponentially
+            // blow up, as more 'mass' is added each refine.
+            let scale_div = Tensor::ones_like(&cur_log_scale) * SQRT_2.ln();
+
+            let one = Tensor::ones([1], &device);
+            let cur_opac = sigmoid(cur_raw_opac.clone());
+            let new_opac = one.clone() - (one - cur_opac).sqrt();
+            let new_raw_opac = inv_sigmoid(new_opac.clamp(1e-24, 1.0 - 1e-24));
+
+            // Scatter needs [N, 3] indices for means and scales.
+            let refine_inds_2d = refine_inds.clone().unsqueeze_dim(1).repeat_dim(1, 3);
+
+            let samples = quaternion_vec_multiply(
+                cur_rots.clone(),
+                Tensor::random([refine_count, 3], Distribution::Normal(0.0, 0.5), &device)
+                    * cur_log_scale.clone().exp(),
+            );
+
+            // Shrink & offset existing splats.
+            splats.means = splats.means.map(|m| {
+                let new_means = m
+                    .inner()
+                    .scatter(0, refine_inds_2d.clone(), -samples.clone());
+                Tensor::from_inner(new_means).require_grad()
+            });
+            splats.log_scales = splats.log_scales.map(|s| {
+                let new_scales = s
+                    .inner()
+                    .scatter(0, refine_inds_2d.clone(), -scale_div.clone());
+                Tensor::from_inner(new_scales).require_grad()
+            });
+            splats.raw_opacity = splats.raw_opacity.map(|m| {
+                let difference = new_raw_opac.clone() - cur_raw_opac.clone();
+                let new_opacities = m.inner().scatter(0, refine_inds.clone(), difference);
+                Tensor::from_inner(new_opacities).require_grad()
+            });
+
+            // Concatenate new splats.
+            let sh_dim = splats.sh_coeffs.dims()[1];
+            splats = map_splats_and_opt(
+                splats,
+                optimizer,
+                |x| Tensor::cat(vec![x, cur_means + samples], 0),
+                |x| Tensor::cat(vec![x, cur_rots], 0),
+                |x| Tensor::cat(vec![x, cur_log_scale - scale_div], 0),
+                |x| Tensor::cat(vec![x, cur_coeff], 0),
+                |x| Tensor::cat(vec![x, new_raw_opac], 0),
+                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 3], &device)], 0),
+                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 4], &device)], 0),
+                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 3], &device)], 0),
+                |x| {
+                    Tensor::cat(
+                        vec![x, Tensor::zeros([refine_count, sh_dim, 3], &device)],
+                        0,
+                    )
+                },
+                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count], &device)], 0),
+            );
+        }
+
+        (
+            splats,
+            RefineStats {
+                num_added: refine_count as u32,
+                num_pruned: pruned_count,
+            },
+        )
+    }
+
+    fn add_noise(
+        &self,
+        mut splats: Splats<Autodiff<MainBackend>>,
+        opacities: Tensor<MainBackend, 1>,
+        visible: Tensor<MainBackend, 1>,
+        lr_mean: f64,
+        train_t: f32,
+    ) -> Splats<Autodiff<MainBackend>> {
+        let mean_noise_weight_scale = self.config.mean_noise_weight * (1.0 - train_t);
+        if mean_noise_weight_scale <= 0.0 {
+            return splats;
+        }
+
+        let device = splats.device();
+        // Add random noise. Only do this in the growth phase, otherwise
+        // let the splats settle in without noise, not much point in exploring regions anymore.
+        let one = Tensor::ones([1], &device);
+        let noise_weight = (one - opacities).powi_scalar(100).clamp(0.0, 1.0);
+        let noise_weight = noise_weight * visible; // Only noise visible gaussians.
+        let noise_weight = noise_weight.unsqueeze_dim(1);
+
+        let samples = quaternion_vec_multiply(
+            splats.rotations_normed().inner(),
+            Tensor::random(
+                [splats.num_splats() as usize, 3],
+                Distribution::Normal(0.0, 1.0),
+                &device,
+            ) * splats.scales().inner(),
+        );
+
+        let noise_weight = noise_weight * (lr_mean as f32 * mean_noise_weight_scale);
+        splats.means = splats
+            .means
+            .map(|m| Tensor::from_inner(m.inner() + samples * noise_weight).require_grad());
+        splats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn_wgpu::WgpuDevice;
+    use glam::Vec3;
+
+    #[tokio::test]
+    async fn refine_starting_over_max_splats() {
+        let device = WgpuDevice::default();
+        let config = TrainConfig::new()
+            .with_max_splats(2)
+            .with_growth_grad_threshold(0.5)
+            .with_growth_select_fraction(1.0);
+        let mut refine = DefaultRefine::new(config.clone());
+
+        // More splats than the max, like when continuing from the splats of a larger scene. All
+        // of them are opaque and over the gradient threshold.
+        let means: Vec<Vec3> = (0..4).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect();
+        let log_scales = vec![Vec3::splat(0.1f32.ln()); 4];
+        let raw_opacities = vec![inverse_sigmoid(0.5); 4];
+        let splats = Splats::from_raw(&means, None, Some(&log_scales), None, Some(&raw_opacities), &device);
+        let mut record = RefineRecord::new(4, &device);
+        record.refine_weight_norm = Tensor::ones([4], &device);
+
+        let (splats, stats) = refine
+            .refine(config.refine_every, 1.0, splats, &mut OptimizerRecord::new(), record)
+            .await;
+        assert_eq!(stats.num_added, 0, "Nothing should grow over the max");
+        assert_eq!(stats.num_pruned, 0);
+        assert_eq!(splats.num_splats(), 4);
+    }
+}
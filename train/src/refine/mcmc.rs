@@ -0,0 +1,276 @@
+use super::{OptimizerRecord, RefineStrategy, map_splats_and_opt, prune_points};
+use crate::{
+    config::{McmcConfig, TrainConfig},
+    msg::RefineStats,
+    quat_vec::quaternion_vec_multiply,
+    stats::RefineRecord,
+};
+
+use async_trait::async_trait;
+use burn::{
+    backend::Autodiff,
+    tensor::{Distribution, Int, Tensor, TensorData, activation::sigmoid},
+};
+use hashbrown::HashMap;
+use rand::distr::{Distribution as _, weighted::WeightedIndex};
+use render::{
+    MainBackend,
+    gaussian_splats::{Splats, inverse_sigmoid},
+};
+
+// Relocating onto a splat more often than this doesn't change the result anymore.
+const MAX_SPLIT: usize = 51;
+
+/// 3DGS-MCMC (Kheradmand et al. 2024). Splats are treated as samples of the scene: dead splats
+/// are relocated onto live ones in a way that preserves the rendered image, and noise on the
+/// means lets splats explore.
+pub(crate) struct McmcRefine {
+    config: TrainConfig,
+    mcmc: McmcConfig,
+}
+
+impl McmcRefine {
+    pub(crate) fn new(config: TrainConfig, mcmc: McmcConfig) -> Self {
+        Self { config, mcmc }
+    }
+}
+
+#[async_trait]
+impl RefineStrategy for McmcRefine {
+    fn should_refine(&self, iter: u32) -> bool {
+        // Dead splats keep being relocated after growth stops.
+        iter > 0 && iter % self.config.refine_every == 0
+    }
+
+    async fn refine(
+        &mut self,
+        iter: u32,
+        _scene_extent: f32,
+        splats: Splats<Autodiff<MainBackend>>,
+        optimizer: &mut OptimizerRecord,
+        stats: RefineRecord<MainBackend>,
+    ) -> (Splats<Autodiff<MainBackend>>, RefineStats) {
+        let device = splats.means.device();
+        let start_count = splats.num_splats();
+
+        let dead = splats
+            .opacities()
+            .inner()
+            .lower_equal_elem(self.mcmc.min_opacity);
+        let (mut splats, _, dead_count) = prune_points(splats, optimizer, stats, dead).await;
+
+        // Dead splats are relocated, and while growing the count goes up until the cap.
+        let target = if iter < self.config.growth_stop_iter {
+            ((start_count as f32 * (1.0 + self.mcmc.growth_rate)).ceil() as u32)
+                .min(self.config.max_splats)
+        } else {
+            start_count
+        };
+        let add_count = target.saturating_sub(splats.num_splats()) as usize;
+
+        let opacities = splats
+            .opacities()
+            .inner()
+            .into_data_async()
+            .await
+            .into_vec::<f32>()
+            .expect("Failed to read opacities");
+        let Some(sources) = sample_with_replacement(&opacities, add_count) else {
+            return (
+                splats,
+                RefineStats {
+                    num_added: 0,
+                    num_pruned: dead_count,
+                },
+            );
+        };
+
+        // Every copy of a source takes a share of its opacity & size.
+        let mut split_counts: HashMap<usize, usize> = HashMap::new();
+        for &source in &sources {
+            *split_counts.entry(source).or_insert(1) += 1;
+        }
+        let mut update_inds = Vec::with_capacity(split_counts.len());
+        let mut opac_deltas = Vec::with_capacity(split_counts.len());
+        let mut log_scale_deltas = Vec::with_capacity(split_counts.len());
+        for (&source, &count) in &split_counts {
+            let opacity = opacities[source].clamp(self.mcmc.min_opacity, 1.0 - 1e-6);
+            let (new_opacity, scale_factor) = relocation_update(opacity, count);
+            let new_opacity = new_opacity.clamp(self.mcmc.min_opacity, 1.0 - 1e-6);
+            update_inds.push(source as i32);
+            opac_deltas.push(inverse_sigmoid(new_opacity) - inverse_sigmoid(opacity));
+            log_scale_deltas.push(scale_factor.ln());
+        }
+
+        let num_updates = update_inds.len();
+        let update_inds: Tensor<MainBackend, 1, Int> =
+            Tensor::from_data(TensorData::new(update_inds, [num_updates]), &device);
+        let opac_deltas: Tensor<MainBackend, 1> =
+            Tensor::from_data(TensorData::new(opac_deltas, [num_updates]), &device);
+        let log_scale_deltas: Tensor<MainBackend, 2> =
+            Tensor::<MainBackend, 1>::from_data(TensorData::new(log_scale_deltas, [num_updates]), &device)
+                .unsqueeze_dim(1)
+                .repeat_dim(1, 3);
+        let update_inds_2d = update_inds.clone().unsqueeze_dim(1).repeat_dim(1, 3);
+
+        // The sources changed, so their Adam moments don't apply anymore. Zero them like the moments of the copies.
+        let num_splats = splats.num_splats() as usize;
+        let keep_moments = Tensor::<MainBackend, 1>::ones([num_splats], &device).scatter(
+            0,
+            update_inds.clone(),
+            Tensor::ones([num_updates], &device).neg(),
+        );
+        let keep_moments_2d = keep_moments.clone().unsqueeze_dim::<2>(1);
+        let keep_moments_3d = keep_moments.clone().reshape([num_splats, 1, 1]);
+
+        splats.raw_opacity = splats.raw_opacity.map(|o| {
+            Tensor::from_inner(o.inner().scatter(0, update_inds, opac_deltas)).require_grad()
+        });
+        splats.log_scales = splats.log_scales.map(|s| {
+            Tensor::from_inner(s.inner().scatter(0, update_inds_2d, log_scale_deltas)).require_grad()
+        });
+
+        // Add the copies, with the updated opacity & scale of their source.
+        let add_count = sources.len();
+        let source_inds: Tensor<MainBackend, 1, Int> = Tensor::from_data(
+            TensorData::new(sources.into_iter().map(|i| i as i32).collect(), [add_count]),
+            &device,
+        );
+        let new_means = splats.means.val().inner().select(0, source_inds.clone());
+        let new_rots = splats.rotation.val().inner().select(0, source_inds.clone());
+        let new_log_scales = splats.log_scales.val().inner().select(0, source_inds.clone());
+        let new_coeffs = splats.sh_coeffs.val().inner().select(0, source_inds.clone());
+        let new_raw_opac = splats.raw_opacity.val().inner().select(0, source_inds);
+
+        let sh_dim = splats.sh_coeffs.dims()[1];
+        let splats = map_splats_and_opt(
+            splats,
+            optimizer,
+            |x| Tensor::cat(vec![x, new_means], 0),
+            |x| Tensor::cat(vec![x, new_rots], 0),
+            |x| Tensor::cat(vec![x, new_log_scales], 0),
+            |x| Tensor::cat(vec![x, new_coeffs], 0),
+            |x| Tensor::cat(vec![x, new_raw_opac], 0),
+            |x| Tensor::cat(vec![x * keep_moments_2d.clone(), Tensor::zeros([add_count, 3], &device)], 0),
+            |x| Tensor::cat(vec![x * keep_moments_2d.clone(), Tensor::zeros([add_count, 4], &device)], 0),
+            |x| Tensor::cat(vec![x * keep_moments_2d.clone(), Tensor::zeros([add_count, 3], &device)], 0),
+            |x| Tensor::cat(vec![x * keep_moments_3d.clone(), Tensor::zeros([add_count, sh_dim, 3], &device)], 0),
+            |x| Tensor::cat(vec![x * keep_moments.clone(), Tensor::zeros([add_count], &device)], 0),
+        );
+
+        (
+            splats,
+            RefineStats {
+                num_added: add_count as u32,
+                num_pruned: dead_count,
+            },
+        )
+    }
+
+    fn regularization(
+        &self,
+        splats: &Splats<Autodiff<MainBackend>>,
+    ) -> Option<Tensor<Autodiff<MainBackend>, 1>> {
+        let opac_reg = splats.opacities().mean() * self.mcmc.opac_reg_weight;
+        let scale_reg = splats.scales().mean() * self.mcmc.scale_reg_weight;
+        Some(opac_reg + scale_reg)
+    }
+
+    fn add_noise(
+        &self,
+        mut splats: Splats<Autodiff<MainBackend>>,
+        opacities: Tensor<MainBackend, 1>,
+        _visible: Tensor<MainBackend, 1>,
+        lr_mean: f64,
+        _train_t: f32,
+    ) -> Splats<Autodiff<MainBackend>> {
+        let device = splats.device();
+
+        // Mostly transparent splats move freely, opaque splats hardly move at all.
+        let noise_weight = sigmoid((opacities - self.mcmc.min_opacity) * -100.0);
+        let noise_weight = noise_weight.unsqueeze_dim(1) * (lr_mean as f32 * self.mcmc.noise_lr);
+
+        // Noise shaped by the covariance of each splat: R S^2 R^T applied to white noise
+        // is distributed like R S^2 applied to white noise.
+        let scales = splats.scales().inner();
+        let samples = quaternion_vec_multiply(
+            splats.rotations_normed().inner(),
+            Tensor::random(
+                [splats.num_splats() as usize, 3],
+                Distribution::Normal(0.0, 1.0),
+                &device,
+            ) * scales.clone()
+                * scales,
+        );
+
+        splats.means = splats
+            .means
+            .map(|m| Tensor::from_inner(m.inner() + samples * noise_weight).require_grad());
+        splats
+    }
+}
+
+// Draws `count` indices weighted by `weights`, with replacement.
+fn sample_with_replacement(weights: &[f32], count: usize) -> Option<Vec<usize>> {
+    if count == 0 {
+        return None;
+    }
+    let weights = weights.iter().map(|&w| if w.is_finite() { w.max(0.0) } else { 0.0 });
+    let dist = WeightedIndex::new(weights).ok()?;
+    let mut rng = rand::rng();
+    Some((0..count).map(|_| dist.sample(&mut rng)).collect())
+}
+
+/// Opacity & scale factor for each of `count` splats replacing one splat, such that together
+/// they render approximately like the original splat (Eq. 9 of the MCMC paper).
+fn relocation_update(opacity: f32, count: usize) -> (f32, f32) {
+    let count = count.clamp(1, MAX_SPLIT);
+    let opacity = opacity as f64;
+    let new_opacity = 1.0 - (1.0 - opacity).powf(1.0 / count as f64);
+
+    let mut denom = 0.0;
+    for i in 1..=count {
+        for k in 0..i {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            denom += binomial(i - 1, k) * sign * new_opacity.powi(k as i32 + 1)
+                / ((k + 1) as f64).sqrt();
+        }
+    }
+    let scale_factor = opacity / denom;
+    (new_opacity as f32, scale_factor as f32)
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_copy_is_unchanged() {
+        let (opacity, scale) = relocation_update(0.4, 1);
+        assert!((opacity - 0.4).abs() < 1e-6);
+        assert!((scale - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn copies_share_opacity() {
+        for count in 2..10 {
+            let (opacity, scale) = relocation_update(0.8, count);
+            // Alpha blending all copies gives back the original opacity.
+            let combined = 1.0 - (1.0 - opacity).powi(count as i32);
+            assert!((combined - 0.8).abs() < 1e-5);
+            assert!(opacity < 0.8);
+            assert!(scale.is_finite() && scale > 0.0);
+        }
+    }
+
+    #[test]
+    fn binomials() {
+        assert_eq!(binomial(5, 0), 1.0);
+        assert_eq!(binomial(5, 2), 10.0);
+        assert_eq!(binomial(6, 3), 20.0);
+    }
+}
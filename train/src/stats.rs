@@ -13,6 +13,8 @@ fn stats_gather_kernel(
     num_visible: &Tensor<u32>,
     refine_weight: &Tensor<Line<f32>>,
     accum_refine_weight: &mut Tensor<f32>,
+    sum_refine_weight: &mut Tensor<f32>,
+    visible_count: &mut Tensor<f32>,
     #[comptime] w: u32,
     #[comptime] h: u32,
 ) {
@@ -34,20 +36,32 @@ fn stats_gather_kernel(
     let refine_norm =
         f32::sqrt(refine_grads[0] * refine_grads[0] + refine_grads[1] * refine_grads[1]);
     accum_refine_weight[global_gid] = f32::max(accum_refine_weight[global_gid], refine_norm);
+    sum_refine_weight[global_gid] += refine_norm;
+    visible_count[global_gid] += 1.0;
 }
 
 pub(crate) struct RefineRecord<B: Backend> {
     // Helper tensors for accumulating the viewspace_xy gradients and the number
     // of observations per gaussian. Used in pruning and densification.
     pub refine_weight_norm: burn::tensor::Tensor<B, 1>,
+    // Sum of the gradient norms & nr. of renders each gaussian was visible in, for the mean gradient.
+    pub refine_weight_sum: burn::tensor::Tensor<B, 1>,
+    pub visible_count: burn::tensor::Tensor<B, 1>,
 }
 
 impl<B: Backend> RefineRecord<B> {
     pub(crate) fn new(num_points: u32, device: &B::Device) -> Self {
         Self {
             refine_weight_norm: burn::tensor::Tensor::<B, 1>::zeros([num_points as usize], device),
+            refine_weight_sum: burn::tensor::Tensor::<B, 1>::zeros([num_points as usize], device),
+            visible_count: burn::tensor::Tensor::<B, 1>::zeros([num_points as usize], device),
         }
     }
+
+    /// Mean gradient norm over the renders each gaussian was visible in.
+    pub(crate) fn mean_refine_weight(&self) -> burn::tensor::Tensor<B, 1> {
+        self.refine_weight_sum.clone() / self.visible_count.clone().clamp_min(1.0)
+    }
 }
 
 impl RefineRecord<MainBackend> {
@@ -76,6 +90,12 @@ impl RefineRecord<MainBackend> {
         let refine_accum = client.resolve_tensor_float::<MainBackendBase>(
             self.refine_weight_norm.clone().into_primitive().tensor(),
         );
+        let refine_sum = client.resolve_tensor_float::<MainBackendBase>(
+            self.refine_weight_sum.clone().into_primitive().tensor(),
+        );
+        let visible_count = client.resolve_tensor_float::<MainBackendBase>(
+            self.visible_count.clone().into_primitive().tensor(),
+        );
 
         const WG_SIZE: u32 = 256;
         // Execute lazily the kernel with the launch information and the given buffers. For
@@ -92,6 +112,8 @@ impl RefineRecord<MainBackend> {
             num_visible.as_tensor_arg::<u32>(1),
             refine_weight.as_tensor_arg::<f32>(2),
             refine_accum.as_tensor_arg::<f32>(1),
+            refine_sum.as_tensor_arg::<f32>(1),
+            visible_count.as_tensor_arg::<f32>(1),
             w,
             h,
         );
@@ -101,7 +123,9 @@ impl RefineRecord<MainBackend> {
 impl<B: Backend> RefineRecord<B> {
     pub(crate) fn keep(self, indices: burn::tensor::Tensor<B, 1, burn::prelude::Int>) -> Self {
         Self {
-            refine_weight_norm: self.refine_weight_norm.select(0, indices),
+            refine_weight_norm: self.refine_weight_norm.select(0, indices.clone()),
+            refine_weight_sum: self.refine_weight_sum.select(0, indices.clone()),
+            visible_count: self.visible_count.select(0, indices),
         }
     }
 }
\ No newline at end of file
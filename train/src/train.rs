@@ -1,84 +1,96 @@
 use crate::{
     adam_scaled::{AdamScaled, AdamScaledConfig, AdamState},
-    config::TrainConfig,
-    msg::{RefineStats, TrainStepStats},
-    multinomial::multinomial_sample,
-    quat_vec::quaternion_vec_multiply,
+    background::{BackgroundModel, composite},
+    camera_refine::{CameraAdjustments, apply_adjustments},
+    config::{LrSchedule, TrainConfig},
+    divergence::{DivergenceGuard, Snapshot},
+    msg::{DivergenceStats, RefineStats, TrainStepStats},
+    refine::{RefineStrategy, create_strategy, map_splats_and_opt},
     ssim::Ssim,
     stats::RefineRecord,
 };
 
 use dataset::scene::SceneBatch;
+use render::camera::Camera;
 use render::sh::sh_coeffs_for_degree;
-use render::{
-    MainBackend,
-    gaussian_splats::{Splats, inverse_sigmoid},
-};
+use render::{MainBackend, gaussian_splats::Splats};
 use render_bwd::burn_glue::SplatForwardDiff;
+#[cfg(feature = "debug-validation")]
+use crate::msg::DivergenceReason;
 use burn::{
+    module::AutodiffModule,
     backend::{
         Autodiff,
         wgpu::{WgpuDevice, WgpuRuntime},
     },
-    lr_scheduler::{
-        LrScheduler,
-        exponential::{ExponentialLrScheduler, ExponentialLrSchedulerConfig},
-    },
-    module::ParamId,
     optim::{GradientsParams, Optimizer, adaptor::OptimizerAdaptor, record::AdaptorRecord},
-    prelude::Backend,
-    tensor::{
-        Bool, Distribution, Tensor, TensorData, TensorPrimitive, activation::sigmoid,
-        backend::AutodiffBackend, s,
-    },
+    tensor::{Tensor, TensorPrimitive, s},
 };
 
 use burn_cubecl::cubecl::Runtime;
-use glam::Vec3;
-use hashbrown::{HashMap, HashSet};
+use glam::{UVec2, Vec3};
+use hashbrown::HashMap;
 use rand::Rng;
-use std::f64::consts::SQRT_2;
 use tracing::trace_span;
 
-const MIN_OPACITY: f32 = 0.99 / 255.0;
-
 type OptimizerType =
 OptimizerAdaptor<AdamScaled, Splats<Autodiff<MainBackend>>, Autodiff<MainBackend>>;
+type CameraOptimizerType =
+OptimizerAdaptor<AdamScaled, CameraAdjustments<Autodiff<MainBackend>>, Autodiff<MainBackend>>;
+type BackgroundOptimizerType =
+OptimizerAdaptor<AdamScaled, BackgroundModel<Autodiff<MainBackend>>, Autodiff<MainBackend>>;
 
 pub struct SplatTrainer {
     config: TrainConfig,
-    sched_mean: ExponentialLrScheduler,
-    sched_scale: ExponentialLrScheduler,
     ssim: Ssim<Autodiff<MainBackend>>,
     refine_record: Option<RefineRecord<MainBackend>>,
     optim: Option<OptimizerType>,
-}
-
-fn inv_sigmoid<B: Backend>(x: Tensor<B, 1>) -> Tensor<B, 1> {
-    (x.clone() / (1.0f32 - x)).log()
+    strategy: Box<dyn RefineStrategy>,
+    guard: DivergenceGuard,
+    rollbacks: u32,
+    // Multiplier of all learning rates, lowered after each rollback.
+    lr_scale: f64,
+    cameras: Option<CameraAdjustments<Autodiff<MainBackend>>>,
+    camera_optim: Option<CameraOptimizerType>,
+    background: Option<BackgroundModel<Autodiff<MainBackend>>>,
+    background_optim: Option<BackgroundOptimizerType>,
 }
 
 fn create_default_optimizer() -> OptimizerType {
     AdamScaledConfig::new().with_epsilon(1e-15).init()
 }
 
+// Learning rate multiplier of each SH coefficient, the higher bands learn slower than the base color.
+fn sh_lr_scales(sh_degree: u32, lr_coeffs_sh_scale: f32, device: &WgpuDevice) -> Tensor<MainBackend, 3> {
+    let coeff_count = sh_coeffs_for_degree(sh_degree) as usize;
+    let mut scales = vec![1.0];
+    for _ in 1..coeff_count {
+        scales.push(1.0 / lr_coeffs_sh_scale);
+    }
+    Tensor::<_, 1>::from_floats(scales.as_slice(), device).reshape([1, coeff_count, 1])
+}
+
 impl SplatTrainer {
-    pub fn new(config: &TrainConfig, device: &WgpuDevice) -> Self {
+    /// Creates a trainer for a scene with `num_views` training views.
+    pub fn new(config: &TrainConfig, num_views: usize, device: &WgpuDevice) -> Self {
         const SSIM_WINDOW_SIZE: usize = 11; // Could be configurable but meh, rather keep consistent.
         let ssim = Ssim::new(SSIM_WINDOW_SIZE, 3, device);
 
-        let decay = (config.lr_mean_end / config.lr_mean).powf(1.0 / config.total_steps as f64);
-        let lr_mean = ExponentialLrSchedulerConfig::new(config.lr_mean, decay);
-
-        let decay = (config.lr_scale_end / config.lr_scale).powf(1.0 / config.total_steps as f64);
-        let lr_scale = ExponentialLrSchedulerConfig::new(config.lr_scale, decay);
-
         Self {
             config: config.clone(),
-            sched_mean: lr_mean.init().expect("Mean lr schedule must be valid."),
-            sched_scale: lr_scale.init().expect("Scale lr schedule must be valid."),
             optim: None,
             refine_record: None,
+            strategy: create_strategy(config),
+            guard: DivergenceGuard::new(config),
+            rollbacks: 0,
+            lr_scale: 1.0,
+            cameras: (config.refine_poses || config.refine_focal)
+                .then(|| CameraAdjustments::new(num_views, device)),
+            camera_optim: None,
+            background: config
+                .learn_background
+                .then(|| BackgroundModel::new(config.background_sh_degree, device)),
+            background_optim: None,
             ssim,
         }
     }
@@ -87,17 +99,29 @@ impl SplatTrainer {
         &mut self,
         scene_extent: f32,
         iter: u32,
-        batch: &SceneBatch<Autodiff<MainBackend>>,
+        batches: &[SceneBatch<Autodiff<MainBackend>>],
         splats: Splats<Autodiff<MainBackend>>,
     ) -> (Splats<Autodiff<MainBackend>>, TrainStepStats<MainBackend>) {
         let mut splats = splats;
 
-        let [img_h, img_w, _] = batch.img_tensor.dims();
-        let camera = &batch.camera;
+        if iter > 0 && self.config.downscale_at(iter) != self.config.downscale_at(iter - 1) {
+            // A new resolution changes the loss, spikes are relative to the new average.
+            self.guard.restart();
+        }
+
+        let sh_degree = self.config.sh_degree_at(iter);
+        if sh_degree > splats.sh_degree() && !self.config.freeze_sh {
+            splats = self.raise_sh_degree(splats, sh_degree);
+        }
 
         let current_opacity = splats.opacities();
-        let (pred_image, aux, refine_weight_holder) = {
-            let background = if batch.has_alpha() {
+        let mut renders = vec![];
+        let mut view_losses = Vec::with_capacity(batches.len());
+
+        for batch in batches {
+            // Views without transparency are composited over the learned background, if any.
+            let learned_background = self.background.as_ref().filter(|_| !batch.has_alpha());
+            let background = if batch.has_alpha() || learned_background.is_some() {
                 // For transparent items, do _not_ use a random background color. This could work
                 // if we blend the background color with the training view, but makes more sense to just use a black background color.
                 Vec3::ZERO
@@ -110,58 +134,82 @@ impl SplatTrainer {
                 )
             };
 
-            let diff_out = <Autodiff<MainBackend> as SplatForwardDiff<_>>::render_splats(
-                camera,
-                glam::uvec2(img_w as u32, img_h as u32),
-                splats.means.val().into_primitive().tensor(),
-                splats.log_scales.val().into_primitive().tensor(),
-                splats.rotation.val().into_primitive().tensor(),
-                splats.sh_coeffs.val().into_primitive().tensor(),
-                current_opacity.clone().into_primitive().tensor(),
-                background,
-            );
-            let img = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
-
-            #[cfg(feature = "debug-validation")]
-            diff_out.aux.debug_assert_valid();
+            let targets = self.training_targets(batch);
+            let num_targets = targets.len();
+            let mut view_loss = None;
+
+            for (gt_image, camera) in targets {
+                let [img_h, img_w, _] = gt_image.dims();
+                let img_size = glam::uvec2(img_w as u32, img_h as u32);
+
+                // The corrected camera stays on the GPU, the renderer has gradients for it.
+                let view = self.cameras.as_ref().map(|cameras| {
+                    cameras.view(
+                        batch.view_index,
+                        &camera,
+                        img_size,
+                        scene_extent,
+                        self.config.refine_poses,
+                        self.config.refine_focal,
+                    )
+                });
+
+                let diff_out = <Autodiff<MainBackend> as SplatForwardDiff<_>>::render_splats(
+                    &camera,
+                    view.map(|view| view.into_primitive().tensor()),
+                    img_size,
+                    splats.means.val().into_primitive().tensor(),
+                    splats.log_scales.val().into_primitive().tensor(),
+                    splats.rotation.val().into_primitive().tensor(),
+                    splats.sh_coeffs.val().into_primitive().tensor(),
+                    current_opacity.clone().into_primitive().tensor(),
+                    background,
+                );
+                let pred_image = Tensor::from_primitive(TensorPrimitive::Float(diff_out.img));
+                let pred_image = match learned_background {
+                    Some(model) => composite(pred_image, model.render(&camera, img_size)),
+                    None => pred_image,
+                };
+
+                #[cfg(feature = "debug-validation")]
+                diff_out.aux.debug_assert_valid();
+
+                let target_loss = {
+                    let _span = trace_span!("Calculate losses", sync_burn = true).entered();
+                    self.image_loss(pred_image.clone(), gt_image, batch.alpha_is_mask)
+                };
+                view_loss = Some(match view_loss {
+                    Some(loss) => loss + target_loss,
+                    None => target_loss,
+                });
+                renders.push((pred_image, diff_out.aux, diff_out.refine_weight_holder, img_size));
+            }
+            let view_loss = view_loss.expect("Need at least one training target") / num_targets as f32;
+            view_losses.push((batch.view_index, view_loss));
+        }
+        let num_renders = renders.len();
 
-            (img, diff_out.aux, diff_out.refine_weight_holder)
-        };
+        let loss = view_losses
+            .iter()
+            .map(|(_, loss)| loss.clone())
+            .reduce(|a, b| a + b)
+            .expect("Need at least one view in the batch")
+            / view_losses.len() as f32;
 
         let train_t = (iter as f32 / self.config.total_steps as f32).clamp(0.0, 1.0);
 
-        let _span = trace_span!("Calculate losses", sync_burn = true).entered();
-
-        let pred_rgb = pred_image.clone().slice(s![.., .., 0..3]);
-        let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..3]);
-
-        let l1_rgb = (pred_rgb.clone() - gt_rgb).abs();
-
-        let total_err = if self.config.ssim_weight > 0.0 {
-            let gt_rgb = batch.img_tensor.clone().slice(s![.., .., 0..3]);
-            let ssim_err = self.ssim.ssim(pred_rgb, gt_rgb);
-            l1_rgb * (1.0 - self.config.ssim_weight) - (ssim_err * self.config.ssim_weight)
-        } else {
-            l1_rgb
-        };
-
-        let loss = if batch.has_alpha() {
-            let alpha_input = batch.img_tensor.clone().slice(s![.., .., 3..4]);
-
-            if batch.alpha_is_mask {
-                (total_err * alpha_input).mean()
-            } else {
-                let pred_alpha = pred_image.clone().slice(s![.., .., 3..4]);
-                total_err.mean()
-                    + (alpha_input - pred_alpha).abs().mean() * self.config.match_alpha_weight
-            }
-        } else {
-            total_err.mean()
-        };
-
         let opac_loss_weight = self.config.opac_loss_weight;
-        let visible: Tensor<_, 1> =
-            Tensor::from_primitive(TensorPrimitive::Float(aux.visible.clone()));
+        // Splats visible in any of the renders.
+        let visible: Tensor<_, 1> = renders
+            .iter()
+            .map(|(_, aux, _, _)| Tensor::from_primitive(TensorPrimitive::Float(aux.visible.clone())))
+            .reduce(|a: Tensor<_, 1>, b| a.max_pair(b))
+            .expect("Need at least one training target");
+
+        let loss = match self.strategy.regularization(&splats) {
+            Some(regularization) => loss + regularization,
+            None => loss,
+        };
 
         let loss = if opac_loss_weight > 0.0 {
             // Invisible splats still have a tiny bit of loss. Otherwise,
@@ -174,28 +222,32 @@ impl SplatTrainer {
 
         let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
 
+        let config = &self.config;
+        let lr_scale = self.lr_scale;
+        // Frozen parameters report a learning rate of zero, and aren't stepped at all.
+        let lr_at = |frozen: bool, schedule: &LrSchedule, start: f64| {
+            if frozen {
+                0.0
+            } else {
+                schedule.lr_at(start, iter, config.total_steps) * lr_scale
+            }
+        };
         let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = (
-            self.sched_mean.step() * scene_extent as f64,
-            self.config.lr_rotation,
+            lr_at(config.freeze_means, &config.lr_mean_schedule, config.lr_mean) * scene_extent as f64,
+            lr_at(config.freeze_rotation, &config.lr_rotation_schedule, config.lr_rotation),
             // Scale is relative to the scene scale, but the exp() activation function
             // means "offsetting" all values also solves the learning rate scaling.
-            self.sched_scale.step(),
-            self.config.lr_coeffs_dc,
-            self.config.lr_opac,
+            lr_at(config.freeze_scales, &config.lr_scale_schedule, config.lr_scale),
+            lr_at(config.freeze_sh, &config.lr_coeffs_schedule, config.lr_coeffs_dc),
+            lr_at(config.freeze_opacity, &config.lr_opac_schedule, config.lr_opac),
         );
 
         let optimizer = self.optim.get_or_insert_with(|| {
-            let sh_degree = splats.sh_degree();
-            let device = splats.device();
-
-            let coeff_count = sh_coeffs_for_degree(sh_degree) as i32;
-            let sh_size = coeff_count;
-            let mut sh_lr_scales = vec![1.0];
-            for _ in 1..sh_size {
-                sh_lr_scales.push(1.0 / self.config.lr_coeffs_sh_scale);
-            }
-            let sh_lr_scales = Tensor::<_, 1>::from_floats(sh_lr_scales.as_slice(), &device)
-                .reshape([1, coeff_count, 1]);
+            let sh_lr_scales = sh_lr_scales(
+                splats.sh_degree(),
+                self.config.lr_coeffs_sh_scale,
+                &splats.device(),
+            );
 
             create_default_optimizer().load_record(HashMap::from([(
                 splats.sh_coeffs.id,
@@ -207,392 +259,458 @@ impl SplatTrainer {
         });
 
         splats = trace_span!("Optimizer step", sync_burn = true).in_scope(|| {
-            splats = trace_span!("SH Coeffs step", sync_burn = true).in_scope(|| {
-                let grad_coeff =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.sh_coeffs.id]);
-                optimizer.step(lr_coeffs, splats, grad_coeff)
-            });
-            splats = trace_span!("Rotation step", sync_burn = true).in_scope(|| {
-                let grad_rot =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.rotation.id]);
-                optimizer.step(lr_rotation, splats, grad_rot)
-            });
-            splats = trace_span!("Scale step", sync_burn = true).in_scope(|| {
-                let grad_scale =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.log_scales.id]);
-                optimizer.step(lr_scale, splats, grad_scale)
-            });
-            splats = trace_span!("Mean step", sync_burn = true).in_scope(|| {
-                let grad_means =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.means.id]);
-                optimizer.step(lr_mean, splats, grad_means)
-            });
-            splats = trace_span!("Opacity step", sync_burn = true).in_scope(|| {
-                let grad_opac =
-                    GradientsParams::from_params(&mut grads, &splats, &[splats.raw_opacity.id]);
-                optimizer.step(lr_opac, splats, grad_opac)
-            });
+            if !config.freeze_sh {
+                splats = trace_span!("SH Coeffs step", sync_burn = true).in_scope(|| {
+                    let grad_coeff =
+                        GradientsParams::from_params(&mut grads, &splats, &[splats.sh_coeffs.id]);
+                    optimizer.step(lr_coeffs, splats, grad_coeff)
+                });
+            }
+            if !config.freeze_rotation {
+                splats = trace_span!("Rotation step", sync_burn = true).in_scope(|| {
+                    let grad_rot =
+                        GradientsParams::from_params(&mut grads, &splats, &[splats.rotation.id]);
+                    optimizer.step(lr_rotation, splats, grad_rot)
+                });
+            }
+            if !config.freeze_scales {
+                splats = trace_span!("Scale step", sync_burn = true).in_scope(|| {
+                    let grad_scale =
+                        GradientsParams::from_params(&mut grads, &splats, &[splats.log_scales.id]);
+                    optimizer.step(lr_scale, splats, grad_scale)
+                });
+            }
+            if !config.freeze_means {
+                splats = trace_span!("Mean step", sync_burn = true).in_scope(|| {
+                    let grad_means =
+                        GradientsParams::from_params(&mut grads, &splats, &[splats.means.id]);
+                    optimizer.step(lr_mean, splats, grad_means)
+                });
+            }
+            if !config.freeze_opacity {
+                splats = trace_span!("Opacity step", sync_burn = true).in_scope(|| {
+                    let grad_opac =
+                        GradientsParams::from_params(&mut grads, &splats, &[splats.raw_opacity.id]);
+                    optimizer.step(lr_opac, splats, grad_opac)
+                });
+            }
             splats
         });
 
-        let _housekeep = trace_span!("Housekeeping", sync_burn = true);
-        // Get the xy gradient norm from the dummy tensor.
-        let refine_weight = refine_weight_holder
-            .grad_remove(&mut grads)
-            .expect("XY gradients need to be calculated.");
+        let lr_camera = self.cameras.is_some().then(|| self.config.lr_camera * self.lr_scale);
+        if let (Some(cameras), Some(lr_camera)) = (self.cameras.take(), lr_camera) {
+            let _span = trace_span!("Camera step", sync_burn = true).entered();
+            let optim = self
+                .camera_optim
+                .get_or_insert_with(|| AdamScaledConfig::new().with_epsilon(1e-15).init());
+            let grad_cameras = GradientsParams::from_params(&mut grads, &cameras, &[cameras.params.id]);
+            self.cameras = Some(optim.step(lr_camera, cameras, grad_cameras));
+        }
 
+        let lr_background = self
+            .background
+            .is_some()
+            .then(|| self.config.lr_background * self.lr_scale);
+        if let (Some(background), Some(lr_background)) = (self.background.take(), lr_background) {
+            let _span = trace_span!("Background step", sync_burn = true).entered();
+            let optim = self
+                .background_optim
+                .get_or_insert_with(|| AdamScaledConfig::new().with_epsilon(1e-15).init());
+            let grad_background =
+                GradientsParams::from_params(&mut grads, &background, &[background.coeffs.id]);
+            self.background = Some(optim.step(lr_background, background, grad_background));
+        }
+
+        let _housekeep = trace_span!("Housekeeping", sync_burn = true);
         let device = splats.device();
         let num_splats = splats.num_splats();
         let record = self
             .refine_record
             .get_or_insert_with(|| RefineRecord::new(num_splats, &device));
 
-        record.gather_stats(
-            refine_weight,
-            glam::uvec2(img_w as u32, img_h as u32),
-            aux.global_from_compact_gid.clone(),
-            aux.num_visible().into_primitive(),
-        );
+        for (_, aux, refine_weight_holder, img_size) in &renders {
+            // Get the xy gradient norm from the dummy tensor. The loss is averaged over the
+            // renders, undo that so the growth threshold means the same for any batch size or
+            // number of patches.
+            let refine_weight = refine_weight_holder
+                .grad_remove(&mut grads)
+                .expect("XY gradients need to be calculated.")
+                * num_renders as f32;
+
+            record.gather_stats(
+                refine_weight,
+                *img_size,
+                aux.global_from_compact_gid.clone(),
+                aux.num_visible().into_primitive(),
+            );
+        }
         drop(_housekeep);
 
-        let mean_noise_weight_scale = self.config.mean_noise_weight * (1.0 - train_t);
-
-        if mean_noise_weight_scale > 0.0 {
-            let device = splats.device();
-            // Add random noise. Only do this in the growth phase, otherwise
-            // let the splats settle in without noise, not much point in exploring regions anymore.
-            // trace_span!("Noise means").in_scope(|| {
-            let one = Tensor::ones([1], &device);
-            let noise_weight = (one - current_opacity.inner())
-                .powi_scalar(100)
-                .clamp(0.0, 1.0);
-            let noise_weight = noise_weight * visible.inner(); // Only noise visible gaussians.
-            let noise_weight = noise_weight.unsqueeze_dim(1);
-
-            let samples = quaternion_vec_multiply(
-                splats.rotations_normed().inner(),
-                Tensor::random(
-                    [splats.num_splats() as usize, 3],
-                    Distribution::Normal(0.0, 1.0),
-                    &device,
-                ) * splats.scales().inner(),
+        if !self.config.freeze_means {
+            splats = self.strategy.add_noise(
+                splats,
+                current_opacity.inner(),
+                visible.inner(),
+                lr_mean,
+                train_t,
             );
-
-            let noise_weight = noise_weight * (lr_mean as f32 * mean_noise_weight_scale);
-            splats.means = splats
-                .means
-                .map(|m| Tensor::from_inner(m.inner() + samples * noise_weight).require_grad());
         }
 
+        // Report the first render, which is the whole first view when not training on patches.
+        let (pred_image, aux, _, _) = renders.swap_remove(0);
         let stats = TrainStepStats {
             pred_image: pred_image.inner(),
             num_visible: aux.num_visible().inner(),
             num_intersections: aux.num_intersections().inner(),
             loss: loss.inner(),
+            view_losses: view_losses
+                .into_iter()
+                .map(|(index, loss)| (index, loss.inner()))
+                .collect(),
             lr_mean,
             lr_rotation,
             lr_scale,
             lr_coeffs,
             lr_opac,
+            lr_camera,
+            lr_background,
         };
 
         (splats, stats)
     }
 
+    // Adds zeroed SH bands up to `sh_degree`. The optimizer state of the existing coefficients is kept.
+    fn raise_sh_degree(
+        &mut self,
+        splats: Splats<Autodiff<MainBackend>>,
+        sh_degree: u32,
+    ) -> Splats<Autodiff<MainBackend>> {
+        log::info!("Raising SH degree to {sh_degree}");
+
+        let Some(optim) = self.optim.take() else {
+            // Nothing optimized yet, the optimizer is created for the new degree.
+            return splats.with_sh_degree(sh_degree);
+        };
+        let mut record = optim.to_record();
+
+        let device = splats.device();
+        let [n, cur_coeffs, _] = splats.sh_coeffs.dims();
+        let added = sh_coeffs_for_degree(sh_degree) as usize - cur_coeffs;
+        let pad = |x: Tensor<MainBackend, 3>| {
+            Tensor::cat(vec![x, Tensor::zeros([n, added, 3], &device)], 1)
+        };
+
+        let splats = map_splats_and_opt(
+            splats,
+            &mut record,
+            |x| x,
+            |x| x,
+            |x| x,
+            pad,
+            |x| x,
+            |x| x,
+            |x| x,
+            |x| x,
+            pad,
+            |x| x,
+        );
+
+        // The new bands get the learning rate of the higher bands.
+        let mut state: AdamState<MainBackend, 3> = record
+            .remove(&splats.sh_coeffs.id)
+            .expect("failed to get optimizer record")
+            .into_state();
+        state.scaling = Some(sh_lr_scales(sh_degree, self.config.lr_coeffs_sh_scale, &device));
+        record.insert(splats.sh_coeffs.id, AdaptorRecord::from_state(state));
+
+        self.optim = Some(create_default_optimizer().load_record(record));
+        splats
+    }
+
+    // The images & cameras to train on this step: the whole view, or random patches of it.
+    fn training_targets(
+        &self,
+        batch: &SceneBatch<Autodiff<MainBackend>>,
+    ) -> Vec<(Tensor<Autodiff<MainBackend>, 3>, Camera)> {
+        let [img_h, img_w, _] = batch.img_tensor.dims();
+        let img_size = glam::uvec2(img_w as u32, img_h as u32);
+
+        let size = match self.config.patch_size {
+            Some(patch_size) => img_size.min(UVec2::splat(patch_size.max(1))),
+            None => img_size,
+        };
+        if size == img_size {
+            return vec![(batch.img_tensor.clone(), batch.camera.clone())];
+        }
+
+        let mut rng = rand::rng();
+        (0..self.config.patches_per_step.max(1))
+            .map(|_| {
+                let offset = glam::uvec2(
+                    rng.random_range(0..=img_size.x - size.x),
+                    rng.random_range(0..=img_size.y - size.y),
+                );
+                let end = offset + size;
+                let image = batch.img_tensor.clone().slice(s![
+                    offset.y as usize..end.y as usize,
+                    offset.x as usize..end.x as usize,
+                    ..
+                ]);
+                (image, batch.camera.crop(img_size, offset, size))
+            })
+            .collect()
+    }
+
+    fn image_loss(
+        &self,
+        pred_image: Tensor<Autodiff<MainBackend>, 3>,
+        gt_image: Tensor<Autodiff<MainBackend>, 3>,
+        alpha_is_mask: bool,
+    ) -> Tensor<Autodiff<MainBackend>, 1> {
+        let pred_rgb = pred_image.clone().slice(s![.., .., 0..3]);
+        let gt_rgb = gt_image.clone().slice(s![.., .., 0..3]);
+
+        let l1_rgb = (pred_rgb.clone() - gt_rgb.clone()).abs();
+
+        let total_err = if self.config.ssim_weight > 0.0 {
+            let ssim_err = self.ssim.ssim(pred_rgb, gt_rgb);
+            l1_rgb * (1.0 - self.config.ssim_weight) - (ssim_err * self.config.ssim_weight)
+        } else {
+            l1_rgb
+        };
+
+        if gt_image.dims()[2] == 4 {
+            let alpha_input = gt_image.slice(s![.., .., 3..4]);
+
+            if alpha_is_mask {
+                (total_err * alpha_input).mean()
+            } else {
+                let pred_alpha = pred_image.slice(s![.., .., 3..4]);
+                total_err.mean()
+                    + (alpha_input - pred_alpha).abs().mean() * self.config.match_alpha_weight
+            }
+        } else {
+            total_err.mean()
+        }
+    }
+
     pub async fn refine_if_needed(
         &mut self,
         iter: u32,
+        scene_extent: f32,
         splats: Splats<Autodiff<MainBackend>>,
     ) -> (Splats<Autodiff<MainBackend>>, Option<RefineStats>) {
-        if iter == 0 || iter % self.config.refine_every != 0 {
+        if self.config.disable_refine || !self.strategy.should_refine(iter) {
             return (splats, None);
         }
+        // Nothing gathered yet since a rollback.
+        let Some(refiner) = self.refine_record.take() else {
+            return (splats, None);
+        };
 
         let device = splats.means.device();
         let client = WgpuRuntime::client(&device);
         client.memory_cleanup();
 
-        // If not refining, update splat to step with gradients applied.
-        // Prune dead splats. This ALWAYS happen even if we're not "refining" anymore.
         let mut record = self
             .optim
             .take()
             .expect("Can only refine after optimizer is initialized")
             .to_record();
-        let refiner = self
-            .refine_record
-            .take()
-            .expect("Can only refine if refine stats are initialized");
-        let alpha_mask = splats
-            .raw_opacity
-            .val()
-            .inner()
-            .lower_elem(inverse_sigmoid(MIN_OPACITY));
-
-        let (mut splats, refiner, pruned_count) =
-            prune_points(splats, &mut record, refiner, alpha_mask).await;
-        let mut add_indices = HashSet::new();
-
-        // Replace dead gaussians if we're still refining.
-        if pruned_count > 0 {
-            // Sample from random opacities.
-            let resampled_weights = splats.opacities().inner();
-            let resampled_weights = resampled_weights
-                .into_data_async()
-                .await
-                .into_vec::<f32>()
-                .expect("Failed to read weights");
-            let resampled_inds = multinomial_sample(&resampled_weights, pruned_count);
-            add_indices.extend(resampled_inds);
-        }
 
-        if iter < self.config.growth_stop_iter {
-            let above_threshold = refiner
-                .refine_weight_norm
-                .clone()
-                .greater_elem(self.config.growth_grad_threshold)
-                .int();
-            let threshold_count = above_threshold.clone().sum().into_scalar_async().await as u32;
-
-            let grow_count =
-                (threshold_count as f32 * self.config.growth_select_fraction).round() as u32;
-
-            let sample_high_grad = grow_count.saturating_sub(pruned_count);
-
-            // Only grow to the max nr. of splats.
-            let cur_splats = splats.num_splats() + add_indices.len() as u32;
-            let grow_count = sample_high_grad.min(self.config.max_splats - cur_splats);
-
-            // If still growing, sample from indices which are over the threshold.
-            if grow_count > 0 {
-                let weights = above_threshold.float() * refiner.refine_weight_norm;
-                let weights = weights
-                    .into_data_async()
-                    .await
-                    .into_vec::<f32>()
-                    .expect("Failed to read weights");
-                let growth_inds = multinomial_sample(&weights, grow_count);
-                add_indices.extend(growth_inds);
-            }
-        }
+        let (splats, stats) = self
+            .strategy
+            .refine(iter, scene_extent, splats, &mut record, refiner)
+            .await;
+
+        self.optim = Some(create_default_optimizer().load_record(record));
+        // Refining changes the loss on purpose.
+        self.guard.restart();
 
-        let refine_count = add_indices.len();
+        client.memory_cleanup();
 
-        if refine_count > 0 {
-            let refine_inds = Tensor::from_data(
-                TensorData::new(add_indices.into_iter().collect(), [refine_count]),
-                &device,
-            );
+        (splats, Some(stats))
+    }
+
+    /// The training `cameras` with their learned corrections, or `None` when cameras aren't refined.
+    pub async fn refined_cameras(
+        &self,
+        cameras: &[Camera],
+        scene_extent: f32,
+    ) -> Option<Vec<Camera>> {
+        let adjustments = self.cameras.as_ref()?.read(scene_extent).await;
+        Some(apply_adjustments(cameras, &adjustments))
+    }
+
+    /// The learned background, or `None` when it isn't learned.
+    pub fn background(&self) -> Option<BackgroundModel<MainBackend>> {
+        self.background.as_ref().map(|background| background.valid())
+    }
+
+    /// Checks the loss of step `iter` and the splats for divergence. When training diverged,
+    /// the splats & optimizer state are rolled back to the last snapshot and the learning rates
+    /// are lowered. Fails when there is nothing to roll back to, or after too many rollbacks.
+    ///
+    /// Steps that refine are always checked, so refining never sees broken splats.
+    pub async fn check_divergence(
+        &mut self,
+        iter: u32,
+        splats: Splats<Autodiff<MainBackend>>,
+        loss: Tensor<MainBackend, 1>,
+    ) -> anyhow::Result<(Splats<Autodiff<MainBackend>>, Option<DivergenceStats>)> {
+        let refines = self.guard.enabled()
+            && !self.config.disable_refine
+            && self.strategy.should_refine(iter);
+        if !self.guard.should_check(iter) && !refines {
+            return Ok((splats, None));
+        }
+
+        // Non-finite values make the sum non-finite, so one value per parameter is enough.
+        let values = Tensor::cat(
+            vec![
+                loss,
+                splats.means.val().inner().sum(),
+                splats.rotation.val().inner().sum(),
+                splats.log_scales.val().inner().sum(),
+                splats.sh_coeffs.val().inner().sum(),
+                splats.raw_opacity.val().inner().sum(),
+            ],
+            0,
+        )
+        .into_data_async()
+        .await
+        .into_vec::<f32>()
+        .expect("Failed to read divergence check values");
+        let params_finite = values[1..].iter().all(|v| v.is_finite());
+
+        let Some(reason) = self.guard.check(values[0], params_finite) else {
+            if self.guard.wants_snapshot(iter) {
+                self.guard.store(Snapshot {
+                    iter,
+                    splats: splats.valid(),
+                    optimizer: self.optim.as_ref().map(|optim| optim.to_record()),
+                    cameras: self.cameras.as_ref().map(|cameras| cameras.values()),
+                    background: self.background.as_ref().map(|background| background.valid()),
+                });
+            }
+            return Ok((splats, None));
+        };
 
-            let cur_means = splats.means.val().inner().select(0, refine_inds.clone());
-            let cur_rots = splats
-                .rotations_normed()
-                .inner()
-                .select(0, refine_inds.clone());
-            let cur_log_scale = splats
-                .log_scales
-                .val()
-                .inner()
-                .select(0, refine_inds.clone());
-            let cur_coeff = splats
-                .sh_coeffs
-                .val()
-                .inner()
-                .select(0, refine_inds.clone());
-            let cur_raw_opac = splats
-                .raw_opacity
-                .val()
-                .inner()
-                .select(0, refine_inds.clone());
-
-            // The amount to offset the scale and opacity should maybe depend on how far away we have sampled these gaussians,
-            // but a fixed amount seems to work ok. The only note is that divide by _less_ than SQRT(2) seems to exponentially
-            // blow up, as more 'mass' is added each refine.
-            let scale_div = Tensor::ones_like(&cur_log_scale) * SQRT_2.ln();
-
-            let one = Tensor::ones([1], &device);
-            let cur_opac = sigmoid(cur_raw_opac.clone());
-            let new_opac = one.clone() - (one - cur_opac).sqrt();
-            let new_raw_opac = inv_sigmoid(new_opac.clamp(1e-24, 1.0 - 1e-24));
-
-            // Scatter needs [N, 3] indices for means and scales.
-            let refine_inds_2d = refine_inds.clone().unsqueeze_dim(1).repeat_dim(1, 3);
-
-            let samples = quaternion_vec_multiply(
-                cur_rots.clone(),
-                Tensor::random([refine_count, 3], Distribution::Normal(0.0, 0.5), &device)
-                    * cur_log_scale.clone().exp(),
+        #[cfg(feature = "debug-validation")]
+        let offending_splats = if reason == DivergenceReason::NonFiniteParams {
+            let offending = crate::divergence::non_finite_splats(&splats).await;
+            log::error!(
+                "{} splats have non-finite parameters, first ones: {:?}",
+                offending.len(),
+                &offending[..offending.len().min(32)]
             );
+            offending
+        } else {
+            vec![]
+        };
+        #[cfg(not(feature = "debug-validation"))]
+        let offending_splats = vec![];
 
-            // Shrink & offset existing splats.
-            splats.means = splats.means.map(|m| {
-                let new_means = m
-                    .inner()
-                    .scatter(0, refine_inds_2d.clone(), -samples.clone());
-                Tensor::from_inner(new_means).require_grad()
-            });
-            splats.log_scales = splats.log_scales.map(|s| {
-                let new_scales = s
-                    .inner()
-                    .scatter(0, refine_inds_2d.clone(), -scale_div.clone());
-                Tensor::from_inner(new_scales).require_grad()
-            });
-            splats.raw_opacity = splats.raw_opacity.map(|m| {
-                let difference = new_raw_opac.clone() - cur_raw_opac.clone();
-                let new_opacities = m.inner().scatter(0, refine_inds.clone(), difference);
-                Tensor::from_inner(new_opacities).require_grad()
-            });
-
-            // Concatenate new splats.
-            let sh_dim = splats.sh_coeffs.dims()[1];
-            splats = map_splats_and_opt(
-                splats,
-                &mut record,
-                |x| Tensor::cat(vec![x, cur_means + samples], 0),
-                |x| Tensor::cat(vec![x, cur_rots], 0),
-                |x| Tensor::cat(vec![x, cur_log_scale - scale_div], 0),
-                |x| Tensor::cat(vec![x, cur_coeff], 0),
-                |x| Tensor::cat(vec![x, new_raw_opac], 0),
-                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 3], &device)], 0),
-                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 4], &device)], 0),
-                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count, 3], &device)], 0),
-                |x| {
-                    Tensor::cat(
-                        vec![x, Tensor::zeros([refine_count, sh_dim, 3], &device)],
-                        0,
-                    )
-                },
-                |x| Tensor::cat(vec![x, Tensor::zeros([refine_count], &device)], 0),
+        let Some(snapshot) = self.guard.snapshot() else {
+            anyhow::bail!("Training diverged at step {iter} before any snapshot was taken: {reason:?}");
+        };
+        self.rollbacks += 1;
+        if self.rollbacks > self.config.max_rollbacks {
+            anyhow::bail!(
+                "Training diverged at step {iter} after {} rollbacks: {reason:?}",
+                self.config.max_rollbacks
             );
         }
+        self.lr_scale *= self.config.divergence_lr_decay;
 
-        self.optim = Some(create_default_optimizer().load_record(record));
-
-        client.memory_cleanup();
+        log::warn!(
+            "Training diverged at step {iter} ({reason:?}), rolling back to step {}",
+            snapshot.iter
+        );
+        let splats = snapshot.splats.clone().into_autodiff();
+        self.optim = snapshot
+            .optimizer
+            .clone()
+            .map(|record| create_default_optimizer().load_record(record));
+        // The gathered stats may not match the restored splats anymore.
+        self.refine_record = None;
+        if let (Some(cameras), Some(values)) = (self.cameras.as_mut(), snapshot.cameras.clone()) {
+            cameras.restore(values);
+            self.camera_optim = None;
+        }
+        if let Some(background) = snapshot.background.clone() {
+            self.background = Some(background.into_autodiff());
+            self.background_optim = None;
+        }
 
-        (
-            splats,
-            Some(RefineStats {
-                num_added: refine_count as u32,
-                num_pruned: pruned_count,
-            }),
-        )
+        let stats = DivergenceStats {
+            reason,
+            rolled_back_to: snapshot.iter,
+            lr_scale: self.lr_scale,
+            offending_splats,
+        };
+        Ok((splats, Some(stats)))
     }
 }
 
-fn map_splats_and_opt(
-    mut splats: Splats<Autodiff<MainBackend>>,
-    record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, Autodiff<MainBackend>>>,
-    map_mean: impl FnOnce(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
-    map_rotation: impl FnOnce(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
-    map_scale: impl FnOnce(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
-    map_coeffs: impl FnOnce(Tensor<MainBackend, 3>) -> Tensor<MainBackend, 3>,
-    map_opac: impl FnOnce(Tensor<MainBackend, 1>) -> Tensor<MainBackend, 1>,
-
-    map_opt_mean: impl Fn(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
-    map_opt_rotation: impl Fn(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
-    map_opt_scale: impl Fn(Tensor<MainBackend, 2>) -> Tensor<MainBackend, 2>,
-    map_opt_coeffs: impl Fn(Tensor<MainBackend, 3>) -> Tensor<MainBackend, 3>,
-    map_opt_opac: impl Fn(Tensor<MainBackend, 1>) -> Tensor<MainBackend, 1>,
-) -> Splats<Autodiff<MainBackend>> {
-    splats.means = splats
-        .means
-        .map(|x| Tensor::from_inner(map_mean(x.inner())).require_grad());
-    map_opt(splats.means.id, record, &map_opt_mean);
-
-    splats.rotation = splats
-        .rotation
-        .map(|x| Tensor::from_inner(map_rotation(x.inner())).require_grad());
-    map_opt(splats.rotation.id, record, &map_opt_rotation);
-
-    splats.log_scales = splats
-        .log_scales
-        .map(|x| Tensor::from_inner(map_scale(x.inner())).require_grad());
-    map_opt(splats.log_scales.id, record, &map_opt_scale);
-
-    splats.sh_coeffs = splats
-        .sh_coeffs
-        .map(|x| Tensor::from_inner(map_coeffs(x.inner())).require_grad());
-    map_opt(splats.sh_coeffs.id, record, &map_opt_coeffs);
-
-    splats.raw_opacity = splats
-        .raw_opacity
-        .map(|x| Tensor::from_inner(map_opac(x.inner())).require_grad());
-    map_opt(splats.raw_opacity.id, record, &map_opt_opac);
-
-    splats
-}
-
-fn map_opt<B: AutodiffBackend, const D: usize>(
-    param_id: ParamId,
-    record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, B>>,
-    map_opt: &impl Fn(Tensor<B::InnerBackend, D>) -> Tensor<B::InnerBackend, D>,
-) {
-    let mut state: AdamState<_, D> = record
-        .remove(&param_id)
-        .expect("failed to get optimizer record")
-        .into_state();
-
-    state.momentum = state.momentum.map(|mut moment| {
-        moment.moment_1 = map_opt(moment.moment_1);
-        moment.moment_2 = map_opt(moment.moment_2);
-        moment
-    });
-
-    record.insert(param_id, AdaptorRecord::from_state(state));
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-// Prunes points based on the given mask.
-//
-// Args:
-//   mask: bool[n]. If True, prune this Gaussian.
-async fn prune_points(
-    mut splats: Splats<Autodiff<MainBackend>>,
-    record: &mut HashMap<ParamId, AdaptorRecord<AdamScaled, Autodiff<MainBackend>>>,
-    mut refiner: RefineRecord<MainBackend>,
-    prune: Tensor<MainBackend, 1, Bool>,
-) -> (
-    Splats<Autodiff<MainBackend>>,
-    RefineRecord<MainBackend>,
-    u32,
-) {
-    assert_eq!(
-        prune.dims()[0] as u32,
-        splats.num_splats(),
-        "Prune mask must have same number of elements as splats"
-    );
-
-    let prune_count = prune.dims()[0];
-    if prune_count == 0 {
-        return (splats, refiner, 0);
+    fn read(tensor: Tensor<MainBackend, 3>) -> Vec<f32> {
+        tensor.into_data().into_vec::<f32>().expect("Wrong type")
     }
 
-    let valid_inds = prune.bool_not().argwhere_async().await;
-
-    if valid_inds.dims()[0] == 0 {
-        log::warn!("Trying to create empty splat!");
-        return (splats, refiner, 0);
+    #[test]
+    fn higher_sh_bands_learn_slower() {
+        let device = WgpuDevice::default();
+        let scales = sh_lr_scales(1, 20.0, &device);
+        assert_eq!(scales.dims(), [1, 4, 1]);
+        assert_eq!(read(scales), vec![1.0, 0.05, 0.05, 0.05]);
+        assert_eq!(read(sh_lr_scales(0, 20.0, &device)), vec![1.0]);
     }
 
-    let start_splats = splats.num_splats();
-    let new_points = valid_inds.dims()[0] as u32;
-    if new_points < start_splats {
-        let valid_inds = valid_inds.squeeze(1);
-        splats = map_splats_and_opt(
-            splats,
-            record,
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
-            |x| x.select(0, valid_inds.clone()),
+    #[test]
+    fn raising_sh_degree_keeps_optimizer_state() {
+        let device = WgpuDevice::default();
+        let config = TrainConfig::new();
+        let mut trainer = SplatTrainer::new(&config, 1, &device);
+        let splats: Splats<Autodiff<MainBackend>> = Splats::from_raw(
+            &[Vec3::ZERO, Vec3::X],
+            None,
+            Some(&[Vec3::ZERO; 2]),
+            None,
+            Some(&[0.0; 2]),
+            &device,
         );
-        refiner = refiner.keep(valid_inds);
+        assert_eq!(splats.sh_degree(), 0);
+
+        // Without an optimizer there is no state to keep.
+        let raised = trainer.raise_sh_degree(splats.clone(), 1);
+        assert_eq!(raised.sh_coeffs.dims(), [2, 4, 3]);
+
+        // One optimizer step, so the base colors have moments.
+        let mut optim = create_default_optimizer();
+        let mut grads = splats.sh_coeffs.val().sum().backward();
+        let grads = GradientsParams::from_params(&mut grads, &splats, &[splats.sh_coeffs.id]);
+        let splats = optim.step(1e-3, splats, grads);
+        trainer.optim = Some(optim);
+
+        let splats = trainer.raise_sh_degree(splats, 2);
+        assert_eq!(splats.sh_coeffs.dims(), [2, 9, 3]);
+
+        let mut record = trainer.optim.take().expect("Optimizer should be kept").to_record();
+        let state: AdamState<MainBackend, 3> = record
+            .remove(&splats.sh_coeffs.id)
+            .expect("SH coefficients should have a state")
+            .into_state();
+        let moment = read(state.momentum.expect("Moments should be kept").moment_1);
+        // The base colors keep their moments, the new bands start at rest.
+        for splat in moment.chunks(9 * 3) {
+            assert!(splat[..3].iter().all(|&m| m != 0.0), "Base color moments were lost");
+            assert!(splat[3..].iter().all(|&m| m == 0.0), "New bands should have no moments");
+        }
+        let scaling = state.scaling.expect("SH coefficients should have LR scales");
+        assert_eq!(read(scaling), read(sh_lr_scales(2, config.lr_coeffs_sh_scale, &device)));
     }
-    (splats, refiner, start_splats - new_points)
-}
\ No newline at end of file
+}
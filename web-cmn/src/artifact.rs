@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ArtifactKind {
+    EvalImage,
+    Splats,
+    Cameras,
+    Background,
+}
+
+/// A file produced by a training run, downloadable from `/scene/{name}/artifacts/{path}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArtifactResponse {
+    pub run: String,
+    pub kind: ArtifactKind,
+    pub iteration: u32,
+    pub path: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the file.
+    pub checksum: String,
+}
@@ -1,3 +1,5 @@
+pub mod artifact;
 pub mod splats;
 pub mod pipeline;
 pub mod scene;
+pub mod upload;
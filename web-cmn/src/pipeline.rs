@@ -1,10 +1,153 @@
-use std::fmt;
 use serde::{Deserialize, Serialize};
+use crate::artifact::ArtifactResponse;
 use crate::splats::RawSplats;
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LearningRates {
+    pub mean: f64,
+    pub rotation: f64,
+    pub scale: f64,
+    pub coeffs: f64,
+    pub opacity: f64,
+    /// Only set when camera corrections are learned.
+    #[serde(default)]
+    pub camera: Option<f64>,
+    /// Only set when a background is learned.
+    #[serde(default)]
+    pub background: Option<f64>,
+}
+
+/// Options of a training run, passed as query parameters when opening the training websocket.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct TrainOptions {
+    /// Path of a splats artifact to continue training from.
+    #[serde(default)]
+    pub init_splats: Option<String>,
+    /// Scene the `init_splats` artifact belongs to, the trained scene when not set.
+    #[serde(default)]
+    pub init_splats_scene: Option<String>,
+    #[serde(default)]
+    pub freeze_means: bool,
+    #[serde(default)]
+    pub freeze_rotation: bool,
+    #[serde(default)]
+    pub freeze_scales: bool,
+    #[serde(default)]
+    pub freeze_sh: bool,
+    #[serde(default)]
+    pub freeze_opacity: bool,
+    /// Train the initial splats without adding or removing any.
+    #[serde(default)]
+    pub disable_refine: bool,
+    /// Hold out every nth view for evaluation, 0 trains on all views. The server picks when not set.
+    #[serde(default)]
+    pub eval_every: Option<usize>,
+    /// Load the images downscaled by this factor, from the `images_N` folder when the scene has one.
+    #[serde(default)]
+    pub downscale: Option<u32>,
+    /// Nr. of images decoded in parallel, the server picks when not set.
+    #[serde(default)]
+    pub decode_workers: Option<usize>,
+    /// Nr. of decoded images to queue up ahead of the trainer, the server picks when not set.
+    #[serde(default)]
+    pub prefetch: Option<usize>,
+    #[serde(default)]
+    pub sampling: ViewSampling,
+    /// Recenter & rescale the scene with +Y up before training, exports keep the original coordinates.
+    #[serde(default)]
+    pub normalize_scene: bool,
+    /// Stop when the eval PSNR hasn't improved for this many evals.
+    #[serde(default)]
+    pub stop_plateau_evals: Option<u32>,
+    /// Smallest PSNR gain in dB that counts as an improvement.
+    #[serde(default)]
+    pub stop_plateau_min_delta: Option<f32>,
+    /// Stop once the eval PSNR reaches this, and the target SSIM when that is set too.
+    #[serde(default)]
+    pub stop_target_psnr: Option<f32>,
+    #[serde(default)]
+    pub stop_target_ssim: Option<f32>,
+    /// Stop after training for this many seconds.
+    #[serde(default)]
+    pub stop_time_budget_secs: Option<u64>,
+    /// Stop when the splat count stayed stable over this many refines.
+    #[serde(default)]
+    pub stop_splat_count_refines: Option<u32>,
+    /// Relative change of the splat count that still counts as stable.
+    #[serde(default)]
+    pub stop_splat_count_tolerance: Option<f32>,
+}
+
+/// Order in which training views are visited, see `dataset::ViewSampling`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ViewSampling {
+    #[default]
+    Uniform,
+    /// Views with a high loss are trained on more often.
+    LossWeighted,
+    Sequential,
+}
+
+/// Stats of the most recent training step.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrainProgress {
+    pub iter: u32,
+    pub elapsed_secs: f32,
+    pub loss: f32,
+    pub num_splats: u32,
+    pub lr: LearningRates,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RefineEvent {
+    pub iter: u32,
+    pub num_added: u32,
+    pub num_pruned: u32,
+    pub num_splats: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EvalEvent {
+    pub iter: u32,
+    pub psnr: f32,
+    pub ssim: f32,
+    /// Which views were evaluated, see `dataset::EvalSplit`.
+    #[serde(default)]
+    pub split: String,
+}
+
+/// Training diverged and was rolled back to an earlier snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DivergenceEvent {
+    pub iter: u32,
+    pub rolled_back_to: u32,
+    /// Multiplier of all learning rates from now on.
+    pub lr_scale: f64,
+    pub reason: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum WiredPipelineMessage {
+    Started {
+        start_iter: u32,
+        total_steps: u32,
+    },
     TrainStep(RawSplats),
-    Done,
+    Progress(TrainProgress),
+    Refine(RefineEvent),
+    Eval(EvalEvent),
+    Diverged(DivergenceEvent),
+    /// A file was saved and can now be downloaded.
+    Artifact(ArtifactResponse),
+    /// Training finished, `reason` says why.
+    Done {
+        reason: String,
+    },
     Error(String),
-}
\ No newline at end of file
+}
+
+/// Messages the client sends back over the training websocket.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum WiredClientMessage {
+    Stop,
+}
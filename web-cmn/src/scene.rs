@@ -3,4 +3,68 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SceneResponse {
     pub name: String,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Result of checking the dataset when it was uploaded.
+    #[serde(default)]
+    pub validation: Option<ValidationReport>,
+}
+
+/// Findings of checking an uploaded dataset.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ValidationReport {
+    pub format: Option<String>,
+    /// Problems that make training fail.
+    pub errors: Vec<String>,
+    /// Problems training works around, like skipping an image.
+    pub warnings: Vec<String>,
+    pub image_count: usize,
+    /// Smallest & largest image by pixel count, as width & height.
+    pub min_resolution: Option<[u32; 2]>,
+    pub max_resolution: Option<[u32; 2]>,
+    pub camera_models: Vec<String>,
+    pub point_count: usize,
+}
+
+/// Body of `POST /scene/{name}/rename`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RenameSceneRequest {
+    pub name: String,
+}
+
+/// Body of `PATCH /scene/{name}`. Fields left out are not changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UpdateSceneRequest {
+    /// Set to an empty string to clear the description.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A camera the dataset was captured with.
+///
+/// Follows the dataset convention: +Z looks forward and +Y points down in camera space.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrainingCamera {
+    pub image_name: String,
+    pub position: [f32; 3],
+    /// Camera to world rotation as a quaternion in xyzw order.
+    pub rotation: [f32; 4],
+    pub fov_x: f32,
+    pub fov_y: f32,
+    pub width: u32,
+    pub height: u32,
+    pub is_eval: bool,
+}
+
+/// The sparse points of a reconstruction, as flat xyz positions & rgb colors.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SparsePoints {
+    pub positions: Vec<f32>,
+    pub colors: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SceneLayoutResponse {
+    pub cameras: Vec<TrainingCamera>,
+    pub points: SparsePoints,
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /uploads`, starts a resumable upload of a zipped scene.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CreateUploadRequest {
+    /// Name of the scene the upload becomes once finalized.
+    pub name: String,
+    pub file_name: String,
+    /// Total size of the file in bytes.
+    pub size: u64,
+}
+
+/// State of an upload session. Chunks must be sent starting at `received`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UploadStatus {
+    pub id: String,
+    pub name: String,
+    pub file_name: String,
+    pub size: u64,
+    pub received: u64,
+}
+
+/// Query of `PUT /uploads/{id}`, the chunk in the body starts at `offset`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UploadChunkQuery {
+    pub offset: u64,
+}
+
+/// Body of `POST /uploads/{id}/finalize`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FinalizeUploadRequest {
+    /// Hex encoded SHA-256 of the whole file.
+    pub checksum: String,
+}
@@ -1,5 +1,6 @@
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec2, Vec3};
+use glam::{Mat4, Quat, Vec2, Vec3};
+use web_cmn::scene::TrainingCamera;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -68,4 +69,30 @@ impl Camera {
         let new_dist = (dist * (1.0 - delta * 0.1)).max(0.1);
         self.eye = self.target - dir * new_dist;
     }
+
+    /// Moves the camera to look through a training view, keeping the current orbit distance.
+    pub fn snap_to(&mut self, view: &TrainingCamera) {
+        let position = Vec3::from_array(view.position);
+        let rotation = Quat::from_array(view.rotation);
+        let dist = (self.target - self.eye).length().max(0.1);
+
+        // Training cameras look down +Z with +Y pointing down.
+        self.eye = position;
+        self.target = position + rotation * Vec3::Z * dist;
+        self.up = rotation * Vec3::NEG_Y;
+        self.fovy = view.fov_y;
+    }
+
+    /// Projects a world space point to pixel coordinates, or None if it's behind the camera.
+    pub fn project(&self, point: Vec3, width: f32, height: f32) -> Option<Vec2> {
+        let clip = self.build_view_projection_matrix() * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(Vec2::new(
+            (ndc.x * 0.5 + 0.5) * width,
+            (0.5 - ndc.y * 0.5) * height,
+        ))
+    }
 }
@@ -12,6 +12,7 @@ mod context;
 mod quad;
 mod preprocessor;
 mod sorter;
+mod overlay;
 
 pub use context::Context;
 pub use splatter::Splatter;
@@ -0,0 +1,211 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Quat, Vec3};
+use wgpu::util::DeviceExt;
+use web_cmn::scene::{SparsePoints, TrainingCamera};
+
+const TRAIN_FRUSTUM_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+const EVAL_FRUSTUM_COLOR: [f32; 4] = [0.3, 0.7, 1.0, 1.0];
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct OverlayVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+}
+
+/// Draws debug geometry on top of the splats: training camera frustums as lines, and the
+/// sparse points of the reconstruction.
+pub struct Overlay {
+    line_pipeline: wgpu::RenderPipeline,
+    point_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    frustum_buffer: Option<wgpu::Buffer>,
+    num_frustum_vertices: u32,
+    point_buffer: Option<wgpu::Buffer>,
+    num_points: u32,
+    pub frustum_scale: f32,
+    pub visible: bool,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, camera_buffer: &wgpu::Buffer) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Overlay Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Overlay Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/overlay.wgsl"));
+
+        let create_pipeline = |label: &str, topology: wgpu::PrimitiveTopology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0=>Float32x3, 1=>Float32x4],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Bgra8Unorm,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let line_pipeline = create_pipeline("Overlay Line Pipeline", wgpu::PrimitiveTopology::LineList);
+        let point_pipeline = create_pipeline("Overlay Point Pipeline", wgpu::PrimitiveTopology::PointList);
+
+        Self {
+            line_pipeline,
+            point_pipeline,
+            bind_group,
+            frustum_buffer: None,
+            num_frustum_vertices: 0,
+            point_buffer: None,
+            num_points: 0,
+            frustum_scale: 0.1,
+            visible: true,
+        }
+    }
+
+    pub fn set_cameras(&mut self, device: &wgpu::Device, cameras: &[TrainingCamera]) {
+        let vertices: Vec<OverlayVertex> = cameras
+            .iter()
+            .flat_map(|cam| frustum_lines(cam, self.frustum_scale))
+            .collect();
+
+        self.num_frustum_vertices = vertices.len() as u32;
+        self.frustum_buffer = (!vertices.is_empty()).then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Overlay Frustum VB"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+    }
+
+    pub fn set_points(&mut self, device: &wgpu::Device, points: &SparsePoints) {
+        let vertices: Vec<OverlayVertex> = points
+            .positions
+            .chunks_exact(3)
+            .zip(points.colors.chunks_exact(3))
+            .map(|(pos, color)| OverlayVertex {
+                pos: [pos[0], pos[1], pos[2]],
+                color: [
+                    color[0] as f32 / 255.0,
+                    color[1] as f32 / 255.0,
+                    color[2] as f32 / 255.0,
+                    1.0,
+                ],
+            })
+            .collect();
+
+        self.num_points = vertices.len() as u32;
+        self.point_buffer = (!vertices.is_empty()).then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Overlay Points VB"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        if !self.visible {
+            return;
+        }
+
+        if let Some(points) = &self.point_buffer {
+            pass.set_pipeline(&self.point_pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.set_vertex_buffer(0, points.slice(..));
+            pass.draw(0..self.num_points, 0..1);
+        }
+
+        if let Some(frustums) = &self.frustum_buffer {
+            pass.set_pipeline(&self.line_pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.set_vertex_buffer(0, frustums.slice(..));
+            pass.draw(0..self.num_frustum_vertices, 0..1);
+        }
+    }
+}
+
+/// Builds the line list for a single camera frustum, with its image plane `depth` units
+/// in front of the camera. The image plane gets a small tick to mark which way is up.
+fn frustum_lines(cam: &TrainingCamera, depth: f32) -> Vec<OverlayVertex> {
+    let color = if cam.is_eval { EVAL_FRUSTUM_COLOR } else { TRAIN_FRUSTUM_COLOR };
+    let position = Vec3::from_array(cam.position);
+    let rotation = Quat::from_array(cam.rotation);
+
+    let half_w = (cam.fov_x * 0.5).tan() * depth;
+    let half_h = (cam.fov_y * 0.5).tan() * depth;
+
+    // Camera space has +Y pointing down, so the top of the image is at -Y.
+    let to_world = |x: f32, y: f32, z: f32| position + rotation * Vec3::new(x, y, z);
+    let corners = [
+        to_world(-half_w, -half_h, depth),
+        to_world(half_w, -half_h, depth),
+        to_world(half_w, half_h, depth),
+        to_world(-half_w, half_h, depth),
+    ];
+    let up_tick = to_world(0.0, -half_h * 1.5, depth);
+
+    let mut segments = vec![];
+    for (i, corner) in corners.iter().enumerate() {
+        segments.push((position, *corner));
+        segments.push((*corner, corners[(i + 1) % 4]));
+    }
+    segments.push((corners[0], up_tick));
+    segments.push((up_tick, corners[1]));
+
+    segments
+        .into_iter()
+        .flat_map(|(a, b)| {
+            [
+                OverlayVertex { pos: a.to_array(), color },
+                OverlayVertex { pos: b.to_array(), color },
+            ]
+        })
+        .collect()
+}
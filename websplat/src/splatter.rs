@@ -1,8 +1,10 @@
 //splatter.rs
 use wgpu::util::DeviceExt;
+use web_cmn::scene::{SparsePoints, TrainingCamera};
 use web_cmn::splats::RawSplats;
 use crate::camera::Camera;
 use crate::context::Context;
+use crate::overlay::Overlay;
 use crate::preprocessor::Preprocessor;
 use crate::renderer::Renderer;
 use crate::sorter::Sorter;
@@ -14,6 +16,7 @@ pub struct Splatter {
     renderer: Renderer,
     preprocessor: Preprocessor,
     sorter: Sorter,
+    overlay: Overlay,
     full_splats: Option<wgpu::Buffer>, // GPU buffer holding all GpuSplats
 }
 
@@ -22,11 +25,13 @@ impl Splatter {
         let renderer = Renderer::new(&ctx.device);
         let preprocessor = Preprocessor::new(&ctx.device);
         let sorter = Sorter::new(&ctx.device);
+        let overlay = Overlay::new(&ctx.device, renderer.camera_buffer());
 
         Self {
             renderer,
             preprocessor,
             sorter,
+            overlay,
             full_splats: None,
         }
     }
@@ -70,6 +75,20 @@ impl Splatter {
         self.renderer.num_splats = num_splats;
     }
 
+    /// Sets the training cameras to draw as frustums.
+    pub fn set_training_cameras(&mut self, ctx: &Context, cameras: &[TrainingCamera]) {
+        self.overlay.set_cameras(&ctx.device, cameras);
+    }
+
+    /// Sets the sparse reconstruction points to draw.
+    pub fn set_sparse_points(&mut self, ctx: &Context, points: &SparsePoints) {
+        self.overlay.set_points(&ctx.device, points);
+    }
+
+    pub fn set_overlay_visible(&mut self, visible: bool) {
+        self.overlay.visible = visible;
+    }
+
     pub fn render(&mut self, ctx: &Context, camera: &Camera) {
         let view_proj = camera.build_view_projection_matrix();
         self.renderer.update_camera(&ctx.queue, &view_proj);
@@ -121,6 +140,8 @@ impl Splatter {
             } else {
                 self.renderer.draw(&mut rpass, self.renderer.num_splats as u32);
             }
+
+            self.overlay.draw(&mut rpass);
         }
 
         ctx.queue.submit(Some(encoder.finish()));